@@ -0,0 +1,7 @@
+//! Seal and validate arbitrary headers with Proof of Work at arbitrary thresholds.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| blockchain_from_scratch::fuzzing::pow_round_trip(data));
@@ -9,6 +9,7 @@ mod p4_accounted_currency;
 //todo fix 2 tests related to serial
 mod p5_digital_cash;
 mod p6_open_ended;
+mod replay;
 
 /// A state machine - Generic over the transition type
 pub trait StateMachine {
@@ -21,6 +22,13 @@ pub trait StateMachine {
 	/// Calculate the resulting state when this state undergoes the given transition
 	fn next_state(starting_state: &Self::State, t: &Self::Transition) -> Self::State;
 
+	/// Whether the given transition can be applied to this state at all. Many machines simply
+	/// ignore invalid transitions in `next_state`, and override this so that tools such as a
+	/// replay can tell an ignored transition apart from one that happens to change nothing.
+	fn is_applicable(_starting_state: &Self::State, _t: &Self::Transition) -> bool {
+		true
+	}
+
 	/// A human-readable name for this state machine. This may be used in user-facing
 	/// programs such as the repl described below. This is not in any way related to
 	/// the correctness of the state machine.
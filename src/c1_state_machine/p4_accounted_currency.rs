@@ -87,6 +87,15 @@ impl StateMachine for AccountedCurrency {
 		}
 		return ret_state;
 	}
+
+	/// A transfer of more than the sender's balance is ignored, so it can not be applied.
+	fn is_applicable(starting_state: &Balances, t: &AccountingTransaction) -> bool {
+		match t {
+			AccountingTransaction::Transfer { sender, amount, .. } =>
+				starting_state.get(sender).is_some_and(|balance| amount <= balance),
+			_ => true,
+		}
+	}
 }
 
 #[test]
@@ -0,0 +1,166 @@
+//! A blockchain stores transitions, not states. Validating a chain only needs the latest state,
+//! but a block explorer wants to show the state at any height, so it must replay the chain's
+//! transitions from genesis and keep every intermediate state along the way.
+//!
+//! Replaying is independent of consensus. It works for any state machine, and only needs the
+//! transitions of each block in order.
+
+use super::StateMachine;
+
+/// Why a chain could not be replayed. The first transition that could not be applied, given as
+/// the index of its block in the chain and its own index within that block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InapplicableTransition {
+	pub block: usize,
+	pub transition: usize,
+}
+
+/// Apply the transitions of a single block to the given state, one at a time and in order.
+///
+/// Each transition must be applicable to the state left by the ones before it, not just to the
+/// state before the block. Two transitions that are each fine alone can conflict, such as two
+/// transfers that together overdraw an account, and then the whole block is invalid. The error is
+/// the index of the first transition that can not be applied.
+pub fn apply_block<SM: StateMachine>(
+	state: &SM::State,
+	transitions: &[SM::Transition],
+) -> Result<SM::State, usize>
+where
+	SM::State: Clone,
+{
+	let mut state = state.clone();
+	for (index, transition) in transitions.iter().enumerate() {
+		if !SM::is_applicable(&state, transition) {
+			return Err(index);
+		}
+		state = SM::next_state(&state, transition);
+	}
+	Ok(state)
+}
+
+/// Replay the transitions of each block in the chain, starting from the genesis state, and return
+/// the state after each block. The state at height `h` is at index `h - 1`.
+///
+/// Stops at the first transition that the state machine says can not be applied.
+pub fn replay<SM: StateMachine>(
+	genesis_state: SM::State,
+	chain: &[Vec<SM::Transition>],
+) -> Result<Vec<SM::State>, InapplicableTransition>
+where
+	SM::State: Clone,
+{
+	let mut states = Vec::with_capacity(chain.len());
+	let mut state = genesis_state;
+	for (block, transitions) in chain.iter().enumerate() {
+		state = apply_block::<SM>(&state, transitions)
+			.map_err(|transition| InapplicableTransition { block, transition })?;
+		states.push(state.clone());
+	}
+	Ok(states)
+}
+
+/// The state machine from chapter 2's blockchain, which adds every transition to its state. A
+/// transition that would overflow the state can not be applied.
+#[cfg(test)]
+struct Adder;
+
+#[cfg(test)]
+impl StateMachine for Adder {
+	type State = u64;
+	type Transition = u64;
+
+	fn next_state(starting_state: &u64, t: &u64) -> u64 {
+		starting_state + t
+	}
+
+	fn is_applicable(starting_state: &u64, t: &u64) -> bool {
+		starting_state.checked_add(*t).is_some()
+	}
+}
+
+#[cfg(test)]
+use super::{
+	p4_accounted_currency::{AccountedCurrency, AccountingTransaction},
+	User,
+};
+#[cfg(test)]
+use std::collections::HashMap;
+
+#[test]
+fn sm_replay_summing_machine() {
+	let chain = vec![vec![1, 2], vec![], vec![3]];
+
+	assert_eq!(replay::<Adder>(0, &chain), Ok(vec![3, 3, 6]));
+	assert_eq!(replay::<Adder>(10, &[]), Ok(vec![]));
+
+	let overflowing = vec![vec![1], vec![2, u64::MAX]];
+	assert_eq!(
+		replay::<Adder>(0, &overflowing),
+		Err(InapplicableTransition { block: 1, transition: 1 })
+	);
+}
+
+#[test]
+fn sm_replay_balance_machine() {
+	use AccountingTransaction::{Mint, Transfer};
+	use User::{Alice, Bob};
+
+	let chain = vec![
+		vec![Mint { minter: Alice, amount: 100 }],
+		vec![Transfer { sender: Alice, receiver: Bob, amount: 30 }],
+		vec![Transfer { sender: Bob, receiver: Alice, amount: 30 }],
+	];
+	assert_eq!(
+		replay::<AccountedCurrency>(HashMap::new(), &chain),
+		Ok(vec![
+			HashMap::from([(Alice, 100)]),
+			HashMap::from([(Alice, 70), (Bob, 30)]),
+			HashMap::from([(Alice, 100)]),
+		])
+	);
+
+	let overdrawn = vec![
+		vec![Mint { minter: Alice, amount: 100 }],
+		vec![
+			Transfer { sender: Alice, receiver: Bob, amount: 60 },
+			Transfer { sender: Alice, receiver: Bob, amount: 60 },
+		],
+	];
+	assert_eq!(
+		replay::<AccountedCurrency>(HashMap::new(), &overdrawn),
+		Err(InapplicableTransition { block: 1, transition: 1 })
+	);
+}
+
+#[test]
+fn sm_apply_block_rejects_jointly_invalid_transfers() {
+	use AccountingTransaction::Transfer;
+	use User::{Alice, Bob, Charlie};
+
+	let state = HashMap::from([(Alice, 100)]);
+	let block = [
+		Transfer { sender: Alice, receiver: Bob, amount: 60 },
+		Transfer { sender: Alice, receiver: Charlie, amount: 60 },
+	];
+
+	// Each transfer is fine on its own, but not after the other.
+	assert!(block.iter().all(|transfer| AccountedCurrency::is_applicable(&state, transfer)));
+	assert_eq!(apply_block::<AccountedCurrency>(&state, &block), Err(1));
+}
+
+#[test]
+fn sm_apply_block_accepts_compatible_transfers() {
+	use AccountingTransaction::Transfer;
+	use User::{Alice, Bob, Charlie};
+
+	let state = HashMap::from([(Alice, 100)]);
+	let block = [
+		Transfer { sender: Alice, receiver: Bob, amount: 60 },
+		Transfer { sender: Alice, receiver: Charlie, amount: 40 },
+	];
+
+	assert_eq!(
+		apply_block::<AccountedCurrency>(&state, &block),
+		Ok(HashMap::from([(Bob, 60), (Charlie, 40)]))
+	);
+}
@@ -0,0 +1,181 @@
+//! So far our chains have been plain slices of blocks, handed around and verified all at once. For
+//! interactive exploration it is handy to have a single chain that grows one block at a time, and
+//! that can also undo its most recent blocks.
+//!
+//! The chain keeps a mempool in step with its blocks. Extrinsics leave the pool when a block that
+//! includes them is added, and return when that block is rolled back.
+
+use super::{
+	mempool::{Extrinsic, Mempool},
+	p4_batched_extrinsics::{Block, Header, MAX_BLOCK_WEIGHT},
+};
+use crate::hash;
+
+/// The reason a rollback could not be performed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RollbackError {
+	/// The number of blocks that were asked to be rolled back.
+	pub requested: usize,
+	/// The number of blocks above genesis, which is the most that can be rolled back.
+	pub available: usize,
+}
+
+/// A single chain of batched extrinsic blocks, starting from a genesis block that is never removed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Blockchain {
+	blocks: Vec<Block>,
+	mempool: Mempool,
+}
+
+impl Blockchain {
+	/// Create a chain consisting of only the given genesis block, with an empty mempool.
+	pub fn new(genesis: Block) -> Self {
+		Blockchain { blocks: vec![genesis], mempool: Mempool::new(MAX_BLOCK_WEIGHT) }
+	}
+
+	/// The extrinsics waiting to be included in a block on top of the tip.
+	pub fn mempool(&self) -> &Mempool {
+		&self.mempool
+	}
+
+	/// Add an extrinsic to the mempool.
+	pub fn submit(&mut self, extrinsic: Extrinsic) {
+		self.mempool.submit(extrinsic);
+	}
+
+	/// The most recent block.
+	pub fn tip(&self) -> &Block {
+		self.blocks.last().expect("genesis is never removed")
+	}
+
+	/// The height of the most recent block.
+	pub fn height(&self) -> u64 {
+		self.tip().header.height()
+	}
+
+	/// The state after executing the most recent block.
+	pub fn state(&self) -> u64 {
+		self.tip().header.state()
+	}
+
+	/// Author a new block containing the given extrinsics on top of the tip.
+	pub fn author(&mut self, extrinsics: Vec<u64>) -> &Block {
+		let block = self.tip().child(extrinsics);
+		self.push(block);
+		self.tip()
+	}
+
+	/// Add the given block on top of the tip, if it is a valid child of the tip.
+	#[must_use]
+	pub fn import(&mut self, block: Block) -> bool {
+		if !self.tip().verify_sub_chain(std::slice::from_ref(&block)) {
+			return false;
+		}
+		self.push(block);
+		true
+	}
+
+	/// Add the given block on top of the tip, and remove its extrinsics from the mempool.
+	fn push(&mut self, block: Block) {
+		self.mempool.enact_block(hash(&block.header), &block.extrinsics());
+		self.blocks.push(block);
+	}
+
+	/// Remove the top `n` blocks, so that the tip and the state revert to what they were before.
+	///
+	/// The removed headers are returned in ascending height order, and the extrinsics of the removed
+	/// blocks return to the mempool. Genesis can never be removed, so asking to remove more blocks
+	/// than there are above genesis is an error, and leaves the chain untouched.
+	pub fn rollback(&mut self, n: usize) -> Result<Vec<Header>, RollbackError> {
+		let available = self.blocks.len() - 1;
+		if n > available {
+			return Err(RollbackError { requested: n, available });
+		}
+
+		let removed = self.blocks.split_off(self.blocks.len() - n);
+		let retracted: Vec<_> = removed.iter().map(|block| hash(&block.header)).collect();
+		self.mempool.reorg(&retracted, &[]);
+		Ok(removed.into_iter().map(|block| block.header).collect())
+	}
+}
+
+#[test]
+fn bc_chain_rollback_part_of_chain() {
+	let mut chain = Blockchain::new(Block::genesis());
+	chain.author(vec![1, 2]);
+	let b1 = chain.tip().clone();
+	let b2 = chain.author(vec![3]).clone();
+	let b3 = chain.author(vec![4, 5]).clone();
+	assert_eq!(chain.state(), 15);
+
+	let removed = chain.rollback(2).unwrap();
+	assert_eq!(removed, vec![b2.header, b3.header]);
+	assert_eq!(chain.tip(), &b1);
+	assert_eq!(chain.height(), 1);
+	assert_eq!(chain.state(), 3);
+
+	assert_eq!(chain.rollback(0), Ok(vec![]));
+	assert_eq!(chain.tip(), &b1);
+}
+
+#[test]
+fn bc_chain_rollback_then_reapply() {
+	let mut chain = Blockchain::new(Block::genesis());
+	chain.author(vec![1]);
+	let b2 = chain.author(vec![2]).clone();
+
+	chain.rollback(1).unwrap();
+	assert!(chain.import(b2.clone()));
+	assert_eq!(chain.tip(), &b2);
+	assert_eq!(chain.state(), 3);
+}
+
+#[test]
+fn bc_chain_rollback_past_genesis_errors() {
+	let mut chain = Blockchain::new(Block::genesis_with_state(7));
+	chain.author(vec![1]);
+	let before = chain.clone();
+
+	assert_eq!(chain.rollback(2), Err(RollbackError { requested: 2, available: 1 }));
+	assert_eq!(chain, before);
+
+	assert_eq!(chain.rollback(1).map(|removed| removed.len()), Ok(1));
+	assert_eq!(chain.state(), 7);
+	assert_eq!(chain.rollback(1), Err(RollbackError { requested: 1, available: 0 }));
+}
+
+#[test]
+fn bc_chain_author_accepts_unsorted_extrinsics() {
+	let mut chain = Blockchain::new(Block::genesis());
+	let block = chain.author(vec![3, 1, 2]).clone();
+
+	assert_eq!(chain.state(), 6);
+	chain.rollback(1).unwrap();
+	assert!(chain.import(block));
+}
+
+#[test]
+fn bc_chain_import_removes_included_extrinsics_from_mempool() {
+	let included = Extrinsic { payload: 2, weight: 3, fee: 4 };
+	let waiting = Extrinsic { payload: 5, weight: 1, fee: 1 };
+	let mut chain = Blockchain::new(Block::genesis());
+	chain.submit(included);
+	chain.submit(waiting);
+
+	let block = Block::genesis().child_weighted(vec![included]).unwrap();
+	assert!(chain.import(block));
+	assert_eq!(chain.mempool().pending(), &[waiting]);
+
+	// Rolling the block back returns its extrinsic to the pool.
+	chain.rollback(1).unwrap();
+	assert_eq!(chain.mempool().pending(), &[waiting, included]);
+}
+
+#[test]
+fn bc_chain_author_removes_included_extrinsics_from_mempool() {
+	let mut chain = Blockchain::new(Block::genesis());
+	chain.submit(Extrinsic { payload: 7, weight: 0, fee: 1 });
+
+	chain.author(vec![7]);
+	assert!(chain.mempool().is_empty());
+}
@@ -0,0 +1,268 @@
+//! Extrinsics don't appear in blocks out of thin air. Users submit them to the network, and they
+//! wait in a pool, typically called the mempool or transaction pool, until an author includes
+//! them in a block.
+//!
+//! Blocks have limited space, so authors must choose which extrinsics to include. In real-world
+//! chains each extrinsic has a weight, modelling the resources it consumes, and a fee offered by
+//! its sender. Authors want to collect the most fees possible without exceeding the block's weight
+//! budget. This is a knapsack problem, and like most real authors, we solve it greedily.
+//!
+//! The pool also has to follow the chain through reorgs. An extrinsic that was included in a block
+//! that is rolled back must return to the pool, but only if no block that is still canonical
+//! includes it. So the pool remembers which canonical block included which extrinsics.
+
+use std::collections::HashMap;
+
+type Hash = u64;
+
+/// An extrinsic waiting in the pool. The payload is what gets executed, exactly as in the batched
+/// extrinsics lesson. The weight and fee are only used to decide which extrinsics get included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Extrinsic {
+	pub payload: u64,
+	pub weight: u64,
+	pub fee: u64,
+}
+
+/// A pool of extrinsics waiting to be included in a block.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mempool {
+	pending: Vec<Extrinsic>,
+	max_block_weight: u64,
+	/// The extrinsics included in each canonical block the pool has been told about, by block hash.
+	canonical: HashMap<Hash, Vec<Extrinsic>>,
+}
+
+impl Mempool {
+	/// Create an empty pool for a chain whose blocks may weigh at most `max_block_weight`.
+	pub fn new(max_block_weight: u64) -> Self {
+		Mempool { pending: Vec::new(), max_block_weight, canonical: HashMap::new() }
+	}
+
+	/// Add an extrinsic to the pool.
+	pub fn submit(&mut self, extrinsic: Extrinsic) {
+		self.pending.push(extrinsic);
+	}
+
+	/// The extrinsics currently waiting in the pool, in submission order.
+	pub fn pending(&self) -> &[Extrinsic] {
+		&self.pending
+	}
+
+	/// The number of extrinsics currently waiting in the pool.
+	pub fn len(&self) -> usize {
+		self.pending.len()
+	}
+
+	/// Whether there are no extrinsics waiting in the pool.
+	pub fn is_empty(&self) -> bool {
+		self.pending.is_empty()
+	}
+
+	/// Remove and return up to `n` extrinsics that fit together within the block weight budget.
+	///
+	/// Extrinsics are considered greedily in order of fee per unit weight, and any that would push
+	/// the block over budget are skipped and left in the pool for a later block. The selected
+	/// extrinsics are returned in the order they were chosen.
+	pub fn drain_best(&mut self, n: usize) -> Vec<Extrinsic> {
+		// Sort by fee per unit weight, comparing fractions without dividing. Ties are broken in
+		// favor of the higher absolute fee.
+		let mut candidates: Vec<usize> = (0..self.pending.len()).collect();
+		candidates.sort_by(|&a, &b| {
+			let (a, b) = (&self.pending[a], &self.pending[b]);
+			let a_value = a.fee as u128 * b.weight as u128;
+			let b_value = b.fee as u128 * a.weight as u128;
+			b_value.cmp(&a_value).then(b.fee.cmp(&a.fee))
+		});
+
+		let mut chosen = Vec::new();
+		let mut remaining_weight = self.max_block_weight;
+		for index in candidates {
+			if chosen.len() == n {
+				break;
+			}
+			if self.pending[index].weight <= remaining_weight {
+				remaining_weight -= self.pending[index].weight;
+				chosen.push(index);
+			}
+		}
+
+		let selected = chosen.iter().map(|&index| self.pending[index]).collect();
+		chosen.sort_unstable_by(|a, b| b.cmp(a));
+		for index in chosen {
+			self.pending.remove(index);
+		}
+		selected
+	}
+
+	/// Remove extrinsics that have been included in a block, so they are not included again.
+	///
+	/// Each included extrinsic removes at most one matching pending extrinsic. Included extrinsics
+	/// that are not in the pool, for example because another node submitted them, are ignored.
+	pub fn remove_included(&mut self, included: &[Extrinsic]) {
+		for extrinsic in included {
+			self.take(extrinsic);
+		}
+	}
+
+	/// Remove and return one pending extrinsic that matches the given one, if there is any.
+	fn take(&mut self, extrinsic: &Extrinsic) -> Option<Extrinsic> {
+		let index = self.pending.iter().position(|pending| same_extrinsic(pending, extrinsic))?;
+		Some(self.pending.remove(index))
+	}
+
+	/// Record that the block with the given hash became canonical, and remove its extrinsics from
+	/// the pool.
+	///
+	/// The pool remembers the extrinsics it removed, fees and all, so that they return unchanged
+	/// if the block is ever retracted.
+	pub fn enact_block(&mut self, block: Hash, extrinsics: &[Extrinsic]) {
+		let included = extrinsics.iter().map(|e| self.take(e).unwrap_or(*e)).collect();
+		self.canonical.insert(block, included);
+	}
+
+	/// Follow a reorg that rolls back the `retracted` blocks and makes the `enacted` blocks
+	/// canonical in their place.
+	///
+	/// The pool knows exactly which canonical blocks include which extrinsics, so an extrinsic of a
+	/// retracted block goes back into the pool only if no canonical block includes it anymore,
+	/// including blocks below the fork point. Retracted blocks the pool was never told about are
+	/// ignored.
+	pub fn reorg(&mut self, retracted: &[Hash], enacted: &[(Hash, Vec<Extrinsic>)]) {
+		let rolled_back: Vec<Extrinsic> =
+			retracted.iter().filter_map(|block| self.canonical.remove(block)).flatten().collect();
+		for (block, extrinsics) in enacted {
+			self.enact_block(*block, extrinsics);
+		}
+		for extrinsic in rolled_back {
+			let still_included = self
+				.canonical
+				.values()
+				.any(|included| included.iter().any(|e| same_extrinsic(e, &extrinsic)));
+			if !still_included {
+				self.pending.push(extrinsic);
+			}
+		}
+	}
+}
+
+/// Whether two extrinsics are the same one. Blocks record the payload and weight of each extrinsic,
+/// but not the fee its sender offered, so the fee is not compared.
+fn same_extrinsic(a: &Extrinsic, b: &Extrinsic) -> bool {
+	a.payload == b.payload && a.weight == b.weight
+}
+
+#[test]
+fn bc_mempool_drain_best_prefers_higher_fee_set_within_budget() {
+	let mut pool = Mempool::new(10);
+	// On its own this is the single most valuable extrinsic, but it fills the whole block.
+	pool.submit(Extrinsic { payload: 1, weight: 10, fee: 9 });
+	// Together these two pay more, for the same total weight.
+	pool.submit(Extrinsic { payload: 2, weight: 5, fee: 5 });
+	pool.submit(Extrinsic { payload: 3, weight: 5, fee: 5 });
+
+	let drained = pool.drain_best(10);
+
+	assert_eq!(drained.iter().map(|e| e.payload).collect::<Vec<_>>(), vec![2, 3]);
+	assert_eq!(drained.iter().map(|e| e.fee).sum::<u64>(), 10);
+	assert_eq!(pool.pending(), &[Extrinsic { payload: 1, weight: 10, fee: 9 }]);
+}
+
+#[test]
+fn bc_mempool_drain_best_respects_count() {
+	let mut pool = Mempool::new(100);
+	pool.submit(Extrinsic { payload: 1, weight: 1, fee: 1 });
+	pool.submit(Extrinsic { payload: 2, weight: 1, fee: 3 });
+	pool.submit(Extrinsic { payload: 3, weight: 1, fee: 2 });
+
+	let drained = pool.drain_best(2);
+
+	assert_eq!(drained.iter().map(|e| e.payload).collect::<Vec<_>>(), vec![2, 3]);
+	assert_eq!(pool.len(), 1);
+}
+
+#[test]
+fn bc_mempool_skips_extrinsics_too_heavy_for_any_block() {
+	let mut pool = Mempool::new(10);
+	pool.submit(Extrinsic { payload: 1, weight: 11, fee: 100 });
+	pool.submit(Extrinsic { payload: 2, weight: 2, fee: 1 });
+
+	let drained = pool.drain_best(10);
+
+	assert_eq!(drained, vec![Extrinsic { payload: 2, weight: 2, fee: 1 }]);
+	assert_eq!(pool.len(), 1);
+}
+
+#[test]
+fn bc_mempool_drain_empty_pool() {
+	let mut pool = Mempool::new(10);
+
+	assert!(pool.drain_best(5).is_empty());
+	assert!(pool.is_empty());
+}
+
+#[test]
+fn bc_mempool_remove_included() {
+	let mut pool = Mempool::new(10);
+	pool.submit(Extrinsic { payload: 1, weight: 1, fee: 1 });
+	pool.submit(Extrinsic { payload: 2, weight: 1, fee: 1 });
+	pool.submit(Extrinsic { payload: 2, weight: 1, fee: 1 });
+
+	pool.remove_included(&[
+		Extrinsic { payload: 2, weight: 1, fee: 1 },
+		Extrinsic { payload: 3, weight: 1, fee: 1 },
+	]);
+
+	assert_eq!(
+		pool.pending(),
+		&[Extrinsic { payload: 1, weight: 1, fee: 1 }, Extrinsic { payload: 2, weight: 1, fee: 1 }]
+	);
+}
+
+#[test]
+fn bc_mempool_reorg_reinjects_only_extrinsics_no_longer_canonical() {
+	let kept = Extrinsic { payload: 1, weight: 1, fee: 1 };
+	let dropped = Extrinsic { payload: 2, weight: 1, fee: 1 };
+	let waiting = Extrinsic { payload: 3, weight: 1, fee: 1 };
+
+	let mut pool = Mempool::new(10);
+	pool.submit(kept);
+	pool.submit(dropped);
+	pool.submit(waiting);
+	pool.enact_block(10, &[kept, dropped]);
+	assert_eq!(pool.pending(), &[waiting]);
+
+	// A competing block that also includes `kept`, but not `dropped`, becomes canonical.
+	pool.reorg(&[10], &[(20, vec![kept])]);
+
+	assert_eq!(pool.pending(), &[waiting, dropped]);
+}
+
+#[test]
+fn bc_mempool_reorg_keeps_extrinsics_included_below_the_fork() {
+	let old = Extrinsic { payload: 1, weight: 1, fee: 1 };
+	let new = Extrinsic { payload: 2, weight: 1, fee: 1 };
+
+	let mut pool = Mempool::new(10);
+	pool.enact_block(10, &[old]);
+	// The same extrinsic was included again in the block that is about to be retracted.
+	pool.enact_block(11, &[old, new]);
+
+	pool.reorg(&[11, 99], &[(21, vec![])]);
+
+	assert_eq!(pool.pending(), &[new]);
+}
+
+#[test]
+fn bc_mempool_enacted_extrinsics_return_with_their_fee() {
+	let offered = Extrinsic { payload: 1, weight: 1, fee: 5 };
+
+	let mut pool = Mempool::new(10);
+	pool.submit(offered);
+	// The block does not know the fee.
+	pool.enact_block(10, &[Extrinsic { fee: 0, ..offered }]);
+	assert!(pool.is_empty());
+
+	pool.reorg(&[10], &[]);
+	assert_eq!(pool.pending(), &[offered]);
+}
@@ -6,6 +6,8 @@
 // against them in future chapters. The prior iterations are not available outside this chapter.
 pub use p6_rich_state::{Block, Header};
 
+pub mod blockchain;
+pub mod mempool;
 mod p1_header_chain;
 mod p2_extrinsic_state;
 mod p3_consensus;
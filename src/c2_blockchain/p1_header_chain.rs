@@ -9,7 +9,7 @@ use crate::hash;
 type Hash = u64;
 
 /// The most basic blockchain header possible. We learned its basic structure from lecture.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Header {
 	parent: Hash,
 	height: u64,
@@ -19,6 +19,17 @@ pub struct Header {
 	consensus_digest: (),
 }
 
+impl std::hash::Hash for Header {
+	fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+		let Header { parent, height, extrinsics_root, state_root, consensus_digest } = self;
+		crate::hash_with_domain(
+			crate::C2_HEADER_DOMAIN,
+			(parent, height, extrinsics_root, state_root, consensus_digest),
+			hasher,
+		)
+	}
+}
+
 // Here are the methods for creating a new header and verifying headers.
 // It is your job to write them.
 impl Header {
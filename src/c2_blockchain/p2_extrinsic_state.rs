@@ -16,7 +16,7 @@ type Hash = u64;
 /// The header is now expanded to contain an extrinsic and a state. Note that we are not
 /// using roots yet, but rather directly embedding some minimal extrinsic and state info
 /// into the header.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Header {
 	parent: Hash,
 	height: u64,
@@ -26,6 +26,17 @@ pub struct Header {
 	consensus_digest: (),
 }
 
+impl std::hash::Hash for Header {
+	fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+		let Header { parent, height, extrinsic, state, consensus_digest } = self;
+		crate::hash_with_domain(
+			crate::C2_HEADER_DOMAIN,
+			(parent, height, extrinsic, state, consensus_digest),
+			hasher,
+		)
+	}
+}
+
 // Here are the methods for creating new header and verifying headers.
 // It is your job to write them.
 impl Header {
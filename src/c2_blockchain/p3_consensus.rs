@@ -7,7 +7,7 @@
 
 // use std::hash::Hash;
 
-use crate::hash;
+use crate::{hash, meets_threshold};
 
 // We will use Rust's built-in hashing where the output type is u64. I'll make an alias
 // so the code is slightly more readable.
@@ -26,7 +26,7 @@ const FORK_HEIGHT: u64 = 2;
 /// For Proof of Work, the consensus digest is basically just a nonce which gets the block
 /// hash below a certain threshold. Although we could call the field `nonce` we will leave
 /// the more general `digest` term. For PoA we would have a cryptographic signature in this field.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Header {
 	parent: Hash,
 	height: u64,
@@ -35,6 +35,17 @@ pub struct Header {
 	consensus_digest: u64,
 }
 
+impl std::hash::Hash for Header {
+	fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+		let Header { parent, height, extrinsic, state, consensus_digest } = self;
+		crate::hash_with_domain(
+			crate::C2_HEADER_DOMAIN,
+			(parent, height, extrinsic, state, consensus_digest),
+			hasher,
+		)
+	}
+}
+
 // Here are the methods for creating new header and verifying headers.
 // It is your job to write them.
 impl Header {
@@ -78,7 +89,7 @@ impl Header {
 			if header.state != prev_header.state + header.extrinsic{
 				return false;
 			}
-			if hash(&header) >= THRESHOLD{
+			if !meets_threshold(&header, THRESHOLD) {
 				return false;
 			}
 			if header.consensus_digest != prev_header.consensus_digest{
@@ -90,6 +101,49 @@ impl Header {
 	}
 	
 
+	/// Verify the given headers exactly like `verify_sub_chain`, but spread the work across the
+	/// given number of threads.
+	///
+	/// Given the previous header, each header's link, height, seal, and digest can be checked
+	/// independently of all the others, so those checks run in parallel. Only the state depends on
+	/// every block before it, so a final sequential pass checks that it accumulates correctly.
+	fn verify_headers_parallel(&self, chain: &[Header], threads: usize) -> bool {
+		let threads = threads.max(1);
+		let chunk_size = chain.len().div_ceil(threads).max(1);
+
+		let links_valid = std::thread::scope(|scope| {
+			let workers: Vec<_> = (0..chain.len())
+				.step_by(chunk_size)
+				.map(|start| {
+					scope.spawn(move || {
+						let end = (start + chunk_size).min(chain.len());
+						(start..end).all(|i| {
+							let prev_header = if i == 0 { self } else { &chain[i - 1] };
+							let header = &chain[i];
+							header.parent == hash(prev_header) &&
+								header.height == prev_header.height + 1 &&
+								meets_threshold(header, THRESHOLD) &&
+								header.consensus_digest == prev_header.consensus_digest
+						})
+					})
+				})
+				.collect();
+			workers.into_iter().all(|worker| worker.join().unwrap())
+		});
+		if !links_valid {
+			return false;
+		}
+
+		let mut state = self.state;
+		for header in chain {
+			state += header.extrinsic;
+			if header.state != state {
+				return false;
+			}
+		}
+		true
+	}
+
 	// After the blockchain ran for a while, a political rift formed in the community.
 	// (See the constant FORK_HEIGHT) which is set to 2 by default.
 	// Most community members have become obsessed over the state of the blockchain.
@@ -115,7 +169,7 @@ impl Header {
 			if header.state % 2 != 0{
 				odd_state += 1;
 			}
-			if hash(&header) >= THRESHOLD{
+			if !meets_threshold(&header, THRESHOLD) {
 				return false;
 			}
 			if header.consensus_digest != prev_header.consensus_digest{
@@ -149,7 +203,7 @@ impl Header {
 			if header.state % 2 == 0{
 				even_state += 1;
 			}
-			if hash(&header) >= THRESHOLD{
+			if !meets_threshold(&header, THRESHOLD) {
 				return false;
 			}
 			if header.consensus_digest != prev_header.consensus_digest{
@@ -182,37 +236,39 @@ impl Header {
 /// G -- 1 -- 2
 ///            \-- 3'-- 4'
 fn build_contentious_forked_chain() -> (Vec<Header>, Vec<Header>, Vec<Header>) {
-	let g = Header::genesis();
+	// The first block that differs between the two chains is at the fork height.
+	build_contentious_forked_chain_with_prefix(FORK_HEIGHT - 1)
+}
 
-	let mut common_chain = vec![g.clone()];
-	let mut last_header = g.clone();
-	for i in 0..1 {
-		let new_header = last_header.child(0); 
-		last_header = new_header.clone();
-        common_chain.push(new_header);
+/// Build two forked chains as in `build_contentious_forked_chain`, with `prefix_len` blocks in the
+/// common prefix after genesis, and three blocks in each suffix.
+fn build_contentious_forked_chain_with_prefix(
+	prefix_len: u64,
+) -> (Vec<Header>, Vec<Header>, Vec<Header>) {
+	let mut common_chain = vec![Header::genesis()];
+	for _ in 0..prefix_len {
+		let child = common_chain.last().unwrap().child(1);
+		common_chain.push(child);
 	}
 
-	let mut even_header = last_header.clone();
-	let mut even_chain = vec![];
-	for i in 0..3{
-		let new_header = even_header.child(2); 
-		even_header = new_header.clone();
-        even_chain.push(new_header);
-	}
+	let fork_point = common_chain.last().unwrap();
+	let even_chain = build_suffix_with_parity(fork_point, 3, true);
+	let odd_chain = build_suffix_with_parity(fork_point, 3, false);
 
-	let mut odd_header = last_header.clone();
-	let mut odd_chain = vec![];
-	for i in 0..3{
-		let mut default_extrinsic = 2;
-		if odd_header.state % 2 == 0{
-			default_extrinsic = 1;
-		}
-		let new_header = odd_header.child(default_extrinsic); 
-		odd_header = new_header.clone();
-        odd_chain.push(new_header);
-	}
+	(common_chain, even_chain, odd_chain)
+}
 
-	return (common_chain, even_chain, odd_chain);
+/// Build `len` blocks on top of the given parent, each of whose state has the wanted parity,
+/// whatever the parity of the parent's state.
+fn build_suffix_with_parity(parent: &Header, len: usize, want_even: bool) -> Vec<Header> {
+	let mut chain: Vec<Header> = Vec::new();
+	for _ in 0..len {
+		let parent = chain.last().unwrap_or(parent);
+		// Adding two keeps the parity of the state, and adding one flips it.
+		let extrinsic = if (parent.state % 2 == 0) == want_even { 2 } else { 1 };
+		chain.push(parent.child(extrinsic));
+	}
+	chain
 }
 
 
@@ -431,3 +487,53 @@ fn bc_3_verify_forked_chain() {
 	assert!(!g.verify_sub_chain_odd(&full_even_chain[..]));
 	assert!(g.verify_sub_chain_odd(&full_odd_chain[..]));
 }
+
+#[test]
+fn bc_3_forked_chain_suffixes_have_their_parity_for_any_prefix() {
+	for prefix_len in 0..6 {
+		let (prefix, even, odd) = build_contentious_forked_chain_with_prefix(prefix_len);
+		let fork_point = prefix.last().unwrap();
+
+		assert_eq!(prefix.len() as u64, prefix_len + 1);
+		assert_eq!(even.len(), 3);
+		assert_eq!(odd.len(), 3);
+		assert!(even.iter().all(|header| header.state % 2 == 0));
+		assert!(odd.iter().all(|header| header.state % 2 == 1));
+		assert!(fork_point.verify_sub_chain(&even));
+		assert!(fork_point.verify_sub_chain(&odd));
+	}
+}
+
+#[cfg(test)]
+fn long_chain(len: u64) -> Vec<Header> {
+	let mut chain = vec![Header::genesis()];
+	for i in 0..len {
+		let child = chain.last().unwrap().child(i % 3);
+		chain.push(child);
+	}
+	chain
+}
+
+#[test]
+fn bc_3_parallel_verification_matches_sequential() {
+	let chain = long_chain(1000);
+	let g = &chain[0];
+
+	let mut bad_link = chain.clone();
+	bad_link[500].height += 1;
+	let mut bad_state = chain.clone();
+	bad_state[700].state += 1;
+	let mut bad_last = chain.clone();
+	bad_last[1000].parent = 0;
+
+	for candidate in [&chain, &bad_link, &bad_state, &bad_last] {
+		let sequential = g.verify_sub_chain(&candidate[1..]);
+		for threads in [1, 3, 8] {
+			assert_eq!(g.verify_headers_parallel(&candidate[1..], threads), sequential);
+		}
+	}
+	assert!(g.verify_headers_parallel(&chain[1..], 8));
+	assert!(!g.verify_headers_parallel(&bad_link[1..], 8));
+	assert!(!g.verify_headers_parallel(&bad_state[1..], 8));
+	assert!(!g.verify_headers_parallel(&bad_last[1..], 8));
+}
@@ -1,13 +1,14 @@
 //! Until now, each block has contained just a single extrinsic. Really we would prefer to batch
 //! them. Now, we stop relying solely on headers, and instead, create complete blocks.
 
-use crate::hash;
+use super::mempool::Extrinsic;
+use crate::{hash, ChainError};
 type Hash = u64;
 
 /// The header no longer contains an extrinsic directly. Rather a vector of extrinsics will be
 /// stored in the block body. We are still storing the state in the header for now. This will change
 /// in an upcoming lesson as well.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Header {
 	parent: Hash,
 	height: u64,
@@ -16,9 +17,29 @@ pub struct Header {
 	// For example, a hash or a Merkle root.
 	extrinsics_root: Hash,
 	state: u64,
+	// The total weight of the extrinsics in the block. Headers built without weighted extrinsics
+	// carry no weight.
+	total_weight: u64,
 	pub consensus_digest: u64,
 }
 
+impl std::hash::Hash for Header {
+	fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+		let Header { parent, height, extrinsics_root, state, total_weight, consensus_digest } = self;
+		crate::hash_with_domain(
+			crate::C2_HEADER_DOMAIN,
+			(parent, height, extrinsics_root, state, total_weight, consensus_digest),
+			hasher,
+		)
+	}
+}
+
+impl crate::ChainHeader for Header {
+	fn height(&self) -> u64 {
+		self.height
+	}
+}
+
 // Methods for creating and verifying headers.
 //
 // With the extrinsics no longer stored in the header, we can no longer do
@@ -28,7 +49,35 @@ pub struct Header {
 impl Header {
 	/// Returns a new valid genesis header.
 	pub fn genesis() -> Self {
-		Header { parent: (0), height: (0), extrinsics_root: (0), state: (0), consensus_digest: (0) }
+		Self::genesis_with_state(0)
+	}
+
+	/// Returns a new valid genesis header whose chain starts from the given state rather than
+	/// zero. This is useful for chains, such as parachains, that launch from a state snapshot.
+	pub fn genesis_with_state(state: u64) -> Self {
+		Header {
+			parent: (0),
+			height: (0),
+			extrinsics_root: (0),
+			state,
+			total_weight: 0,
+			consensus_digest: (0),
+		}
+	}
+
+	/// The total weight of the extrinsics in this block.
+	pub fn total_weight(&self) -> u64 {
+		self.total_weight
+	}
+
+	/// The height of this block.
+	pub fn height(&self) -> u64 {
+		self.height
+	}
+
+	/// The state after executing this block.
+	pub fn state(&self) -> u64 {
+		self.state
 	}
 
 	/// Create and return a valid child header.
@@ -40,6 +89,7 @@ impl Header {
 			height: self.height + 1,
 			extrinsics_root,
 			state,
+			total_weight: 0,
 			consensus_digest: self.consensus_digest
 		}
 	}
@@ -83,11 +133,29 @@ impl Header {
 	}
 }
 
+/// An extrinsic that may only be applied once the chain reaches a given height. This models a
+/// time-lock, such as a payment that is scheduled for the future.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimeLocked {
+	pub payload: u64,
+	pub activate_at_height: u64,
+}
+
+/// The most a single block may weigh. Authors that pack blocks from a `Mempool` created with this
+/// budget never exceed it.
+pub const MAX_BLOCK_WEIGHT: u64 = 1_000_000;
+
 /// A complete Block is a header and the extrinsics.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Block {
 	pub(crate) header: Header,
 	pub(crate) body: Vec<u64>,
+	/// The weight of each extrinsic in the body, in the same order. Blocks built without weighted
+	/// extrinsics carry no weights, and weigh nothing.
+	pub(crate) weights: Vec<u64>,
+	/// Time-locked extrinsics, executed after the body. A block is only valid if every one of
+	/// them has reached its activation height.
+	pub(crate) time_locked: Vec<TimeLocked>,
 }
 
 // Methods for creating and verifying blocks.
@@ -98,60 +166,291 @@ pub struct Block {
 impl Block {
 	/// Returns a new valid genesis block. By convention this block has no extrinsics.
 	pub fn genesis() -> Self {
-		let header = Header::genesis();
+		Self::genesis_with_state(0)
+	}
+
+	/// Returns a new valid genesis block whose chain starts from the given state.
+	pub fn genesis_with_state(state: u64) -> Self {
+		let header = Header::genesis_with_state(state);
 
-		Block { header, body: Vec::new() }
+		Block { header, body: Vec::new(), weights: Vec::new(), time_locked: Vec::new() }
 	}
 
 	/// Create and return a valid child block.
 	/// The extrinsics are batched now, so we need to execute each of them.
-	pub fn child(&self, extrinsics: Vec<u64>) -> Self {
+	///
+	/// The extrinsics are sorted into canonical (ascending) order first, because validators reject
+	/// blocks whose extrinsics are out of order.
+	pub fn child(&self, mut extrinsics: Vec<u64>) -> Self {
+		extrinsics.sort_unstable();
+		self.child_unchecked(extrinsics, Vec::new(), Vec::new())
+	}
+
+	/// Create and return a valid child block that also includes the given time-locked extrinsics.
+	///
+	/// Returns `None` if any of the time-locked extrinsics would not yet be active at the child's
+	/// height, or if either kind of extrinsic is not in canonical order, because validators would
+	/// reject such a block.
+	pub fn child_with_time_locked(
+		&self,
+		extrinsics: Vec<u64>,
+		time_locked: Vec<TimeLocked>,
+	) -> Option<Self> {
+		let block = self.child_unchecked(extrinsics, Vec::new(), time_locked);
+		(block.time_locks_active() && block.extrinsics_sorted()).then_some(block)
+	}
+
+	/// Create a child block, without checking that its time-locked extrinsics are active, that its
+	/// extrinsics are in canonical order, that it is within the weight limit, or that its state
+	/// fits in a `u64`. There is either one weight per extrinsic, or none at all.
+	fn child_unchecked(
+		&self,
+		extrinsics: Vec<u64>,
+		weights: Vec<u64>,
+		time_locked: Vec<TimeLocked>,
+	) -> Self {
 		let new_height = self.header.height + 1;
-		let new_state = &self.header.state + extrinsics.iter().sum::<u64>();
-		let extrinsics_root = hash(&extrinsics);
 
 		let header = Header {
 			parent: hash(&self.header),
 			height: new_height,
-			extrinsics_root,
-			state: new_state,
+			extrinsics_root: 0,
+			state: 0,
+			total_weight: 0,
 			consensus_digest: self.header.consensus_digest
 		};
 
-		Block{header, body: extrinsics}
+		let mut block = Block { header, body: extrinsics, weights, time_locked };
+		block.header.extrinsics_root = block.extrinsics_root();
+		block.header.state = block.state_after(self.header.state).unwrap_or(u64::MAX);
+		block.header.total_weight = block.weight().unwrap_or(u64::MAX);
+		block
+	}
 
-		
+	/// The root committing to all of the block's extrinsics. Blocks without weights or
+	/// time-locked extrinsics keep the same root they always had.
+	fn extrinsics_root(&self) -> Hash {
+		match (self.weights.is_empty(), self.time_locked.is_empty()) {
+			(true, true) => hash(&self.body),
+			(true, false) => hash(&(&self.body, &self.time_locked)),
+			(false, _) => hash(&(&self.body, &self.weights, &self.time_locked)),
+		}
+	}
+
+	/// The total weight of the extrinsics in this block, recomputed from the body.
+	///
+	/// Returns `None` if the body does not have exactly one weight per extrinsic, or if the total
+	/// does not fit in a `u64`.
+	fn weight(&self) -> Option<u64> {
+		if self.weights.is_empty() {
+			return Some(0);
+		}
+		if self.weights.len() != self.body.len() {
+			return None;
+		}
+		self.weights.iter().try_fold(0u64, |total, weight| total.checked_add(*weight))
+	}
+
+	/// Check that the header commits to the block's real weight, and that it is within the limit.
+	fn check_weight(&self) -> Result<(), ChainError> {
+		match self.weight() {
+			Some(weight) if weight != self.header.total_weight => Err(ChainError::WrongWeight),
+			Some(weight) if weight <= MAX_BLOCK_WEIGHT => Ok(()),
+			_ => Err(ChainError::Overweight),
+		}
+	}
+
+	/// The sum of all the extrinsics in this block, including the time-locked ones.
+	///
+	/// Returns `None` if the sum does not fit in a `u64`, because no block can execute it.
+	fn extrinsics_sum(&self) -> Option<u64> {
+		self.body
+			.iter()
+			.chain(self.time_locked.iter().map(|e| &e.payload))
+			.try_fold(0u64, |sum, extrinsic| sum.checked_add(*extrinsic))
+	}
+
+	/// The state after executing this block on top of the given parent state.
+	///
+	/// Returns `None` if the state would overflow a `u64`, because no block can execute it.
+	fn state_after(&self, parent_state: u64) -> Option<u64> {
+		parent_state.checked_add(self.extrinsics_sum()?)
+	}
+
+	/// Whether the extrinsics in the body, and the time-locked extrinsics, are each in canonical
+	/// (ascending) order.
+	///
+	/// Without a canonical order, an author could reorder the same set of extrinsics to produce
+	/// many different extrinsics roots, and so many different blocks, for the same execution.
+	fn extrinsics_sorted(&self) -> bool {
+		self.body.windows(2).all(|pair| pair[0] <= pair[1]) &&
+			self.time_locked.windows(2).all(|pair| pair[0] <= pair[1])
+	}
+
+	/// The extrinsics in the body, as a `Mempool` knows them. Blocks do not record fees, so every
+	/// fee is zero, and in a block built without weights every weight is zero too.
+	pub fn extrinsics(&self) -> Vec<Extrinsic> {
+		let weights = self.weights.iter().copied().chain(std::iter::repeat(0));
+		self.body
+			.iter()
+			.zip(weights)
+			.map(|(payload, weight)| Extrinsic { payload: *payload, weight, fee: 0 })
+			.collect()
+	}
+
+	/// Whether this block carries at least one extrinsic with a non-zero payload, counting the
+	/// time-locked ones.
+	pub fn has_payload(&self) -> bool {
+		self.body.iter().chain(self.time_locked.iter().map(|e| &e.payload)).any(|p| *p != 0)
+	}
+
+	/// Whether every time-locked extrinsic in this block has reached its activation height.
+	fn time_locks_active(&self) -> bool {
+		self.time_locked.iter().all(|e| e.activate_at_height <= self.header.height)
+	}
+
+	/// Create and return a valid child block from weighted extrinsics, such as those drained from
+	/// the mempool. The header commits to the total weight of the block.
+	///
+	/// Returns `None` if the block would weigh more than `MAX_BLOCK_WEIGHT`, because validators
+	/// would reject it.
+	pub fn child_weighted(&self, mut extrinsics: Vec<Extrinsic>) -> Option<Self> {
+		extrinsics.sort_unstable_by_key(|e| e.payload);
+		let (body, weights) = extrinsics.into_iter().map(|e| (e.payload, e.weight)).unzip();
+		let block = self.child_unchecked(body, weights, Vec::new());
+		block.check_weight().is_ok().then_some(block)
+	}
+
+	/// Create and return a valid child block, refusing to pack more extrinsics than the given
+	/// limit. Unbounded blocks are a denial of service vector, so authors that respect a
+	/// `max_extrinsics_per_block` must not produce a block that validators would reject.
+	///
+	/// Passing `None` places no limit on the block size and behaves exactly like `child`.
+	pub fn child_with_limit(
+		&self,
+		extrinsics: Vec<u64>,
+		max_extrinsics_per_block: Option<usize>,
+	) -> Option<Self> {
+		if max_extrinsics_per_block.is_some_and(|max| extrinsics.len() > max) {
+			return None;
+		}
+
+		Some(self.child(extrinsics))
 	}
 
 	/// Verify that all the given blocks form a valid chain from this block to the tip.
 	///
 	/// We need to verify the headers as well as execute all transactions and check the final state.
+	#[must_use]
 	pub fn verify_sub_chain(&self, chain: &[Block]) -> bool {
-		
+		self.verify_sub_chain_with_limit(chain, None)
+	}
 
-		let mut prev_block = self;
+	/// Verify that all the given blocks form a valid chain from this block to the tip, and that
+	/// every one of them carries a non-zero extrinsic.
+	///
+	/// Some chains disallow empty blocks after genesis, so that miners can not pad the chain with
+	/// filler blocks. The rule is independent of the others, and `Block::has_payload` can be
+	/// combined with any of the other verifiers in the same way.
+	#[must_use]
+	pub fn verify_sub_chain_non_empty(&self, chain: &[Block]) -> bool {
+		chain.iter().all(Block::has_payload) && self.verify_sub_chain(chain)
+	}
 
-		for block in chain{
-			if block.header.parent != hash(&prev_block.header){
-				return false;
+	/// Verify that all the given blocks form a valid chain from this block to the tip, and return
+	/// the state at the tip.
+	///
+	/// This is the same check as `verify_sub_chain`, but it reports why an invalid chain was
+	/// rejected, and hands back the final state so callers don't need to re-execute the chain. An
+	/// empty chain leaves the state unchanged.
+	pub fn compute_final_state(&self, chain: &[Block]) -> Result<u64, ChainError> {
+		let mut parent = &self.header;
+
+		for block in chain {
+			if block.header.parent != hash(parent) {
+				return Err(ChainError::WrongParent);
+			}
+			if parent.height.checked_add(1) != Some(block.header.height) {
+				return Err(ChainError::WrongHeight);
+			}
+			if !block.time_locks_active() {
+				return Err(ChainError::InactiveTimeLock);
+			}
+			if block.header.extrinsics_root != block.extrinsics_root() {
+				return Err(ChainError::WrongExtrinsicsRoot);
+			}
+			if !block.extrinsics_sorted() {
+				return Err(ChainError::UnsortedExtrinsics);
+			}
+			block.check_weight()?;
+			if block.state_after(parent.state) != Some(block.header.state) {
+				return Err(ChainError::BadState);
 			}
+			parent = &block.header;
+		}
+		Ok(parent.state)
+	}
 
-			let mut prev_header = &prev_block.header;
-			let mut state = &prev_block.header.state;
-		
-			if block.header.parent != hash(prev_header){
+	/// Find the first block in the chain whose state does not match the result of executing it.
+	///
+	/// Returns the index of that block in the chain, along with the expected and the actual state.
+	/// The expected state is `None` if executing the block would overflow, because then no state
+	/// can match. Only the state is checked, so a chain that is invalid for another reason, such
+	/// as a broken parent link, still reports its first state mismatch. This is a debugging aid for
+	/// chains rejected with `ChainError::BadState`.
+	pub fn first_state_divergence(&self, chain: &[Block]) -> Option<(usize, Option<u64>, u64)> {
+		let mut parent = &self.header;
+
+		for (index, block) in chain.iter().enumerate() {
+			let expected = block.state_after(parent.state);
+			if expected != Some(block.header.state) {
+				return Some((index, expected, block.header.state));
+			}
+			parent = &block.header;
+		}
+		None
+	}
+
+	/// Verify that all the given blocks form a valid chain from this block to the tip, and that
+	/// no block carries more than `max_extrinsics_per_block` extrinsics.
+	///
+	/// This is the block-size analogue of the block weight limits used in real-world chains. It is
+	/// independent of consensus; a perfectly well-sealed block is still rejected if it is too big.
+	#[must_use]
+	pub fn verify_sub_chain_with_limit(
+		&self,
+		chain: &[Block],
+		max_extrinsics_per_block: Option<usize>,
+	) -> bool {
+		let mut parent = &self.header;
+
+		for block in chain {
+			let extrinsics_count = block.body.len() + block.time_locked.len();
+			if max_extrinsics_per_block.is_some_and(|max| extrinsics_count > max) {
+				return false;
+			}
+			if !block.time_locks_active() {
+				return false;
+			}
+			if block.header.extrinsics_root != block.extrinsics_root() {
+				return false;
+			}
+			if !block.extrinsics_sorted() {
+				return false;
+			}
+			if block.check_weight().is_err() {
+				return false;
+			}
+			if block.header.parent != hash(parent) {
 				return false;
 			}
-			if block.header.height != prev_header.height + 1{
+			if parent.height.checked_add(1) != Some(block.header.height) {
 				return false;
 			}
-			if block.header.state != state + block.body.iter().sum::<u64>(){
+			if block.state_after(parent.state) != Some(block.header.state) {
 				return false;
 			}
-			prev_header = &block.header;
-			state = &block.header.state;
-			
-			prev_block = block;
+			parent = &block.header;
 		}
 		true
 	}
@@ -171,6 +470,7 @@ fn build_invalid_child_block_with_valid_header(parent: &Header) -> Block {
 		height: parent.height + 1,
         extrinsics_root: hash(&vec![1, 2, 3]), 
         state: 100, 
+        total_weight: 0,
         consensus_digest: 0, 
     };
 
@@ -179,6 +479,8 @@ fn build_invalid_child_block_with_valid_header(parent: &Header) -> Block {
     Block {
         header: child_header,
         body: invalid_extrinsics,
+        weights: vec![],
+        time_locked: vec![],
     }
 }
 
@@ -200,6 +502,26 @@ fn bc_4_genesis_block() {
 	assert!(gb.body.is_empty());
 }
 
+#[test]
+fn bc_4_genesis_with_state() {
+	let g = Header::genesis_with_state(42);
+	assert_eq!(g.height, 0);
+	assert_eq!(g.state, 42);
+	assert_eq!(Header::genesis_with_state(0), Header::genesis());
+}
+
+#[test]
+fn bc_4_verify_chain_from_non_zero_genesis() {
+	let g = Block::genesis_with_state(100);
+	let b1 = g.child(vec![1, 2]);
+	let b2 = b1.child(vec![3]);
+
+	assert_eq!(b2.header.state, 106);
+	assert!(g.verify_sub_chain(&[b1.clone(), b2.clone()]));
+	// The same blocks do not extend a chain that started from a different state.
+	assert!(!Block::genesis().verify_sub_chain(&[b1, b2]));
+}
+
 #[test]
 fn bc_4_child_block_empty() {
 	let b0 = Block::genesis();
@@ -207,7 +529,10 @@ fn bc_4_child_block_empty() {
 
 	assert_eq!(b1.header.height, 1);
 	assert_eq!(b1.header.parent, hash(&b0.header));
-	assert_eq!(b1, Block { header: b1.header.clone(), body: vec![] });
+	assert_eq!(
+		b1,
+		Block { header: b1.header.clone(), body: vec![], weights: vec![], time_locked: vec![] }
+	);
 }
 
 #[test]
@@ -217,7 +542,15 @@ fn bc_4_child_block() {
 
 	assert_eq!(b1.header.height, 1);
 	assert_eq!(b1.header.parent, hash(&b0.header));
-	assert_eq!(b1, Block { header: b1.header.clone(), body: vec![1, 2, 3, 4, 5] });
+	assert_eq!(
+		b1,
+		Block {
+			header: b1.header.clone(),
+			body: vec![1, 2, 3, 4, 5],
+			weights: vec![],
+			time_locked: vec![],
+		}
+	);
 }
 
 #[test]
@@ -252,7 +585,14 @@ fn bc_4_verify_three_blocks() {
 #[test]
 fn bc_4_invalid_header_does_not_check() {
 	let g = Header::genesis();
-	let h1 = Header { parent: 0, height: 100, extrinsics_root: 0, state: 100, consensus_digest: 0 };
+	let h1 = Header {
+		parent: 0,
+		height: 100,
+		extrinsics_root: 0,
+		state: 100,
+		total_weight: 0,
+		consensus_digest: 0,
+	};
 
 	assert!(!g.verify_child(&h1));
 }
@@ -289,3 +629,258 @@ fn bc_4_student_invalid_block_really_is_invalid() {
 	// Make sure that the block is not valid when executed.
 	assert!(!gb.verify_sub_chain(&[b1]));
 }
+
+#[test]
+fn bc_4_child_with_limit_refuses_to_overpack() {
+	let b0 = Block::genesis();
+
+	assert!(b0.child_with_limit(vec![1, 2, 3], Some(3)).is_some());
+	assert!(b0.child_with_limit(vec![1, 2, 3, 4], Some(3)).is_none());
+	assert!(b0.child_with_limit(vec![1, 2, 3, 4], None).is_some());
+}
+
+#[test]
+fn bc_4_block_at_extrinsic_limit_is_valid() {
+	let b0 = Block::genesis();
+	let b1 = b0.child(vec![1, 2, 3]);
+
+	assert!(b0.verify_sub_chain_with_limit(&[b1], Some(3)));
+}
+
+#[test]
+fn bc_4_block_over_extrinsic_limit_is_invalid() {
+	let b0 = Block::genesis();
+	let b1 = b0.child(vec![1, 2, 3, 4]);
+
+	// The block is otherwise perfectly valid.
+	assert!(b0.verify_sub_chain(&[b1.clone()]));
+	assert!(!b0.verify_sub_chain_with_limit(&[b1], Some(3)));
+}
+
+#[test]
+fn bc_4_child_weighted_commits_to_total_weight() {
+	let b0 = Block::genesis();
+	let b1 = b0
+		.child_weighted(vec![
+			Extrinsic { payload: 2, weight: 4, fee: 0 },
+			Extrinsic { payload: 1, weight: 3, fee: 0 },
+		])
+		.unwrap();
+
+	assert_eq!(b1.header.total_weight(), 7);
+	assert_eq!(b1.body, vec![1, 2]);
+	assert_eq!(b1.weights, vec![3, 4]);
+	assert!(b0.verify_sub_chain(&[b1]));
+}
+
+#[test]
+fn bc_4_declared_weight_must_match_extrinsics() {
+	let b0 = Block::genesis();
+	let mut b1 = b0.child_weighted(vec![Extrinsic { payload: 1, weight: 3, fee: 0 }]).unwrap();
+	b1.header.total_weight = 1;
+
+	assert!(!b0.verify_sub_chain(&[b1.clone()]));
+	assert_eq!(b0.compute_final_state(&[b1]), Err(ChainError::WrongWeight));
+}
+
+#[test]
+fn bc_4_overweight_block_is_rejected() {
+	let b0 = Block::genesis();
+	let heavy = |weight| Extrinsic { payload: 1, weight, fee: 0 };
+
+	assert!(b0.child_weighted(vec![heavy(MAX_BLOCK_WEIGHT)]).is_some());
+	assert_eq!(b0.child_weighted(vec![heavy(MAX_BLOCK_WEIGHT + 1)]), None);
+	// Weights that overflow when added up are no way around the limit.
+	assert_eq!(b0.child_weighted(vec![heavy(u64::MAX), heavy(2)]), None);
+
+	let b1 = b0.child_unchecked(vec![1], vec![MAX_BLOCK_WEIGHT + 1], vec![]);
+	assert!(!b0.verify_sub_chain(std::slice::from_ref(&b1)));
+	assert_eq!(b0.compute_final_state(&[b1]), Err(ChainError::Overweight));
+}
+
+#[test]
+fn bc_4_compute_final_state_matches_sum() {
+	let g = Block::genesis_with_state(10);
+	let b1 = g.child(vec![1, 2, 3]);
+	let b2 = b1.child(vec![]);
+	let b3 = b2.child(vec![40, 50]);
+
+	assert_eq!(g.compute_final_state(&[b1.clone(), b2, b3]), Ok(10 + 1 + 2 + 3 + 40 + 50));
+	assert_eq!(g.compute_final_state(&[]), Ok(10));
+
+	let mut bad = b1.child(vec![7]);
+	bad.body = vec![8];
+	bad.header.extrinsics_root = hash(&bad.body);
+	assert_eq!(g.compute_final_state(&[b1.clone(), bad.clone()]), Err(ChainError::BadState));
+	assert!(!g.verify_sub_chain(&[b1, bad]));
+}
+
+#[test]
+fn bc_4_time_locked_extrinsic_too_early_is_rejected() {
+	let b0 = Block::genesis();
+	let b1 = b0.child(vec![1]);
+	let locked = TimeLocked { payload: 10, activate_at_height: 3 };
+
+	// An honest author cannot include the extrinsic yet.
+	assert_eq!(b1.child_with_time_locked(vec![2], vec![locked]), None);
+
+	// A dishonest author who includes it anyway produces an invalid block.
+	let b2 = b1.child_unchecked(vec![2], vec![], vec![locked]);
+	assert!(!b0.verify_sub_chain(&[b1.clone(), b2.clone()]));
+	assert_eq!(b0.compute_final_state(&[b1, b2]), Err(ChainError::InactiveTimeLock));
+}
+
+#[test]
+fn bc_4_time_locked_extrinsic_applied_from_activation_height() {
+	let b0 = Block::genesis();
+	let b1 = b0.child(vec![1]);
+	let b2 = b1.child(vec![2]);
+	let locked = TimeLocked { payload: 10, activate_at_height: 3 };
+
+	let b3 = b2.child_with_time_locked(vec![3], vec![locked]).unwrap();
+	assert_eq!(b3.header.state, 16);
+	assert!(b0.verify_sub_chain(&[b1.clone(), b2.clone(), b3.clone()]));
+
+	let b4 = b3.child_with_time_locked(vec![], vec![locked]).unwrap();
+	assert_eq!(b0.compute_final_state(&[b1, b2, b3, b4]), Ok(26));
+}
+
+#[test]
+fn bc_4_child_sorts_unsorted_extrinsics() {
+	let b0 = Block::genesis();
+	let b1 = b0.child(vec![3, 1, 2]);
+
+	assert_eq!(b1, b0.child(vec![1, 2, 3]));
+	assert!(b0.verify_sub_chain(&[b1]));
+}
+
+#[test]
+fn bc_4_sorted_child_commits_to_its_extrinsics() {
+	let b0 = Block::genesis();
+	let b1 = b0.child(vec![1, 2, 3]);
+
+	assert_eq!(b1.header.extrinsics_root, hash(&vec![1u64, 2, 3]));
+	assert!(b0.verify_sub_chain(&[b1]));
+}
+
+#[test]
+fn bc_4_unsorted_time_locked_extrinsics_are_rejected() {
+	let b0 = Block::genesis();
+	let first = TimeLocked { payload: 1, activate_at_height: 1 };
+	let second = TimeLocked { payload: 2, activate_at_height: 1 };
+
+	assert!(b0.child_with_time_locked(vec![], vec![first, second]).is_some());
+	assert_eq!(b0.child_with_time_locked(vec![], vec![second, first]), None);
+
+	let b1 = b0.child_unchecked(vec![], vec![], vec![second, first]);
+	assert!(!b0.verify_sub_chain(std::slice::from_ref(&b1)));
+	assert_eq!(b0.compute_final_state(&[b1]), Err(ChainError::UnsortedExtrinsics));
+}
+
+#[test]
+fn bc_4_body_must_match_extrinsics_root() {
+	let b0 = Block::genesis();
+	let mut b1 = b0.child(vec![1, 2, 3]);
+	b1.body = vec![0, 2, 4];
+
+	assert!(!b0.verify_sub_chain(std::slice::from_ref(&b1)));
+	assert_eq!(b0.compute_final_state(&[b1]), Err(ChainError::WrongExtrinsicsRoot));
+}
+
+#[test]
+fn bc_4_shuffled_extrinsics_are_rejected() {
+	let b0 = Block::genesis();
+	let mut b1 = b0.child(vec![1, 2, 3]);
+	b1.body = vec![2, 3, 1];
+	b1.header.extrinsics_root = hash(&b1.body);
+
+	assert!(!b0.verify_sub_chain(&[b1.clone()]));
+	assert_eq!(b0.compute_final_state(&[b1]), Err(ChainError::UnsortedExtrinsics));
+}
+
+#[test]
+fn bc_4_extrinsics_sum_does_not_overflow() {
+	let b0 = Block::genesis();
+	let locked = TimeLocked { payload: u64::MAX, activate_at_height: 1 };
+
+	assert_eq!(b0.child(vec![1, 2]).extrinsics_sum(), Some(3));
+	assert_eq!(b0.child_unchecked(vec![1, u64::MAX], vec![], vec![]).extrinsics_sum(), None);
+	assert_eq!(b0.child_unchecked(vec![1], vec![], vec![locked]).extrinsics_sum(), None);
+}
+
+#[test]
+fn bc_4_overflowing_state_is_a_bad_state() {
+	let b0 = Block::genesis_with_state(u64::MAX);
+	let b1 = b0.child_unchecked(vec![1], vec![], vec![]);
+
+	assert_eq!(b1.header.state, u64::MAX);
+	assert_eq!(b0.compute_final_state(&[b1]), Err(ChainError::BadState));
+}
+
+#[test]
+fn bc_4_overflowing_state_or_height_is_invalid() {
+	let b0 = Block::genesis_with_state(u64::MAX);
+	let b1 = b0.child_unchecked(vec![1], vec![], vec![]);
+	assert!(!b0.verify_sub_chain(&[b1]));
+
+	// A child of the last possible height would have to wrap around to height zero.
+	let mut last = Block::genesis();
+	last.header.height = u64::MAX;
+	let mut b1 = Block::genesis().child(vec![]);
+	b1.header.parent = hash(&last.header);
+	b1.header.height = 0;
+	assert!(!last.verify_sub_chain(&[b1]));
+}
+
+#[test]
+fn bc_4_first_state_divergence_finds_third_block() {
+	let g = Block::genesis();
+	let b1 = g.child(vec![1]);
+	let b2 = b1.child(vec![2]);
+	let mut b3 = b2.child(vec![3]);
+	b3.header.state = 100;
+	let b4 = b3.child(vec![4]);
+	let chain = [b1, b2, b3, b4];
+
+	assert_eq!(g.compute_final_state(&chain), Err(ChainError::BadState));
+	assert_eq!(g.first_state_divergence(&chain), Some((2, Some(6), 100)));
+	assert_eq!(g.first_state_divergence(&chain[..2]), None);
+}
+
+#[test]
+fn bc_4_first_state_divergence_reports_overflowing_block() {
+	let g = Block::genesis_with_state(u64::MAX - 1);
+	let b1 = g.child(vec![1]);
+	let b2 = b1.child_unchecked(vec![1], vec![], vec![]);
+
+	assert_eq!(g.first_state_divergence(std::slice::from_ref(&b1)), None);
+	assert_eq!(g.first_state_divergence(&[b1, b2]), Some((1, None, u64::MAX)));
+}
+
+#[test]
+fn bc_4_non_empty_rule_rejects_empty_block() {
+	let g = Block::genesis();
+	let b1 = g.child(vec![1]);
+	let empty = b1.child(vec![]);
+	let zero = b1.child(vec![0, 0]);
+
+	assert!(g.verify_sub_chain(&[b1.clone(), empty.clone()]));
+	assert!(!g.verify_sub_chain_non_empty(&[b1.clone(), empty]));
+	assert!(!g.verify_sub_chain_non_empty(&[b1, zero]));
+}
+
+#[test]
+fn bc_4_non_empty_rule_accepts_blocks_with_extrinsics() {
+	let g = Block::genesis();
+	let b1 = g.child(vec![0, 3]);
+	let b2 = b1.child(vec![2]);
+	let locked = TimeLocked { payload: 5, activate_at_height: 3 };
+	let b3 = b2.child_with_time_locked(vec![], vec![locked]).unwrap();
+
+	assert!(g.verify_sub_chain_non_empty(&[b1.clone(), b2.clone(), b3]));
+
+	// The rule composes with the others, so an otherwise invalid chain is still rejected.
+	let mut bad = b2.child(vec![4]);
+	bad.header.state = 0;
+	assert!(!g.verify_sub_chain_non_empty(&[b1, b2, bad]));
+}
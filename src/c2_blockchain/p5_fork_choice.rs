@@ -7,8 +7,10 @@
 //! Since we have nothing to add to the Block or Header data structures in this lesson,
 //! we will import them from the previous lesson.
 
+use std::cmp::Ordering;
+
 use super::p4_batched_extrinsics::{Block, Header};
-use crate::hash;
+use crate::{canonical_cmp, hash, meets_threshold};
 
 const THRESHOLD: u64 = u64::max_value() / 100;
 
@@ -54,19 +56,26 @@ pub trait ForkChoice {
 	}
 }
 
-/// The "best" chain is simply the longest chain.
+/// The "best" chain is simply the longest chain. Chains of the same length are ranked by
+/// `canonical_cmp`.
 pub struct LongestChainRule;
 
+impl LongestChainRule {
+	fn cmp(chain_1: &[Header], chain_2: &[Header]) -> Ordering {
+		chain_1.len().cmp(&chain_2.len()).then_with(|| canonical_cmp(chain_1, chain_2))
+	}
+}
+
 impl ForkChoice for LongestChainRule {
 	fn first_chain_is_better(chain_1: &[Header], chain_2: &[Header]) -> bool {
-		chain_1.len() > chain_2.len()
+		Self::cmp(chain_1, chain_2) == Ordering::Greater
 	}
 
 	fn best_chain<'a>(candidate_chains: &[&'a [Header]]) -> &'a [Header] {
 		// Remember, this method is provided. You _can_ solve the exercise by
 		// simply deleting this block. It is up to you to decide whether this fork
 		// choice warrants a custom implementation.
-		candidate_chains.iter().max_by_key(|chain| chain.len()).copied().unwrap_or(&[])
+		candidate_chains.iter().copied().max_by(|a, b| Self::cmp(a, b)).unwrap_or(&[])
 	}
 }
 
@@ -88,11 +97,8 @@ fn mine_extra_hard(block: &mut Block, threshold: u64) {
 	loop {
         block.header.consensus_digest += 1;
 
-        // Calculate the hash of the block's header
-        let hash_value = hash(&block.header);
-
         // Check if the hash meets the threshold
-        if hash_value < threshold {
+        if meets_threshold(&block.header, threshold) {
             // If the hash meets the threshold, stop mining
             break;
         }
@@ -115,7 +121,11 @@ impl ForkChoice for HeaviestChainRule {
 			}
 		}
 
-		return chain_1_con_d_max <= chain_2_con_d_max;
+		// Ties go to the chain that ranks higher in the canonical order.
+		match chain_1_con_d_max.cmp(&chain_2_con_d_max) {
+			Ordering::Equal => canonical_cmp(chain_1, chain_2) == Ordering::Greater,
+			ordering => ordering == Ordering::Less,
+		}
 	}
 
 	fn best_chain<'a>(candidate_chains: &[&'a [Header]]) -> &'a [Header] {
@@ -145,24 +155,28 @@ impl ForkChoice for HeaviestChainRule {
 /// 2. Interleaved Pow/PoA. In each round there is one author who is allowed to author. Anyone else
 ///    is allowed to mine a PoW-style block. The best chain is the one with the most PoA blocks, and
 ///    ties are broken by the most accumulated work.
+///
+/// Chains with as many even blocks as each other are ranked by `canonical_cmp`.
 pub struct MostBlocksWithEvenHash;
 
+impl MostBlocksWithEvenHash {
+	fn cmp(chain_1: &[Header], chain_2: &[Header]) -> Ordering {
+		let even_blocks =
+			|chain: &[Header]| chain.iter().filter(|&header| hash(&header) % 2 == 0).count();
+		even_blocks(chain_1)
+			.cmp(&even_blocks(chain_2))
+			.then_with(|| canonical_cmp(chain_1, chain_2))
+	}
+}
+
 impl ForkChoice for MostBlocksWithEvenHash {
 	fn first_chain_is_better(chain_1: &[Header], chain_2: &[Header]) -> bool {
-		let even_blocks_chain_1 = chain_1.iter().filter(|&header| hash(&header) % 2 == 0).count();
-        let even_blocks_chain_2 = chain_2.iter().filter(|&header| hash(&header) % 2 == 0).count();
-        even_blocks_chain_1 > even_blocks_chain_2
+		Self::cmp(chain_1, chain_2) == Ordering::Greater
 	}
 
 	fn best_chain<'a>(candidate_chains: &[&'a [Header]]) -> &'a [Header] {
 		// Remember, this method is provided.
-		candidate_chains
-            .iter()
-            .max_by_key(|&&chain| {
-                chain.iter().filter(|&header| hash(&header) % 2 == 0).count()
-            })
-            .copied()
-            .unwrap_or(&[])
+		candidate_chains.iter().copied().max_by(|a, b| Self::cmp(a, b)).unwrap_or(&[])
 	}
 }
 
@@ -200,6 +214,8 @@ fn create_fork_one_side_longer_other_side_heavier() -> (Vec<Header>, Vec<Header>
         let mut block = Block {
             header: prefix.last().unwrap().child(hash(&[i]), i),
             body: vec![],
+            weights: vec![],
+            time_locked: vec![],
         };
 		block.header.consensus_digest = THRESHOLD;
         prefix.push(block.header.clone());
@@ -211,6 +227,8 @@ fn create_fork_one_side_longer_other_side_heavier() -> (Vec<Header>, Vec<Header>
         let mut block = Block {
             header: prefix.last().unwrap().child(hash(&[i]), i),
             body: vec![],
+            weights: vec![],
+            time_locked: vec![],
         };
         // Mine the block extra hard to increase difficulty
         mine_extra_hard(&mut block, THRESHOLD / 2);
@@ -338,3 +356,19 @@ fn bc_5_longest_vs_heaviest() {
 
 	assert_eq!(HeaviestChainRule::best_chain(&[&longest_chain, &pow_chain]), &pow_chain);
 }
+
+#[test]
+fn bc_5_fork_choice_is_independent_of_input_order() {
+	let g = Header::genesis();
+	let forks: Vec<Vec<Header>> =
+		(1..=3).map(|i| vec![g.clone(), g.child(hash(&[i]), i)]).collect();
+	let forward: Vec<&[Header]> = forks.iter().map(Vec::as_slice).collect();
+	let backward: Vec<&[Header]> = forward.iter().rev().copied().collect();
+
+	assert_eq!(LongestChainRule::best_chain(&forward), LongestChainRule::best_chain(&backward));
+	assert_eq!(HeaviestChainRule::best_chain(&forward), HeaviestChainRule::best_chain(&backward));
+	assert_eq!(
+		MostBlocksWithEvenHash::best_chain(&forward),
+		MostBlocksWithEvenHash::best_chain(&backward)
+	);
+}
@@ -29,7 +29,7 @@ pub struct State {
 /// the complete state. This hash will allow block verifiers to cryptographically confirm
 /// that they got the same state as the author without having a complete copy of the
 /// author's state
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Header {
 	parent: Hash,
 	height: u64,
@@ -40,6 +40,17 @@ pub struct Header {
 	consensus_digest: u64,
 }
 
+impl std::hash::Hash for Header {
+	fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+		let Header { parent, height, extrinsics_root, state_root, consensus_digest } = self;
+		crate::hash_with_domain(
+			crate::C2_HEADER_DOMAIN,
+			(parent, height, extrinsics_root, state_root, consensus_digest),
+			hasher,
+		)
+	}
+}
+
 // Methods for creating and verifying headers.
 //
 // We already moved the execution logic to the block level in the last section.
@@ -48,7 +59,7 @@ pub struct Header {
 // default. So we need to commit the initial state root to the genesis header here.
 impl Header {
 	/// Returns a new valid genesis header.
-	fn genesis(genesis_state_root: Hash) -> Self {
+	pub(crate) fn genesis(genesis_state_root: Hash) -> Self {
 		Header{
 			parent: 0,
 			height: 0,
@@ -164,6 +175,7 @@ impl Block {
 	/// This time we need to validate the initial block itself by confirming that we
 	/// have been given a valid pre-state. And we still need to verify the headers,
 	/// execute all transactions, and check the final state.
+	#[must_use]
 	pub fn verify_sub_chain(&self, pre_state: &State, chain: &[Block]) -> bool {
 		let mut prev_state = pre_state.clone();
         let mut prev_block = self;
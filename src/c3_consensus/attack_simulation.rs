@@ -0,0 +1,309 @@
+//! Proof of Work is secure as long as honest miners control most of the hash power. In this module
+//! we put that assumption to the test by simulating an honest miner competing against an attacker
+//! with a configurable share of the hash power.
+//!
+//! The attacker may mine honestly, or use the selfish mining strategy. A selfish miner withholds
+//! the blocks it finds on a private chain, and only publishes them when doing so will override
+//! blocks the honest miner has just published. Either way, nodes decide which chain is canonical
+//! with the `ChainStore`'s canonical order: the higher chain wins, then the one with more work, and
+//! then the one whose tip has the lowest hash.
+//!
+//! Who finds each block is drawn from a pseudo-random sequence derived from a seed, so every
+//! simulation is reproducible.
+//!
+//! After the fact, selfish mining leaves a telltale shape in the block tree. Honest forks happen
+//! when two miners find blocks at about the same time, so they rarely orphan more than one block.
+//! A selfish miner instead reveals several withheld blocks at once, orphaning a whole run of
+//! honestly mined blocks. `detect_selfish_mining` looks for that shape in a node's chain store.
+
+use super::{chain_store::ChainStore, Consensus, Header};
+use crate::hash;
+
+type Hash = u64;
+
+/// Blocks record who mined them in their extrinsics root.
+const HONEST: u64 = 0;
+const ATTACKER: u64 = 1;
+
+/// How the attacking miner behaves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+	/// Build on the canonical head and publish every block as soon as it is found.
+	Honest,
+	/// Build on a private chain, and only publish it to override the honest miner's blocks.
+	Selfish,
+}
+
+/// The outcome of a simulation. A miner wins a round when it authored most of the blocks in the
+/// canonical chain at the end of the round.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SimulationReport {
+	pub honest_wins: u64,
+	pub attacker_wins: u64,
+	pub ties: u64,
+}
+
+/// A simulation of an honest miner competing against an attacker, over many independent rounds.
+pub struct AttackSimulation<'a, C: Consensus> {
+	engine: &'a C,
+	/// The percentage of the hash power controlled by the attacker.
+	attacker_share: u64,
+	strategy: Strategy,
+	blocks_per_round: u64,
+	seed: u64,
+}
+
+impl<'a, C: Consensus> AttackSimulation<'a, C> {
+	/// Create a simulation in which the attacker controls `attacker_share` percent of the hash
+	/// power, and mines according to the given strategy. Every round starts from the engine's
+	/// genesis header.
+	///
+	/// By default each round lasts for 20 blocks, and the seed is zero.
+	pub fn new(engine: &'a C, attacker_share: u64, strategy: Strategy) -> Self {
+		assert!(attacker_share <= 100, "the attacker share is a percentage");
+		AttackSimulation { engine, attacker_share, strategy, blocks_per_round: 20, seed: 0 }
+	}
+
+	/// The number of blocks found in each round, by either miner.
+	pub fn blocks_per_round(mut self, blocks_per_round: u64) -> Self {
+		self.blocks_per_round = blocks_per_round;
+		self
+	}
+
+	/// The seed from which the sequence of block finders is derived.
+	pub fn seed(mut self, seed: u64) -> Self {
+		self.seed = seed;
+		self
+	}
+
+	/// Run the given number of rounds, and report who won each of them.
+	pub fn run(&self, rounds: u64) -> SimulationReport {
+		let mut report = SimulationReport::default();
+		for round in 0..rounds {
+			let (honest, attacker) = self.run_round(round);
+			match honest.cmp(&attacker) {
+				std::cmp::Ordering::Greater => report.honest_wins += 1,
+				std::cmp::Ordering::Less => report.attacker_wins += 1,
+				std::cmp::Ordering::Equal => report.ties += 1,
+			}
+		}
+		report
+	}
+
+	/// Run a single round, and return how many canonical blocks the honest miner and the attacker
+	/// each authored.
+	fn run_round(&self, round: u64) -> (u64, u64) {
+		let mut store = ChainStore::new(self.engine.genesis_header());
+		// The attacker's blocks that have not been published yet.
+		let mut private: Vec<Header<C::Digest>> = Vec::new();
+
+		for step in 0..self.blocks_per_round {
+			let attacker_found = hash(&(self.seed, round, step)) % 100 < self.attacker_share;
+			let head = store.get(store.head()).expect("the head is always known").clone();
+
+			if !attacker_found {
+				store.import(self.mine(&head, HONEST)).expect("parent is the known head");
+
+				// A selfish attacker publishes when the honest miner gets close to its private
+				// chain, and gives up when the honest miner gets ahead.
+				let public_height = head.height + 1;
+				match private.last().map(|tip| tip.height) {
+					Some(private_height) if private_height < public_height => private.clear(),
+					Some(private_height) if private_height <= public_height + 1 =>
+						Self::publish(&mut store, &mut private),
+					_ => (),
+				}
+				continue;
+			}
+
+			match self.strategy {
+				Strategy::Honest => {
+					store.import(self.mine(&head, ATTACKER)).expect("parent is the known head");
+				},
+				Strategy::Selfish => {
+					let parent = private.last().unwrap_or(&head);
+					private.push(self.mine(parent, ATTACKER));
+				},
+			}
+		}
+		Self::publish(&mut store, &mut private);
+
+		// Walk the canonical chain back to genesis, counting who authored each block.
+		let (mut honest, mut attacker) = (0, 0);
+		let mut current = store.head();
+		while current != store.genesis() {
+			let header = store.get(current).expect("canonical ancestors are known");
+			if header.extrinsics_root == ATTACKER {
+				attacker += 1;
+			} else {
+				honest += 1;
+			}
+			current = header.parent;
+		}
+		(honest, attacker)
+	}
+
+	/// Mine a child of the given parent on behalf of the given miner.
+	fn mine(&self, parent: &Header<C::Digest>, miner: u64) -> Header<C::Digest> {
+		let partial = Header {
+			parent: hash(parent),
+			height: parent.height + 1,
+			extrinsics_root: miner,
+			state_root: parent.state_root,
+			consensus_digest: (),
+		};
+		self.engine
+			.seal(&parent.consensus_digest, partial)
+			.expect("simulated engines can always seal")
+	}
+
+	/// Publish all of the attacker's private blocks.
+	fn publish(store: &mut ChainStore<C::Digest>, private: &mut Vec<Header<C::Digest>>) {
+		for header in private.drain(..) {
+			store.import(header).expect("private blocks extend known blocks");
+		}
+	}
+}
+
+/// Forks that orphan at least this many blocks are reported by `detect_selfish_mining`.
+pub const SUSPICIOUS_REORG_DEPTH: u64 = 2;
+
+/// A fork in which a run of blocks was orphaned by a competing branch, as selfish mining would do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SuspiciousEvent {
+	/// The last canonical block the two branches have in common.
+	pub fork_point: Hash,
+	/// The number of blocks orphaned.
+	pub depth: u64,
+	/// The orphaned blocks, in ascending height order.
+	pub orphaned: Vec<Hash>,
+	/// The canonical blocks that overrode them, up to one block higher than the orphaned branch,
+	/// in ascending height order. These are the blocks that were likely withheld.
+	pub overriding: Vec<Hash>,
+}
+
+/// Find the forks in the given store that orphaned at least `SUSPICIOUS_REORG_DEPTH` blocks, one
+/// event per orphaned leaf, in ascending order of leaf hash.
+///
+/// This is a forensic tool. The store does not record when blocks arrived, so a deep fork is only
+/// evidence of withholding, not proof. It does not affect which chain is canonical.
+pub fn detect_selfish_mining<D: Clone + Eq + std::hash::Hash>(
+	store: &ChainStore<D>,
+) -> Vec<SuspiciousEvent> {
+	let mut events = Vec::new();
+	for leaf in store.leaves().into_iter().filter(|leaf| !store.is_canonical(*leaf)) {
+		let mut orphaned = Vec::new();
+		let mut current = leaf;
+		while !store.is_canonical(current) {
+			orphaned.push(current);
+			current = store.get(current).expect("ancestors of known blocks are known").parent;
+		}
+		orphaned.reverse();
+
+		let depth = orphaned.len() as u64;
+		if depth < SUSPICIOUS_REORG_DEPTH {
+			continue;
+		}
+		let overriding = store
+			.headers_between(current, store.head())
+			.unwrap_or_default()
+			.iter()
+			.skip(1)
+			.take(orphaned.len() + 1)
+			.map(hash)
+			.collect();
+		events.push(SuspiciousEvent { fork_point: current, depth, orphaned, overriding });
+	}
+	events
+}
+
+#[cfg(test)]
+use super::{
+	p1_pow::{moderate_difficulty_pow, PoW},
+	test_support::sealed_child,
+};
+
+/// A store in which an honest chain of `honest` blocks competes with a harder mined branch of
+/// `withheld` blocks from the same fork point at height 1. The withheld branch becomes canonical
+/// once it is at least as high as the honest chain.
+#[cfg(test)]
+fn contested_store(honest: u64, withheld: u64) -> (ChainStore<u64>, Vec<Header<u64>>) {
+	let light = PoW::new(u64::MAX / 2);
+	let heavy = PoW::new(u64::MAX / 1000);
+	let genesis = light.genesis_header();
+	let mut store = ChainStore::new(genesis.clone());
+
+	let fork_point = sealed_child(&light, &genesis, 1);
+	store.import(fork_point.clone()).unwrap();
+	let mut honest_chain = vec![fork_point.clone()];
+	for _ in 0..honest {
+		honest_chain.push(sealed_child(&light, honest_chain.last().unwrap(), HONEST));
+		store.import(honest_chain.last().unwrap().clone()).unwrap();
+	}
+	let mut withheld_chain = vec![fork_point];
+	for _ in 0..withheld {
+		withheld_chain.push(sealed_child(&heavy, withheld_chain.last().unwrap(), ATTACKER));
+	}
+	for header in withheld_chain.iter().skip(1) {
+		store.import(header.clone()).unwrap();
+	}
+	(store, withheld_chain)
+}
+
+#[test]
+fn cs_detect_selfish_mining_flags_burst() {
+	let (store, withheld) = contested_store(2, 3);
+	assert_eq!(store.head(), hash(withheld.last().unwrap()));
+
+	let events = detect_selfish_mining(&store);
+	assert_eq!(events.len(), 1);
+	assert_eq!(events[0].fork_point, hash(&withheld[0]));
+	assert_eq!(events[0].depth, 2);
+	assert_eq!(events[0].overriding, withheld[1..].iter().map(hash).collect::<Vec<_>>());
+}
+
+#[test]
+fn cs_detect_selfish_mining_ignores_honest_store() {
+	let engine = moderate_difficulty_pow();
+	let genesis = engine.genesis_header();
+	let mut store = ChainStore::new(genesis.clone());
+	let mut parent = genesis;
+	for extrinsic in 1..=5 {
+		parent = sealed_child(&engine, &parent, extrinsic);
+		store.import(parent.clone()).unwrap();
+	}
+	assert_eq!(detect_selfish_mining(&store), vec![]);
+
+	// A single block lost to a natural race is not suspicious either.
+	let (store, _) = contested_store(1, 2);
+	assert_eq!(store.leaves().len(), 2);
+	assert_eq!(detect_selfish_mining(&store), vec![]);
+}
+
+#[test]
+fn cs_simulation_honest_majority_wins_most_rounds() {
+	let engine = moderate_difficulty_pow();
+	let report = AttackSimulation::new(&engine, 40, Strategy::Honest)
+		.blocks_per_round(50)
+		.run(100);
+
+	assert!(report.honest_wins >= 80, "{report:?}");
+}
+
+#[test]
+fn cs_simulation_is_reproducible() {
+	let engine = moderate_difficulty_pow();
+	let simulation =
+		|seed| AttackSimulation::new(&engine, 40, Strategy::Selfish).seed(seed).run(20);
+
+	assert_eq!(simulation(7), simulation(7));
+}
+
+#[test]
+fn cs_simulation_selfish_mining_beats_honest_mining() {
+	let engine = moderate_difficulty_pow();
+	let honest = AttackSimulation::new(&engine, 40, Strategy::Honest).run(100);
+	let selfish = AttackSimulation::new(&engine, 40, Strategy::Selfish).run(100);
+
+	assert!(selfish.attacker_wins > honest.attacker_wins, "{honest:?} {selfish:?}");
+}
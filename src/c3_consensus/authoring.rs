@@ -0,0 +1,237 @@
+//! Authoring ties together the pieces a block producer needs. It takes the best extrinsics from the
+//! mempool, builds a block on top of the canonical head of its chain store, and has the consensus
+//! engine seal it.
+//!
+//! Some chains forbid empty blocks, so that authors can not pad the chain with filler. Under that
+//! policy an author with nothing to include simply waits, rather than producing a block that would
+//! be rejected. Like the test chains in this chapter, authored blocks follow the adder convention
+//! from chapter 2, so the state root is the running sum of all extrinsic payloads.
+//!
+//! Once a block is imported, the mempool has to follow the canonical chain, including through
+//! reorgs, so that no extrinsic is included twice or silently dropped.
+
+use std::collections::HashMap;
+
+use super::{
+	block::Block,
+	chain_store::{ChainStore, ImportOutcome},
+	Consensus, Header,
+};
+use crate::{
+	c2_blockchain::mempool::{Extrinsic, Mempool},
+	hash, merkle_root, ChainError,
+};
+
+/// Whether an author may produce a block that includes no extrinsics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyBlockPolicy {
+	Allow,
+	Forbid,
+}
+
+/// Author and seal a block on top of the store's canonical head, including up to `max_extrinsics`
+/// of the best extrinsics from the mempool.
+///
+/// Returns `None` if the policy forbids empty blocks and no extrinsic in the mempool fits in the
+/// block, if the state or height would overflow, or if the engine can not seal the block. In every
+/// one of those cases the mempool is left exactly as it was, including its order.
+pub fn author_next_block<C: Consensus + ?Sized>(
+	engine: &C,
+	store: &ChainStore<C::Digest>,
+	mempool: &mut Mempool,
+	max_extrinsics: usize,
+	policy: EmptyBlockPolicy,
+) -> Option<Block<C::Digest>> {
+	let parent = store.get(store.head()).expect("the head is always in the store");
+	// Draw from a copy, so the mempool only changes once the block is sealed.
+	let mut remaining = mempool.clone();
+	let extrinsics = remaining.drain_best(max_extrinsics);
+	if extrinsics.is_empty() && policy == EmptyBlockPolicy::Forbid {
+		return None;
+	}
+
+	let body: Vec<u64> = extrinsics.iter().map(|extrinsic| extrinsic.payload).collect();
+	let state_root = body
+		.iter()
+		.try_fold(parent.state_root, |state, payload| state.checked_add(*payload))?;
+	let partial = Header {
+		parent: hash(parent),
+		height: parent.height.checked_add(1)?,
+		state_root,
+		extrinsics_root: merkle_root(&body),
+		consensus_digest: (),
+	};
+	let header = engine.seal(&parent.consensus_digest, partial)?;
+	*mempool = remaining;
+	Some(Block { header, body })
+}
+
+/// Import a header into the store, and keep the mempool in step with the canonical chain.
+///
+/// The extrinsics of every block that becomes canonical leave the mempool. When the import causes a
+/// reorg, the extrinsics of the retracted blocks return to it, unless a block that is still
+/// canonical includes them too. `included` records the extrinsics of every block imported this
+/// way, by hash, so that a reorg onto a fork knows what the fork's blocks include.
+pub fn import_block<D: Clone + Eq + std::hash::Hash>(
+	store: &mut ChainStore<D>,
+	mempool: &mut Mempool,
+	included: &mut HashMap<u64, Vec<Extrinsic>>,
+	header: Header<D>,
+	extrinsics: Vec<Extrinsic>,
+) -> Result<ImportOutcome, ChainError> {
+	let block = hash(&header);
+	let old_head = store.head();
+	let outcome = store.import(header)?;
+	included.insert(block, extrinsics);
+	if outcome == ImportOutcome::SideChain {
+		return Ok(outcome);
+	}
+
+	// Without a reorg the old head is still canonical, so nothing is retracted.
+	let retracted = branch(store, old_head, |hash| store.is_canonical(hash));
+	let enacted: Vec<_> = branch(store, block, |hash| store.is_ancestor(hash, old_head))
+		.into_iter()
+		.map(|hash| (hash, included.get(&hash).cloned().unwrap_or_default()))
+		.collect();
+	mempool.reorg(&retracted, &enacted);
+	Ok(outcome)
+}
+
+/// The hashes of `tip` and its ancestors, down to but excluding the first one `stop` accepts, in
+/// ascending height order.
+fn branch<D: Clone + Eq + std::hash::Hash>(
+	store: &ChainStore<D>,
+	mut tip: u64,
+	stop: impl Fn(u64) -> bool,
+) -> Vec<u64> {
+	let mut branch = Vec::new();
+	while !stop(tip) {
+		branch.push(tip);
+		tip = store.get(tip).expect("the ancestors of known headers are known").parent;
+	}
+	branch.reverse();
+	branch
+}
+
+#[cfg(test)]
+use super::{p1_pow::PoW, p4_even_only::EvenOnly, test_support::sealed_child};
+
+#[cfg(test)]
+fn store_and_engine() -> (ChainStore<u64>, PoW) {
+	let engine = PoW::new(u64::MAX / 4);
+	(ChainStore::new(engine.genesis_header()), engine)
+}
+
+#[test]
+fn cs_author_empty_mempool() {
+	let (store, engine) = store_and_engine();
+	let mut mempool = Mempool::new(10);
+
+	let forbidden = author_next_block(&engine, &store, &mut mempool, 5, EmptyBlockPolicy::Forbid);
+	assert_eq!(forbidden, None);
+
+	let block =
+		author_next_block(&engine, &store, &mut mempool, 5, EmptyBlockPolicy::Allow).unwrap();
+	assert!(block.body.is_empty());
+	assert_eq!(engine.check_block(&0, &block), Ok(()));
+}
+
+#[test]
+fn cs_author_non_empty_mempool() {
+	for policy in [EmptyBlockPolicy::Allow, EmptyBlockPolicy::Forbid] {
+		let (mut store, engine) = store_and_engine();
+		let mut mempool = Mempool::new(10);
+		mempool.submit(Extrinsic { payload: 3, weight: 4, fee: 1 });
+		mempool.submit(Extrinsic { payload: 4, weight: 4, fee: 2 });
+
+		let block = author_next_block(&engine, &store, &mut mempool, 5, policy).unwrap();
+		assert_eq!(block.body, vec![4, 3]);
+		assert_eq!(block.header.state_root, 7);
+		assert!(mempool.is_empty());
+
+		assert_eq!(engine.check_block(&0, &block), Ok(()));
+		store.import(block.header).unwrap();
+	}
+}
+
+#[test]
+fn cs_author_forbid_skips_when_nothing_fits() {
+	let (store, engine) = store_and_engine();
+	let mut mempool = Mempool::new(10);
+	mempool.submit(Extrinsic { payload: 3, weight: 11, fee: 1 });
+
+	let block = author_next_block(&engine, &store, &mut mempool, 5, EmptyBlockPolicy::Forbid);
+	assert_eq!(block, None);
+	assert_eq!(mempool.len(), 1);
+}
+
+#[test]
+fn cs_author_seal_failure_keeps_mempool_order() {
+	// The state would be odd, so this engine refuses to seal the block.
+	let engine = EvenOnly::new(PoW::new(u64::MAX / 4));
+	let store = ChainStore::new(engine.genesis_header());
+	let mut mempool = Mempool::new(10);
+	mempool.submit(Extrinsic { payload: 3, weight: 4, fee: 1 });
+	mempool.submit(Extrinsic { payload: 4, weight: 4, fee: 2 });
+	let before = mempool.clone();
+
+	let block = author_next_block(&engine, &store, &mut mempool, 5, EmptyBlockPolicy::Allow);
+	assert_eq!(block, None);
+	assert_eq!(mempool, before);
+}
+
+#[test]
+fn cs_author_refuses_to_overflow_the_state() {
+	let engine = PoW::new(u64::MAX / 4);
+	let genesis = Header { state_root: u64::MAX - 1, ..engine.genesis_header() };
+	let store = ChainStore::new(genesis);
+	let mut mempool = Mempool::new(10);
+	mempool.submit(Extrinsic { payload: 3, weight: 4, fee: 1 });
+	let before = mempool.clone();
+
+	let block = author_next_block(&engine, &store, &mut mempool, 5, EmptyBlockPolicy::Allow);
+	assert_eq!(block, None);
+	assert_eq!(mempool, before);
+}
+
+#[test]
+fn cs_import_block_removes_included_extrinsics_from_mempool() {
+	let (mut store, engine) = store_and_engine();
+	let genesis = engine.genesis_header();
+	let [first, second, third] = [1, 2, 3].map(|payload| Extrinsic { payload, weight: 1, fee: 1 });
+	let mut mempool = Mempool::new(10);
+	for extrinsic in [first, second, third] {
+		mempool.submit(extrinsic);
+	}
+	let mut included = HashMap::new();
+
+	let block = sealed_child(&engine, &genesis, 1);
+	let outcome = import_block(&mut store, &mut mempool, &mut included, block, vec![first]);
+	assert_eq!(outcome, Ok(ImportOutcome::NewHead));
+	assert_eq!(mempool.pending(), &[second, third]);
+}
+
+#[test]
+fn cs_import_block_follows_reorgs() {
+	let (mut store, engine) = store_and_engine();
+	let genesis = engine.genesis_header();
+	let [first, second, third] = [1, 2, 3].map(|payload| Extrinsic { payload, weight: 1, fee: 1 });
+	let mut mempool = Mempool::new(10);
+	for extrinsic in [first, second, third] {
+		mempool.submit(extrinsic);
+	}
+	let mut included = HashMap::new();
+
+	let block = sealed_child(&engine, &genesis, 1);
+	import_block(&mut store, &mut mempool, &mut included, block, vec![first, second]).unwrap();
+	assert_eq!(mempool.pending(), &[third]);
+
+	// A longer fork that includes `second` and `third`, but not `first`, takes over.
+	let fork = sealed_child(&engine, &genesis, 2);
+	let fork_tip = sealed_child(&engine, &fork, 3);
+	import_block(&mut store, &mut mempool, &mut included, fork, vec![second]).unwrap();
+	import_block(&mut store, &mut mempool, &mut included, fork_tip.clone(), vec![third]).unwrap();
+
+	assert_eq!(store.head(), hash(&fork_tip));
+	assert_eq!(mempool.pending(), &[first]);
+}
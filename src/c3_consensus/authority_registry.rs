@@ -0,0 +1,60 @@
+//! Identity-based consensus engines know which authority should author each block, but a node
+//! also needs to know where to find that authority on the network. The `AuthorityRegistry` maps
+//! each authority to an address.
+//!
+//! Addresses are plain strings, so the registry does not commit to any particular transport. A
+//! real networking layer would parse them into whatever it needs.
+
+use std::collections::HashMap;
+
+use super::ConsensusAuthority;
+
+/// The network addresses of the consensus authorities that have registered one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuthorityRegistry {
+	addresses: HashMap<ConsensusAuthority, String>,
+}
+
+impl AuthorityRegistry {
+	/// Create a registry in which no authority has an address yet.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Record the address of the given authority, replacing any address it registered before.
+	pub fn register(&mut self, authority: ConsensusAuthority, address: String) {
+		self.addresses.insert(authority, address);
+	}
+
+	/// The address of the given authority, if it has registered one.
+	pub fn resolve(&self, authority: ConsensusAuthority) -> Option<&str> {
+		self.addresses.get(&authority).map(String::as_str)
+	}
+}
+
+#[test]
+fn cs_registry_resolves_registered_authorities() {
+	let mut registry = AuthorityRegistry::new();
+	registry.register(ConsensusAuthority::Alice, "10.0.0.1:30333".into());
+	registry.register(ConsensusAuthority::Bob, "10.0.0.2:30333".into());
+
+	assert_eq!(registry.resolve(ConsensusAuthority::Alice), Some("10.0.0.1:30333"));
+	assert_eq!(registry.resolve(ConsensusAuthority::Bob), Some("10.0.0.2:30333"));
+}
+
+#[test]
+fn cs_registry_register_replaces_address() {
+	let mut registry = AuthorityRegistry::new();
+	registry.register(ConsensusAuthority::Alice, "10.0.0.1:30333".into());
+	registry.register(ConsensusAuthority::Alice, "10.0.0.9:30333".into());
+
+	assert_eq!(registry.resolve(ConsensusAuthority::Alice), Some("10.0.0.9:30333"));
+}
+
+#[test]
+fn cs_registry_unregistered_authority() {
+	let mut registry = AuthorityRegistry::new();
+	registry.register(ConsensusAuthority::Alice, "10.0.0.1:30333".into());
+
+	assert_eq!(registry.resolve(ConsensusAuthority::Charlie), None);
+}
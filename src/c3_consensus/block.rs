@@ -0,0 +1,87 @@
+//! Consensus engines only ever see headers, so nothing so far stops a header from claiming any
+//! extrinsics root it likes. A node that downloads the full block can do better: it recomputes the
+//! Merkle root of the block's extrinsics and checks that the header commits to exactly that body.
+
+use super::Header;
+use crate::{merkle_root, ChainError};
+
+/// A header together with the extrinsics it commits to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Block<D> {
+	pub header: Header<D>,
+	pub body: Vec<u64>,
+}
+
+impl<D> Block<D> {
+	/// Check that the header's extrinsics root is the Merkle root of the body.
+	pub fn check_extrinsics_root(&self) -> Result<(), ChainError> {
+		if self.header.extrinsics_root != merkle_root(&self.body) {
+			return Err(ChainError::WrongExtrinsicsRoot);
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+use super::{p1_pow::PoW, Consensus};
+#[cfg(test)]
+use crate::hash;
+
+/// A block on top of the given parent, sealed by the given engine, whose header commits to the
+/// given body.
+#[cfg(test)]
+fn sealed_block<C: Consensus>(
+	engine: &C,
+	parent: &Header<C::Digest>,
+	body: Vec<u64>,
+) -> Block<C::Digest> {
+	let partial = Header {
+		parent: hash(parent),
+		height: parent.height + 1,
+		extrinsics_root: merkle_root(&body),
+		state_root: parent.state_root + body.iter().sum::<u64>(),
+		consensus_digest: (),
+	};
+	let header = engine.seal(&parent.consensus_digest, partial).unwrap();
+	Block { header, body }
+}
+
+#[test]
+fn cs_block_with_matching_root_is_accepted() {
+	let engine = PoW::new(u64::MAX / 4);
+	let genesis = engine.genesis_header();
+	let block = sealed_block(&engine, &genesis, vec![1, 2, 3]);
+
+	assert_eq!(block.check_extrinsics_root(), Ok(()));
+	assert!(engine.validate_block(&genesis.consensus_digest, &block));
+}
+
+#[test]
+fn cs_block_with_tampered_body_is_rejected() {
+	let engine = PoW::new(u64::MAX / 4);
+	let genesis = engine.genesis_header();
+	let mut block = sealed_block(&engine, &genesis, vec![1, 2, 3]);
+	// The seal is still valid, because it only covers the header.
+	block.body[1] = 5;
+
+	assert!(engine.validate(&genesis.consensus_digest, &block.header));
+	assert_eq!(
+		engine.check_block(&genesis.consensus_digest, &block),
+		Err(ChainError::WrongExtrinsicsRoot)
+	);
+	assert!(!engine.validate_block(&genesis.consensus_digest, &block));
+}
+
+#[test]
+fn cs_block_with_repeated_last_extrinsic_is_rejected() {
+	let engine = PoW::new(u64::MAX / 4);
+	let genesis = engine.genesis_header();
+	let mut block = sealed_block(&engine, &genesis, vec![1, 2, 3]);
+	block.body.push(3);
+
+	assert_eq!(block.check_extrinsics_root(), Err(ChainError::WrongExtrinsicsRoot));
+	assert_eq!(
+		engine.check_block(&genesis.consensus_digest, &block),
+		Err(ChainError::WrongExtrinsicsRoot)
+	);
+}
@@ -0,0 +1,84 @@
+//! A Bloom filter is a compact set that can answer "definitely not a member" or "probably a
+//! member". It never forgets an item it was given, but it may claim to contain items it was never
+//! given. With `m` bits, `k` hash functions and `n` items inserted, the chance of such a false
+//! positive is roughly `(1 - e^(-k * n / m))^k`, so it can be kept as low as needed by choosing
+//! enough bits.
+//!
+//! Because of false positives, a positive answer must never be acted on alone. It only says that a
+//! more expensive, exact check is worth doing.
+
+use crate::hash;
+
+type Hash = u64;
+
+/// A Bloom filter over hashes. The bits are packed 64 to a word.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BloomFilter {
+	words: Vec<u64>,
+	bits: usize,
+	hashes: u32,
+}
+
+impl BloomFilter {
+	/// Create an empty filter with the given number of bits, that sets `hashes` bits per item.
+	pub fn new(bits: usize, hashes: u32) -> Self {
+		assert!(bits > 0 && hashes > 0, "a Bloom filter needs at least one bit and one hash");
+		BloomFilter { words: vec![0; bits.div_ceil(64)], bits, hashes }
+	}
+
+	/// Add an item to the filter.
+	pub fn insert(&mut self, item: Hash) {
+		for position in self.positions(item) {
+			self.words[position / 64] |= 1 << (position % 64);
+		}
+	}
+
+	/// Whether the item may have been added. `false` is always right, `true` may be a false
+	/// positive.
+	pub fn might_contain(&self, item: Hash) -> bool {
+		self.positions(item)
+			.all(|position| self.words[position / 64] & 1 << (position % 64) != 0)
+	}
+
+	/// The bits that represent the given item.
+	fn positions(&self, item: Hash) -> impl Iterator<Item = usize> {
+		let len = self.bits as u64;
+		(0..self.hashes).map(move |seed| (hash(&(seed, item)) % len) as usize)
+	}
+}
+
+#[test]
+fn cs_bloom_never_forgets() {
+	let mut filter = BloomFilter::new(64, 3);
+	assert!(!filter.might_contain(7));
+
+	for item in 0..20 {
+		filter.insert(item);
+	}
+	assert!((0..20).all(|item| filter.might_contain(item)));
+}
+
+#[test]
+fn cs_bloom_packs_bits_into_words() {
+	let filter = BloomFilter::new(65, 1);
+	assert_eq!(filter.words.len(), 2);
+
+	// Every bit of a word-sized filter is reachable.
+	let mut filter = BloomFilter::new(64, 1);
+	for item in 0..10_000 {
+		filter.insert(item);
+	}
+	assert_eq!(filter.words, vec![u64::MAX]);
+}
+
+#[test]
+fn cs_bloom_false_positives_are_bounded() {
+	// 100 items in 1024 bits with 4 hashes gives a false positive rate of about 1%.
+	let mut filter = BloomFilter::new(1024, 4);
+	for item in 0..100 {
+		filter.insert(item);
+	}
+
+	let false_positives = (1000..11_000).filter(|item| filter.might_contain(*item)).count();
+	assert!(false_positives < 500, "{false_positives} false positives in 10000");
+}
@@ -0,0 +1,141 @@
+//! Most chains are meant to run forever, but some have a defined end. Test chains, and ephemeral
+//! side chains that settle back to a main chain, only ever need a fixed number of blocks. This
+//! module provides a higher-order engine that ends the chain at a given height.
+
+use super::{Consensus, ConsensusAuthority, Header};
+use crate::ChainError;
+
+/// A Consensus engine that rejects every header above `max_height`, and refuses to seal one.
+/// Wraps an inner consensus engine whose rules will also be enforced.
+pub struct Bounded<Inner> {
+	inner: Inner,
+	max_height: u64,
+}
+
+impl<Inner: Consensus> Bounded<Inner> {
+	/// Create a new engine on top of the given engine, whose chain ends at `max_height`.
+	pub fn new(inner: Inner, max_height: u64) -> Self {
+		Bounded { inner, max_height }
+	}
+}
+
+impl<Inner: Consensus> Consensus for Bounded<Inner> {
+	type Digest = Inner::Digest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that the header is not beyond the end of the chain, and that the inner engine accepts
+	/// it.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if header.height > self.max_height {
+			return Err(ChainError::BeyondMaxHeight);
+		}
+		self.inner.check_header(parent_digest, header)
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(parent_digest, header)
+	}
+
+	fn validate_with_parent(
+		&self,
+		parent: &Header<Self::Digest>,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		header.height <= self.max_height && self.inner.validate_with_parent(parent, header)
+	}
+
+	/// Seal the partial header with the inner engine, unless it is beyond the end of the chain.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		if partial_header.height > self.max_height {
+			return None;
+		}
+		self.inner.seal(parent_digest, partial_header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.inner.genesis_header()
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(header)
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("max-height");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Bounded {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	p1_pow::moderate_difficulty_pow,
+	test_support::{check_adder_chain, sealed_adder_chain},
+};
+
+#[test]
+fn cs_bounded_seals_up_to_max_height() {
+	let engine = Bounded::new(moderate_difficulty_pow(), 3);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+
+	assert_eq!(chain.last().unwrap().height, 3);
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_bounded_rejects_beyond_max_height() {
+	use crate::hash;
+
+	let engine = Bounded::new(moderate_difficulty_pow(), 3);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+	let tip = chain.last().unwrap();
+	let partial = Header {
+		parent: hash(tip),
+		height: tip.height + 1,
+		extrinsics_root: 4,
+		state_root: tip.state_root + 4,
+		consensus_digest: (),
+	};
+	assert_eq!(engine.seal(&tip.consensus_digest, partial.clone()), None);
+
+	// A header sealed by the unbounded inner engine is still rejected.
+	let beyond = moderate_difficulty_pow().seal(&tip.consensus_digest, partial).unwrap();
+	assert_eq!(
+		engine.check_header(&tip.consensus_digest, &beyond),
+		Err(ChainError::BeyondMaxHeight)
+	);
+	assert!(!engine.validate_with_parent(tip, &beyond));
+	assert_eq!(
+		check_adder_chain(&engine, &genesis, &[chain, vec![beyond]].concat()),
+		Err(ChainError::BeyondMaxHeight)
+	);
+}
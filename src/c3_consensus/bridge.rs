@@ -0,0 +1,197 @@
+//! A light-client bridge lets one chain follow another. Each block commits to the latest head of
+//! the bridged chain that its author has seen, so that contracts on this chain can later prove
+//! things about the other one. The bridged chain only ever moves forward, so neither may the
+//! commitments to it.
+//!
+//! Like the author in `BlockReward`, the commitment lives in the digest, because the header is
+//! shared by every engine in this chapter. We identify the bridged head by its height, so that
+//! commitments can be compared. Real bridges commit to the head's hash as well, and prove its
+//! height with the bridged chain's own consensus rules.
+
+use super::{Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::ChainError;
+
+/// The digest of a `Bridge` engine. The inner engine's digest along with the height of the bridged
+/// chain's head, if the chain has committed to one yet.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BridgeDigest<D> {
+	pub(crate) inner: D,
+	pub(crate) bridge_commitment: Option<u64>,
+}
+
+/// A Consensus engine that requires bridge commitments never to decrease from one block to the
+/// next. Once the chain has committed to a bridged head, every later block must commit to one too.
+/// Wraps an inner consensus engine whose rules will also be enforced.
+pub struct Bridge<Inner> {
+	inner: Inner,
+}
+
+impl<Inner: Consensus> Bridge<Inner> {
+	/// Create a new engine that enforces bridge commitments on top of the given engine.
+	pub fn new(inner: Inner) -> Self {
+		Bridge { inner }
+	}
+
+	/// The header as the inner engine sees it, carrying only the inner digest.
+	fn inner_header(header: &Header<BridgeDigest<Inner::Digest>>) -> Header<Inner::Digest> {
+		header.with_digest(header.consensus_digest.inner.clone())
+	}
+
+	/// Seal the partial header with the inner engine, committing to the given bridged head.
+	///
+	/// Returns `None` if the commitment is behind the parent's.
+	pub fn seal_with_commitment(
+		&self,
+		parent_digest: &BridgeDigest<Inner::Digest>,
+		partial_header: Header<()>,
+		bridge_commitment: Option<u64>,
+	) -> Option<Header<BridgeDigest<Inner::Digest>>> {
+		// `None` orders before any `Some`, so this also rules out dropping the commitment.
+		if bridge_commitment < parent_digest.bridge_commitment {
+			return None;
+		}
+		let header = self.inner.seal(&parent_digest.inner, partial_header)?;
+
+		Some(header.map_digest(|inner| BridgeDigest { inner, bridge_commitment }))
+	}
+}
+
+impl<Inner: Consensus> Consensus for Bridge<Inner> {
+	type Digest = BridgeDigest<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that the bridge commitment did not move backwards, and that the inner engine accepts
+	/// the header.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if header.consensus_digest.bridge_commitment < parent_digest.bridge_commitment {
+			return Err(ChainError::DecreasingBridgeCommitment);
+		}
+		self.inner.check_header(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Seal the partial header with the inner engine, keeping the parent's bridge commitment.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.seal_with_commitment(parent_digest, partial_header, parent_digest.bridge_commitment)
+	}
+
+	/// Genesis does not commit to the bridged chain.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.inner.genesis_header();
+
+		genesis.map_digest(|inner| BridgeDigest { inner, bridge_commitment: None })
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header))
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(&Self::inner_header(header))
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("monotonic-bridge-commitment");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Bridged {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	header_builder::HeaderBuilder, p1_pow::moderate_difficulty_pow, test_support::check_adder_chain,
+};
+
+/// A partial child of the given parent carrying the given extrinsic, following the adder
+/// convention.
+#[cfg(test)]
+fn partial_child<D: std::hash::Hash>(parent: &Header<D>, extrinsic: u64) -> Header<()> {
+	HeaderBuilder::new()
+		.child_of(parent)
+		.extrinsics_root(extrinsic)
+		.state_root(parent.state_root + extrinsic)
+		.build()
+		.unwrap()
+}
+
+#[test]
+fn cs_bridge_accepts_increasing_commitments() {
+	let engine = Bridge::new(moderate_difficulty_pow());
+	let genesis = engine.genesis_header();
+
+	let mut chain = Vec::new();
+	for (extrinsic, commitment) in [(1, None), (2, Some(10)), (3, Some(10)), (4, Some(12))] {
+		let parent = chain.last().unwrap_or(&genesis);
+		let partial = partial_child(parent, extrinsic);
+		let header = engine
+			.seal_with_commitment(&parent.consensus_digest, partial, commitment)
+			.unwrap();
+		chain.push(header);
+	}
+	// Plain sealing carries the commitment forward.
+	let partial = partial_child(&chain[3], 5);
+	chain.push(engine.seal(&chain[3].consensus_digest, partial).unwrap());
+
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+	assert_eq!(chain[4].consensus_digest.bridge_commitment, Some(12));
+}
+
+#[test]
+fn cs_bridge_rejects_decreasing_commitment() {
+	let engine = Bridge::new(moderate_difficulty_pow());
+	let genesis = engine.genesis_header();
+	let partial = partial_child(&genesis, 1);
+	let parent = engine
+		.seal_with_commitment(&genesis.consensus_digest, partial, Some(10))
+		.unwrap();
+
+	for commitment in [Some(9), None] {
+		let partial = partial_child(&parent, 2);
+		assert_eq!(
+			engine.seal_with_commitment(&parent.consensus_digest, partial, commitment),
+			None
+		);
+
+		// Seal the header with the inner engine alone, so that only the commitment is wrong.
+		let partial = partial_child(&parent, 2);
+		let inner =
+			moderate_difficulty_pow().seal(&parent.consensus_digest.inner, partial).unwrap();
+		let header =
+			inner.map_digest(|inner| BridgeDigest { inner, bridge_commitment: commitment });
+		assert!(engine.validate_seal_only(&parent.consensus_digest, &header));
+		assert_eq!(
+			engine.check_sub_chain(&parent, &[header]),
+			Err(ChainError::DecreasingBridgeCommitment)
+		);
+	}
+}
@@ -0,0 +1,217 @@
+//! Checking a seal can be expensive. Real-world PoA engines verify a cryptographic signature, and
+//! during fork choice the same header may be checked again and again. This module provides a
+//! higher-order engine that remembers the outcome of recent checks, so that each distinct check is
+//! only performed once.
+//!
+//! A header may be valid on top of one parent and invalid on top of another, so the outcome is
+//! remembered for each pair of parent digest and header.
+
+use std::{
+	cell::RefCell,
+	collections::{HashMap, VecDeque},
+};
+
+use super::{Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::{hash, ChainError};
+
+/// A Consensus engine that wraps another engine and caches the results of its header checks.
+///
+/// At most `capacity` results are kept. When the cache is full, the least recently used result
+/// is evicted.
+pub struct Cached<Inner> {
+	inner: Inner,
+	capacity: usize,
+	results: RefCell<HashMap<u64, Result<(), ChainError>>>,
+	/// The keys of the cached results, least recently used first.
+	recency: RefCell<VecDeque<u64>>,
+}
+
+impl<Inner: Consensus> Cached<Inner> {
+	/// Create a new engine that caches up to `capacity` results of the given engine's checks.
+	pub fn new(inner: Inner, capacity: usize) -> Self {
+		Cached {
+			inner,
+			capacity,
+			results: RefCell::new(HashMap::new()),
+			recency: RefCell::new(VecDeque::new()),
+		}
+	}
+
+	/// The number of results currently cached.
+	pub fn len(&self) -> usize {
+		self.results.borrow().len()
+	}
+
+	/// Whether no results are currently cached.
+	pub fn is_empty(&self) -> bool {
+		self.results.borrow().is_empty()
+	}
+
+	/// Mark the given key as the most recently used.
+	fn touch(&self, key: u64) {
+		let mut recency = self.recency.borrow_mut();
+		if let Some(index) = recency.iter().position(|k| *k == key) {
+			recency.remove(index);
+		}
+		recency.push_back(key);
+	}
+}
+
+impl<Inner: Consensus> Consensus for Cached<Inner> {
+	type Digest = Inner::Digest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Return the cached result if there is one. Otherwise check the header with the inner engine
+	/// and remember the result.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let key = hash(&(parent_digest, header));
+		let cached = self.results.borrow().get(&key).copied();
+		if let Some(result) = cached {
+			self.touch(key);
+			return result;
+		}
+		if self.capacity == 0 {
+			return self.inner.check_header(parent_digest, header);
+		}
+
+		let result = self.inner.check_header(parent_digest, header);
+		if self.results.borrow().len() >= self.capacity {
+			if let Some(evicted) = self.recency.borrow_mut().pop_front() {
+				self.results.borrow_mut().remove(&evicted);
+			}
+		}
+		self.results.borrow_mut().insert(key, result);
+		self.touch(key);
+		result
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(parent_digest, header)
+	}
+
+	fn validate_with_parent(
+		&self,
+		parent: &Header<Self::Digest>,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_with_parent(parent, header)
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.inner.seal(parent_digest, partial_header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.inner.genesis_header()
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(header)
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		self.inner.rules()
+	}
+
+	fn human_name() -> String {
+		format!("Cached {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use std::cell::Cell;
+
+#[cfg(test)]
+use super::from_fns::from_fns;
+
+/// An engine that accepts headers with an even state root, and counts how often it validates.
+#[cfg(test)]
+fn counting_engine(calls: &Cell<u64>) -> impl Consensus<Digest = u64> + '_ {
+	from_fns(
+		move |_: &u64, header: &Header<u64>| {
+			calls.set(calls.get() + 1);
+			header.state_root.is_multiple_of(2)
+		},
+		|_: &u64, _: Header<()>| None,
+	)
+}
+
+#[cfg(test)]
+fn header(state_root: u64) -> Header<u64> {
+	Header { parent: 0, height: 1, extrinsics_root: 0, state_root, consensus_digest: 0 }
+}
+
+#[test]
+fn cs_cached_hit_returns_stored_result() {
+	let calls = Cell::new(0);
+	let engine = Cached::new(counting_engine(&calls), 8);
+	assert!(engine.is_empty());
+
+	assert!(engine.validate(&0, &header(2)));
+	assert!(!engine.validate(&0, &header(3)));
+	assert_eq!(calls.get(), 2);
+
+	for _ in 0..3 {
+		assert!(engine.validate(&0, &header(2)));
+		assert_eq!(engine.check_header(&0, &header(3)), Err(ChainError::InvalidSeal));
+	}
+	assert_eq!(calls.get(), 2);
+	assert_eq!(engine.len(), 2);
+}
+
+#[test]
+fn cs_cached_distinguishes_parent_digests() {
+	let calls = Cell::new(0);
+	let engine = Cached::new(counting_engine(&calls), 8);
+
+	assert!(engine.validate(&0, &header(2)));
+	assert!(engine.validate(&1, &header(2)));
+	assert!(engine.validate(&1, &header(2)));
+	assert_eq!(calls.get(), 2);
+}
+
+#[test]
+fn cs_cached_evicts_least_recently_used() {
+	let calls = Cell::new(0);
+	let engine = Cached::new(counting_engine(&calls), 2);
+
+	assert!(engine.validate(&0, &header(2)));
+	assert!(engine.validate(&0, &header(4)));
+	// Using the first header again makes the second the least recently used.
+	assert!(engine.validate(&0, &header(2)));
+	assert!(engine.validate(&0, &header(6)));
+	assert_eq!(calls.get(), 3);
+	assert_eq!(engine.len(), 2);
+
+	assert!(engine.validate(&0, &header(2)));
+	assert_eq!(calls.get(), 3);
+	assert!(engine.validate(&0, &header(4)));
+	assert_eq!(calls.get(), 4);
+}
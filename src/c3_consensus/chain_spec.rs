@@ -0,0 +1,260 @@
+//! A `GenesisBuilder` says how a chain starts, and a `ConsensusConfig` says how it continues. To
+//! launch the same chain on many machines, both must be shared, exactly. Substrate bundles them
+//! into a chain spec file that every node of a network loads at startup. `ChainSpec` is our
+//! version of that.
+//!
+//! Chain specs are meant to be read and edited by people, so unlike headers on the wire, they are
+//! stored as JSON. This crate has no dependencies, so rather than pulling in serde, the spec reads
+//! and writes its own small, flat JSON object.
+//!
+//! A spec builds exactly one thing, the engine, and the engine's genesis header is the chain's
+//! genesis. There is no second genesis kept alongside it that could disagree.
+
+use super::{
+	config::{ConfiguredConsensus, ConsensusConfig},
+	ConsensusAuthority,
+};
+
+/// Which consensus engine a chain runs, along with any parameters specific to that engine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineSpec {
+	/// Proof of Work at the spec's difficulty.
+	Pow,
+	/// Proof of Authority in which the spec's authorities take turns by height.
+	PoaRoundRobin,
+	/// Aura with the spec's authorities and the given slot duration in milliseconds.
+	Aura { slot_duration: u64 },
+}
+
+/// Everything a node needs to launch a chain, in a form that can be shared as a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainSpec {
+	pub genesis_state: u64,
+	/// The time, in milliseconds since the Unix epoch, at which the chain launches.
+	pub genesis_timestamp: u64,
+	pub engine: EngineSpec,
+	pub authorities: Vec<ConsensusAuthority>,
+	/// The initial Proof of Work threshold. Engines that do not mine ignore it.
+	pub difficulty: u64,
+}
+
+/// The reasons a chain spec can fail to load.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpecError {
+	/// The input is not a flat JSON object of numbers, strings, and arrays of strings.
+	Malformed,
+	/// A field the spec requires is missing, or has the wrong type.
+	MissingField(&'static str),
+	/// The engine is not one this crate knows how to build.
+	UnknownEngine(String),
+	/// One of the authorities is not a known authority.
+	UnknownAuthority(String),
+}
+
+impl ChainSpec {
+	/// Build the consensus engine this spec describes. Its genesis header starts from the spec's
+	/// genesis state.
+	pub fn build(&self) -> ConfiguredConsensus {
+		let authorities = self.authorities.clone();
+		let consensus = match self.engine {
+			EngineSpec::Pow => ConsensusConfig::pow(self.difficulty),
+			EngineSpec::PoaRoundRobin => ConsensusConfig::poa_round_robin(authorities),
+			EngineSpec::Aura { slot_duration } => ConsensusConfig::aura(authorities, slot_duration),
+		};
+		ConsensusConfig::genesis_state(consensus, self.genesis_state).build()
+	}
+
+	/// Write the spec as a JSON object.
+	pub fn to_json(&self) -> String {
+		let (engine, slot_duration) = match self.engine {
+			EngineSpec::Pow => ("pow", None),
+			EngineSpec::PoaRoundRobin => ("poa_round_robin", None),
+			EngineSpec::Aura { slot_duration } => ("aura", Some(slot_duration)),
+		};
+		let authorities: Vec<String> =
+			self.authorities.iter().map(|authority| format!("\"{authority:?}\"")).collect();
+
+		let mut fields = vec![
+			format!("\"genesis_state\": {}", self.genesis_state),
+			format!("\"genesis_timestamp\": {}", self.genesis_timestamp),
+			format!("\"engine\": \"{engine}\""),
+		];
+		if let Some(slot_duration) = slot_duration {
+			fields.push(format!("\"slot_duration\": {slot_duration}"));
+		}
+		fields.push(format!("\"authorities\": [{}]", authorities.join(", ")));
+		fields.push(format!("\"difficulty\": {}", self.difficulty));
+		format!("{{\n  {}\n}}", fields.join(",\n  "))
+	}
+
+	/// Read a spec written by `to_json`. Fields may come in any order, and unknown fields are
+	/// ignored.
+	pub fn from_json(json: &str) -> Result<Self, SpecError> {
+		let fields = parse_object(json).ok_or(SpecError::Malformed)?;
+		let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, v)| v);
+		let number = |name: &'static str| match field(name) {
+			Some(Value::Number(n)) => Ok(*n),
+			_ => Err(SpecError::MissingField(name)),
+		};
+
+		let engine = match field("engine") {
+			Some(Value::String(engine)) => match engine.as_str() {
+				"pow" => EngineSpec::Pow,
+				"poa_round_robin" => EngineSpec::PoaRoundRobin,
+				"aura" => EngineSpec::Aura { slot_duration: number("slot_duration")? },
+				_ => return Err(SpecError::UnknownEngine(engine.clone())),
+			},
+			_ => return Err(SpecError::MissingField("engine")),
+		};
+		let authorities = match field("authorities") {
+			Some(Value::Strings(names)) => names
+				.iter()
+				.map(|name| match name.as_str() {
+					"Alice" => Ok(ConsensusAuthority::Alice),
+					"Bob" => Ok(ConsensusAuthority::Bob),
+					"Charlie" => Ok(ConsensusAuthority::Charlie),
+					_ => Err(SpecError::UnknownAuthority(name.clone())),
+				})
+				.collect::<Result<_, _>>()?,
+			_ => return Err(SpecError::MissingField("authorities")),
+		};
+
+		Ok(ChainSpec {
+			genesis_state: number("genesis_state")?,
+			genesis_timestamp: number("genesis_timestamp")?,
+			engine,
+			authorities,
+			difficulty: number("difficulty")?,
+		})
+	}
+}
+
+/// A value in a chain spec.
+enum Value {
+	Number(u64),
+	String(String),
+	Strings(Vec<String>),
+}
+
+/// Parse a flat JSON object whose values are unsigned integers, strings, or arrays of strings.
+/// Strings may not contain escapes.
+fn parse_object(json: &str) -> Option<Vec<(String, Value)>> {
+	let mut rest = json.trim().strip_prefix('{')?.strip_suffix('}')?.trim();
+	let mut fields = Vec::new();
+	while !rest.is_empty() {
+		let (key, after_key) = parse_string(rest)?;
+		let after_colon = after_key.trim_start().strip_prefix(':')?.trim_start();
+		let (value, after_value) = parse_value(after_colon)?;
+		fields.push((key, value));
+
+		rest = after_value.trim_start();
+		if let Some(after_comma) = rest.strip_prefix(',') {
+			rest = after_comma.trim_start();
+			if rest.is_empty() {
+				return None;
+			}
+		} else if !rest.is_empty() {
+			return None;
+		}
+	}
+	Some(fields)
+}
+
+/// Parse a value at the start of the input, and return it along with the rest of the input.
+fn parse_value(input: &str) -> Option<(Value, &str)> {
+	if input.starts_with('"') {
+		let (string, rest) = parse_string(input)?;
+		return Some((Value::String(string), rest));
+	}
+	if let Some(mut rest) = input.strip_prefix('[') {
+		let mut strings = Vec::new();
+		loop {
+			rest = rest.trim_start();
+			if let Some(after) = rest.strip_prefix(']') {
+				return Some((Value::Strings(strings), after));
+			}
+			if !strings.is_empty() {
+				rest = rest.strip_prefix(',')?.trim_start();
+			}
+			let (string, after) = parse_string(rest)?;
+			strings.push(string);
+			rest = after;
+		}
+	}
+	let digits = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+	let number = input[..digits].parse().ok()?;
+	Some((Value::Number(number), &input[digits..]))
+}
+
+/// Parse a string without escapes at the start of the input, and return it along with the rest
+/// of the input.
+fn parse_string(input: &str) -> Option<(String, &str)> {
+	let body = input.strip_prefix('"')?;
+	let end = body.find('"')?;
+	let string = &body[..end];
+	if string.contains('\\') {
+		return None;
+	}
+	Some((string.to_string(), &body[end + 1..]))
+}
+
+#[cfg(test)]
+use super::test_support::sealed_child;
+
+#[cfg(test)]
+fn aura_spec() -> ChainSpec {
+	ChainSpec {
+		genesis_state: 100,
+		genesis_timestamp: 1_700_000_000_000,
+		engine: EngineSpec::Aura { slot_duration: 6000 },
+		authorities: vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob],
+		difficulty: u64::MAX / 100,
+	}
+}
+
+#[test]
+fn cs_chain_spec_round_trips_through_json() {
+	for spec in [
+		aura_spec(),
+		ChainSpec { engine: EngineSpec::Pow, ..aura_spec() },
+		ChainSpec { engine: EngineSpec::PoaRoundRobin, authorities: vec![], ..aura_spec() },
+	] {
+		assert_eq!(ChainSpec::from_json(&spec.to_json()), Ok(spec));
+	}
+}
+
+#[test]
+fn cs_chain_spec_loaded_copy_builds_identical_chain() {
+	let spec = ChainSpec { engine: EngineSpec::PoaRoundRobin, ..aura_spec() };
+	let loaded = ChainSpec::from_json(&spec.to_json()).unwrap();
+
+	let engine = spec.build();
+	let loaded_engine = loaded.build();
+
+	let genesis_header = engine.genesis_header();
+	assert_eq!(genesis_header.state_root, 100);
+	assert_eq!(genesis_header, loaded_engine.genesis_header());
+	let child = sealed_child(&*engine, &genesis_header, 1);
+	assert_eq!(child, sealed_child(&*loaded_engine, &genesis_header, 1));
+	assert_eq!(loaded_engine.check_sub_chain(&genesis_header, &[child]), Ok(()));
+}
+
+#[test]
+fn cs_chain_spec_rejects_bad_json() {
+	assert_eq!(ChainSpec::from_json("not json"), Err(SpecError::Malformed));
+	assert_eq!(ChainSpec::from_json("{\"engine\": \"pow\",}"), Err(SpecError::Malformed));
+
+	let json = aura_spec().to_json();
+	assert_eq!(
+		ChainSpec::from_json(&json.replace("aura", "babe")),
+		Err(SpecError::UnknownEngine("babe".into()))
+	);
+	assert_eq!(
+		ChainSpec::from_json(&json.replace("Bob", "Mallory")),
+		Err(SpecError::UnknownAuthority("Mallory".into()))
+	);
+	assert_eq!(
+		ChainSpec::from_json(&json.replace("\"difficulty\"", "\"threshold\"")),
+		Err(SpecError::MissingField("difficulty"))
+	);
+}
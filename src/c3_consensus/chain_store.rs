@@ -0,0 +1,770 @@
+//! So far we have validated chains that were handed to us as complete slices. A real node instead
+//! learns about blocks one at a time, often from many different peers, and must remember all of
+//! them, including those on forks, in order to decide which chain is best. The `ChainStore` is
+//! that memory.
+//!
+//! The store only cares about how headers are linked together. It is the caller's job to check
+//! the headers with a consensus engine before importing them.
+
+use std::{
+	cmp::Reverse,
+	collections::{HashMap, HashSet, VecDeque},
+	rc::Rc,
+};
+
+use super::{bloom::BloomFilter, Header};
+use crate::{block_work, canonical_key, hash, ChainError};
+
+type Hash = u64;
+
+/// The size of the filter of headers known to be bad. With 4 hashes, a thousand bad headers give a
+/// false positive rate of about 2%.
+const KNOWN_BAD_BITS: usize = 8192;
+const KNOWN_BAD_HASHES: u32 = 4;
+
+/// How many rejected headers are remembered exactly, along with why they were rejected. Beyond
+/// that the oldest are forgotten, and are checked again if they ever come back.
+const MAX_REJECTED: usize = 1024;
+
+/// What happened to the canonical head when a header was imported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportOutcome {
+	/// The header extended the canonical head, and is now the head itself.
+	NewHead,
+	/// The header did not become the head. It is on a fork that ranks below the canonical chain,
+	/// or it was already known.
+	SideChain,
+	/// The header became the head by way of a fork, so `depth` blocks of the previous canonical
+	/// chain are no longer canonical.
+	Reorg { depth: u64 },
+}
+
+/// A batch of received headers, sorted by how they relate to the canonical head.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Classification<D> {
+	/// New headers that descend from the canonical head through other headers in the batch. Each
+	/// header comes after its parent, so they can be imported in order.
+	pub connected_chain: Vec<Header<D>>,
+	/// New headers that can not be connected to the head, usually because their parent has not
+	/// been received yet.
+	pub orphans: Vec<Header<D>>,
+	/// Headers that are already in the store, or that appeared earlier in the batch.
+	pub duplicates: Vec<Header<D>>,
+}
+
+/// A callback told about each reorg of a `ChainStore`. It receives the headers that are no longer
+/// canonical, and then those that became canonical in their place, both in ascending height order.
+pub type ReorgSubscriber<D> = Rc<dyn Fn(&[Header<D>], &[Header<D>])>;
+
+/// All the headers a node knows about, organized as a tree rooted at genesis.
+///
+/// The canonical head is the tip of the best chain according to `canonical_cmp`, so nodes that know
+/// the same headers agree on the head no matter the order they imported them in.
+#[derive(Clone)]
+pub struct ChainStore<D> {
+	headers: HashMap<Hash, Header<D>>,
+	total_work: HashMap<Hash, u128>,
+	genesis: Hash,
+	head: Hash,
+	reorg_subscribers: Vec<ReorgSubscriber<D>>,
+	known_bad: BloomFilter,
+	rejected: HashMap<Hash, ChainError>,
+	rejected_order: VecDeque<Hash>,
+}
+
+impl<D: std::fmt::Debug> std::fmt::Debug for ChainStore<D> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ChainStore")
+			.field("headers", &self.headers)
+			.field("total_work", &self.total_work)
+			.field("genesis", &self.genesis)
+			.field("head", &self.head)
+			.field("reorg_subscribers", &self.reorg_subscribers.len())
+			.field("rejected", &self.rejected)
+			.finish()
+	}
+}
+
+impl<D: Clone + Eq + std::hash::Hash> ChainStore<D> {
+	/// Create a store that knows only the given genesis header.
+	pub fn new(genesis: Header<D>) -> Self {
+		let genesis_hash = hash(&genesis);
+		ChainStore {
+			headers: HashMap::from([(genesis_hash, genesis)]),
+			total_work: HashMap::from([(genesis_hash, 0)]),
+			genesis: genesis_hash,
+			head: genesis_hash,
+			reorg_subscribers: Vec::new(),
+			known_bad: BloomFilter::new(KNOWN_BAD_BITS, KNOWN_BAD_HASHES),
+			rejected: HashMap::new(),
+			rejected_order: VecDeque::new(),
+		}
+	}
+
+	/// Register a callback to be told about every future reorg, for example so that a wallet can
+	/// revert the transactions it saw in blocks that are no longer canonical. Subscribers are
+	/// called in the order they were registered. Cloning the store shares its subscribers.
+	pub fn on_reorg(&mut self, subscriber: impl Fn(&[Header<D>], &[Header<D>]) + 'static) {
+		self.reorg_subscribers.push(Rc::new(subscriber));
+	}
+
+	/// The hash of the genesis header.
+	pub fn genesis(&self) -> Hash {
+		self.genesis
+	}
+
+	/// The hash of the canonical head.
+	pub fn head(&self) -> Hash {
+		self.head
+	}
+
+	/// The header with the given hash, if it is known.
+	pub fn get(&self, hash: Hash) -> Option<&Header<D>> {
+		self.headers.get(&hash)
+	}
+
+	/// Whether the header with the given hash is known.
+	pub fn contains(&self, hash: Hash) -> bool {
+		self.headers.contains_key(&hash)
+	}
+
+	/// The number of known headers, including genesis.
+	pub fn len(&self) -> usize {
+		self.headers.len()
+	}
+
+	/// The total work of the chain from genesis up to and including the given header. Genesis
+	/// itself is not credited with any work.
+	pub fn total_work(&self, hash: Hash) -> Option<u128> {
+		self.total_work.get(&hash).copied()
+	}
+
+	/// Add a header to the store, and make it the canonical head if its chain now ranks highest.
+	/// The outcome tells the caller whether the head changed, for example so that a network layer
+	/// only gossips new heads.
+	///
+	/// The header's parent must already be known. Importing a known header again is harmless.
+	/// A header that was rejected before, or marked bad, is rejected again for the same reason
+	/// without being checked.
+	pub fn import(&mut self, header: Header<D>) -> Result<ImportOutcome, ChainError> {
+		let header_hash = hash(&header);
+		if self.contains(header_hash) {
+			return Ok(ImportOutcome::SideChain);
+		}
+		// The filter clears most headers without a lookup. A positive may be false, so it is only
+		// trusted once the exact record confirms it.
+		if self.known_bad.might_contain(header_hash) {
+			if let Some(error) = self.rejected.get(&header_hash) {
+				return Err(*error);
+			}
+		}
+
+		// An unknown parent may still arrive, so that is not held against the header.
+		let parent = self.get(header.parent).ok_or(ChainError::UnknownParent)?;
+		if let Err(error) = header.check_child_of(parent) {
+			self.mark_bad(header_hash, error);
+			return Err(error);
+		}
+
+		let parent_hash = header.parent;
+		let total_work = self.total_work[&parent_hash] + block_work(&header);
+		self.headers.insert(header_hash, header);
+		self.total_work.insert(header_hash, total_work);
+
+		if self.canonical_key(header_hash) <= self.canonical_key(self.head) {
+			return Ok(ImportOutcome::SideChain);
+		}
+		let old_head = std::mem::replace(&mut self.head, header_hash);
+		if parent_hash == old_head {
+			return Ok(ImportOutcome::NewHead);
+		}
+		let ancestor = self.common_ancestor(old_head, parent_hash);
+		let depth = self.headers[&old_head].height - self.headers[&ancestor].height;
+		if !self.reorg_subscribers.is_empty() {
+			let retracted = self.branch(ancestor, old_head);
+			let enacted = self.branch(ancestor, header_hash);
+			self.reorg_subscribers
+				.iter()
+				.for_each(|subscriber| subscriber(&retracted, &enacted));
+		}
+		Ok(ImportOutcome::Reorg { depth })
+	}
+
+	/// Remember that the header with the given hash is invalid, for example because a consensus
+	/// engine rejected it, so that a peer re-sending it can be turned away cheaply.
+	///
+	/// Only the most recent `MAX_REJECTED` headers are remembered, so a peer can not grow the
+	/// store without bound by sending invalid headers.
+	pub fn mark_bad(&mut self, hash: Hash, error: ChainError) {
+		self.known_bad.insert(hash);
+		if self.rejected.insert(hash, error).is_some() {
+			return;
+		}
+		self.rejected_order.push_back(hash);
+		if self.rejected_order.len() > MAX_REJECTED {
+			let oldest = self.rejected_order.pop_front().expect("the queue is not empty");
+			self.rejected.remove(&oldest);
+		}
+	}
+
+	/// The key `canonical_cmp` would rank the chain ending at the given known header by.
+	fn canonical_key(&self, tip: Hash) -> (u64, u128, Reverse<Hash>) {
+		canonical_key(self.headers[&tip].height, self.total_work[&tip], tip)
+	}
+
+	/// The headers after `ancestor` up to and including `tip`, in ascending height order.
+	/// `ancestor` must be an ancestor of `tip`.
+	fn branch(&self, ancestor: Hash, mut tip: Hash) -> Vec<Header<D>> {
+		let mut headers = Vec::new();
+		while tip != ancestor {
+			let header = &self.headers[&tip];
+			headers.push(header.clone());
+			tip = header.parent;
+		}
+		headers.reverse();
+		headers
+	}
+
+	/// The most recent header that both given headers descend from. Both must be known.
+	fn common_ancestor(&self, mut a: Hash, mut b: Hash) -> Hash {
+		while a != b {
+			if self.headers[&a].height >= self.headers[&b].height {
+				a = self.headers[&a].parent;
+			} else {
+				b = self.headers[&b].parent;
+			}
+		}
+		a
+	}
+
+	/// Sort an unordered batch of headers, such as those gathered from several peers, into those
+	/// that extend the canonical head, orphans, and duplicates.
+	///
+	/// Nothing is imported, and the headers are not checked beyond how they link together.
+	pub fn classify_headers(&self, headers: Vec<Header<D>>) -> Classification<D> {
+		let mut seen = HashSet::new();
+		let mut duplicates = Vec::new();
+		let mut fresh = Vec::new();
+		for header in headers {
+			let header_hash = hash(&header);
+			if self.contains(header_hash) || !seen.insert(header_hash) {
+				duplicates.push(header);
+			} else {
+				fresh.push((header_hash, header));
+			}
+		}
+
+		// Walk outwards from the head, one generation at a time.
+		let mut connected = HashSet::new();
+		let mut frontier = VecDeque::from([self.head]);
+		while let Some(parent) = frontier.pop_front() {
+			for (header_hash, header) in &fresh {
+				if header.parent == parent && connected.insert(*header_hash) {
+					frontier.push_back(*header_hash);
+				}
+			}
+		}
+
+		let mut connected_chain = Vec::new();
+		let mut orphans = Vec::new();
+		for (header_hash, header) in fresh {
+			if connected.contains(&header_hash) {
+				connected_chain.push(header);
+			} else {
+				orphans.push(header);
+			}
+		}
+		connected_chain.sort_by_key(|header| header.height);
+
+		Classification { connected_chain, orphans, duplicates }
+	}
+
+	/// The hashes of all headers that have no known children, in ascending order.
+	pub fn leaves(&self) -> Vec<Hash> {
+		let mut leaves: Vec<Hash> = self
+			.headers
+			.keys()
+			.filter(|h| !self.headers.values().any(|header| header.parent == **h))
+			.copied()
+			.collect();
+		leaves.sort_unstable();
+		leaves
+	}
+
+	/// Whether `ancestor` is `descendant` itself, or one of its ancestors.
+	pub fn is_ancestor(&self, ancestor: Hash, descendant: Hash) -> bool {
+		let Some(target) = self.get(ancestor) else { return false };
+
+		let mut current = descendant;
+		while let Some(header) = self.get(current) {
+			if current == ancestor {
+				return true;
+			}
+			if header.height <= target.height {
+				return false;
+			}
+			current = header.parent;
+		}
+		false
+	}
+
+	/// Whether the header with the given hash is on the canonical chain, that is, whether it is
+	/// the canonical head or one of its ancestors. Headers on forks, including those that were
+	/// canonical before a reorg, are not canonical.
+	pub fn is_canonical(&self, hash: Hash) -> bool {
+		self.is_ancestor(hash, self.head)
+	}
+
+	/// The canonical chain, from genesis up to and including the canonical head.
+	pub fn canonical_chain(&self) -> Vec<Header<D>> {
+		self.chain_to(self.head)
+	}
+
+	/// The chain from genesis up to and including the highest known header, whether or not it is
+	/// canonical. Chains of equal height are ranked by `canonical_cmp`.
+	pub fn longest_chain(&self) -> Vec<Header<D>> {
+		self.best_chain_by(|store, leaf| store.headers[&leaf].height)
+	}
+
+	/// The chain from genesis up to and including the known header with the most accumulated work,
+	/// whether or not it is canonical. Chains with equal work are ranked by `canonical_cmp`.
+	pub fn heaviest_chain(&self) -> Vec<Header<D>> {
+		self.best_chain_by(|store, leaf| store.total_work[&leaf])
+	}
+
+	/// The chain ending at the leaf that ranks highest by the given measure, with ties broken by
+	/// `canonical_cmp`.
+	fn best_chain_by<K: Ord>(&self, measure: impl Fn(&Self, Hash) -> K) -> Vec<Header<D>> {
+		let tip = self
+			.leaves()
+			.into_iter()
+			.max_by_key(|leaf| (measure(self, *leaf), self.canonical_key(*leaf)))
+			.expect("genesis is always known");
+		self.chain_to(tip)
+	}
+
+	/// The chain from genesis up to and including the given known header.
+	fn chain_to(&self, tip: Hash) -> Vec<Header<D>> {
+		let mut chain = self.branch(self.genesis, tip);
+		chain.insert(0, self.headers[&self.genesis].clone());
+		chain
+	}
+
+	/// The canonical headers from `from` up to and including `to`, in ascending height order.
+	///
+	/// This is what a node serves to a peer that is syncing headers. Returns `None` unless both
+	/// headers are canonical and `from` is an ancestor of `to`, so a reversed range, or one that
+	/// reaches onto a fork, gives nothing.
+	pub fn headers_between(&self, from: Hash, to: Hash) -> Option<Vec<Header<D>>> {
+		if !self.is_canonical(to) || !self.is_ancestor(from, to) {
+			return None;
+		}
+
+		let mut headers = Vec::new();
+		let mut current = to;
+		loop {
+			let header = self.get(current)?;
+			headers.push(header.clone());
+			if current == from {
+				break;
+			}
+			current = header.parent;
+		}
+		headers.reverse();
+		Some(headers)
+	}
+}
+
+/// Choose the best head that does not conflict with the finalized block.
+///
+/// Finalized blocks can never be reverted, so any leaf that does not descend from the finalized
+/// block is ruled out no matter how it ranks. Among the remaining leaves, the best according to
+/// `canonical_cmp` wins. This is the finality-bounded fork choice that hybrid PoW and finality
+/// gadget chains use in production.
+///
+/// Returns `None` if the finalized block is not in the store.
+pub fn best_head<D: Clone + Eq + std::hash::Hash>(
+	store: &ChainStore<D>,
+	finalized: Hash,
+) -> Option<Hash> {
+	if !store.contains(finalized) {
+		return None;
+	}
+
+	store
+		.leaves()
+		.into_iter()
+		.filter(|leaf| store.is_ancestor(finalized, *leaf))
+		.max_by_key(|leaf| store.canonical_key(*leaf))
+}
+
+#[cfg(test)]
+use crate::canonical_cmp;
+#[cfg(test)]
+use std::cmp::Ordering;
+
+#[cfg(test)]
+fn mined_child(parent: &Header<u64>, extrinsic: u64, threshold: u64) -> Header<u64> {
+	let mut header = Header {
+		parent: hash(parent),
+		height: parent.height + 1,
+		extrinsics_root: extrinsic,
+		state_root: parent.state_root + extrinsic,
+		consensus_digest: 0,
+	};
+	while !crate::meets_threshold(&header, threshold) {
+		header.consensus_digest += 1;
+	}
+	header
+}
+
+/// Build a store with two forks from genesis. The first is short and lightly mined. The second
+/// is longer and mined much harder, so it has far more work.
+#[cfg(test)]
+fn forked_store() -> (ChainStore<u64>, Vec<Header<u64>>, Vec<Header<u64>>) {
+	let genesis =
+		Header { parent: 0, height: 0, extrinsics_root: 0, state_root: 0, consensus_digest: 0 };
+	let mut store = ChainStore::new(genesis.clone());
+
+	let mut light = vec![mined_child(&genesis, 1, u64::MAX / 2)];
+	light.push(mined_child(&light[0], 1, u64::MAX / 2));
+
+	let mut heavy = vec![mined_child(&genesis, 2, u64::MAX / 1000)];
+	heavy.push(mined_child(&heavy[0], 2, u64::MAX / 1000));
+	heavy.push(mined_child(&heavy[1], 2, u64::MAX / 1000));
+
+	for header in light.iter().chain(heavy.iter()) {
+		store.import(header.clone()).unwrap();
+	}
+	(store, light, heavy)
+}
+
+#[test]
+fn cs_store_head_follows_canonical_order() {
+	let (store, _, heavy) = forked_store();
+
+	assert_eq!(store.head(), hash(&heavy[2]));
+	assert_eq!(store.leaves().len(), 2);
+}
+
+#[test]
+fn cs_store_rejects_unknown_parent() {
+	let (mut store, light, heavy) = forked_store();
+	let mut orphan = light[1].clone();
+	orphan.parent = 12345;
+
+	assert_eq!(store.import(orphan), Err(ChainError::UnknownParent));
+	assert_eq!(store.len(), 1 + light.len() + heavy.len());
+}
+
+#[test]
+fn cs_store_fast_rejects_known_bad_headers() {
+	let (mut store, light, heavy) = forked_store();
+	let mut skipped = mined_child(&light[1], 3, u64::MAX / 2);
+	skipped.height += 1;
+
+	assert_eq!(store.import(skipped.clone()), Err(ChainError::WrongHeight));
+	assert_eq!(store.import(skipped), Err(ChainError::WrongHeight));
+
+	// A header marked bad by the caller is turned away without any checks at all.
+	let side = mined_child(&heavy[0], 3, u64::MAX / 2);
+	store.mark_bad(hash(&side), ChainError::InsufficientWork);
+	assert_eq!(store.import(side.clone()), Err(ChainError::InsufficientWork));
+	assert!(!store.contains(hash(&side)));
+}
+
+#[test]
+fn cs_store_bloom_false_positive_still_imports() {
+	let (mut store, light, _) = forked_store();
+	// A single bit is set by any insertion, so every header is a positive from now on.
+	store.known_bad = BloomFilter::new(1, 1);
+	store.mark_bad(12345, ChainError::WrongHeight);
+
+	let valid = mined_child(&light[1], 3, u64::MAX / 2);
+	assert!(store.known_bad.might_contain(hash(&valid)));
+	assert_eq!(store.import(valid.clone()), Ok(ImportOutcome::SideChain));
+	assert!(store.contains(hash(&valid)));
+}
+
+#[test]
+fn cs_store_forgets_oldest_rejections() {
+	let (mut store, light, _) = forked_store();
+	let first = mined_child(&light[1], 3, u64::MAX / 2);
+	store.mark_bad(hash(&first), ChainError::InsufficientWork);
+	for bad in 0..MAX_REJECTED as u64 {
+		store.mark_bad(bad, ChainError::WrongHeight);
+	}
+	assert_eq!(store.rejected.len(), MAX_REJECTED);
+
+	// The oldest rejection was forgotten, so the header is checked again, and is fine.
+	assert_eq!(store.import(first.clone()), Ok(ImportOutcome::SideChain));
+	assert!(store.contains(hash(&first)));
+	assert_eq!(store.rejected.get(&0), Some(&ChainError::WrongHeight));
+}
+
+#[test]
+fn cs_store_import_extending_head() {
+	let (_, _, heavy) = forked_store();
+	let genesis =
+		Header { parent: 0, height: 0, extrinsics_root: 0, state_root: 0, consensus_digest: 0 };
+	let mut store = ChainStore::new(genesis);
+
+	assert_eq!(store.len(), 1);
+
+	for header in &heavy {
+		assert_eq!(store.import(header.clone()), Ok(ImportOutcome::NewHead));
+	}
+	assert_eq!(store.head(), hash(&heavy[2]));
+	assert_eq!(store.len(), 1 + heavy.len());
+}
+
+#[test]
+fn cs_store_import_onto_shorter_fork() {
+	let (mut store, light, heavy) = forked_store();
+	let side = mined_child(&light[1], 3, u64::MAX / 2);
+
+	assert_eq!(store.import(side), Ok(ImportOutcome::SideChain));
+	assert_eq!(store.import(light[0].clone()), Ok(ImportOutcome::SideChain));
+	assert_eq!(store.head(), hash(&heavy[2]));
+}
+
+#[test]
+fn cs_store_import_reorg() {
+	let (_, light, heavy) = forked_store();
+	let genesis =
+		Header { parent: 0, height: 0, extrinsics_root: 0, state_root: 0, consensus_digest: 0 };
+	let mut store = ChainStore::new(genesis);
+
+	for header in &light {
+		store.import(header.clone()).unwrap();
+	}
+
+	// The heavy fork takes over once it is as high as the light one, because it has more work.
+	assert_eq!(store.import(heavy[0].clone()), Ok(ImportOutcome::SideChain));
+	assert_eq!(store.import(heavy[1].clone()), Ok(ImportOutcome::Reorg { depth: 2 }));
+	assert_eq!(store.import(heavy[2].clone()), Ok(ImportOutcome::NewHead));
+}
+
+#[test]
+fn cs_store_on_reorg_notifies_subscribers() {
+	use std::cell::RefCell;
+
+	let (_, light, heavy) = forked_store();
+	let genesis =
+		Header { parent: 0, height: 0, extrinsics_root: 0, state_root: 0, consensus_digest: 0 };
+	let mut store = ChainStore::new(genesis);
+
+	let first = Rc::new(RefCell::new(Vec::new()));
+	let second = Rc::new(RefCell::new(0));
+	let log = first.clone();
+	store.on_reorg(move |retracted, enacted| {
+		log.borrow_mut().push((retracted.to_vec(), enacted.to_vec()))
+	});
+	let count = second.clone();
+	store.on_reorg(move |_, _| *count.borrow_mut() += 1);
+
+	// Extending the head or a side chain is not a reorg.
+	for header in &light {
+		store.import(header.clone()).unwrap();
+	}
+	assert!(first.borrow().is_empty());
+
+	store.import(heavy[0].clone()).unwrap();
+	assert_eq!(store.import(heavy[1].clone()), Ok(ImportOutcome::Reorg { depth: 2 }));
+	store.import(heavy[2].clone()).unwrap();
+	assert_eq!(*first.borrow(), vec![(light.clone(), heavy[..2].to_vec())]);
+	assert_eq!(*second.borrow(), 1);
+}
+
+#[test]
+fn cs_store_is_canonical_follows_reorg() {
+	let (_, light, heavy) = forked_store();
+	let genesis =
+		Header { parent: 0, height: 0, extrinsics_root: 0, state_root: 0, consensus_digest: 0 };
+	let mut store = ChainStore::new(genesis.clone());
+
+	for header in &light {
+		store.import(header.clone()).unwrap();
+	}
+	assert!(store.is_canonical(hash(&genesis)));
+	assert!(light.iter().all(|header| store.is_canonical(hash(header))));
+	assert!(!store.is_canonical(hash(&heavy[0])));
+
+	for header in &heavy {
+		store.import(header.clone()).unwrap();
+	}
+	assert_eq!(store.head(), hash(&heavy[2]));
+	assert!(store.is_canonical(hash(&genesis)));
+	assert!(heavy.iter().all(|header| store.is_canonical(hash(header))));
+	assert!(light.iter().all(|header| !store.is_canonical(hash(header))));
+	assert!(!store.is_canonical(12345));
+}
+
+#[test]
+fn cs_store_headers_between_canonical_range() {
+	let (store, _, heavy) = forked_store();
+	let genesis = store.get(store.genesis()).unwrap().clone();
+
+	assert_eq!(
+		store.headers_between(store.genesis(), hash(&heavy[2])),
+		Some(vec![genesis, heavy[0].clone(), heavy[1].clone(), heavy[2].clone()])
+	);
+	assert_eq!(
+		store.headers_between(hash(&heavy[1]), hash(&heavy[1])),
+		Some(vec![heavy[1].clone()])
+	);
+}
+
+#[test]
+fn cs_store_headers_between_reversed_range() {
+	let (store, _, heavy) = forked_store();
+
+	assert_eq!(store.headers_between(hash(&heavy[2]), hash(&heavy[0])), None);
+}
+
+#[test]
+fn cs_store_headers_between_across_fork() {
+	let (store, light, heavy) = forked_store();
+
+	assert_eq!(store.headers_between(store.genesis(), hash(&light[1])), None);
+	assert_eq!(store.headers_between(hash(&light[0]), hash(&heavy[2])), None);
+}
+
+#[test]
+fn cs_store_canonical_chain_follows_the_highest_tip() {
+	let (mut store, mut light, heavy) = forked_store();
+	let genesis = store.get(store.genesis()).unwrap().clone();
+
+	assert_eq!(store.canonical_chain(), [vec![genesis.clone()], heavy].concat());
+
+	// The light fork grows higher than the heavy one, so it wins despite having far less work.
+	for _ in 0..2 {
+		let child = mined_child(light.last().unwrap(), 1, u64::MAX / 2);
+		store.import(child.clone()).unwrap();
+		light.push(child);
+	}
+	assert_eq!(store.head(), hash(light.last().unwrap()));
+	assert_eq!(store.canonical_chain(), [vec![genesis], light].concat());
+}
+
+#[test]
+fn cs_store_longest_and_heaviest_chains() {
+	let (mut store, mut light, heavy) = forked_store();
+	let genesis = store.get(store.genesis()).unwrap().clone();
+
+	assert_eq!(store.heaviest_chain(), [vec![genesis.clone()], heavy.clone()].concat());
+	assert_eq!(store.longest_chain(), store.heaviest_chain());
+
+	// The light fork grows longer than the heavy one, but still has far less work.
+	for _ in 0..2 {
+		let child = mined_child(light.last().unwrap(), 1, u64::MAX / 2);
+		store.import(child.clone()).unwrap();
+		light.push(child);
+	}
+	assert_eq!(store.heaviest_chain(), [vec![genesis.clone()], heavy].concat());
+	assert_eq!(store.longest_chain(), [vec![genesis], light].concat());
+}
+
+#[test]
+fn cs_store_classify_headers_sorts_out_duplicates_and_orphans() {
+	let (store, _, heavy) = forked_store();
+	let c1 = mined_child(&heavy[2], 1, u64::MAX / 2);
+	let c2 = mined_child(&c1, 1, u64::MAX / 2);
+	let mut unknown = c1.clone();
+	unknown.extrinsics_root = 99;
+	let orphan = mined_child(&unknown, 1, u64::MAX / 2);
+
+	let classification = store.classify_headers(vec![
+		c2.clone(),
+		orphan.clone(),
+		c1.clone(),
+		heavy[1].clone(),
+		c1.clone(),
+	]);
+
+	assert_eq!(classification.connected_chain, vec![c1.clone(), c2]);
+	assert_eq!(classification.orphans, vec![orphan]);
+	assert_eq!(classification.duplicates, vec![heavy[1].clone(), c1]);
+}
+
+#[test]
+fn cs_store_classify_headers_fork_is_not_connected() {
+	let (store, light, _) = forked_store();
+	let side = mined_child(&light[1], 1, u64::MAX / 2);
+
+	let classification = store.classify_headers(vec![side.clone()]);
+
+	assert!(classification.connected_chain.is_empty());
+	assert_eq!(classification.orphans, vec![side]);
+	assert!(classification.duplicates.is_empty());
+}
+
+#[test]
+fn cs_best_head_selects_best_compatible_with_finality() {
+	let (store, _, heavy) = forked_store();
+
+	assert_eq!(best_head(&store, store.genesis()), Some(hash(&heavy[2])));
+	assert_eq!(best_head(&store, hash(&heavy[0])), Some(hash(&heavy[2])));
+}
+
+#[test]
+fn cs_best_head_never_conflicts_with_finality() {
+	let (store, light, heavy) = forked_store();
+	assert!(store.total_work(hash(&heavy[2])) > store.total_work(hash(&light[1])));
+
+	// The heavy fork conflicts with the finalized block, so the light fork must win.
+	assert_eq!(best_head(&store, hash(&light[0])), Some(hash(&light[1])));
+}
+
+#[test]
+fn cs_best_head_unknown_finalized_block() {
+	let (store, _, _) = forked_store();
+
+	assert_eq!(best_head(&store, 12345), None);
+}
+
+/// Children of the given parent whose hashes are in the upper half of the hash space, so that each
+/// of them is credited with exactly one unit of work.
+#[cfg(test)]
+fn equal_work_children(parent: &Header<u64>, count: usize) -> Vec<Header<u64>> {
+	(1..)
+		.map(|extrinsic| mined_child(parent, extrinsic, u64::MAX))
+		.filter(|child| block_work(child) == 1)
+		.take(count)
+		.collect()
+}
+
+#[test]
+fn cs_canonical_cmp_ranks_height_then_work_then_lowest_hash() {
+	let (store, light, heavy) = forked_store();
+	let genesis = store.get(store.genesis()).unwrap();
+	let ties = equal_work_children(genesis, 2);
+	let (low, high) = if hash(&ties[0]) < hash(&ties[1]) { (0, 1) } else { (1, 0) };
+
+	assert_eq!(canonical_cmp(&light, &heavy[..2]), Ordering::Less);
+	assert_eq!(canonical_cmp(&light, &heavy), Ordering::Less);
+	assert_eq!(canonical_cmp(&[ties[low].clone()], &[ties[high].clone()]), Ordering::Greater);
+	assert_eq!(canonical_cmp::<Header<u64>>(&[], &light), Ordering::Less);
+}
+
+#[test]
+fn cs_store_head_is_independent_of_import_order() {
+	let genesis =
+		Header { parent: 0, height: 0, extrinsics_root: 0, state_root: 0, consensus_digest: 0 };
+	let forks = equal_work_children(&genesis, 4);
+	let best = forks.iter().min_by_key(|child| hash(*child)).unwrap();
+
+	let mut forward = ChainStore::new(genesis.clone());
+	let mut backward = ChainStore::new(genesis.clone());
+	for child in &forks {
+		forward.import(child.clone()).unwrap();
+	}
+	for child in forks.iter().rev() {
+		backward.import(child.clone()).unwrap();
+	}
+
+	assert_eq!(forward.head(), hash(best));
+	assert_eq!(backward.head(), hash(best));
+	assert_eq!(best_head(&forward, forward.genesis()), best_head(&backward, backward.genesis()));
+	assert_eq!(forward.canonical_chain(), backward.canonical_chain());
+}
@@ -0,0 +1,75 @@
+//! Some consensus engines, such as slot-based PoA, depend on the current time. Reading the system
+//! time directly would make those engines impossible to test deterministically, so time-dependent
+//! engines instead ask a `Clock` what time it is.
+
+use std::{
+	cell::Cell,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A source of the current time, in milliseconds since the Unix epoch.
+pub trait Clock {
+	/// The current time in milliseconds.
+	fn now(&self) -> u64;
+}
+
+/// Engines usually borrow their clock, so that the owner can keep using it too.
+impl<C: Clock + ?Sized> Clock for &C {
+	fn now(&self) -> u64 {
+		(**self).now()
+	}
+}
+
+/// The real wall clock. This is what a real node would use.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> u64 {
+		SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+	}
+}
+
+/// A clock that only moves when it is told to. This is what tests use.
+#[derive(Clone, Debug, Default)]
+pub struct MockClock {
+	now: Cell<u64>,
+}
+
+impl MockClock {
+	/// Create a mock clock starting at the given time.
+	pub fn new(now: u64) -> Self {
+		MockClock { now: Cell::new(now) }
+	}
+
+	/// Set the current time.
+	pub fn set(&self, now: u64) {
+		self.now.set(now);
+	}
+
+	/// Move the current time forward by the given number of milliseconds.
+	pub fn advance(&self, millis: u64) {
+		self.now.set(self.now.get().saturating_add(millis));
+	}
+}
+
+impl Clock for MockClock {
+	fn now(&self) -> u64 {
+		self.now.get()
+	}
+}
+
+#[test]
+fn cs_mock_clock_advances() {
+	let clock = MockClock::new(5);
+	clock.advance(10);
+	assert_eq!(clock.now(), 15);
+
+	clock.set(3);
+	assert_eq!((&clock).now(), 3);
+}
+
+#[test]
+fn cs_system_clock_is_after_epoch() {
+	assert!(SystemClock.now() > 0);
+}
@@ -0,0 +1,187 @@
+//! Nodes exchange headers over the network constantly, so the wire format matters. Rather than a
+//! self-describing format like JSON, we use a compact fixed layout. Every field is written in
+//! little-endian order, one after the other, followed by the consensus digest. A PoW header is
+//! exactly 40 bytes.
+
+use super::{p3_poa::SlotDigest, ConsensusAuthority, Header};
+
+/// The reasons a byte buffer can fail to decode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+	/// The buffer is not exactly the size of an encoded header.
+	WrongLength { expected: usize, actual: usize },
+	/// The bytes in the digest position are not a valid digest.
+	InvalidDigest,
+}
+
+/// A consensus digest with a fixed-size binary encoding.
+pub trait DigestCodec: Sized {
+	/// The number of bytes in the encoded digest.
+	const ENCODED_LEN: usize;
+
+	/// Append the encoded digest to the buffer.
+	fn encode_to(&self, out: &mut Vec<u8>);
+
+	/// Decode a digest from exactly `ENCODED_LEN` bytes.
+	fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+impl DigestCodec for () {
+	const ENCODED_LEN: usize = 0;
+
+	fn encode_to(&self, _: &mut Vec<u8>) {}
+
+	fn decode(_: &[u8]) -> Option<Self> {
+		Some(())
+	}
+}
+
+impl DigestCodec for u64 {
+	const ENCODED_LEN: usize = 8;
+
+	fn encode_to(&self, out: &mut Vec<u8>) {
+		out.extend_from_slice(&self.to_le_bytes());
+	}
+
+	fn decode(bytes: &[u8]) -> Option<Self> {
+		Some(u64::from_le_bytes(bytes.try_into().ok()?))
+	}
+}
+
+impl DigestCodec for ConsensusAuthority {
+	const ENCODED_LEN: usize = 1;
+
+	fn encode_to(&self, out: &mut Vec<u8>) {
+		out.push(match self {
+			ConsensusAuthority::Alice => 0,
+			ConsensusAuthority::Bob => 1,
+			ConsensusAuthority::Charlie => 2,
+		});
+	}
+
+	fn decode(bytes: &[u8]) -> Option<Self> {
+		match bytes {
+			[0] => Some(ConsensusAuthority::Alice),
+			[1] => Some(ConsensusAuthority::Bob),
+			[2] => Some(ConsensusAuthority::Charlie),
+			_ => None,
+		}
+	}
+}
+
+impl DigestCodec for SlotDigest {
+	const ENCODED_LEN: usize = u64::ENCODED_LEN + ConsensusAuthority::ENCODED_LEN;
+
+	fn encode_to(&self, out: &mut Vec<u8>) {
+		self.slot.encode_to(out);
+		self.signature.encode_to(out);
+	}
+
+	fn decode(bytes: &[u8]) -> Option<Self> {
+		let (slot, signature) = bytes.split_at_checked(u64::ENCODED_LEN)?;
+		Some(SlotDigest {
+			slot: u64::decode(slot)?,
+			signature: ConsensusAuthority::decode(signature)?,
+		})
+	}
+}
+
+impl<D: DigestCodec> Header<D> {
+	/// The number of bytes in an encoded header with this digest type.
+	pub const ENCODED_LEN: usize = 4 * 8 + D::ENCODED_LEN;
+
+	/// Encode the header in the compact binary wire format.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut out = Vec::with_capacity(Self::ENCODED_LEN);
+		for field in [self.parent, self.height, self.state_root, self.extrinsics_root] {
+			out.extend_from_slice(&field.to_le_bytes());
+		}
+		self.consensus_digest.encode_to(&mut out);
+		out
+	}
+
+	/// Decode a header from the compact binary wire format.
+	///
+	/// The buffer must be exactly the size of an encoded header, so truncated or padded input is
+	/// rejected rather than partially decoded.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+		if bytes.len() != Self::ENCODED_LEN {
+			return Err(DecodeError::WrongLength {
+				expected: Self::ENCODED_LEN,
+				actual: bytes.len(),
+			});
+		}
+
+		let field = |i: usize| u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap());
+		Ok(Header {
+			parent: field(0),
+			height: field(1),
+			state_root: field(2),
+			extrinsics_root: field(3),
+			consensus_digest: D::decode(&bytes[4 * 8..]).ok_or(DecodeError::InvalidDigest)?,
+		})
+	}
+}
+
+#[test]
+fn cs_codec_pow_header_round_trip() {
+	let header = Header {
+		parent: 0x0102_0304_0506_0708,
+		height: 7,
+		state_root: u64::MAX,
+		extrinsics_root: 42,
+		consensus_digest: 99u64,
+	};
+	let bytes = header.to_bytes();
+
+	assert_eq!(bytes.len(), 40);
+	assert_eq!(&bytes[..8], &[8, 7, 6, 5, 4, 3, 2, 1]);
+	assert_eq!(Header::from_bytes(&bytes), Ok(header));
+}
+
+#[test]
+fn cs_codec_slot_header_round_trip() {
+	let header = Header {
+		parent: 1,
+		height: 2,
+		state_root: 3,
+		extrinsics_root: 4,
+		consensus_digest: SlotDigest { slot: 5, signature: ConsensusAuthority::Charlie },
+	};
+
+	assert_eq!(Header::from_bytes(&header.to_bytes()), Ok(header));
+}
+
+#[test]
+fn cs_codec_truncated_buffer_errors() {
+	let header =
+		Header { parent: 1, height: 2, state_root: 3, extrinsics_root: 4, consensus_digest: 5u64 };
+	let bytes = header.to_bytes();
+
+	assert_eq!(
+		Header::<u64>::from_bytes(&bytes[..39]),
+		Err(DecodeError::WrongLength { expected: 40, actual: 39 })
+	);
+	assert!(Header::<u64>::from_bytes(&[]).is_err());
+}
+
+#[test]
+fn cs_codec_invalid_authority_errors() {
+	let header = Header {
+		parent: 1,
+		height: 2,
+		state_root: 3,
+		extrinsics_root: 4,
+		consensus_digest: ConsensusAuthority::Alice,
+	};
+	let mut bytes = header.to_bytes();
+	*bytes.last_mut().unwrap() = 9;
+
+	assert_eq!(Header::<ConsensusAuthority>::from_bytes(&bytes), Err(DecodeError::InvalidDigest));
+}
+
+#[test]
+fn cs_codec_short_slot_digest_is_rejected() {
+	assert_eq!(SlotDigest::decode(&[]), None);
+	assert_eq!(SlotDigest::decode(&[5, 0, 0]), None);
+}
@@ -0,0 +1,398 @@
+//! Each engine in this chapter has its own constructor, and its own digest type. That is fine when
+//! the engine is fixed at compile time, but applications often want to choose their consensus
+//! from configuration at runtime.
+//!
+//! `ConsensusConfig` is one entry point for all of them. It describes an engine, possibly composed
+//! of other engines, and builds it into a boxed engine. Boxed engines must share a digest type, so
+//! every configured engine uses `ConfiguredDigest`, which can hold the digest of any of the
+//! underlying engines.
+
+use super::{
+	clock::SystemClock,
+	p1_pow::PoW,
+	p3_poa::{Aura, PoaRoundRobinByHeight, SlotDigest},
+	p4_even_only::EvenOnly,
+	p6_forking::Forked,
+	Consensus, ConsensusAuthority, ConsensusExt, EngineKind, Header,
+};
+use crate::ChainError;
+
+/// The digest of any engine built from a `ConsensusConfig`.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConfiguredDigest {
+	/// The nonce of a Proof of Work engine.
+	Work(u64),
+	/// The signer of a round robin Proof of Authority engine.
+	Authority(ConsensusAuthority),
+	/// The slot and signer of an Aura engine.
+	Slot(SlotDigest),
+}
+
+/// A digest that can be stored in, and recovered from, a `ConfiguredDigest`.
+trait DigestVariant: Sized {
+	fn wrap(self) -> ConfiguredDigest;
+	fn unwrap(digest: &ConfiguredDigest) -> Option<&Self>;
+}
+
+impl DigestVariant for u64 {
+	fn wrap(self) -> ConfiguredDigest {
+		ConfiguredDigest::Work(self)
+	}
+
+	fn unwrap(digest: &ConfiguredDigest) -> Option<&Self> {
+		match digest {
+			ConfiguredDigest::Work(nonce) => Some(nonce),
+			_ => None,
+		}
+	}
+}
+
+impl DigestVariant for ConsensusAuthority {
+	fn wrap(self) -> ConfiguredDigest {
+		ConfiguredDigest::Authority(self)
+	}
+
+	fn unwrap(digest: &ConfiguredDigest) -> Option<&Self> {
+		match digest {
+			ConfiguredDigest::Authority(authority) => Some(authority),
+			_ => None,
+		}
+	}
+}
+
+impl DigestVariant for SlotDigest {
+	fn wrap(self) -> ConfiguredDigest {
+		ConfiguredDigest::Slot(self)
+	}
+
+	fn unwrap(digest: &ConfiguredDigest) -> Option<&Self> {
+		match digest {
+			ConfiguredDigest::Slot(slot) => Some(slot),
+			_ => None,
+		}
+	}
+}
+
+/// A boxed engine built from a `ConsensusConfig`.
+pub type ConfiguredConsensus = Box<dyn Consensus<Digest = ConfiguredDigest>>;
+
+/// A description of a consensus engine, which can be built into a boxed engine.
+pub struct ConsensusConfig(Spec);
+
+enum Spec {
+	Pow { threshold: u64 },
+	PoaRoundRobin { authorities: Vec<ConsensusAuthority> },
+	Aura { authorities: Vec<ConsensusAuthority>, slot_duration: u64 },
+	EvenOnly(Box<ConsensusConfig>),
+	Forked { before: Box<ConsensusConfig>, after: Box<ConsensusConfig>, fork_height: u64 },
+	GenesisState { inner: Box<ConsensusConfig>, state: u64 },
+}
+
+impl ConsensusConfig {
+	/// Proof of Work with the given threshold.
+	pub fn pow(threshold: u64) -> Self {
+		ConsensusConfig(Spec::Pow { threshold })
+	}
+
+	/// Proof of Authority in which the given authorities take turns by height.
+	pub fn poa_round_robin(authorities: Vec<ConsensusAuthority>) -> Self {
+		ConsensusConfig(Spec::PoaRoundRobin { authorities })
+	}
+
+	/// Aura with the given authorities and slot duration in milliseconds, following the system
+	/// clock.
+	pub fn aura(authorities: Vec<ConsensusAuthority>, slot_duration: u64) -> Self {
+		ConsensusConfig(Spec::Aura { authorities, slot_duration })
+	}
+
+	/// The given engine, with the additional rule that every state root must be even.
+	pub fn even_only(inner: ConsensusConfig) -> Self {
+		ConsensusConfig(Spec::EvenOnly(Box::new(inner)))
+	}
+
+	/// The `before` engine up to the given height, and the `after` engine from that height on.
+	pub fn forked(before: ConsensusConfig, after: ConsensusConfig, fork_height: u64) -> Self {
+		ConsensusConfig(Spec::Forked {
+			before: Box::new(before),
+			after: Box::new(after),
+			fork_height,
+		})
+	}
+
+	/// The given engine, launched from the given state rather than zero.
+	pub fn genesis_state(inner: ConsensusConfig, state: u64) -> Self {
+		ConsensusConfig(Spec::GenesisState { inner: Box::new(inner), state })
+	}
+
+	/// Build the described engine.
+	pub fn build(self) -> ConfiguredConsensus {
+		match self.0 {
+			Spec::Pow { threshold } => Adapted(PoW::new(threshold)).boxed(),
+			Spec::PoaRoundRobin { authorities } => {
+				Adapted(PoaRoundRobinByHeight::new(authorities)).boxed()
+			}
+			Spec::Aura { authorities, slot_duration } => {
+				Adapted(Aura::new(authorities, slot_duration, SystemClock)).boxed()
+			}
+			Spec::EvenOnly(inner) => EvenOnly::new(inner.build()).boxed(),
+			Spec::Forked { before, after, fork_height } => {
+				Forked::new(before.build(), after.build(), fork_height).boxed()
+			}
+			Spec::GenesisState { inner, state } => {
+				WithGenesisState { inner: inner.build(), state }.boxed()
+			}
+		}
+	}
+}
+
+/// One of the engines of this chapter, using `ConfiguredDigest` in place of its own digest.
+/// Digests of any other variant are rejected.
+struct Adapted<Inner>(Inner);
+
+impl<Inner> Adapted<Inner>
+where
+	Inner: Consensus,
+	Inner::Digest: DigestVariant,
+{
+	/// The header as the inner engine sees it, if it carries a digest of the inner engine.
+	fn inner_header(header: &Header<ConfiguredDigest>) -> Option<Header<Inner::Digest>> {
+		let digest = Inner::Digest::unwrap(&header.consensus_digest)?.clone();
+		Some(header.with_digest(digest))
+	}
+}
+
+impl<Inner> Consensus for Adapted<Inner>
+where
+	Inner: Consensus,
+	Inner::Digest: DigestVariant,
+{
+	type Digest = ConfiguredDigest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let parent_digest = Inner::Digest::unwrap(parent_digest).ok_or(ChainError::InvalidSeal)?;
+		let header = Self::inner_header(header).ok_or(ChainError::InvalidSeal)?;
+		self.0.check_header(parent_digest, &header)
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let header = self.0.seal(Inner::Digest::unwrap(parent_digest)?, partial_header)?;
+		Some(header.map_digest(DigestVariant::wrap))
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.0.genesis_header().map_digest(DigestVariant::wrap)
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.0.seal_author(&Self::inner_header(header)?)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		Self::inner_header(header).map_or(header.height, |inner| self.0.seal_slot(&inner))
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.0.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.0.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		self.0.rules()
+	}
+}
+
+/// An engine whose genesis starts from the given state. Everything else belongs to the inner
+/// engine.
+struct WithGenesisState {
+	inner: ConfiguredConsensus,
+	state: u64,
+}
+
+impl Consensus for WithGenesisState {
+	type Digest = ConfiguredDigest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.inner.validate(parent_digest, header)
+	}
+
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		self.inner.check_header(parent_digest, header)
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(parent_digest, header)
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.inner.seal(parent_digest, partial_header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		Header { state_root: self.state, ..self.inner.genesis_header() }
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(header)
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		self.inner.rules()
+	}
+}
+
+#[cfg(test)]
+use super::test_support::{check_adder_chain, sealed_adder_chain, sealed_child};
+
+/// Seal a single block with the configured engine on top of its genesis, and check it.
+#[cfg(test)]
+fn seals_one_block(config: ConsensusConfig) -> Header<ConfiguredDigest> {
+	let engine = config.build();
+	let genesis = engine.genesis_header();
+	let child = sealed_child(&*engine, &genesis, 2);
+	assert_eq!(engine.check_sub_chain(&genesis, std::slice::from_ref(&child)), Ok(()));
+	child
+}
+
+#[test]
+fn cs_config_builds_pow() {
+	let child = seals_one_block(ConsensusConfig::pow(u64::MAX / 4));
+	assert!(matches!(child.consensus_digest, ConfiguredDigest::Work(_)));
+}
+
+#[test]
+fn cs_config_builds_poa_round_robin() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let child = seals_one_block(ConsensusConfig::poa_round_robin(vec![Alice, Bob]));
+	assert_eq!(child.consensus_digest, ConfiguredDigest::Authority(Bob));
+}
+
+#[test]
+fn cs_config_builds_aura() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let child = seals_one_block(ConsensusConfig::aura(vec![Alice, Bob], 1));
+	assert!(matches!(child.consensus_digest, ConfiguredDigest::Slot(_)));
+}
+
+#[test]
+fn cs_config_builds_even_only() {
+	seals_one_block(ConsensusConfig::even_only(ConsensusConfig::pow(u64::MAX / 4)));
+
+	let engine = ConsensusConfig::even_only(ConsensusConfig::pow(u64::MAX / 4)).build();
+	let genesis = engine.genesis_header();
+
+	let odd = Header {
+		parent: crate::hash(&genesis),
+		height: 1,
+		extrinsics_root: 3,
+		state_root: 3,
+		consensus_digest: (),
+	};
+	assert_eq!(engine.seal(&genesis.consensus_digest, odd), None);
+}
+
+#[test]
+fn cs_config_builds_forked() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let config = || {
+		ConsensusConfig::forked(
+			ConsensusConfig::pow(u64::MAX / 4),
+			ConsensusConfig::poa_round_robin(vec![Alice, Bob]),
+			3,
+		)
+	};
+	let child = seals_one_block(config());
+	assert!(matches!(child.consensus_digest, ConfiguredDigest::Work(_)));
+
+	let engine = config().build();
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&*engine, &genesis, 4);
+	assert!(matches!(chain[1].consensus_digest, ConfiguredDigest::Work(_)));
+	assert_eq!(chain[2].consensus_digest, ConfiguredDigest::Authority(Bob));
+	assert_eq!(chain[3].consensus_digest, ConfiguredDigest::Authority(Alice));
+	assert_eq!(check_adder_chain(&*engine, &genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_config_even_only_rules_include_inner_rules() {
+	let pow = ConsensusConfig::pow(u64::MAX / 4).build();
+	let even_pow = ConsensusConfig::even_only(ConsensusConfig::pow(u64::MAX / 4)).build();
+
+	assert_eq!(pow.rules(), vec!["parent-link", "height+1", "pow-threshold"]);
+	assert_eq!(even_pow.rules(), vec!["parent-link", "height+1", "pow-threshold", "even-state"]);
+}
+
+#[test]
+fn cs_config_forked_rules_include_both_engines() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let forked = ConsensusConfig::forked(
+		ConsensusConfig::pow(u64::MAX / 4),
+		ConsensusConfig::poa_round_robin(vec![Alice, Bob]),
+		3,
+	)
+	.build();
+
+	assert_eq!(
+		forked.rules(),
+		vec![
+			"parent-link",
+			"height+1",
+			"pow-threshold",
+			"round-robin-by-height",
+			"engine-by-fork-height"
+		]
+	);
+}
+
+#[test]
+fn cs_config_genesis_state_sets_the_genesis_state_root() {
+	let engine = ConsensusConfig::genesis_state(ConsensusConfig::pow(u64::MAX / 4), 100).build();
+	let genesis = engine.genesis_header();
+
+	assert_eq!(genesis.state_root, 100);
+	assert_eq!(genesis.consensus_digest, ConfiguredDigest::Work(0));
+	let child = sealed_child(&*engine, &genesis, 1);
+	assert_eq!(engine.check_sub_chain(&genesis, &[child]), Ok(()));
+}
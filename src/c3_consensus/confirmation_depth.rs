@@ -0,0 +1,96 @@
+//! Finality gadgets like GRANDPA make blocks final by having authorities vote on them. Bitcoin has
+//! no such votes. Instead, wallets and exchanges treat a block as final once enough blocks are
+//! built on top of it, because the work needed to reorganize it away grows with every block.
+//!
+//! This finality is only probabilistic. An attacker with enough work can still revert a "final"
+//! block, but the deeper the block is buried, the less likely that is. It needs nothing but the
+//! headers, which makes it a good fit for light clients.
+
+use super::chain_store::ChainStore;
+
+type Hash = u64;
+
+/// Considers a block final once it is buried under at least `depth` blocks on the canonical
+/// chain. With a depth of zero, every canonical block, including the head, is final.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConfirmationDepthFinality {
+	depth: u64,
+}
+
+impl ConfirmationDepthFinality {
+	/// Bitcoin wallets conventionally wait for six confirmations.
+	pub const BITCOIN: Self = ConfirmationDepthFinality { depth: 6 };
+
+	pub fn new(depth: u64) -> Self {
+		ConfirmationDepthFinality { depth }
+	}
+
+	/// Whether the header with the given hash is final in the given store.
+	///
+	/// Genesis is always final. Headers on forks are never final, however deep they are.
+	pub fn is_final<D: Clone + Eq + std::hash::Hash>(
+		&self,
+		store: &ChainStore<D>,
+		hash: Hash,
+	) -> bool {
+		if hash == store.genesis() {
+			return true;
+		}
+		let (Some(header), Some(head)) = (store.get(hash), store.get(store.head())) else {
+			return false;
+		};
+		store.is_canonical(hash) && header.height.saturating_add(self.depth) <= head.height
+	}
+
+	/// The hash of the highest final header in the given store. While the chain is shorter than
+	/// the depth, that is genesis.
+	pub fn finalized_head<D: Clone + Eq + std::hash::Hash>(&self, store: &ChainStore<D>) -> Hash {
+		let mut current = store.head();
+		for _ in 0..self.depth {
+			match store.get(current) {
+				Some(header) if current != store.genesis() => current = header.parent,
+				_ => break,
+			}
+		}
+		current
+	}
+}
+
+#[cfg(test)]
+use super::{chain_store::best_head, p1_pow::PoW, test_support::sealed_adder_chain, Consensus};
+#[cfg(test)]
+use crate::hash;
+
+#[test]
+fn cs_confirmation_depth_buried_blocks_are_final() {
+	let engine = PoW::new(u64::MAX / 4);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 10);
+	let mut store = ChainStore::new(genesis);
+	for header in &chain {
+		store.import(header.clone()).unwrap();
+	}
+	let finality = ConfirmationDepthFinality::new(3);
+
+	// The head is at height 10, so height 7 is buried under three blocks, but height 8 is not.
+	assert!(finality.is_final(&store, hash(&chain[6])));
+	assert!(!finality.is_final(&store, hash(&chain[7])));
+	assert_eq!(finality.finalized_head(&store), hash(&chain[6]));
+	assert_eq!(best_head(&store, finality.finalized_head(&store)), Some(store.head()));
+}
+
+#[test]
+fn cs_confirmation_depth_short_chain_finalizes_genesis() {
+	let engine = PoW::new(u64::MAX / 4);
+	let genesis = engine.genesis_header();
+	let mut store = ChainStore::new(genesis.clone());
+	for header in sealed_adder_chain(&engine, &genesis, 2) {
+		store.import(header).unwrap();
+	}
+
+	let finality = ConfirmationDepthFinality::BITCOIN;
+	assert_eq!(finality.finalized_head(&store), store.genesis());
+	assert!(finality.is_final(&store, store.genesis()));
+	assert!(!finality.is_final(&store, store.head()));
+	assert!(ConfirmationDepthFinality::new(0).is_final(&store, store.head()));
+}
@@ -0,0 +1,228 @@
+//! So far each engine's digest has been a single value of its own type. Substrate headers instead
+//! carry a digest that is a list of typed log items. Some are written before the block is executed,
+//! such as the slot an author claims. Others are written by the runtime, such as an announcement of
+//! the next authority set. The seal is always the last item, added once everything else is final.
+//!
+//! A list of items lets several engines and the runtime share one header without knowing about each
+//! other. Here we model that with a higher-order engine whose digest is such a log. The inner
+//! engine's digest becomes the seal item, and the other items are carried alongside it.
+
+use super::{Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::ChainError;
+
+/// One item in a `DigestLog`.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DigestItem<D> {
+	/// Information the author provides before the block is executed, such as a claimed slot.
+	PreRuntime(u64),
+	/// A message from the runtime to the consensus engine, such as an authority set change.
+	Consensus(u64),
+	/// The inner engine's digest, which seals the block.
+	Seal(D),
+}
+
+/// The digest of a `Logged` engine. A list of items, the last of which is the only seal.
+#[derive(Hash, Debug, PartialEq, Eq, Clone)]
+pub struct DigestLog<D> {
+	pub(crate) items: Vec<DigestItem<D>>,
+}
+
+impl<D> DigestLog<D> {
+	/// All items in the log, in order.
+	pub fn items(&self) -> &[DigestItem<D>] {
+		&self.items
+	}
+
+	/// The seal, if the log ends with one and contains no other.
+	pub fn seal(&self) -> Option<&D> {
+		let (last, rest) = self.items.split_last()?;
+		match last {
+			DigestItem::Seal(seal) if !rest.iter().any(|i| matches!(i, DigestItem::Seal(_))) =>
+				Some(seal),
+			_ => None,
+		}
+	}
+}
+
+/// A Consensus engine whose digest is a log of items. Wraps an inner consensus engine, whose
+/// digest is the log's seal, and whose rules will also be enforced.
+///
+/// The inner engine only seals the inner header, so the other items are not covered by the seal.
+/// Substrate avoids that by sealing the hash of the header with every other item already in it.
+pub struct Logged<Inner> {
+	inner: Inner,
+}
+
+impl<Inner: Consensus> Logged<Inner> {
+	/// Create a new engine that keeps its digest in a log on top of the given engine.
+	pub fn new(inner: Inner) -> Self {
+		Logged { inner }
+	}
+
+	/// The header as the inner engine sees it, carrying only the seal. Returns `None` if the log
+	/// is not properly sealed.
+	pub fn inner_header(
+		header: &Header<DigestLog<Inner::Digest>>,
+	) -> Option<Header<Inner::Digest>> {
+		Some(header.with_digest(header.consensus_digest.seal()?.clone()))
+	}
+
+	/// Seal the partial header with the inner engine, and log the seal after the given items.
+	///
+	/// Returns `None` if the items already contain a seal.
+	pub fn seal_with_items(
+		&self,
+		parent_digest: &DigestLog<Inner::Digest>,
+		partial_header: Header<()>,
+		mut items: Vec<DigestItem<Inner::Digest>>,
+	) -> Option<Header<DigestLog<Inner::Digest>>> {
+		if items.iter().any(|item| matches!(item, DigestItem::Seal(_))) {
+			return None;
+		}
+		let header = self.inner.seal(parent_digest.seal()?, partial_header)?;
+
+		Some(header.map_digest(|seal| {
+			items.push(DigestItem::Seal(seal));
+			DigestLog { items }
+		}))
+	}
+}
+
+impl<Inner: Consensus> Consensus for Logged<Inner> {
+	type Digest = DigestLog<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Locate the seal in the log, and check it with the inner engine against the parent's seal.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let parent_seal = parent_digest.seal().ok_or(ChainError::InvalidSeal)?;
+		let inner_header = Self::inner_header(header).ok_or(ChainError::InvalidSeal)?;
+		self.inner.check_header(parent_seal, &inner_header)
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		match (parent_digest.seal(), Self::inner_header(header)) {
+			(Some(parent_seal), Some(inner_header)) =>
+				self.inner.validate_seal_only(parent_seal, &inner_header),
+			_ => false,
+		}
+	}
+
+	/// Seal the partial header with the inner engine, logging nothing but the seal.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.seal_with_items(parent_digest, partial_header, Vec::new())
+	}
+
+	/// The inner engine's genesis, with its digest as the only item in the log.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.inner.genesis_header();
+
+		genesis.map_digest(|inner| DigestLog { items: vec![DigestItem::Seal(inner)] })
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header)?)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		Self::inner_header(header).map_or(header.height, |inner| self.inner.seal_slot(&inner))
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("single-trailing-seal");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Logged {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{p3_poa::PoaRoundRobinByHeight, test_support::sealed_child};
+#[cfg(test)]
+use crate::hash;
+
+#[cfg(test)]
+fn round_robin() -> PoaRoundRobinByHeight {
+	PoaRoundRobinByHeight::new(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob])
+}
+
+#[test]
+fn cs_digest_log_round_trips_header_with_items() {
+	let engine = Logged::new(round_robin());
+	let genesis = engine.genesis_header();
+	let partial = Header {
+		parent: hash(&genesis),
+		height: 1,
+		state_root: 1,
+		extrinsics_root: 1,
+		consensus_digest: (),
+	};
+	let items = vec![DigestItem::PreRuntime(7), DigestItem::Consensus(8)];
+	let header = engine.seal_with_items(&genesis.consensus_digest, partial, items).unwrap();
+
+	let log = header.consensus_digest.items();
+	assert_eq!(log.len(), 3);
+	assert_eq!(log[..2], [DigestItem::PreRuntime(7), DigestItem::Consensus(8)]);
+	assert_eq!(header.consensus_digest.seal(), Some(&ConsensusAuthority::Bob));
+	assert_eq!(engine.check_sub_chain(&genesis, std::slice::from_ref(&header)), Ok(()));
+
+	// Stripping the log leaves a header the inner engine accepts on its own.
+	let inner_genesis = Logged::<PoaRoundRobinByHeight>::inner_header(&genesis).unwrap();
+	let inner_header = Logged::<PoaRoundRobinByHeight>::inner_header(&header).unwrap();
+	assert_eq!(inner_genesis, round_robin().genesis_header());
+	assert_eq!(inner_header.consensus_digest, ConsensusAuthority::Bob);
+	assert_eq!(round_robin().check_header(&inner_genesis.consensus_digest, &inner_header), Ok(()));
+}
+
+#[test]
+fn cs_digest_log_requires_single_trailing_seal() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let engine = Logged::new(round_robin());
+	let genesis = engine.genesis_header();
+	let header = sealed_child(&engine, &genesis, 1);
+	let with_items = |items| Header { consensus_digest: DigestLog { items }, ..header.clone() };
+
+	let unsealed = with_items(vec![DigestItem::PreRuntime(7)]);
+	let seal_not_last = with_items(vec![DigestItem::Seal(Bob), DigestItem::PreRuntime(7)]);
+	let two_seals = with_items(vec![DigestItem::Seal(Alice), DigestItem::Seal(Bob)]);
+	for header in [unsealed, seal_not_last, two_seals] {
+		assert_eq!(header.consensus_digest.seal(), None);
+		assert_eq!(engine.check_sub_chain(&genesis, &[header]), Err(ChainError::InvalidSeal));
+	}
+
+	let partial = Header {
+		parent: hash(&genesis),
+		height: 1,
+		state_root: 1,
+		extrinsics_root: 1,
+		consensus_digest: (),
+	};
+	let items = vec![DigestItem::Seal(Bob)];
+	assert_eq!(engine.seal_with_items(&genesis.consensus_digest, partial, items), None);
+}
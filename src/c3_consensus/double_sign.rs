@@ -0,0 +1,321 @@
+//! In identity-based engines like the randomized PoA engine, sealing a block costs the author
+//! nothing. So unlike a PoW miner, who must split their hash power between forks, an authority can
+//! cheaply sign a block on every fork at once. This is known as the "nothing-at-stake" problem.
+//!
+//! We cannot stop an authority from double signing, but we can catch them. A node that hears about
+//! headers from several forks remembers who signed in each slot, and flags any authority that
+//! signed two different headers in the same slot. The two headers are the evidence that could
+//! later be used to punish the offender. For most engines a slot is just a height, but slot-based
+//! engines like Aura may seal several heights' worth of forks in different slots quite honestly.
+//!
+//! Catching an offender is only useful if something is done about it. `EquivocationGuard` sits in
+//! front of a `ChainStore`, so that conflicting headers are rejected on import and their author
+//! can be slashed, meaning none of their headers are accepted again.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{
+	chain_store::{ChainStore, ImportOutcome},
+	Consensus, ConsensusAuthority, Header,
+};
+use crate::{hash, ChainError};
+
+/// Proof that an authority sealed two conflicting headers in the same slot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DoubleSign<D> {
+	pub offender: ConsensusAuthority,
+	pub first: Header<D>,
+	pub second: Header<D>,
+}
+
+/// Watches headers from any number of chains and reports authorities who double sign.
+///
+/// Seeing the same header more than once, for example because two forks share a common prefix,
+/// is not an offence. Neither is an authority signing on several forks in different slots.
+pub struct DoubleSignDetector<'a, C: Consensus> {
+	engine: &'a C,
+	seen: HashMap<(ConsensusAuthority, u64), Header<C::Digest>>,
+}
+
+impl<'a, C: Consensus> DoubleSignDetector<'a, C> {
+	/// Create a detector that uses the given engine to attribute headers to their authors.
+	pub fn new(engine: &'a C) -> Self {
+		DoubleSignDetector { engine, seen: HashMap::new() }
+	}
+
+	/// Record a single header. If its author already signed a different header in the same slot,
+	/// the offence is returned along with both headers.
+	///
+	/// Headers the engine cannot attribute to an author are ignored.
+	pub fn observe(&mut self, header: &Header<C::Digest>) -> Option<DoubleSign<C::Digest>> {
+		let offence = self.conflict(header);
+		if offence.is_none() {
+			self.record(header);
+		}
+		offence
+	}
+
+	/// The offence the header reveals against the headers recorded so far, without recording it.
+	pub fn conflict(&self, header: &Header<C::Digest>) -> Option<DoubleSign<C::Digest>> {
+		let author = self.engine.seal_author(header)?;
+		let first = self.seen.get(&(author, self.engine.seal_slot(header)))?;
+
+		(hash(first) != hash(header)).then(|| DoubleSign {
+			offender: author,
+			first: first.clone(),
+			second: header.clone(),
+		})
+	}
+
+	/// Remember who signed the header and in which slot, unless a header is already recorded there.
+	fn record(&mut self, header: &Header<C::Digest>) {
+		if let Some(author) = self.engine.seal_author(header) {
+			let slot = self.engine.seal_slot(header);
+			self.seen.entry((author, slot)).or_insert_with(|| header.clone());
+		}
+	}
+
+	/// Record every header in the given chain, returning all the offences it reveals.
+	pub fn observe_chain(&mut self, chain: &[Header<C::Digest>]) -> Vec<DoubleSign<C::Digest>> {
+		chain.iter().filter_map(|header| self.observe(header)).collect()
+	}
+}
+
+/// Checks headers before importing them into a `ChainStore`, and rejects those that reveal their
+/// author double signing.
+pub struct EquivocationGuard<'a, C: Consensus> {
+	detector: DoubleSignDetector<'a, C>,
+	auto_slash: bool,
+	slashed: HashSet<ConsensusAuthority>,
+	offences: Vec<DoubleSign<C::Digest>>,
+}
+
+impl<'a, C: Consensus> EquivocationGuard<'a, C> {
+	/// Create a guard that checks headers with the given engine. If `auto_slash` is set, an
+	/// authority caught double signing is slashed, and all of their later headers are rejected.
+	pub fn new(engine: &'a C, auto_slash: bool) -> Self {
+		EquivocationGuard {
+			detector: DoubleSignDetector::new(engine),
+			auto_slash,
+			slashed: HashSet::new(),
+			offences: Vec::new(),
+		}
+	}
+
+	/// Check the header against its parent in the store, and import it unless its author double
+	/// signed with it or has been slashed.
+	///
+	/// Only validly sealed headers count as evidence, so a forged header can not get an honest
+	/// authority slashed. A header only becomes evidence against later ones once the store has
+	/// imported it.
+	pub fn import(
+		&mut self,
+		store: &mut ChainStore<C::Digest>,
+		header: Header<C::Digest>,
+	) -> Result<ImportOutcome, ChainError> {
+		let parent = store.get(header.parent).ok_or(ChainError::UnknownParent)?;
+		self.detector.engine.check_sub_chain(parent, std::slice::from_ref(&header))?;
+
+		if let Some(author) = self.detector.engine.seal_author(&header) {
+			if self.slashed.contains(&author) {
+				return Err(ChainError::Equivocation);
+			}
+		}
+		if let Some(offence) = self.detector.conflict(&header) {
+			if self.auto_slash {
+				self.slashed.insert(offence.offender);
+			}
+			self.offences.push(offence);
+			return Err(ChainError::Equivocation);
+		}
+		let outcome = store.import(header.clone())?;
+		self.detector.record(&header);
+		Ok(outcome)
+	}
+
+	/// Whether the given authority has been slashed.
+	pub fn is_slashed(&self, authority: ConsensusAuthority) -> bool {
+		self.slashed.contains(&authority)
+	}
+
+	/// Every offence caught so far, in the order they were caught.
+	pub fn offences(&self) -> &[DoubleSign<C::Digest>] {
+		&self.offences
+	}
+}
+
+#[cfg(test)]
+use super::{
+	p3_poa::{PoaRoundRobinBySlot, RandomizedPoa, SlotDigest},
+	test_support::sealed_child,
+};
+
+#[cfg(test)]
+fn randomized_engine() -> RandomizedPoa {
+	RandomizedPoa::new(
+		vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob, ConsensusAuthority::Charlie],
+		42,
+	)
+}
+
+#[cfg(test)]
+fn genesis() -> Header<ConsensusAuthority> {
+	Header {
+		parent: 0,
+		height: 0,
+		extrinsics_root: 0,
+		state_root: 0,
+		consensus_digest: ConsensusAuthority::Alice,
+	}
+}
+
+#[test]
+fn cs_double_sign_shared_prefix_is_not_an_offence() {
+	let engine = randomized_engine();
+	let b1 = sealed_child(&engine, &genesis(), 1);
+	let b2 = sealed_child(&engine, &b1, 2);
+	let b3 = sealed_child(&engine, &b2, 3);
+
+	let mut detector = DoubleSignDetector::new(&engine);
+	assert!(detector.observe_chain(&[b1.clone(), b2.clone()]).is_empty());
+	assert!(detector.observe_chain(&[b1, b2, b3]).is_empty());
+}
+
+#[test]
+fn cs_double_sign_signing_on_forks_at_different_heights_is_honest() {
+	let engine = randomized_engine();
+	let by_alice =
+		|height| Header { height, consensus_digest: ConsensusAuthority::Alice, ..genesis() };
+
+	// Alice signs on two forks, but never twice at the same height.
+	let fork_a = [by_alice(1), Header { parent: 1, ..by_alice(2) }];
+	let fork_b = [Header { parent: 2, ..by_alice(3) }, Header { parent: 3, ..by_alice(4) }];
+
+	let mut detector = DoubleSignDetector::new(&engine);
+	assert!(detector.observe_chain(&fork_a).is_empty());
+	assert!(detector.observe_chain(&fork_b).is_empty());
+}
+
+#[test]
+fn cs_double_sign_conflicting_headers_are_reported() {
+	let engine = randomized_engine();
+	let honest = sealed_child(&engine, &genesis(), 1);
+	let conflicting = sealed_child(&engine, &genesis(), 2);
+	let offender = engine.expected_author(1).unwrap();
+
+	let mut detector = DoubleSignDetector::new(&engine);
+	assert_eq!(detector.observe(&honest), None);
+	assert_eq!(
+		detector.observe(&conflicting),
+		Some(DoubleSign { offender, first: honest, second: conflicting })
+	);
+}
+
+#[test]
+fn cs_double_sign_found_across_forks() {
+	let engine = randomized_engine();
+	let shared = sealed_child(&engine, &genesis(), 1);
+	let fork_a = [shared.clone(), sealed_child(&engine, &shared, 2)];
+	let fork_b = [shared.clone(), sealed_child(&engine, &shared, 3)];
+
+	let mut detector = DoubleSignDetector::new(&engine);
+	assert!(detector.observe_chain(&fork_a).is_empty());
+
+	let offences = detector.observe_chain(&fork_b);
+	assert_eq!(offences.len(), 1);
+	assert_eq!(offences[0].offender, engine.expected_author(2).unwrap());
+	assert_eq!(offences[0].first, fork_a[1]);
+	assert_eq!(offences[0].second, fork_b[1]);
+}
+
+#[test]
+fn cs_double_sign_slot_engines_are_judged_by_slot() {
+	let engine = PoaRoundRobinBySlot::new(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob]);
+	let genesis = engine.genesis_header();
+	let by_alice = |slot, extrinsic| Header {
+		parent: hash(&genesis),
+		height: 1,
+		extrinsics_root: extrinsic,
+		state_root: extrinsic,
+		consensus_digest: SlotDigest { slot, signature: ConsensusAuthority::Alice },
+	};
+
+	// Alice signs two forks at the same height, but in different slots of her own.
+	let mut detector = DoubleSignDetector::new(&engine);
+	assert_eq!(detector.observe(&by_alice(2, 1)), None);
+	assert_eq!(detector.observe(&by_alice(4, 2)), None);
+
+	// Signing twice in the same slot is an offence.
+	let offence = DoubleSign {
+		offender: ConsensusAuthority::Alice,
+		first: by_alice(2, 1),
+		second: by_alice(2, 3),
+	};
+	assert_eq!(detector.observe_chain(&[by_alice(2, 3)]), vec![offence]);
+}
+
+#[test]
+fn cs_equivocation_guard_rejects_and_slashes_double_signer() {
+	let engine = randomized_engine();
+	let mut store = ChainStore::new(genesis());
+	let mut guard = EquivocationGuard::new(&engine, true);
+
+	let b1 = sealed_child(&engine, &genesis(), 1);
+	let b2 = sealed_child(&engine, &b1, 2);
+	assert_eq!(guard.import(&mut store, b1.clone()), Ok(ImportOutcome::NewHead));
+	assert_eq!(guard.import(&mut store, b2.clone()), Ok(ImportOutcome::NewHead));
+	assert!(guard.offences().is_empty());
+
+	// The author of the first block signs a conflicting header at the same height.
+	let offender = engine.expected_author(1).unwrap();
+	let conflicting = sealed_child(&engine, &genesis(), 3);
+	assert_eq!(guard.import(&mut store, conflicting.clone()), Err(ChainError::Equivocation));
+	assert!(!store.contains(hash(&conflicting)));
+	assert!(guard.is_slashed(offender));
+	assert_eq!(guard.offences().len(), 1);
+	assert_eq!(guard.offences()[0].offender, offender);
+
+	// Re-importing a known header is turned away once its author is slashed, but is harmless,
+	// and not an offence, when its author is honest.
+	assert_ne!(engine.expected_author(2), Some(offender));
+	assert_eq!(guard.import(&mut store, b1), Err(ChainError::Equivocation));
+	assert_eq!(guard.import(&mut store, b2), Ok(ImportOutcome::SideChain));
+	assert_eq!(guard.offences().len(), 1);
+}
+
+#[test]
+fn cs_equivocation_guard_without_auto_slash_only_rejects() {
+	let engine = randomized_engine();
+	let mut store = ChainStore::new(genesis());
+	let mut guard = EquivocationGuard::new(&engine, false);
+
+	let honest = sealed_child(&engine, &genesis(), 1);
+	let conflicting = sealed_child(&engine, &genesis(), 2);
+	assert_eq!(guard.import(&mut store, honest.clone()), Ok(ImportOutcome::NewHead));
+	assert_eq!(guard.import(&mut store, conflicting), Err(ChainError::Equivocation));
+	assert!(!guard.is_slashed(engine.expected_author(1).unwrap()));
+
+	// A forged header is rejected for its seal, and is not evidence of anything.
+	let mut forged = sealed_child(&engine, &honest, 3);
+	forged.consensus_digest = match forged.consensus_digest {
+		ConsensusAuthority::Alice => ConsensusAuthority::Bob,
+		_ => ConsensusAuthority::Alice,
+	};
+	assert_eq!(guard.import(&mut store, forged), Err(ChainError::WrongAuthority));
+	assert_eq!(guard.offences().len(), 1);
+}
+
+#[test]
+fn cs_equivocation_guard_only_records_imported_headers() {
+	let engine = randomized_engine();
+	let mut store = ChainStore::new(genesis());
+	let mut guard = EquivocationGuard::new(&engine, true);
+
+	// The store turns the first header away, so it is not evidence against the second.
+	let rejected = sealed_child(&engine, &genesis(), 1);
+	let accepted = sealed_child(&engine, &genesis(), 2);
+	store.mark_bad(hash(&rejected), ChainError::BadState);
+	assert_eq!(guard.import(&mut store, rejected), Err(ChainError::BadState));
+	assert_eq!(guard.import(&mut store, accepted), Ok(ImportOutcome::NewHead));
+	assert!(guard.offences().is_empty());
+}
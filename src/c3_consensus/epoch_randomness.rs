@@ -0,0 +1,277 @@
+//! `RandomizedPoa` draws each height's author from an epoch seed, but so far the seed has simply
+//! been handed to it. Where should the seed come from? It must be unpredictable before the epoch
+//! starts, so that nobody can arrange to be chosen, yet every node must derive exactly the same
+//! value.
+//!
+//! BABE-style protocols answer this by accumulating randomness from the chain itself. Each block is
+//! mixed into an accumulator, and when an epoch ends, the accumulated value becomes the randomness
+//! of the next epoch. Real protocols mix in VRF outputs, which the block author can not bias. We
+//! mix in each block's identity and signer with the `hash` helper, which is deterministic but can
+//! be ground by authors who try many sets of extrinsics.
+//!
+//! Different forks accumulate different randomness, so the accumulator can not be a single value
+//! that imports advance. Like `MonotonicState`, each block carries it in its digest instead.
+
+use super::{
+	genesis_with_digest, p3_poa::randomized_author, Consensus, ConsensusAuthority, EngineKind,
+	Header, BASE_RULES,
+};
+use crate::{hash, ChainError};
+
+/// Randomness accumulated from the blocks in each epoch.
+///
+/// Epochs are `epoch_length` blocks long. The first epoch spans heights `1..=epoch_length`, and is
+/// driven by the genesis randomness.
+///
+/// Accumulating is a pure function of the blocks mixed in, so each block can carry the randomness
+/// as of itself, and every fork accumulates its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EpochRandomness {
+	epoch_length: u64,
+	/// The randomness of the epoch in progress, finalized at the end of the previous epoch.
+	current: u64,
+	/// Everything mixed in so far.
+	accumulator: u64,
+}
+
+impl EpochRandomness {
+	/// Start accumulating randomness from the given genesis randomness, which is also the
+	/// randomness of the first epoch.
+	pub fn new(genesis_randomness: u64, epoch_length: u64) -> Self {
+		assert!(epoch_length > 0, "epochs must contain at least one block");
+		EpochRandomness {
+			epoch_length,
+			current: genesis_randomness,
+			accumulator: genesis_randomness,
+		}
+	}
+
+	/// The randomness of the epoch in progress.
+	pub fn current(&self) -> u64 {
+		self.current
+	}
+
+	/// The randomness after mixing in the given contribution of the block at the given height.
+	/// Blocks must be mixed in order, starting with the first block after genesis.
+	///
+	/// If the block is the last of its epoch, the accumulated randomness is finalized as the
+	/// randomness of the next epoch.
+	pub fn accumulated<T: std::hash::Hash>(&self, height: u64, contribution: &T) -> Self {
+		let accumulator = hash(&(self.accumulator, contribution));
+		let current =
+			if height.is_multiple_of(self.epoch_length) { accumulator } else { self.current };
+		EpochRandomness { epoch_length: self.epoch_length, current, accumulator }
+	}
+}
+
+/// The digest of an `EpochRandomizedPoa` engine. The authority who signed the block, and the
+/// epoch randomness after the block is mixed in.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EpochDigest {
+	pub(crate) signature: ConsensusAuthority,
+	pub(crate) randomness: EpochRandomness,
+}
+
+/// A `RandomizedPoa` whose epoch seeds are accumulated from the chain itself.
+///
+/// Each block mixes its own identity and signer into the randomness carried by its parent's
+/// digest, and commits to the result in its own digest. So the schedule of any block can be
+/// re-derived from its parent alone, whatever else has been imported.
+pub struct EpochRandomizedPoa {
+	authorities: Vec<ConsensusAuthority>,
+	genesis_randomness: EpochRandomness,
+}
+
+impl EpochRandomizedPoa {
+	/// Create a new engine with the given authorities, whose first epoch is driven by the given
+	/// genesis randomness.
+	pub fn new(
+		authorities: Vec<ConsensusAuthority>,
+		genesis_randomness: u64,
+		epoch_length: u64,
+	) -> Self {
+		EpochRandomizedPoa {
+			authorities,
+			genesis_randomness: EpochRandomness::new(genesis_randomness, epoch_length),
+		}
+	}
+
+	/// The digest a child of the block with the given digest must carry, if there are any
+	/// authorities.
+	fn expected_digest<D>(
+		&self,
+		parent_digest: &EpochDigest,
+		header: &Header<D>,
+	) -> Option<EpochDigest> {
+		let randomness = parent_digest.randomness;
+		let signature = randomized_author(&self.authorities, header.height, randomness.current())?;
+		Some(EpochDigest {
+			signature,
+			randomness: randomness.accumulated(header.height, &(header.id(), signature)),
+		})
+	}
+}
+
+impl Consensus for EpochRandomizedPoa {
+	type Digest = EpochDigest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Re-derive the eligible authority and the randomness from the parent's digest, and check
+	/// that the header carries both.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let expected =
+			self.expected_digest(parent_digest, header).ok_or(ChainError::WrongAuthority)?;
+		let digest = &header.consensus_digest;
+		if digest.signature != expected.signature {
+			return Err(ChainError::WrongAuthority);
+		}
+		if digest.randomness != expected.randomness {
+			return Err(ChainError::InvalidSeal);
+		}
+		Ok(())
+	}
+
+	/// Sign the partial header as the authority who is eligible at its height.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let consensus_digest = self.expected_digest(parent_digest, &partial_header)?;
+		Some(partial_header.with_digest(consensus_digest))
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let signature = randomized_author(&self.authorities, 0, self.genesis_randomness.current())
+			.unwrap_or(ConsensusAuthority::Alice);
+		genesis_with_digest(EpochDigest { signature, randomness: self.genesis_randomness })
+	}
+
+	/// The authority's signature is part of the digest.
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		Some(header.consensus_digest.signature)
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::RandomizedPoa
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["random-authority", "epoch-randomness"]].concat()
+	}
+
+	fn human_name() -> String {
+		"Epoch Randomized Proof of Authority".into()
+	}
+}
+
+#[cfg(test)]
+use super::test_support::{check_adder_chain, sealed_adder_chain, sealed_child};
+
+#[cfg(test)]
+fn chain_with_digests(digests: &[u64]) -> Vec<Header<u64>> {
+	digests
+		.iter()
+		.zip(1..)
+		.map(|(&consensus_digest, height)| Header {
+			parent: 0,
+			height,
+			extrinsics_root: 0,
+			state_root: 0,
+			consensus_digest,
+		})
+		.collect()
+}
+
+/// The randomness of every epoch after the first, accumulated from the given chain.
+#[cfg(test)]
+fn finalized_randomness(chain: &[Header<u64>]) -> Vec<u64> {
+	let mut randomness = EpochRandomness::new(7, 3);
+	let mut finalized = Vec::new();
+	for header in chain {
+		randomness = randomness.accumulated(header.height, &header.consensus_digest);
+		if header.height % 3 == 0 {
+			finalized.push(randomness.current());
+		}
+	}
+	finalized
+}
+
+#[test]
+fn cs_epoch_randomness_is_deterministic() {
+	let chain = chain_with_digests(&[1, 2, 3, 4, 5, 6]);
+
+	let first = finalized_randomness(&chain);
+	assert_eq!(first.len(), 2);
+	assert_eq!(first, finalized_randomness(&chain.clone()));
+}
+
+#[test]
+fn cs_epoch_randomness_diverges_with_the_chain() {
+	let chain = chain_with_digests(&[1, 2, 3, 4, 5, 6]);
+	let fork = chain_with_digests(&[1, 2, 3, 4, 9, 6]);
+
+	let (original, forked) = (finalized_randomness(&chain), finalized_randomness(&fork));
+	// The chains agree on the first epoch, so they agree on the randomness it produced.
+	assert_eq!(original[0], forked[0]);
+	assert_ne!(original[1], forked[1]);
+}
+
+#[test]
+fn cs_epoch_randomness_is_only_finalized_at_epoch_end() {
+	let genesis = EpochRandomness::new(7, 3);
+
+	let first = genesis.accumulated(1, &1u64);
+	let second = first.accumulated(2, &2u64);
+	assert_eq!(second.current(), 7);
+	let third = second.accumulated(3, &3u64);
+	assert_ne!(third.current(), 7);
+	assert_eq!(third.accumulated(4, &4u64).current(), third.current());
+}
+
+#[test]
+fn cs_epoch_randomized_poa_follows_randomness_in_the_digest() {
+	use ConsensusAuthority::{Alice, Bob, Charlie};
+
+	let engine = EpochRandomizedPoa::new(vec![Alice, Bob, Charlie], 42, 4);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 12);
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+	assert_ne!(chain[11].consensus_digest.randomness.current(), 42);
+
+	// A fresh engine, which has imported nothing, validates the chain just the same.
+	let fresh = EpochRandomizedPoa::new(vec![Alice, Bob, Charlie], 42, 4);
+	assert_eq!(fresh.check_sub_chain(&genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_epoch_randomized_poa_forks_carry_their_own_randomness() {
+	use ConsensusAuthority::{Alice, Bob, Charlie};
+
+	let engine = EpochRandomizedPoa::new(vec![Alice, Bob, Charlie], 42, 4);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 4);
+
+	// Two children of the same parent with different extrinsics end the epoch differently, and
+	// each is valid on its own fork.
+	let left = sealed_child(&engine, &chain[2], 1);
+	let right = sealed_child(&engine, &chain[2], 2);
+	assert_ne!(left.consensus_digest.randomness, right.consensus_digest.randomness);
+	assert_eq!(engine.check_sub_chain(&chain[2], std::slice::from_ref(&left)), Ok(()));
+	assert_eq!(engine.check_sub_chain(&chain[2], std::slice::from_ref(&right)), Ok(()));
+
+	// A header can not claim the randomness of another fork.
+	let mut stolen = left;
+	stolen.consensus_digest.randomness = right.consensus_digest.randomness;
+	assert_eq!(
+		engine.check_header(&chain[2].consensus_digest, &stolen),
+		Err(ChainError::InvalidSeal)
+	);
+}
@@ -0,0 +1,81 @@
+//! Writing a whole struct and trait impl just to try out a new consensus rule is a lot of
+//! ceremony. This module provides an adapter that builds a consensus engine from a pair of
+//! closures, which makes it easy to prototype a rule inline, for example in a test. Any
+//! configuration the rule needs is simply captured by the closures.
+
+use std::marker::PhantomData;
+
+use super::{genesis_with_digest, Consensus, Header};
+
+/// A consensus engine whose validation and sealing logic are provided by closures.
+struct FnConsensus<D, V, S> {
+	validate: V,
+	seal: S,
+	digest: PhantomData<D>,
+}
+
+impl<D, V, S> Consensus for FnConsensus<D, V, S>
+where
+	D: Clone + core::fmt::Debug + Default + Eq + PartialEq + std::hash::Hash,
+	V: Fn(&D, &Header<D>) -> bool,
+	S: Fn(&D, Header<()>) -> Option<Header<D>>,
+{
+	type Digest = D;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		(self.validate)(parent_digest, header)
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		(self.seal)(parent_digest, partial_header)
+	}
+
+	/// Genesis carries the default digest.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(D::default())
+	}
+
+	fn human_name() -> String {
+		"Closure-based Consensus".into()
+	}
+}
+
+/// Build a consensus engine from a validation closure and a sealing closure.
+///
+/// The closures have exactly the same meaning as `Consensus::validate` and `Consensus::seal`. The
+/// genesis header carries the default digest.
+pub fn from_fns<D, V, S>(validate: V, seal: S) -> impl Consensus<Digest = D>
+where
+	D: Clone + core::fmt::Debug + Default + Eq + PartialEq + std::hash::Hash,
+	V: Fn(&D, &Header<D>) -> bool,
+	S: Fn(&D, Header<()>) -> Option<Header<D>>,
+{
+	FnConsensus { validate, seal, digest: PhantomData }
+}
+
+#[test]
+fn cs_from_fns_even_state_rule() {
+	use super::test_support::sealed_child;
+	use crate::ChainError;
+
+	let engine = from_fns(
+		|_: &(), header: &Header<()>| header.state_root.is_multiple_of(2),
+		|_: &(), partial: Header<()>| partial.state_root.is_multiple_of(2).then_some(partial),
+	);
+	let genesis =
+		Header { parent: 0, height: 0, extrinsics_root: 0, state_root: 0, consensus_digest: () };
+
+	let b1 = sealed_child(&engine, &genesis, 2);
+	let b2 = sealed_child(&engine, &b1, 4);
+	assert_eq!(engine.check_sub_chain(&genesis, &[b1.clone(), b2.clone()]), Ok(()));
+	assert!(engine.verify_sub_chain(&(), &[b1.clone(), b2]));
+
+	// An odd state can be neither sealed nor validated.
+	let partial = Header { state_root: b1.state_root + 1, ..b1.clone() };
+	assert_eq!(engine.seal(&(), partial.clone()), None);
+	assert_eq!(engine.check_sub_chain(&genesis, &[partial]), Err(ChainError::InvalidSeal));
+}
@@ -0,0 +1,183 @@
+//! Every chain starts from a genesis block, and so far each chapter has built its own with a
+//! handful of hard-coded zeros. Real chains are launched from a genesis configuration instead, sometimes
+//! called a chain spec, which says what the initial state is and how consensus starts out. This
+//! module provides such a configuration, and builds the matching genesis for both the batched
+//! blocks from the previous chapter and the consensus headers of this one.
+
+use super::{
+	p1_pow::PoW,
+	p3_poa::{PoaRoundRobinByHeight, SlotDigest},
+	safe::checked_slot_index,
+	ConsensusAuthority, Header,
+};
+use crate::c2_blockchain::p4_batched_extrinsics::Block;
+
+/// A genesis configuration from which the genesis block and consensus engine of a chain are built.
+///
+/// Anything not configured keeps its default. The state starts at zero, there are no authorities,
+/// the PoW threshold is moderate (roughly 1 in 100 nonces are valid), and the chain launches at
+/// time zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenesisBuilder {
+	state: u64,
+	authorities: Vec<ConsensusAuthority>,
+	threshold: u64,
+	timestamp: u64,
+}
+
+impl Default for GenesisBuilder {
+	fn default() -> Self {
+		GenesisBuilder {
+			state: 0,
+			authorities: Vec::new(),
+			threshold: u64::MAX / 100,
+			timestamp: 0,
+		}
+	}
+}
+
+impl GenesisBuilder {
+	/// Start from the default configuration.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Launch the chain from the given state rather than zero.
+	pub fn state(mut self, state: u64) -> Self {
+		self.state = state;
+		self
+	}
+
+	/// The authorities who may author blocks in identity-based engines.
+	pub fn authorities(mut self, authorities: Vec<ConsensusAuthority>) -> Self {
+		self.authorities = authorities;
+		self
+	}
+
+	/// The Proof of Work difficulty, expressed as the threshold that header hashes must be below.
+	pub fn difficulty(mut self, threshold: u64) -> Self {
+		self.threshold = threshold;
+		self
+	}
+
+	/// The time, in milliseconds since the Unix epoch, at which the chain launches. Slot-based
+	/// engines start counting slots from here.
+	pub fn timestamp(mut self, timestamp: u64) -> Self {
+		self.timestamp = timestamp;
+		self
+	}
+
+	/// The genesis block for the batched extrinsics chain of the previous chapter.
+	pub fn batched_block(&self) -> Block {
+		Block::genesis_with_state(self.state)
+	}
+
+	/// The genesis header for a Proof of Work chain. Genesis is not mined, so the nonce is zero.
+	pub fn pow_header(&self) -> Header<u64> {
+		self.header(0)
+	}
+
+	/// A Proof of Work engine at the configured difficulty.
+	pub fn pow_engine(&self) -> PoW {
+		PoW::new(self.threshold)
+	}
+
+	/// The genesis header for a Proof of Authority chain, nominally signed by the first authority.
+	/// Returns `None` if there are no authorities.
+	pub fn poa_header(&self) -> Option<Header<ConsensusAuthority>> {
+		self.authorities.first().map(|authority| self.header(*authority))
+	}
+
+	/// A round robin by height engine with the configured authorities.
+	pub fn round_robin_engine(&self) -> PoaRoundRobinByHeight {
+		PoaRoundRobinByHeight::new(self.authorities.clone())
+	}
+
+	/// The genesis header for a slot-based chain with slots of the given duration in milliseconds.
+	/// The genesis claims the slot in progress at the launch time, nominally signed by the
+	/// authority whose turn it is. Returns `None` if there are no authorities or the duration is zero.
+	pub fn slot_header(&self, slot_duration: u64) -> Option<Header<SlotDigest>> {
+		let slot = self.timestamp.checked_div(slot_duration)?;
+		let signature = self.authorities[checked_slot_index(slot, self.authorities.len())?];
+
+		Some(self.header(SlotDigest { slot, signature }))
+	}
+
+	/// The genesis header carrying the given digest.
+	fn header<D>(&self, consensus_digest: D) -> Header<D> {
+		Header {
+			parent: 0,
+			height: 0,
+			extrinsics_root: 0,
+			state_root: self.state,
+			consensus_digest,
+		}
+	}
+}
+
+#[cfg(test)]
+use super::test_support::{check_adder_chain, sealed_child};
+
+#[test]
+fn cs_genesis_default_matches_hand_built_genesis() {
+	let builder = GenesisBuilder::new();
+
+	assert_eq!(builder.batched_block(), Block::genesis());
+	assert_eq!(
+		builder.pow_header(),
+		Header { parent: 0, height: 0, extrinsics_root: 0, state_root: 0, consensus_digest: 0 }
+	);
+	assert_eq!(builder.poa_header(), None);
+}
+
+#[test]
+fn cs_genesis_custom_state_and_difficulty_pow_child_validates() {
+	let builder = GenesisBuilder::new().state(100).difficulty(u64::MAX / 1000);
+	let engine = builder.pow_engine();
+	let genesis = builder.pow_header();
+	assert_eq!(genesis.state_root, 100);
+
+	let child = sealed_child(&engine, &genesis, 5);
+	assert_eq!(child.state_root, 105);
+	assert!(crate::meets_threshold(&child, u64::MAX / 1000));
+	assert_eq!(check_adder_chain(&engine, &genesis, &[child]), Ok(()));
+}
+
+#[test]
+fn cs_genesis_custom_state_batched_child_validates() {
+	let genesis = GenesisBuilder::new().state(100).batched_block();
+	let child = genesis.child(vec![1, 2]);
+
+	assert!(genesis.verify_sub_chain(std::slice::from_ref(&child)));
+	assert!(!Block::genesis().verify_sub_chain(&[child]));
+}
+
+#[test]
+fn cs_genesis_authorities_and_timestamp() {
+	let builder = GenesisBuilder::new()
+		.state(7)
+		.authorities(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob])
+		.timestamp(30);
+
+	let genesis = builder.poa_header().unwrap();
+	assert_eq!(genesis.consensus_digest, ConsensusAuthority::Alice);
+	let engine = builder.round_robin_engine();
+	let child = sealed_child(&engine, &genesis, 1);
+	assert_eq!(check_adder_chain(&engine, &genesis, &[child]), Ok(()));
+
+	let slot_genesis = builder.slot_header(10).unwrap();
+	assert_eq!(
+		slot_genesis.consensus_digest,
+		SlotDigest { slot: 3, signature: ConsensusAuthority::Bob }
+	);
+	assert_eq!(builder.slot_header(0), None);
+}
+
+#[test]
+fn cs_genesis_hash_differs_from_field_identical_c2_genesis() {
+	let c2_genesis = crate::c2_blockchain::Header::genesis(0);
+	let c3_genesis = GenesisBuilder::new().pow_header();
+
+	// Both headers are all zeros, so only the domain tags tell them apart.
+	assert_ne!(crate::hash(&c2_genesis), crate::hash(&c3_genesis));
+}
@@ -0,0 +1,230 @@
+//! The `hash` helper used throughout this crate gives a 64 bit hash of a value's `Hash` impl. That
+//! is fine for learning, but real chains use 256 bit hash functions, and standard tooling expects
+//! them.
+//!
+//! This module gives headers a 256 bit hash through a pluggable hash function. The default is a toy
+//! built from the standard library, so this crate keeps its zero dependencies. A real hash function
+//! from an external crate can be dropped in by implementing `Hasher256` for it, and SHA-256 is
+//! built in behind the `sha256` feature.
+//!
+//! The preimage is the byte stream a header's `Hash` impl writes, domain tag first. Every header
+//! has one, including those whose digest has no wire encoding. The toy hash function's first lane
+//! is the standard library's 64 bit hash of the same stream, so a header's `hash` is exactly the
+//! low 8 bytes of its `hash256`, and every PoW seal mined so far keeps its meaning.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use super::Header;
+
+/// A hash function from bytes to 32 bytes, such as SHA-256 or BLAKE2b-256.
+pub trait Hasher256 {
+	fn hash256(bytes: &[u8]) -> [u8; 32];
+}
+
+/// A toy 256 bit hash made of four independent 64 bit lanes. The first lane is the standard
+/// library's hash of the bytes, and each later lane hashes its own index before the bytes. Not
+/// secure.
+pub struct ToyHasher;
+
+impl Hasher256 for ToyHasher {
+	fn hash256(bytes: &[u8]) -> [u8; 32] {
+		let mut out = [0; 32];
+		for lane in 0..4u8 {
+			let mut hasher = DefaultHasher::new();
+			if lane > 0 {
+				hasher.write_u8(lane);
+			}
+			hasher.write(bytes);
+			out[lane as usize * 8..][..8].copy_from_slice(&hasher.finish().to_le_bytes());
+		}
+		out
+	}
+}
+
+/// SHA-256, as specified in FIPS 180-4.
+#[cfg(feature = "sha256")]
+pub struct Sha256;
+
+#[cfg(feature = "sha256")]
+impl Hasher256 for Sha256 {
+	fn hash256(bytes: &[u8]) -> [u8; 32] {
+		const K: [u32; 64] = [
+			0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+			0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+			0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+			0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+			0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+			0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+			0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+			0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+			0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+			0xc67178f2,
+		];
+		let mut state: [u32; 8] = [
+			0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+			0x5be0cd19,
+		];
+
+		// Pad to a whole number of 64 byte blocks, ending with the message length in bits.
+		let mut message = bytes.to_vec();
+		message.push(0x80);
+		while message.len() % 64 != 56 {
+			message.push(0);
+		}
+		message.extend_from_slice(&(bytes.len() as u64).wrapping_mul(8).to_be_bytes());
+
+		for block in message.chunks_exact(64) {
+			let mut schedule = [0u32; 64];
+			for (word, bytes) in schedule.iter_mut().zip(block.chunks_exact(4)) {
+				*word = u32::from_be_bytes(bytes.try_into().expect("the chunk is 4 bytes"));
+			}
+			for i in 16..64 {
+				let (w15, w2) = (schedule[i - 15], schedule[i - 2]);
+				let s0 = w15.rotate_right(7) ^ w15.rotate_right(18) ^ (w15 >> 3);
+				let s1 = w2.rotate_right(17) ^ w2.rotate_right(19) ^ (w2 >> 10);
+				schedule[i] = schedule[i - 16]
+					.wrapping_add(s0)
+					.wrapping_add(schedule[i - 7])
+					.wrapping_add(s1);
+			}
+
+			let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+			for (k, w) in K.iter().zip(schedule) {
+				let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+				let ch = (e & f) ^ (!e & g);
+				let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(*k).wrapping_add(w);
+				let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+				let maj = (a & b) ^ (a & c) ^ (b & c);
+				let t2 = s0.wrapping_add(maj);
+				(h, g, f, e, d, c, b, a) =
+					(g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+			}
+			for (word, added) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+				*word = word.wrapping_add(added);
+			}
+		}
+
+		let mut out = [0; 32];
+		for (bytes, word) in out.chunks_exact_mut(4).zip(state) {
+			bytes.copy_from_slice(&word.to_be_bytes());
+		}
+		out
+	}
+}
+
+/// The low 8 bytes of a 256 bit hash, read in little-endian order.
+pub fn low_u64(hash: &[u8; 32]) -> u64 {
+	u64::from_le_bytes(hash[..8].try_into().expect("the slice is 8 bytes"))
+}
+
+/// A `Hasher` that records the bytes it is given, rather than hashing them.
+struct Preimage(Vec<u8>);
+
+impl Hasher for Preimage {
+	fn write(&mut self, bytes: &[u8]) {
+		self.0.extend_from_slice(bytes);
+	}
+
+	fn finish(&self) -> u64 {
+		unreachable!("the preimage is read directly, never finished")
+	}
+}
+
+impl<D: Hash> Header<D> {
+	/// The 256 bit hash of the header, under the toy hash function. Its low 8 bytes are the
+	/// header's 64 bit `hash`.
+	pub fn hash256(&self) -> [u8; 32] {
+		self.hash256_with::<ToyHasher>()
+	}
+
+	/// The 256 bit hash of the header, under the given hash function. Like the 64 bit hash, the
+	/// preimage starts with this chapter's domain tag.
+	pub fn hash256_with<H: Hasher256>(&self) -> [u8; 32] {
+		let mut preimage = Preimage(Vec::new());
+		self.hash(&mut preimage);
+		H::hash256(&preimage.0)
+	}
+}
+
+#[cfg(test)]
+use super::ConsensusAuthority;
+#[cfg(test)]
+use crate::hash;
+
+#[cfg(test)]
+fn header(height: u64) -> Header<u64> {
+	Header { parent: 1, height, state_root: 3, extrinsics_root: 4, consensus_digest: 5 }
+}
+
+#[test]
+fn cs_hash256_is_deterministic() {
+	assert_eq!(header(2).hash256(), header(2).hash256());
+	assert_ne!(header(2).hash256(), header(3).hash256());
+}
+
+#[test]
+fn cs_hash_is_the_low_bytes_of_hash256() {
+	let signed = header(2).with_digest(ConsensusAuthority::Bob);
+	let unsealed = header(2).with_digest(());
+	let wrapped = header(2).with_digest((7u64, vec![ConsensusAuthority::Alice]));
+
+	assert_eq!(hash(&header(2)), low_u64(&header(2).hash256()));
+	assert_eq!(hash(&signed), low_u64(&signed.hash256()));
+	assert_eq!(hash(&unsealed), low_u64(&unsealed.hash256()));
+	assert_eq!(hash(&wrapped), low_u64(&wrapped.hash256()));
+}
+
+#[test]
+fn cs_toy_hasher_lanes_are_independent() {
+	let digest = ToyHasher::hash256(&[1, 2, 3]);
+	let lanes: Vec<_> = digest.chunks_exact(8).collect();
+
+	for (i, lane) in lanes.iter().enumerate() {
+		assert!(lanes[i + 1..].iter().all(|other| other != lane));
+	}
+}
+
+#[test]
+fn cs_hash256_with_custom_hasher() {
+	// Not a hash at all, but it shows exactly which bytes the hash function is given.
+	struct Prefix;
+	impl Hasher256 for Prefix {
+		fn hash256(bytes: &[u8]) -> [u8; 32] {
+			let mut out = [0; 32];
+			out.copy_from_slice(&bytes[..32]);
+			out
+		}
+	}
+
+	let prefix = header(2).hash256_with::<Prefix>();
+	assert_eq!(prefix[0], b'3');
+	assert_eq!(&prefix[1..9], &1u64.to_ne_bytes());
+	assert_eq!(&prefix[9..17], &2u64.to_ne_bytes());
+}
+
+#[cfg(feature = "sha256")]
+#[test]
+fn cs_sha256_matches_known_vectors() {
+	let hex =
+		|digest: [u8; 32]| digest.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+
+	assert_eq!(
+		hex(Sha256::hash256(b"")),
+		"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+	);
+	assert_eq!(
+		hex(Sha256::hash256(b"abc")),
+		"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+	);
+	assert_eq!(
+		hex(Sha256::hash256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+		"248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+	);
+}
+
+#[cfg(feature = "sha256")]
+#[test]
+fn cs_hash256_with_sha256() {
+	assert_eq!(header(2).hash256_with::<Sha256>(), header(2).hash256_with::<Sha256>());
+	assert_ne!(header(2).hash256_with::<Sha256>(), header(2).hash256());
+}
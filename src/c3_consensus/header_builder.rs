@@ -0,0 +1,146 @@
+//! Headers have five fields, four of which are plain hashes or numbers, so it is easy to build one
+//! with two fields swapped or with a height that does not follow its parent. `HeaderBuilder` names
+//! every field as it is set, and checks that the header it builds is consistent with its parent.
+
+use super::{Hash, Header};
+use crate::{hash, ChainError};
+
+/// Builds a header one field at a time. Fields that are never set are zero, so an untouched builder
+/// gives a genesis-like header.
+#[derive(Clone, Debug, Default)]
+pub struct HeaderBuilder<D> {
+	parent: Hash,
+	parent_height: Option<u64>,
+	height: Option<u64>,
+	state_root: Hash,
+	extrinsics_root: Hash,
+	consensus_digest: D,
+}
+
+impl HeaderBuilder<()> {
+	/// Start building a partial header, with no consensus digest yet.
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl<D> HeaderBuilder<D> {
+	/// Build on top of the given parent. The height follows the parent's unless it is set
+	/// explicitly, in which case `build` checks that it does.
+	pub fn child_of<P: std::hash::Hash>(mut self, parent: &Header<P>) -> Self {
+		self.parent = hash(parent);
+		self.parent_height = Some(parent.height);
+		self
+	}
+
+	/// Set the parent hash directly. Unlike `child_of`, the height can not be checked.
+	pub fn parent(mut self, parent: Hash) -> Self {
+		self.parent = parent;
+		self.parent_height = None;
+		self
+	}
+
+	pub fn height(mut self, height: u64) -> Self {
+		self.height = Some(height);
+		self
+	}
+
+	pub fn state_root(mut self, state_root: Hash) -> Self {
+		self.state_root = state_root;
+		self
+	}
+
+	pub fn extrinsics_root(mut self, extrinsics_root: Hash) -> Self {
+		self.extrinsics_root = extrinsics_root;
+		self
+	}
+
+	/// Set the consensus digest, which may change the type of header being built.
+	pub fn digest<E>(self, consensus_digest: E) -> HeaderBuilder<E> {
+		HeaderBuilder {
+			parent: self.parent,
+			parent_height: self.parent_height,
+			height: self.height,
+			state_root: self.state_root,
+			extrinsics_root: self.extrinsics_root,
+			consensus_digest,
+		}
+	}
+
+	/// Build the header.
+	///
+	/// Fails with `WrongHeight` if the header was built on top of a parent with `child_of`, and its
+	/// height is not exactly one more than the parent's.
+	pub fn build(self) -> Result<Header<D>, ChainError> {
+		let child_height = self.parent_height.map(|height| height.checked_add(1));
+		let height = match (self.height, child_height) {
+			(Some(height), Some(expected)) if Some(height) != expected =>
+				return Err(ChainError::WrongHeight),
+			(Some(height), _) => height,
+			(None, Some(expected)) => expected.ok_or(ChainError::WrongHeight)?,
+			(None, None) => 0,
+		};
+
+		Ok(Header {
+			parent: self.parent,
+			height,
+			state_root: self.state_root,
+			extrinsics_root: self.extrinsics_root,
+			consensus_digest: self.consensus_digest,
+		})
+	}
+}
+
+#[cfg(test)]
+use super::{p1_pow::PoW, Consensus};
+
+#[test]
+fn cs_header_builder_builds_valid_child() {
+	let engine = PoW::new(u64::MAX / 4);
+	let genesis = engine.genesis_header();
+	let partial = HeaderBuilder::new()
+		.child_of(&genesis)
+		.extrinsics_root(3)
+		.state_root(3)
+		.build()
+		.unwrap();
+
+	assert_eq!(
+		partial,
+		Header {
+			parent: hash(&genesis),
+			height: 1,
+			state_root: 3,
+			extrinsics_root: 3,
+			consensus_digest: (),
+		}
+	);
+	let header = engine.seal(&genesis.consensus_digest, partial).unwrap();
+	assert!(header.is_child_of(&genesis));
+	assert!(engine.validate(&genesis.consensus_digest, &header));
+
+	let rebuilt = HeaderBuilder::new()
+		.child_of(&genesis)
+		.height(1)
+		.extrinsics_root(3)
+		.state_root(3)
+		.digest(header.consensus_digest)
+		.build();
+	assert_eq!(rebuilt, Ok(header));
+}
+
+#[test]
+fn cs_header_builder_rejects_inconsistent_height() {
+	let genesis = PoW::new(u64::MAX / 4).genesis_header();
+
+	assert_eq!(
+		HeaderBuilder::new().child_of(&genesis).height(2).build(),
+		Err(ChainError::WrongHeight)
+	);
+
+	let last = HeaderBuilder::new().height(u64::MAX).build().unwrap();
+	assert_eq!(HeaderBuilder::new().child_of(&last).build(), Err(ChainError::WrongHeight));
+
+	// Without a parent header, there is nothing to check the height against.
+	assert!(HeaderBuilder::new().parent(hash(&genesis)).height(2).build().is_ok());
+}
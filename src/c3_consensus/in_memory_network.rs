@@ -0,0 +1,177 @@
+//! A test harness that connects several `ChainStore` nodes through an in-memory network, so that
+//! header propagation and fork choice can be exercised together.
+//!
+//! Real networks deliver messages late, out of order, or not at all. The network can simulate
+//! the first two by delivering each batch of messages in reverse order, so that children arrive
+//! before their parents, and the last by dropping every nth message. Both are deterministic, so
+//! tests stay reproducible.
+
+use std::collections::VecDeque;
+
+use super::{chain_store::ChainStore, Header};
+use crate::ChainError;
+
+/// Several `ChainStore` nodes that gossip headers to each other.
+pub struct InMemoryNetwork<D> {
+	nodes: Vec<ChainStore<D>>,
+	/// Headers a node received before their parent, waiting for the parent to arrive.
+	orphans: Vec<Vec<Header<D>>>,
+	/// Messages sent but not yet delivered, along with the index of the node they are sent to.
+	in_flight: VecDeque<(usize, Header<D>)>,
+	reorder: bool,
+	drop_every: Option<usize>,
+	sent: usize,
+}
+
+impl<D: Clone + Eq + std::hash::Hash> InMemoryNetwork<D> {
+	/// Connect the given nodes to each other, with a network that delivers every message in order.
+	pub fn new(nodes: Vec<ChainStore<D>>) -> Self {
+		let orphans = vec![Vec::new(); nodes.len()];
+		InMemoryNetwork {
+			nodes,
+			orphans,
+			in_flight: VecDeque::new(),
+			reorder: false,
+			drop_every: None,
+			sent: 0,
+		}
+	}
+
+	/// Deliver each batch of messages in the reverse of the order they were sent.
+	pub fn reorder(mut self) -> Self {
+		self.reorder = true;
+		self
+	}
+
+	/// Drop every `n`th message sent. `n` must not be zero.
+	pub fn drop_every(mut self, n: usize) -> Self {
+		assert!(n > 0, "can not drop every zeroth message");
+		self.drop_every = Some(n);
+		self
+	}
+
+	/// The node with the given index.
+	pub fn node(&self, index: usize) -> &ChainStore<D> {
+		&self.nodes[index]
+	}
+
+	/// The canonical head of every node.
+	pub fn heads(&self) -> Vec<u64> {
+		self.nodes.iter().map(ChainStore::head).collect()
+	}
+
+	/// Send the given header from the given node to all of its peers.
+	pub fn broadcast(&mut self, from: usize, header: &Header<D>) {
+		for to in (0..self.nodes.len()).filter(|to| *to != from) {
+			self.sent += 1;
+			if self.drop_every.is_some_and(|n| self.sent.is_multiple_of(n)) {
+				continue;
+			}
+			self.in_flight.push_back((to, header.clone()));
+		}
+	}
+
+	/// Send every header of the given node's canonical chain, except genesis, to all of its peers.
+	pub fn announce_canonical(&mut self, from: usize) {
+		let node = &self.nodes[from];
+		let chain = node.headers_between(node.genesis(), node.head()).unwrap_or_default();
+		for header in chain.iter().skip(1) {
+			self.broadcast(from, header);
+		}
+	}
+
+	/// Deliver all messages in flight, and have each node import the headers it receives.
+	///
+	/// Headers whose parent a node does not know yet are kept until the parent arrives. Headers
+	/// that are invalid for any other reason are discarded.
+	pub fn deliver_all(&mut self) {
+		let mut batch: Vec<_> = self.in_flight.drain(..).collect();
+		if self.reorder {
+			batch.reverse();
+		}
+		for (to, header) in batch {
+			self.receive(to, header);
+		}
+	}
+
+	/// Import a header into the given node, along with any orphans it was the missing parent of.
+	fn receive(&mut self, to: usize, header: Header<D>) {
+		match self.nodes[to].import(header.clone()) {
+			Err(ChainError::UnknownParent) => {
+				self.orphans[to].push(header);
+				return;
+			}
+			Err(_) => return,
+			Ok(_) => {}
+		}
+
+		let orphans = std::mem::take(&mut self.orphans[to]);
+		let (ready, waiting): (Vec<_>, Vec<_>) =
+			orphans.into_iter().partition(|orphan| self.nodes[to].contains(orphan.parent));
+		self.orphans[to] = waiting;
+		for orphan in ready {
+			self.receive(to, orphan);
+		}
+	}
+}
+
+#[cfg(test)]
+use super::{p1_pow::PoW, test_support::sealed_adder_chain, Consensus};
+#[cfg(test)]
+use crate::hash;
+
+/// Two nodes that share a genesis, but have each followed a different fork of it. The second
+/// fork is higher, so it is canonical.
+#[cfg(test)]
+fn forked_nodes() -> (Vec<ChainStore<u64>>, Header<u64>) {
+	let light = PoW::new(u64::MAX / 2);
+	let heavy = PoW::new(u64::MAX / 1000);
+	let genesis = light.genesis_header();
+
+	let mut nodes = vec![ChainStore::new(genesis.clone()), ChainStore::new(genesis.clone())];
+	for header in sealed_adder_chain(&light, &genesis, 2) {
+		nodes[0].import(header).unwrap();
+	}
+	let heavy_chain = sealed_adder_chain(&heavy, &genesis, 4);
+	for header in &heavy_chain {
+		nodes[1].import(header.clone()).unwrap();
+	}
+	(nodes, heavy_chain.last().unwrap().clone())
+}
+
+#[test]
+fn cs_network_nodes_on_different_forks_converge_on_canonical_chain() {
+	let (nodes, heavy_tip) = forked_nodes();
+	let mut network = InMemoryNetwork::new(nodes);
+	assert_ne!(network.heads()[0], network.heads()[1]);
+
+	network.announce_canonical(0);
+	network.announce_canonical(1);
+	network.deliver_all();
+
+	assert_eq!(network.heads(), vec![hash(&heavy_tip); 2]);
+	// Both nodes also know the losing fork.
+	assert_eq!(network.node(0).leaves().len(), 2);
+	assert_eq!(network.node(1).leaves().len(), 2);
+}
+
+#[test]
+fn cs_network_converges_despite_reordering_and_drops() {
+	let (nodes, heavy_tip) = forked_nodes();
+	let mut network = InMemoryNetwork::new(nodes).reorder().drop_every(4);
+
+	// Children arrive before their parents, and some headers are lost, so a single round of
+	// gossip is not enough.
+	network.announce_canonical(0);
+	network.announce_canonical(1);
+	network.deliver_all();
+	assert_ne!(network.heads()[0], hash(&heavy_tip));
+
+	for _ in 0..5 {
+		network.announce_canonical(0);
+		network.announce_canonical(1);
+		network.deliver_all();
+	}
+
+	assert_eq!(network.heads(), vec![hash(&heavy_tip); 2]);
+}
@@ -0,0 +1,201 @@
+//! Checking a block means rebuilding the Merkle tree of its extrinsics, so an author who packs a
+//! huge body makes every node that imports the block do a lot of work. Bounding the depth of the
+//! tree bounds that work: a tree of depth `d` holds at most `2^d` extrinsics.
+//!
+//! The extrinsics root alone says nothing about how many extrinsics are under it, so the limit can
+//! only be enforced on complete blocks. It is checked by counting the body before the tree is
+//! rebuilt, so an oversized block is rejected without doing the work it was meant to cause.
+
+use super::{block::Block, Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::ChainError;
+
+/// The depth of the Merkle tree over the given number of extrinsics. A tree over no extrinsics or
+/// a single extrinsic has depth zero.
+pub fn merkle_depth(extrinsics: usize) -> u32 {
+	extrinsics.max(1).next_power_of_two().trailing_zeros()
+}
+
+/// A Consensus engine that bounds the depth of each block's extrinsics tree. Wraps an inner
+/// consensus engine whose rules will also be enforced.
+pub struct MaxTreeDepth<Inner> {
+	inner: Inner,
+	max_depth: u32,
+}
+
+impl<Inner: Consensus> MaxTreeDepth<Inner> {
+	/// Create a new engine that allows trees of at most the given depth on top of the given
+	/// engine.
+	pub fn new(inner: Inner, max_depth: u32) -> Self {
+		MaxTreeDepth { inner, max_depth }
+	}
+
+	/// Whether a tree over the given number of extrinsics is within the depth limit, which is the
+	/// case when there are at most `2^max_depth` of them.
+	pub fn fits(&self, extrinsics: usize) -> bool {
+		1usize.checked_shl(self.max_depth).is_none_or(|max| extrinsics <= max)
+	}
+
+	/// Seal a block with the given body.
+	///
+	/// Returns `None` if the body holds more than `2^max_depth` extrinsics, or if the inner engine
+	/// can not seal it.
+	pub fn seal_block(
+		&self,
+		parent_digest: &Inner::Digest,
+		partial_header: Header<()>,
+		body: Vec<u64>,
+	) -> Option<Block<Inner::Digest>> {
+		if !self.fits(body.len()) {
+			return None;
+		}
+		let header = self.inner.seal(parent_digest, partial_header)?;
+		Some(Block { header, body })
+	}
+}
+
+impl<Inner: Consensus> Consensus for MaxTreeDepth<Inner> {
+	type Digest = Inner::Digest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Without the body, the depth of the tree is unknown, so a header is checked by the inner
+	/// engine alone.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		self.inner.check_header(parent_digest, header)
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(parent_digest, header)
+	}
+
+	/// Check that the body is within the depth limit before rebuilding its tree, and then check
+	/// the block like any other engine.
+	fn check_block(
+		&self,
+		parent_digest: &Self::Digest,
+		block: &Block<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if !self.fits(block.body.len()) {
+			return Err(ChainError::TreeTooDeep);
+		}
+		block.check_extrinsics_root()?;
+		self.check_header(parent_digest, &block.header)
+	}
+
+	/// Seal the partial header with the inner engine. Without the body, the depth of the tree is
+	/// unknown, so use `seal_block` to have the author refuse oversized blocks.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.inner.seal(parent_digest, partial_header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.inner.genesis_header()
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(header)
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("max-tree-depth");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Max Tree Depth {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{header_builder::HeaderBuilder, p1_pow::PoW};
+#[cfg(test)]
+use crate::merkle_root;
+
+/// A partial child of the given parent whose header commits to the given body.
+#[cfg(test)]
+fn partial_child(parent: &Header<u64>, body: &[u64]) -> Header<()> {
+	HeaderBuilder::new()
+		.child_of(parent)
+		.state_root(parent.state_root + body.iter().sum::<u64>())
+		.extrinsics_root(merkle_root(body))
+		.build()
+		.unwrap()
+}
+
+#[test]
+fn cs_max_tree_depth_merkle_depth() {
+	assert_eq!(merkle_depth(0), 0);
+	assert_eq!(merkle_depth(1), 0);
+	assert_eq!(merkle_depth(2), 1);
+	assert_eq!(merkle_depth(3), 2);
+	assert_eq!(merkle_depth(4), 2);
+	assert_eq!(merkle_depth(5), 3);
+}
+
+#[test]
+fn cs_max_tree_depth_accepts_full_tree() {
+	let engine = MaxTreeDepth::new(PoW::new(u64::MAX / 4), 3);
+	let genesis = engine.genesis_header();
+	let body: Vec<u64> = (1..=8).collect();
+
+	let block = engine
+		.seal_block(&genesis.consensus_digest, partial_child(&genesis, &body), body)
+		.unwrap();
+	assert_eq!(engine.check_block(&genesis.consensus_digest, &block), Ok(()));
+}
+
+#[test]
+fn cs_max_tree_depth_rejects_one_extrinsic_too_many() {
+	let engine = MaxTreeDepth::new(PoW::new(u64::MAX / 4), 3);
+	let genesis = engine.genesis_header();
+	let body: Vec<u64> = (1..=9).collect();
+	let partial = partial_child(&genesis, &body);
+
+	// The author refuses to pack the block.
+	assert_eq!(engine.seal_block(&genesis.consensus_digest, partial.clone(), body.clone()), None);
+
+	// A header sealed without its body passes on its own, but the body gives it away.
+	let header = engine.seal(&genesis.consensus_digest, partial).unwrap();
+	assert!(engine.validate(&genesis.consensus_digest, &header));
+	let block = Block { header, body };
+	assert_eq!(engine.check_block(&genesis.consensus_digest, &block), Err(ChainError::TreeTooDeep));
+}
+
+#[test]
+fn cs_max_tree_depth_rejects_oversized_body_before_checking_root() {
+	let engine = MaxTreeDepth::new(PoW::new(u64::MAX / 4), 3);
+	let genesis = engine.genesis_header();
+	let header = engine.seal(&genesis.consensus_digest, partial_child(&genesis, &[])).unwrap();
+
+	// The root does not match either, but the size alone is enough to reject the block.
+	let block = Block { header, body: vec![0; 9] };
+	assert_eq!(engine.check_block(&genesis.consensus_digest, &block), Err(ChainError::TreeTooDeep));
+	assert!(MaxTreeDepth::new(PoW::new(u64::MAX / 4), 64).fits(usize::MAX));
+}
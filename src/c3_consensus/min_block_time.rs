@@ -0,0 +1,237 @@
+//! Proof of Work only controls block time on average, and Proof of Authority not at all, since an
+//! authority may sign as fast as it likes. Some chains also enforce a minimum interval between
+//! blocks, so that no author can flood the chain however lucky or fast they are.
+//!
+//! Here we enforce that with a higher-order engine. Headers in this chapter have no timestamp, so,
+//! like the slot in `SlotDigest`, the timestamp lives in the digest. The engine asks an injected
+//! `Clock` for the time when sealing, so that tests stay deterministic.
+//!
+//! A minimum interval alone is easy to game. An author could stamp a block far in the future, and
+//! every later block would have to wait for that time to come. So, like Aura rejects slots that
+//! have not started yet, this engine rejects timestamps too far ahead of the local clock. And the
+//! timestamp is part of what the inner engine seals, so nobody but the author can change it.
+
+use super::{clock::Clock, Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::{hash, ChainError};
+
+/// How far, in milliseconds, a header's timestamp may be ahead of the local clock. Clocks of
+/// honest nodes never agree exactly, so a little drift must be tolerated. This matches the drift
+/// Substrate's timestamp pallet allows.
+pub const MAX_DRIFT: u64 = 30_000;
+
+/// The digest of a `MinBlockTime` engine. The inner engine's digest along with the time, in
+/// milliseconds since the Unix epoch, at which the block was sealed.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TimedDigest<D> {
+	pub(crate) inner: D,
+	pub(crate) timestamp: u64,
+}
+
+/// A Consensus engine that requires at least `min_interval` milliseconds between a block and its
+/// parent. Wraps an inner consensus engine whose rules will also be enforced.
+pub struct MinBlockTime<Inner, C: Clock> {
+	inner: Inner,
+	min_interval: u64,
+	clock: C,
+}
+
+impl<Inner: Consensus, C: Clock> MinBlockTime<Inner, C> {
+	/// Create a new engine that enforces the given minimum interval on top of the given engine.
+	pub fn new(inner: Inner, min_interval: u64, clock: C) -> Self {
+		MinBlockTime { inner, min_interval, clock }
+	}
+
+	/// The earliest time at which a child of a block with the given digest may be sealed.
+	pub fn earliest_child(&self, parent_digest: &TimedDigest<Inner::Digest>) -> u64 {
+		parent_digest.timestamp.saturating_add(self.min_interval)
+	}
+
+	/// The extrinsics root the inner engine seals, committing to the timestamp as well.
+	fn committed_root(extrinsics_root: u64, timestamp: u64) -> u64 {
+		hash(&(extrinsics_root, timestamp))
+	}
+
+	/// The header as the inner engine sees it, carrying only the inner digest, and committing to
+	/// the timestamp in its extrinsics root.
+	fn inner_header(header: &Header<TimedDigest<Inner::Digest>>) -> Header<Inner::Digest> {
+		let digest = &header.consensus_digest;
+		Header {
+			extrinsics_root: Self::committed_root(header.extrinsics_root, digest.timestamp),
+			..header.with_digest(digest.inner.clone())
+		}
+	}
+}
+
+impl<Inner: Consensus, C: Clock> Consensus for MinBlockTime<Inner, C> {
+	type Digest = TimedDigest<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that enough time passed since the parent, that the timestamp is not too far in the
+	/// future, and that the inner engine accepts the header.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let timestamp = header.consensus_digest.timestamp;
+		if timestamp < self.earliest_child(parent_digest) {
+			return Err(ChainError::BlockTooSoon);
+		}
+		if timestamp > self.clock.now().saturating_add(MAX_DRIFT) {
+			return Err(ChainError::FutureTimestamp);
+		}
+		self.inner.check_header(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Seal the partial header with the inner engine, stamped with the current time. Returns
+	/// `None` if the minimum interval has not passed yet, in which case the author should wait
+	/// until `earliest_child` and try again.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let timestamp = self.clock.now();
+		if timestamp < self.earliest_child(parent_digest) {
+			return None;
+		}
+		let extrinsics_root = partial_header.extrinsics_root;
+		let committed = Header {
+			extrinsics_root: Self::committed_root(extrinsics_root, timestamp),
+			..partial_header
+		};
+		let header = self.inner.seal(&parent_digest.inner, committed)?;
+
+		Some(Header {
+			extrinsics_root,
+			..header.map_digest(|inner| TimedDigest { inner, timestamp })
+		})
+	}
+
+	/// Genesis is stamped with time zero, so the first block may be sealed at any time.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.inner.genesis_header();
+
+		genesis.map_digest(|inner| TimedDigest { inner, timestamp: 0 })
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header))
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(&Self::inner_header(header))
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("min-block-time");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Min Block Time {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	clock::MockClock, p1_pow::PoW, p3_poa::PoaRoundRobinByHeight, test_support::sealed_child,
+};
+
+#[cfg(test)]
+fn round_robin() -> PoaRoundRobinByHeight {
+	PoaRoundRobinByHeight::new(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob])
+}
+
+#[test]
+fn cs_min_block_time_accepts_exact_interval() {
+	let clock = MockClock::new(1_000);
+	let engine = MinBlockTime::new(round_robin(), 500, &clock);
+	let genesis = engine.genesis_header();
+	let parent = sealed_child(&engine, &genesis, 1);
+
+	clock.advance(500);
+	let child = sealed_child(&engine, &parent, 2);
+
+	assert_eq!(child.consensus_digest.timestamp, 1_500);
+	assert_eq!(engine.check_sub_chain(&genesis, &[parent, child]), Ok(()));
+}
+
+#[test]
+fn cs_min_block_time_rejects_too_soon_block() {
+	let clock = MockClock::new(1_000);
+	let engine = MinBlockTime::new(round_robin(), 500, &clock);
+	let parent = sealed_child(&engine, &engine.genesis_header(), 1);
+
+	// An impatient author who ignores the minimum interval.
+	clock.advance(499);
+	let impatient = MinBlockTime::new(round_robin(), 0, &clock);
+	let child = sealed_child(&impatient, &parent, 2);
+	assert_eq!(
+		engine.check_sub_chain(&parent, std::slice::from_ref(&child)),
+		Err(ChainError::BlockTooSoon)
+	);
+
+	// A patient author waits instead.
+	let partial = child.with_digest(());
+	assert_eq!(engine.seal(&parent.consensus_digest, partial), None);
+	assert_eq!(engine.earliest_child(&parent.consensus_digest), 1_500);
+}
+
+#[test]
+fn cs_min_block_time_rejects_timestamp_beyond_drift() {
+	let clock = MockClock::new(1_000);
+	let engine = MinBlockTime::new(round_robin(), 500, &clock);
+	let genesis = engine.genesis_header();
+
+	// An author whose clock runs fast stamps its block far ahead of ours.
+	let fast_clock = MockClock::new(1_000 + MAX_DRIFT + 1);
+	let fast = MinBlockTime::new(round_robin(), 500, &fast_clock);
+	let early = sealed_child(&fast, &genesis, 1);
+	assert_eq!(
+		engine.check_header(&genesis.consensus_digest, &early),
+		Err(ChainError::FutureTimestamp)
+	);
+
+	// Within the drift, the block is accepted.
+	fast_clock.set(1_000 + MAX_DRIFT);
+	let child = sealed_child(&fast, &genesis, 1);
+	assert_eq!(engine.check_header(&genesis.consensus_digest, &child), Ok(()));
+}
+
+#[test]
+fn cs_min_block_time_seal_commits_to_timestamp() {
+	let clock = MockClock::new(1_000);
+	let engine = MinBlockTime::new(PoW::new(u64::MAX >> 16), 500, &clock);
+	let genesis = engine.genesis_header();
+	let mut child = sealed_child(&engine, &genesis, 1);
+	assert_eq!(engine.check_header(&genesis.consensus_digest, &child), Ok(()));
+
+	// A relayer pushes the timestamp forward, hoping to delay the next block.
+	child.consensus_digest.timestamp += 10_000;
+	clock.set(child.consensus_digest.timestamp);
+	assert_eq!(
+		engine.check_header(&genesis.consensus_digest, &child),
+		Err(ChainError::InsufficientWork)
+	);
+}
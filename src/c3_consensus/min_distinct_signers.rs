@@ -0,0 +1,194 @@
+//! A Proof of Authority chain can stall in a way that is easy to miss. If most authorities go
+//! offline, or are censored, the remaining ones may keep producing blocks on their own. The chain
+//! still grows, but it is no longer the decentralized chain its users signed up for.
+//!
+//! Here we detect that with a higher-order engine that requires a minimum number of distinct
+//! authorities among the signers of each window of recent blocks. As described on
+//! [`Consensus::Digest`], its digest records who signed the most recent blocks, alongside the inner
+//! engine's digest.
+
+use super::{Consensus, ConsensusAuthority, Header};
+use crate::ChainError;
+
+/// The digest of a `MinDistinctSigners` engine. The inner engine's digest, along with the signers
+/// of the most recent blocks.
+#[derive(Hash, Debug, PartialEq, Eq, Clone)]
+pub struct SignersDigest<D> {
+	pub(crate) inner: D,
+	/// The signers of up to `window` blocks, oldest first, ending with this block. Blocks whose
+	/// inner engine does not say who signed them are recorded as `None`.
+	pub(crate) recent_signers: Vec<Option<ConsensusAuthority>>,
+}
+
+/// A Consensus engine that rejects a block if the last `window` blocks, including it, were signed
+/// by fewer than `min_distinct` authorities. Wraps an inner consensus engine whose rules will also
+/// be enforced.
+///
+/// The rule only applies once the chain is at least `window` blocks long.
+pub struct MinDistinctSigners<Inner> {
+	inner: Inner,
+	window: usize,
+	min_distinct: usize,
+}
+
+impl<Inner: Consensus> MinDistinctSigners<Inner> {
+	/// Create a new engine on top of the given engine. The window must not be empty.
+	pub fn new(inner: Inner, window: usize, min_distinct: usize) -> Self {
+		assert!(window > 0, "the window must hold at least one block");
+		MinDistinctSigners { inner, window, min_distinct }
+	}
+
+	/// The signers of the window ending with a block signed by the given signer.
+	///
+	/// Returns `None` if the window is full, but holds too few distinct signers.
+	fn signers_after(
+		&self,
+		parent_signers: &[Option<ConsensusAuthority>],
+		signer: Option<ConsensusAuthority>,
+	) -> Option<Vec<Option<ConsensusAuthority>>> {
+		let mut signers = parent_signers.to_vec();
+		signers.push(signer);
+		signers.drain(..signers.len().saturating_sub(self.window));
+
+		if signers.len() < self.window {
+			return Some(signers);
+		}
+		let mut distinct = Vec::new();
+		for signer in signers.iter().flatten() {
+			if !distinct.contains(signer) {
+				distinct.push(*signer);
+			}
+		}
+		(distinct.len() >= self.min_distinct).then_some(signers)
+	}
+
+	/// The header as the inner engine sees it, carrying only the inner digest.
+	fn inner_header(header: &Header<SignersDigest<Inner::Digest>>) -> Header<Inner::Digest> {
+		header.with_digest(header.consensus_digest.inner.clone())
+	}
+}
+
+impl<Inner: Consensus> Consensus for MinDistinctSigners<Inner> {
+	type Digest = SignersDigest<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that the inner engine accepts the header, that the window holds enough distinct
+	/// signers, and that the digest records the signers correctly.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let inner_header = Self::inner_header(header);
+		self.inner.check_header(&parent_digest.inner, &inner_header)?;
+
+		let signer = self.inner.seal_author(&inner_header);
+		let signers = self
+			.signers_after(&parent_digest.recent_signers, signer)
+			.ok_or(ChainError::TooFewSigners)?;
+		if header.consensus_digest.recent_signers != signers {
+			return Err(ChainError::InvalidSeal);
+		}
+		Ok(())
+	}
+
+	/// Check only the inner seal, not the window of recent signers.
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Seal the partial header with the inner engine. Headers that would leave too few distinct
+	/// signers in the window can not be sealed.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let header = self.inner.seal(&parent_digest.inner, partial_header)?;
+		let signer = self.inner.seal_author(&header);
+		let recent_signers = self.signers_after(&parent_digest.recent_signers, signer)?;
+
+		Some(header.map_digest(|inner| SignersDigest { inner, recent_signers }))
+	}
+
+	/// Genesis is not signed, so it starts with an empty window.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.inner.genesis_header();
+
+		genesis.map_digest(|inner| SignersDigest { inner, recent_signers: Vec::new() })
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header))
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(&Self::inner_header(header))
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("min-distinct-signers");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Min Distinct Signers {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	p3_poa::PoaRoundRobinByHeight,
+	test_support::{check_adder_chain, sealed_adder_chain, sealed_child},
+};
+
+#[test]
+fn cs_min_distinct_signers_accepts_diverse_window() {
+	use ConsensusAuthority::{Alice, Bob, Charlie};
+
+	let engine =
+		MinDistinctSigners::new(PoaRoundRobinByHeight::new(vec![Alice, Bob, Charlie]), 3, 3);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 7);
+
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+	assert_eq!(chain.last().unwrap().consensus_digest.recent_signers.len(), 3);
+}
+
+#[test]
+fn cs_min_distinct_signers_rejects_dominated_window() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	// Alice signs heights 3 and 4 in a row.
+	let authorities = vec![Alice, Alice, Bob];
+	let engine = MinDistinctSigners::new(PoaRoundRobinByHeight::new(authorities.clone()), 2, 2);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+
+	let parent = &chain[2];
+	let lenient = MinDistinctSigners::new(PoaRoundRobinByHeight::new(authorities), 2, 1);
+	let dominated = sealed_child(&lenient, parent, 4);
+
+	assert_eq!(engine.check_sub_chain(parent, &[dominated]), Err(ChainError::TooFewSigners));
+	let partial = Header {
+		parent: crate::hash(parent),
+		height: 4,
+		extrinsics_root: 4,
+		state_root: parent.state_root + 4,
+		consensus_digest: (),
+	};
+	assert_eq!(engine.seal(&parent.consensus_digest, partial), None);
+}
@@ -3,15 +3,69 @@
 //! previous module, then look at PoA, and other consensus engines all implementing the same simple
 //! interface.
 
+mod attack_simulation;
+mod authority_registry;
+mod authoring;
+mod block;
+mod bloom;
+mod bounded;
+mod bridge;
+mod cached;
+mod chain_spec;
+mod chain_store;
+mod clock;
+mod codec;
+mod config;
+mod confirmation_depth;
+mod digest_log;
+mod double_sign;
+mod epoch_randomness;
+mod from_fns;
+mod genesis;
+mod hash256;
+mod header_builder;
+#[cfg(test)]
+mod in_memory_network;
+mod max_tree_depth;
+mod min_block_time;
+mod min_distinct_signers;
+mod monotonic_state;
+mod network;
+mod or;
+mod ordered_execution;
 mod p1_pow;
 mod p2_dictator;
 mod p3_poa; // exercise: dictator is a special case of poa. Create dictator in terms of PoA.
 mod p4_even_only;
 mod p5_interleave;
 mod p6_forking;
+mod parity_rule;
+mod recording;
+mod rewards;
+mod safe;
+mod spv;
+mod stage_rules;
+mod supply_capped;
+mod sync;
+#[cfg(test)]
+mod test_support;
+mod uncles;
+
+use block::Block;
+use spv::SpvProof;
+use crate::{hash, ChainError};
+
+/// Entry points for the fuzz targets in `fuzz/`. See `fuzz/README.md`.
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing {
+	pub use super::p1_pow::fuzz_pow_round_trip as pow_round_trip;
+}
 
 type Hash = u64;
 
+/// The rules `check_sub_chain` enforces for every engine, whatever else the engine checks.
+const BASE_RULES: [&str; 2] = ["parent-link", "height+1"];
+
 /// A Block Header similar to prior chapters of this tutorial.
 ///
 /// Different consensus engines, require different information in the consensus digest.
@@ -20,7 +74,7 @@ type Hash = u64;
 /// Consensus engines do not know or care about the blockchain's state machine,
 /// which means they can operate entirely at the header level. They never need to touch
 /// the complete blocks.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Header<Digest> {
 	parent: Hash,
 	height: u64,
@@ -28,11 +82,89 @@ pub struct Header<Digest> {
 	extrinsics_root: Hash,
 	consensus_digest: Digest,
 }
+
+impl<Digest: std::hash::Hash> std::hash::Hash for Header<Digest> {
+	fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+		let Header { parent, height, state_root, extrinsics_root, consensus_digest } = self;
+		crate::hash_with_domain(
+			crate::C3_HEADER_DOMAIN,
+			(parent, height, state_root, extrinsics_root, consensus_digest),
+			hasher,
+		)
+	}
+}
+
+impl<Digest: std::hash::Hash> crate::ChainHeader for Header<Digest> {
+	fn height(&self) -> u64 {
+		self.height
+	}
+}
+
+/// A stable identifier for a block, derived only from the fields that identify it.
+///
+/// The full hash of a header also covers its consensus digest, which changes with every nonce a
+/// miner tries. A `BlockId` does not, so it stays the same throughout the search and can key maps
+/// of blocks that are still being sealed. It is the hash of the partial header that was sealed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u64);
+
+impl<Digest> Header<Digest> {
+	/// The identifier of this block, which ignores the consensus digest.
+	pub fn id(&self) -> BlockId {
+		let Header { parent, height, state_root, extrinsics_root, .. } = *self;
+		BlockId(hash(&Header { parent, height, state_root, extrinsics_root, consensus_digest: () }))
+	}
+
+	/// The same header, with its digest replaced by the result of the given function.
+	///
+	/// Higher-order engines use this to convert between their own headers and the headers their
+	/// inner engines see.
+	pub fn map_digest<E>(self, f: impl FnOnce(Digest) -> E) -> Header<E> {
+		let Header { parent, height, state_root, extrinsics_root, consensus_digest } = self;
+		let consensus_digest = f(consensus_digest);
+		Header { parent, height, state_root, extrinsics_root, consensus_digest }
+	}
+
+	/// The same header, carrying the given digest instead.
+	pub fn with_digest<E>(&self, consensus_digest: E) -> Header<E> {
+		let Header { parent, height, state_root, extrinsics_root, .. } = *self;
+		Header { parent, height, state_root, extrinsics_root, consensus_digest }
+	}
+}
+
+impl<Digest: std::hash::Hash> Header<Digest> {
+	/// Whether this header is linked to the given parent by hash and height. The state and
+	/// consensus digest are not checked.
+	pub fn is_child_of(&self, parent: &Header<Digest>) -> bool {
+		self.check_child_of(parent).is_ok()
+	}
+
+	/// Like `is_child_of`, but reports which part of the link is broken.
+	pub(crate) fn check_child_of(&self, parent: &Header<Digest>) -> Result<(), ChainError> {
+		if self.parent != hash(parent) {
+			return Err(ChainError::WrongParent);
+		}
+		if parent.height.checked_add(1) != Some(self.height) {
+			return Err(ChainError::WrongHeight);
+		}
+		Ok(())
+	}
+}
+
 /// A Consensus Engine. Responsible for Sealing blocks and verifying their seals
 ///
 /// Consensus exists independently of execution logic, and therefore operates
 /// only on the block headers.
+///
+/// This trait is dyn compatible, so engines can be chosen at runtime and stored as
+/// `Box<dyn Consensus<Digest = _>>`. Methods that would break that, such as those returning `Self`,
+/// belong in `ConsensusExt` or must be bounded by `Self: Sized`.
 pub trait Consensus {
+	/// The consensus data a header carries, such as a nonce or a signature.
+	///
+	/// An engine only ever sees the digest of a header's parent, never the earlier blocks. Engines
+	/// whose rules depend on history, such as `MonotonicState` or `ParityRule`, therefore carry the
+	/// history they need forward in their own digest, alongside the digest of the engine they wrap.
 	type Digest: Clone + core::fmt::Debug + Eq + PartialEq + std::hash::Hash;
 
 	/// Validates that a header is valid according to consensus rules. This
@@ -44,8 +176,39 @@ pub trait Consensus {
 	/// digest and the parent digest. For example, they may need to check that the
 	/// slot number is increasing. Therefore the parent digest is also passed
 	/// here. Other consensus engines will not need to use the parent digest at all.
+	#[must_use]
 	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool;
 
+	/// Validates a header against its complete parent header, rather than just the parent digest.
+	///
+	/// Some rules, such as retargeting difficulty from the parent block, need more context than
+	/// the parent digest carries. Engines with such rules override this method. The default simply
+	/// extracts the parent digest and calls `validate`.
+	#[must_use]
+	fn validate_with_parent(
+		&self,
+		parent: &Header<Self::Digest>,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.validate(&parent.consensus_digest, header)
+	}
+
+	/// Validates only the seal itself, such as the work proof or the signature, ignoring any rule
+	/// about the header's height or state.
+	///
+	/// This lets a tool check a single header and its claimed parent digest without knowing
+	/// whether the header's state is correct. Most engines only ever check the seal, so the
+	/// default calls `validate`. Engines that add rules about height or state override this to
+	/// check just the seal of the engine they wrap.
+	#[must_use]
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.validate(parent_digest, header)
+	}
+
 	/// Takes a partial header that does not yet have a consensus digest attached. Returns
 	/// a new header including the consensus digest that is valid according to the consensus rules.
 	///
@@ -61,45 +224,347 @@ pub trait Consensus {
 		parent_digest: &Self::Digest,
 		partial_header: Header<()>,
 	) -> Option<Header<Self::Digest>>;
-	// NOTE TO SELF. For slot-based PoA etc, just look at the system time. It's what real-world aura
-	// does
+
+	/// The genesis header of a chain governed by this engine.
+	///
+	/// Genesis is never validated, but its digest is the parent digest of the first block, so each
+	/// engine knows best what it should be. For example, slot-based engines start at slot zero.
+	fn genesis_header(&self) -> Header<Self::Digest>;
 
 	/// Verify that all the given headers are valid according to the consensus rules.
 	///
 	/// This method assumes that the parent_digest is valid, and verifies all the
 	/// following headers relative to the given parent digest. This is a provided method
 	/// on the trait, so it must be general enough to work for any specific consensus engine.
+	///
+	/// To verify a whole chain, pass the digest of the engine's own genesis header. There is no
+	/// default, because zero is only the right genesis digest for some engines, such as Proof of
+	/// Work. Proof of Authority genesis headers carry a real designated digest.
+	#[must_use]
 	fn verify_sub_chain(
 		&self,
 		parent_digest: &Self::Digest,
 		chain: &[Header<Self::Digest>],
 	) -> bool {
-		// Iterate over each pair of adjacent headers in the chain.
-        for window in chain.windows(2) {
-            let (parent_header, header) = match window {
-                [parent_header, header] => (parent_header, header),
-                _ => return false, // This should never happen due to the loop condition.
-            };
+		let mut parent_digest = parent_digest;
+
+		for header in chain {
+			if !self.validate(parent_digest, header) {
+				return false;
+			}
+			parent_digest = &header.consensus_digest;
+		}
+		true
+	}
+
+	/// The number of leading headers in the given chain that form a valid chain on top of the
+	/// given parent header.
+	///
+	/// A syncing node can keep that many headers from an untrusted peer, and discard the rest. Like
+	/// `check_sub_chain`, this checks that each header is linked to its parent by hash and height,
+	/// as well as the consensus rules.
+	fn longest_valid_prefix(
+		&self,
+		parent: &Header<Self::Digest>,
+		chain: &[Header<Self::Digest>],
+	) -> usize {
+		let mut parent = parent;
+
+		for (valid, header) in chain.iter().enumerate() {
+			if self.check_sub_chain(parent, std::slice::from_ref(header)).is_err() {
+				return valid;
+			}
+			parent = header;
+		}
+		chain.len()
+	}
+
+	/// Validate a header exactly like `validate`, but report why an invalid header is invalid.
+	///
+	/// The default implementation can only say that the seal is invalid. Engines that can tell
+	/// their failure modes apart should override this to give a more precise reason, and may
+	/// then implement `validate` in terms of it so the two never disagree.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if self.validate(parent_digest, header) {
+			Ok(())
+		} else {
+			Err(ChainError::InvalidSeal)
+		}
+	}
+
+	/// Verify that the given headers form a valid chain on top of the given parent header, and
+	/// report the reason the first invalid header was rejected.
+	///
+	/// Unlike `verify_sub_chain`, this also checks that each header is linked to its parent by
+	/// hash and height, so it is suitable for checking headers received from untrusted peers.
+	///
+	/// Each header is judged by `validate_with_parent`, so engines that need the whole parent
+	/// header are checked by their own rules. When a header is rejected, `check_header` is asked
+	/// for the precise reason.
+	fn check_sub_chain(
+		&self,
+		parent: &Header<Self::Digest>,
+		chain: &[Header<Self::Digest>],
+	) -> Result<(), ChainError> {
+		let mut parent = parent;
+
+		for header in chain {
+			header.check_child_of(parent)?;
+			if !self.validate_with_parent(parent, header) {
+				self.check_header(&parent.consensus_digest, header)?;
+				return Err(ChainError::InvalidSeal);
+			}
+			parent = header;
+		}
+		Ok(())
+	}
+
+	/// A proof, for a light client that trusts the first header of the given canonical chain, that
+	/// the header at `target_height` is in the chain.
+	///
+	/// Returns `None` if the chain has no header at that height after its first one.
+	fn spv_proof(
+		&self,
+		chain: &[Header<Self::Digest>],
+		target_height: u64,
+	) -> Option<SpvProof<Self::Digest>> {
+		let checkpoint = chain.first()?;
+		let len = usize::try_from(target_height.checked_sub(checkpoint.height)?).ok()?;
+		Some(SpvProof { headers: chain.get(1..=len)?.to_vec() })
+	}
+
+	/// Verify that the proof links the trusted checkpoint to the header with the given hash, through
+	/// validly sealed headers.
+	fn verify_spv_proof(
+		&self,
+		checkpoint: &Header<Self::Digest>,
+		proof: &SpvProof<Self::Digest>,
+		target: Hash,
+	) -> Result<(), ChainError> {
+		self.check_sub_chain(checkpoint, &proof.headers)?;
+		if hash(proof.headers.last().unwrap_or(checkpoint)) != target {
+			return Err(ChainError::UnprovenTarget);
+		}
+		Ok(())
+	}
+
+	/// Check a complete block exactly like `check_header`, but also check that its header commits
+	/// to its body. Unlike headers, blocks can not claim an extrinsics root they do not carry.
+	fn check_block(
+		&self,
+		parent_digest: &Self::Digest,
+		block: &Block<Self::Digest>,
+	) -> Result<(), ChainError> {
+		block.check_extrinsics_root()?;
+		self.check_header(parent_digest, &block.header)
+	}
+
+	/// Validate a complete block exactly like `validate`, but also check that its header commits
+	/// to its body.
+	#[must_use]
+	fn validate_block(&self, parent_digest: &Self::Digest, block: &Block<Self::Digest>) -> bool {
+		self.check_block(parent_digest, block).is_ok()
+	}
+
+	/// Check that the engine is internally consistent, by sealing a sample child of genesis and
+	/// checking that the result is valid.
+	///
+	/// This catches the class of bug where `seal` and `validate` disagree with each other, or where
+	/// the engine can not seal on top of its own genesis at all.
+	#[must_use]
+	fn self_test(&self) -> bool {
+		let genesis = self.genesis_header();
+		let partial = Header {
+			parent: hash(&genesis),
+			height: genesis.height + 1,
+			state_root: genesis.state_root,
+			extrinsics_root: 0,
+			consensus_digest: (),
+		};
+
+		match self.seal(&genesis.consensus_digest, partial) {
+			Some(header) => self.check_sub_chain(&genesis, &[header]).is_ok(),
+			None => false,
+		}
+	}
+
+	/// The authority who sealed the given header, if the engine has a notion of one.
+	///
+	/// Identity-based engines can always say who signed a block. Engines like Proof of Work,
+	/// where anyone may author a block, return `None`, which is also the default.
+	fn seal_author(&self, _header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		None
+	}
+
+	/// The slot the given header was sealed in. An honest author seals at most one header per
+	/// slot, so this is what double signing is judged by.
+	///
+	/// Slot-based engines read the slot from the digest. Every other engine authors one block per
+	/// height, so the height is the slot, which is also the default.
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		header.height
+	}
+
+	/// Which kind of engine this is.
+	///
+	/// Unlike `human_name`, this is meant to be matched on by code that needs to treat particular
+	/// engines differently. Engines that are not one of the well-known kinds, such as ad-hoc
+	/// engines built for experiments, return `EngineKind::Custom`, which is also the default.
+	fn kind(&self) -> EngineKind {
+		EngineKind::Custom
+	}
 
-            // Validate the current header against the parent header.
-            if !self.validate(&(), header) {
-                return false;
-            }
+	/// Roughly how many hash attempts sealing the next block is expected to take, so that an
+	/// authoring loop can budget its time.
+	///
+	/// Identity-based engines seal with a single signature, which is also the default. Mining
+	/// engines override this with the expected number of nonces they must try.
+	fn estimate_seal_cost(&self) -> u64 {
+		1
+	}
 
-            // Check if the current header's parent hash matches the previous header's hash.
-            if parent_header.consensus_digest != header.parent {
-                return false;
-            }
+	/// Short names for the rules this engine enforces, for teaching and debugging.
+	///
+	/// The default is just the rules every engine gets from `check_sub_chain`. Engines add their
+	/// own after those, and engines that wrap another list the inner engine's rules first.
+	fn rules(&self) -> Vec<&'static str> {
+		BASE_RULES.to_vec()
 	}
 
 	/// A human-readable name for this engine. This may be used in user-facing
 	/// programs error reporting. This is not in any way related to
 	/// the correctness of the consensus logic.
-	fn human_name() -> String {
+	fn human_name() -> String
+	where
+		Self: Sized,
+	{
 		"Unnamed Consensus Engine".into()
 	}
 }
 
+/// Helpers for every consensus engine that can not be part of `Consensus` itself without making
+/// it dyn incompatible.
+pub trait ConsensusExt: Consensus + Sized {
+	/// Box this engine, so it can be stored alongside engines of other types that use the same
+	/// digest.
+	fn boxed<'a>(self) -> Box<dyn Consensus<Digest = Self::Digest> + 'a>
+	where
+		Self: 'a,
+	{
+		Box::new(self)
+	}
+}
+
+impl<C: Consensus> ConsensusExt for C {}
+
+/// A boxed engine is an engine too, so boxed engines chosen at runtime can be wrapped by
+/// higher-order engines like any other. Every method is forwarded, so the engine in the box
+/// behaves exactly as it would unboxed.
+impl<C: Consensus + ?Sized> Consensus for Box<C> {
+	type Digest = C::Digest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		(**self).validate(parent_digest, header)
+	}
+
+	fn validate_with_parent(
+		&self,
+		parent: &Header<Self::Digest>,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		(**self).validate_with_parent(parent, header)
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		(**self).validate_seal_only(parent_digest, header)
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		(**self).seal(parent_digest, partial_header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		(**self).genesis_header()
+	}
+
+	fn verify_sub_chain(
+		&self,
+		parent_digest: &Self::Digest,
+		chain: &[Header<Self::Digest>],
+	) -> bool {
+		(**self).verify_sub_chain(parent_digest, chain)
+	}
+
+	fn longest_valid_prefix(
+		&self,
+		parent: &Header<Self::Digest>,
+		chain: &[Header<Self::Digest>],
+	) -> usize {
+		(**self).longest_valid_prefix(parent, chain)
+	}
+
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		(**self).check_header(parent_digest, header)
+	}
+
+	fn check_sub_chain(
+		&self,
+		parent: &Header<Self::Digest>,
+		chain: &[Header<Self::Digest>],
+	) -> Result<(), ChainError> {
+		(**self).check_sub_chain(parent, chain)
+	}
+
+	fn check_block(
+		&self,
+		parent_digest: &Self::Digest,
+		block: &Block<Self::Digest>,
+	) -> Result<(), ChainError> {
+		(**self).check_block(parent_digest, block)
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		(**self).seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		(**self).seal_slot(header)
+	}
+
+	fn kind(&self) -> EngineKind {
+		(**self).kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		(**self).estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		(**self).rules()
+	}
+}
+
+/// A genesis header with no state that carries the given digest.
+fn genesis_with_digest<D>(consensus_digest: D) -> Header<D> {
+	Header { parent: 0, height: 0, state_root: 0, extrinsics_root: 0, consensus_digest }
+}
+
 /// A trivial consensus engine that considers all blocks valid, and does not have
 /// a meaningful consensus digest.
 impl Consensus for () {
@@ -117,6 +582,39 @@ impl Consensus for () {
         // We simply return a new header with the provided partial header.
         Some(partial_header)
 	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(())
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::Trivial
+	}
+}
+
+/// The well-known consensus engines in this chapter.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EngineKind {
+	/// The trivial engine that accepts every block.
+	Trivial,
+	/// Proof of Work with a fixed threshold.
+	Pow,
+	/// Proof of Work whose threshold is retargeted from the parent's digest.
+	RetargetingPow,
+	/// A single dictator signs every block.
+	Dictator,
+	/// Any authority may sign any block.
+	SimplePoa,
+	/// Authorities take turns by block height.
+	RoundRobinHeight,
+	/// Authorities take turns by slot number.
+	RoundRobinSlot,
+	/// Authorities take turns by slots derived from the clock.
+	Aura,
+	/// The author of each height is drawn pseudo-randomly from an epoch seed.
+	RandomizedPoa,
+	/// Any other engine.
+	Custom,
 }
 
 /// A set of consensus authority accounts that can be used in
@@ -127,3 +625,87 @@ pub enum ConsensusAuthority {
 	Bob,
 	Charlie,
 }
+
+#[cfg(test)]
+fn child_of(parent: &Header<()>) -> Header<()> {
+	Header {
+		parent: hash(parent),
+		height: parent.height + 1,
+		state_root: parent.state_root,
+		extrinsics_root: 0,
+		consensus_digest: (),
+	}
+}
+
+#[test]
+fn cs_is_child_of_accepts_genuine_child() {
+	let genesis =
+		Header { parent: 0, height: 0, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	let child = child_of(&genesis);
+
+	assert!(child.is_child_of(&genesis));
+	assert!(!genesis.is_child_of(&child));
+}
+
+#[test]
+fn cs_is_child_of_rejects_broken_links() {
+	let genesis =
+		Header { parent: 0, height: 0, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+
+	let wrong_parent = Header { parent: hash(&genesis) + 1, ..child_of(&genesis) };
+	assert!(!wrong_parent.is_child_of(&genesis));
+	assert_eq!(wrong_parent.check_child_of(&genesis), Err(ChainError::WrongParent));
+
+	let wrong_height = Header { height: 2, ..child_of(&genesis) };
+	assert!(!wrong_height.is_child_of(&genesis));
+	assert_eq!(wrong_height.check_child_of(&genesis), Err(ChainError::WrongHeight));
+}
+
+#[test]
+fn cs_block_id_is_stable_across_nonces() {
+	let header = |nonce: u64| Header {
+		parent: 1,
+		height: 2,
+		state_root: 3,
+		extrinsics_root: 4,
+		consensus_digest: nonce,
+	};
+
+	assert_eq!(header(0).id(), header(1).id());
+	assert_ne!(hash(&header(0)), hash(&header(1)));
+	assert_ne!(header(0).id(), Header { state_root: 5, ..header(0) }.id());
+
+	let partial =
+		Header { parent: 1, height: 2, state_root: 3, extrinsics_root: 4, consensus_digest: () };
+	assert_eq!(header(0).id(), BlockId(hash(&partial)));
+}
+
+#[test]
+fn cs_longest_valid_prefix() {
+	use p1_pow::moderate_difficulty_pow;
+	use test_support::sealed_adder_chain;
+
+	let engine = moderate_difficulty_pow();
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 8);
+	// The chain with the header at the given index re-sealed with a nonce that misses the
+	// threshold.
+	let invalidated = |index: usize| {
+		let mut chain = chain.clone();
+		let parent_digest = if index == 0 { 0 } else { chain[index - 1].consensus_digest };
+		while engine.validate(&parent_digest, &chain[index]) {
+			chain[index].consensus_digest += 1;
+		}
+		chain
+	};
+
+	assert_eq!(engine.longest_valid_prefix(&genesis, &chain), 8);
+	assert_eq!(engine.longest_valid_prefix(&genesis, &invalidated(0)), 0);
+	assert_eq!(engine.longest_valid_prefix(&genesis, &invalidated(4)), 4);
+
+	// Every seal is valid on its own, but the fifth header does not follow the fourth.
+	let mut reordered = chain.clone();
+	reordered.swap(4, 5);
+	assert!(engine.verify_sub_chain(&0, &reordered));
+	assert_eq!(engine.longest_valid_prefix(&genesis, &reordered), 4);
+}
@@ -0,0 +1,190 @@
+//! Some chains track a quantity that may only ever grow, such as a cumulative counter. Like
+//! `EvenOnly`, we can express that requirement as a higher-order consensus engine that wraps an
+//! inner engine and adds one more rule on top of it.
+//!
+//! The catch is that checking the rule requires the parent's state root, but engines are only
+//! handed the parent's digest. So this engine commits to the state root in its own digest,
+//! alongside the inner engine's digest. The parent's state root can then be read from the parent
+//! digest.
+
+use super::{Consensus, ConsensusAuthority, Header};
+use crate::ChainError;
+
+/// The digest of a `MonotonicState` engine. The inner engine's digest along with the state root of
+/// the header it seals.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct MonotonicDigest<D> {
+	pub(crate) inner: D,
+	pub(crate) state_root: u64,
+}
+
+/// A Consensus engine that requires the state root never to decrease from one block to the next.
+/// Wraps an inner consensus engine whose rules will also be enforced.
+pub struct MonotonicState<Inner> {
+	inner: Inner,
+}
+
+impl<Inner: Consensus> MonotonicState<Inner> {
+	/// Create a new engine that enforces monotonic state on top of the given engine.
+	pub fn new(inner: Inner) -> Self {
+		MonotonicState { inner }
+	}
+
+	/// The header as the inner engine sees it, carrying only the inner digest.
+	fn inner_header(header: &Header<MonotonicDigest<Inner::Digest>>) -> Header<Inner::Digest> {
+		header.with_digest(header.consensus_digest.inner.clone())
+	}
+}
+
+impl<Inner: Consensus> Consensus for MonotonicState<Inner> {
+	type Digest = MonotonicDigest<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that the digest commits to the header's state root, that the state did not decrease,
+	/// and that the inner engine accepts the header.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if header.consensus_digest.state_root != header.state_root {
+			return Err(ChainError::InvalidSeal);
+		}
+		if header.state_root < parent_digest.state_root {
+			return Err(ChainError::DecreasingState);
+		}
+		self.inner.check_header(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Seal the partial header with the inner engine. Headers whose state decreased can not be
+	/// sealed.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		if partial_header.state_root < parent_digest.state_root {
+			return None;
+		}
+		let header = self.inner.seal(&parent_digest.inner, partial_header)?;
+
+		let state_root = header.state_root;
+		Some(header.map_digest(|inner| MonotonicDigest { inner, state_root }))
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.inner.genesis_header();
+
+		let state_root = genesis.state_root;
+		genesis.map_digest(|inner| MonotonicDigest { inner, state_root })
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header))
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(&Self::inner_header(header))
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("monotonic-state");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Monotonic State {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	p1_pow::moderate_difficulty_pow,
+	test_support::{check_adder_chain, sealed_adder_chain, sealed_child},
+};
+
+#[cfg(test)]
+fn genesis() -> Header<MonotonicDigest<u64>> {
+	Header {
+		parent: 0,
+		height: 0,
+		extrinsics_root: 0,
+		state_root: 0,
+		consensus_digest: MonotonicDigest { inner: 0, state_root: 0 },
+	}
+}
+
+#[test]
+fn cs_monotonic_state_accepts_non_decreasing_chain() {
+	let engine = MonotonicState::new(moderate_difficulty_pow());
+	let mut chain = sealed_adder_chain(&engine, &genesis(), 3);
+	// An empty block leaves the state unchanged, which is still allowed.
+	chain.push(sealed_child(&engine, chain.last().unwrap(), 0));
+
+	assert_eq!(check_adder_chain(&engine, &genesis(), &chain), Ok(()));
+	assert!(engine.verify_sub_chain(&genesis().consensus_digest, &chain));
+}
+
+#[test]
+fn cs_monotonic_state_rejects_decreasing_state() {
+	use crate::hash;
+
+	let engine = MonotonicState::new(moderate_difficulty_pow());
+	let chain = sealed_adder_chain(&engine, &genesis(), 3);
+	let parent = chain.last().unwrap();
+	let partial = Header {
+		parent: hash(parent),
+		height: parent.height + 1,
+		extrinsics_root: 0,
+		state_root: parent.state_root - 1,
+		consensus_digest: (),
+	};
+	assert_eq!(engine.seal(&parent.consensus_digest, partial.clone()), None);
+
+	// Seal the header with the inner engine alone, so that only the state is wrong.
+	let inner = moderate_difficulty_pow().seal(&parent.consensus_digest.inner, partial).unwrap();
+	let state_root = inner.state_root;
+	let header = inner.map_digest(|inner| MonotonicDigest { inner, state_root });
+
+	assert_eq!(
+		engine.check_sub_chain(parent, std::slice::from_ref(&header)),
+		Err(ChainError::DecreasingState)
+	);
+	assert!(!engine.verify_sub_chain(&genesis().consensus_digest, &[chain, vec![header]].concat()));
+}
+
+#[test]
+fn cs_monotonic_state_validate_seal_only_ignores_state() {
+	let engine = MonotonicState::new(moderate_difficulty_pow());
+	let chain = sealed_adder_chain(&engine, &genesis(), 3);
+	let (parent, header) = (&chain[1].consensus_digest, &chain[2]);
+	assert!(engine.validate_seal_only(parent, header));
+
+	// A state the rules forbid does not matter, as long as the seal covers it.
+	let decreased = sealed_child(&engine, &genesis(), 1);
+	let parent_digest = MonotonicDigest { state_root: 100, ..genesis().consensus_digest };
+	assert!(!engine.validate(&parent_digest, &decreased));
+	assert!(engine.validate_seal_only(&parent_digest, &decreased));
+
+	// But a tampered nonce is still caught. Sealing finds the lowest winning nonce, so any lower
+	// nonce is invalid.
+	let mut tampered = header.clone();
+	tampered.consensus_digest.inner -= 1;
+	assert!(!engine.validate_seal_only(parent, &tampered));
+}
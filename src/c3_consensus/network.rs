@@ -0,0 +1,170 @@
+//! Two networks may run the very same engine with the very same authorities, for example a test
+//! network and the main network. Without anything to tell them apart, a block built for one could
+//! be replayed on the other. The classic protection is a network id that every header carries and
+//! commits to, as Ethereum does with its chain id.
+//!
+//! Like the commitment in `Bridge`, the network id lives in the digest, because the header is
+//! shared by every engine in this chapter. The digest is part of the header's hash, so headers
+//! from different networks never share a hash, and the genesis header pins the network for the
+//! whole chain.
+
+use super::{Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::ChainError;
+
+/// The digest of a `Network` engine. The inner engine's digest along with the id of the network
+/// the header was built for.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct NetworkDigest<D> {
+	pub(crate) inner: D,
+	pub(crate) network_id: u64,
+}
+
+/// A Consensus engine that only accepts headers built for its own network, on top of a parent
+/// from the same network. Wraps an inner consensus engine whose rules will also be enforced.
+pub struct Network<Inner> {
+	inner: Inner,
+	network_id: u64,
+}
+
+impl<Inner: Consensus> Network<Inner> {
+	/// Create a new engine for the given network on top of the given engine.
+	pub fn new(inner: Inner, network_id: u64) -> Self {
+		Network { inner, network_id }
+	}
+
+	/// The header as the inner engine sees it, carrying only the inner digest.
+	fn inner_header(header: &Header<NetworkDigest<Inner::Digest>>) -> Header<Inner::Digest> {
+		header.with_digest(header.consensus_digest.inner.clone())
+	}
+
+	/// The given header as this engine sees it, carrying this engine's network id.
+	fn outer_header(&self, header: Header<Inner::Digest>) -> Header<NetworkDigest<Inner::Digest>> {
+		header.map_digest(|inner| NetworkDigest { inner, network_id: self.network_id })
+	}
+}
+
+impl<Inner: Consensus> Consensus for Network<Inner> {
+	type Digest = NetworkDigest<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that both the header and its parent belong to this network, and that the inner engine
+	/// accepts the header.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if header.consensus_digest.network_id != self.network_id ||
+			parent_digest.network_id != self.network_id
+		{
+			return Err(ChainError::WrongNetwork);
+		}
+		self.inner.check_header(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Check only the inner seal, even if the header belongs to another network.
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Seal the partial header with the inner engine, for this network. Headers can not be sealed
+	/// on top of a parent from another network.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		if parent_digest.network_id != self.network_id {
+			return None;
+		}
+		let header = self.inner.seal(&parent_digest.inner, partial_header)?;
+		Some(self.outer_header(header))
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.outer_header(self.inner.genesis_header())
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header))
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(&Self::inner_header(header))
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("network-id");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Network {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	p3_poa::PoaRoundRobinByHeight,
+	test_support::{check_adder_chain, sealed_adder_chain},
+};
+#[cfg(test)]
+use crate::hash;
+
+#[cfg(test)]
+fn network(network_id: u64) -> Network<PoaRoundRobinByHeight> {
+	use ConsensusAuthority::{Alice, Bob};
+
+	Network::new(PoaRoundRobinByHeight::new(vec![Alice, Bob]), network_id)
+}
+
+#[test]
+fn cs_network_accepts_matching_network_id() {
+	let engine = network(1);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+
+	assert!(chain.iter().all(|header| header.consensus_digest.network_id == 1));
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_network_rejects_mismatched_network_id() {
+	let (main, test) = (network(1), network(2));
+	let genesis = main.genesis_header();
+	let chain = sealed_adder_chain(&main, &genesis, 2);
+
+	// The test network rejects the main network's headers, and can not even build on its genesis.
+	assert_ne!(hash(&genesis), hash(&test.genesis_header()));
+	assert_eq!(test.check_sub_chain(&genesis, &chain), Err(ChainError::WrongNetwork));
+	let partial = Header {
+		parent: hash(&genesis),
+		height: 1,
+		state_root: 1,
+		extrinsics_root: 1,
+		consensus_digest: (),
+	};
+	assert_eq!(test.seal(&genesis.consensus_digest, partial), None);
+
+	// A header that claims another network than its genesis is rejected, even with a valid seal.
+	let mut foreign = chain[0].clone();
+	foreign.consensus_digest.network_id = 2;
+	assert!(main.validate_seal_only(&genesis.consensus_digest, &foreign));
+	assert_eq!(main.check_sub_chain(&genesis, &[foreign]), Err(ChainError::WrongNetwork));
+}
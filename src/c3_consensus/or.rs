@@ -0,0 +1,305 @@
+//! Switching a live chain from one engine to another rarely happens at a single, exact height.
+//! During a soft transition, authors upgrade at their own pace, so for a grace window the chain
+//! accepts blocks sealed under either the new rules or the old ones. Once the window closes, only
+//! the new rules remain.
+//!
+//! `Or` expresses that as a combinator over two engines. Its digest records which engine sealed
+//! each block, so the header is checked by that engine alone. Blocks of the two engines may be
+//! interleaved, so a block may build on a parent sealed by the other engine, whose digest it can
+//! not understand. So the digest also carries each engine's latest digest on the chain, and every
+//! block is checked against the last block of its own engine, not against genesis.
+
+use super::{Consensus, ConsensusAuthority, Header, BASE_RULES};
+use crate::ChainError;
+
+/// Which of the two engines of an `Or` sealed a header.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SealedBy {
+	A,
+	B,
+}
+
+/// The digest of an `Or` engine. Which engine sealed the header, and the latest digest of each
+/// engine. The digest of the engine that sealed the header is its seal, and the other one is
+/// carried unchanged from the parent.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct OrDigest<A, B> {
+	pub(crate) sealed_by: SealedBy,
+	pub(crate) a: A,
+	pub(crate) b: B,
+}
+
+/// A Consensus engine that accepts a header sealed by either of two engines. The first engine
+/// holds the new rules, and the second the old ones, which are only accepted below the height at
+/// which the grace window closes. Sealing prefers the first engine, and falls back to the second.
+pub struct Or<A, B> {
+	a: A,
+	b: B,
+	grace_end: u64,
+}
+
+impl<A: Consensus, B: Consensus> Or<A, B> {
+	/// Create a new engine that accepts headers sealed by either engine, until the given height.
+	/// From that height on, only headers sealed by the first engine are accepted.
+	pub fn new(a: A, b: B, grace_end: u64) -> Self {
+		Or { a, b, grace_end }
+	}
+
+	/// Whether the second engine may seal a header at the given height.
+	fn in_grace_window(&self, height: u64) -> bool {
+		height < self.grace_end
+	}
+}
+
+impl<A: Consensus, B: Consensus> Consensus for Or<A, B> {
+	type Digest = OrDigest<A::Digest, B::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check the header with the engine that sealed it, against that engine's latest digest. The
+	/// other engine's digest must be carried unchanged, and the second engine is only accepted
+	/// within the grace window.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let digest = &header.consensus_digest;
+		match digest.sealed_by {
+			SealedBy::A => {
+				if digest.b != parent_digest.b {
+					return Err(ChainError::InvalidSeal);
+				}
+				self.a.check_header(&parent_digest.a, &header.with_digest(digest.a.clone()))
+			},
+			SealedBy::B => {
+				if !self.in_grace_window(header.height) {
+					return Err(ChainError::GraceWindowClosed);
+				}
+				if digest.a != parent_digest.a {
+					return Err(ChainError::InvalidSeal);
+				}
+				self.b.check_header(&parent_digest.b, &header.with_digest(digest.b.clone()))
+			},
+		}
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		let digest = &header.consensus_digest;
+		match digest.sealed_by {
+			SealedBy::A =>
+				self.a.validate_seal_only(&parent_digest.a, &header.with_digest(digest.a.clone())),
+			SealedBy::B =>
+				self.b.validate_seal_only(&parent_digest.b, &header.with_digest(digest.b.clone())),
+		}
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let height = partial_header.height;
+		if let Some(header) = self.a.seal(&parent_digest.a, partial_header.clone()) {
+			let digest = OrDigest {
+				sealed_by: SealedBy::A,
+				a: header.consensus_digest.clone(),
+				b: parent_digest.b.clone(),
+			};
+			return Some(header.with_digest(digest));
+		}
+		if !self.in_grace_window(height) {
+			return None;
+		}
+		let header = self.b.seal(&parent_digest.b, partial_header)?;
+		let digest = OrDigest {
+			sealed_by: SealedBy::B,
+			a: parent_digest.a.clone(),
+			b: header.consensus_digest.clone(),
+		};
+		Some(header.with_digest(digest))
+	}
+
+	/// The first engine's genesis, carrying the genesis digests of both engines.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.a.genesis_header();
+		let digest = OrDigest {
+			sealed_by: SealedBy::A,
+			a: genesis.consensus_digest.clone(),
+			b: self.b.genesis_header().consensus_digest,
+		};
+		genesis.with_digest(digest)
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		let digest = &header.consensus_digest;
+		match digest.sealed_by {
+			SealedBy::A => self.a.seal_author(&header.with_digest(digest.a.clone())),
+			SealedBy::B => self.b.seal_author(&header.with_digest(digest.b.clone())),
+		}
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		let digest = &header.consensus_digest;
+		match digest.sealed_by {
+			SealedBy::A => self.a.seal_slot(&header.with_digest(digest.a.clone())),
+			SealedBy::B => self.b.seal_slot(&header.with_digest(digest.b.clone())),
+		}
+	}
+
+	/// Sealing tries the first engine before the second, so it costs at least as much.
+	fn estimate_seal_cost(&self) -> u64 {
+		self.a.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["sealed-by-either-engine", "grace-window"]].concat()
+	}
+
+	fn human_name() -> String {
+		format!("{} or {}", A::human_name(), B::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	p1_pow::PoW,
+	p3_poa::{PoaRoundRobinByHeight, PoaRoundRobinBySlot},
+	test_support::{check_adder_chain, sealed_adder_chain, sealed_child},
+};
+#[cfg(test)]
+use crate::hash;
+
+/// Alice and Bob take turns under the new rules, and Proof of Work is still accepted below height
+/// 10.
+#[cfg(test)]
+fn transition(authorities: Vec<ConsensusAuthority>) -> Or<PoaRoundRobinByHeight, PoW> {
+	Or::new(PoaRoundRobinByHeight::new(authorities), PoW::new(u64::MAX / 4), 10)
+}
+
+/// The given partial header, carrying the digest of an `Or` engine.
+#[cfg(test)]
+fn or_header<A, B>(
+	partial: &Header<()>,
+	sealed_by: SealedBy,
+	a: A,
+	b: B,
+) -> Header<OrDigest<A, B>> {
+	partial.with_digest(OrDigest { sealed_by, a, b })
+}
+
+#[test]
+fn cs_or_seal_prefers_first_engine() {
+	let engine = transition(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob]);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+	assert!(chain.iter().all(|header| header.consensus_digest.sealed_by == SealedBy::A));
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+
+	// With no authorities, the first engine can not seal, so the second one does.
+	let fallback = Or::new(PoaRoundRobinBySlot::new(vec![]), PoW::new(u64::MAX / 4), 10);
+	let genesis = fallback.genesis_header();
+	let chain = sealed_adder_chain(&fallback, &genesis, 3);
+	assert!(chain.iter().all(|header| header.consensus_digest.sealed_by == SealedBy::B));
+	assert_eq!(check_adder_chain(&fallback, &genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_or_accepts_either_engine() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let engine = transition(vec![Alice, Bob]);
+	let genesis = engine.genesis_header();
+	let partial = Header {
+		parent: hash(&genesis),
+		height: 1,
+		state_root: 1,
+		extrinsics_root: 1,
+		consensus_digest: (),
+	};
+	let pow = PoW::new(u64::MAX / 4).seal(&0, partial.clone()).unwrap();
+
+	// Valid only under the first engine, and only under the second.
+	let signed = or_header(&partial, SealedBy::A, Bob, 0);
+	assert_eq!(engine.check_sub_chain(&genesis, &[signed]), Ok(()));
+	let mined = or_header(&partial, SealedBy::B, Alice, pow.consensus_digest);
+	assert_eq!(engine.check_sub_chain(&genesis, &[mined]), Ok(()));
+
+	// Valid under neither.
+	assert_eq!(
+		engine.check_sub_chain(&genesis, &[or_header(&partial, SealedBy::A, Alice, 0)]),
+		Err(ChainError::WrongAuthority)
+	);
+	let unmined =
+		(0..).find(|nonce| !PoW::new(u64::MAX / 4).validate(&0, &partial.with_digest(*nonce)));
+	let unmined = or_header(&partial, SealedBy::B, Alice, unmined.unwrap());
+	assert_eq!(engine.check_sub_chain(&genesis, &[unmined]), Err(ChainError::InsufficientWork));
+}
+
+#[test]
+fn cs_or_checks_each_engine_against_its_own_last_block() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	// The slot engine must see its slots increase across the whole chain, even when Proof of Work
+	// blocks come in between.
+	let engine = Or::new(PoaRoundRobinBySlot::new(vec![Alice, Bob]), PoW::new(u64::MAX / 4), 10);
+	let genesis = engine.genesis_header();
+	let signed = sealed_child(&engine, &genesis, 1);
+	assert_eq!(signed.consensus_digest.sealed_by, SealedBy::A);
+
+	let partial = |parent: &Header<_>, height| Header {
+		parent: hash(parent),
+		height,
+		state_root: 0,
+		extrinsics_root: 0,
+		consensus_digest: (),
+	};
+	let pow = PoW::new(u64::MAX / 4).seal(&0, partial(&signed, 2)).unwrap();
+	let mined = or_header(
+		&partial(&signed, 2),
+		SealedBy::B,
+		signed.consensus_digest.a,
+		pow.consensus_digest,
+	);
+	assert_eq!(engine.check_sub_chain(&signed, std::slice::from_ref(&mined)), Ok(()));
+
+	// Re-using the slot of the last signed block is rejected, even though a Proof of Work block
+	// came in between.
+	let (last_signed, last_mined) = (signed.consensus_digest.a, mined.consensus_digest.b);
+	let replayed = or_header(&partial(&mined, 3), SealedBy::A, last_signed, last_mined);
+	assert_eq!(engine.check_sub_chain(&mined, &[replayed]), Err(ChainError::NonMonotonicSlot));
+
+	// And a header may not rewrite the other engine's latest digest.
+	let mut forged = sealed_child(&engine, &mined, 3);
+	assert_eq!(engine.check_sub_chain(&mined, std::slice::from_ref(&forged)), Ok(()));
+	forged.consensus_digest.b += 1;
+	assert_eq!(engine.check_sub_chain(&mined, &[forged]), Err(ChainError::InvalidSeal));
+}
+
+#[test]
+fn cs_or_rejects_second_engine_after_grace_window() {
+	let engine = Or::new(PoaRoundRobinBySlot::new(vec![]), PoW::new(u64::MAX / 4), 3);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 2);
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+
+	// At the end of the window, the second engine no longer seals, and its headers are rejected.
+	let parent = &chain[1];
+	let partial = Header {
+		parent: hash(parent),
+		height: 3,
+		state_root: 3,
+		extrinsics_root: 3,
+		consensus_digest: (),
+	};
+	assert_eq!(engine.seal(&parent.consensus_digest, partial.clone()), None);
+	let pow = PoW::new(u64::MAX / 4).seal(&0, partial.clone()).unwrap();
+	let late = or_header(&partial, SealedBy::B, parent.consensus_digest.a, pow.consensus_digest);
+	assert_eq!(engine.check_sub_chain(parent, &[late]), Err(ChainError::GraceWindowClosed));
+}
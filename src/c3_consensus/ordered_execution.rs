@@ -0,0 +1,199 @@
+//! The canonical ordering rule from chapter 2 constrains how a block's extrinsics are encoded. It
+//! says nothing about how they are executed. When extrinsics do not commute, an executor that is
+//! free to pick its own order could choose whichever order leaves it with the most favorable
+//! state, and still produce a block whose header looks perfectly valid.
+//!
+//! This engine closes that gap by requiring the state root to be exactly the result of executing
+//! the block's extrinsics in the order they are stored. Like `MonotonicState`, it needs the
+//! parent's state root, so it commits to the state root in its own digest. Execution can only be
+//! checked with the body in hand, so the rule is enforced by `check_block` rather than by
+//! `check_header`.
+
+use super::{block::Block, Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::ChainError;
+
+/// Apply a single extrinsic to a state, giving the new state.
+pub type Execute = fn(u64, u64) -> u64;
+
+/// The digest of an `OrderedExecution` engine. The inner engine's digest along with the state root
+/// of the header it seals.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ExecutionDigest<D> {
+	pub(crate) inner: D,
+	pub(crate) state_root: u64,
+}
+
+/// A Consensus engine that requires each block's state root to be the result of executing its
+/// extrinsics strictly in their stored order. Wraps an inner consensus engine whose rules will
+/// also be enforced.
+pub struct OrderedExecution<Inner> {
+	inner: Inner,
+	execute: Execute,
+}
+
+impl<Inner: Consensus> OrderedExecution<Inner> {
+	/// Create a new engine that executes extrinsics with the given function on top of the given
+	/// engine.
+	pub fn new(inner: Inner, execute: Execute) -> Self {
+		OrderedExecution { inner, execute }
+	}
+
+	/// The state after executing the given extrinsics, in order, on top of the given state.
+	pub fn execute(&self, pre_state: u64, extrinsics: &[u64]) -> u64 {
+		extrinsics
+			.iter()
+			.fold(pre_state, |state, extrinsic| (self.execute)(state, *extrinsic))
+	}
+
+	/// The header as the inner engine sees it, carrying only the inner digest.
+	fn inner_header(header: &Header<ExecutionDigest<Inner::Digest>>) -> Header<Inner::Digest> {
+		header.with_digest(header.consensus_digest.inner.clone())
+	}
+}
+
+impl<Inner: Consensus> Consensus for OrderedExecution<Inner> {
+	type Digest = ExecutionDigest<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that the digest commits to the header's state root, and that the inner engine accepts
+	/// the header. Without the body, this can not check how the state root was reached.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if header.consensus_digest.state_root != header.state_root {
+			return Err(ChainError::InvalidSeal);
+		}
+		self.inner.check_header(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Check the block like any other engine, and also that executing its body in order on top of
+	/// the parent's state gives exactly the header's state root.
+	fn check_block(
+		&self,
+		parent_digest: &Self::Digest,
+		block: &Block<Self::Digest>,
+	) -> Result<(), ChainError> {
+		block.check_extrinsics_root()?;
+		if self.execute(parent_digest.state_root, &block.body) != block.header.state_root {
+			return Err(ChainError::BadState);
+		}
+		self.check_header(parent_digest, &block.header)
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let header = self.inner.seal(&parent_digest.inner, partial_header)?;
+
+		let state_root = header.state_root;
+		Some(header.map_digest(|inner| ExecutionDigest { inner, state_root }))
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.inner.genesis_header();
+
+		let state_root = genesis.state_root;
+		genesis.map_digest(|inner| ExecutionDigest { inner, state_root })
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header))
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(&Self::inner_header(header))
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("ordered-execution");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Ordered Execution {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::p1_pow::PoW;
+#[cfg(test)]
+use crate::{hash, merkle_root};
+
+/// An engine whose extrinsics do not commute, so the order they run in changes the state.
+#[cfg(test)]
+fn engine() -> OrderedExecution<PoW> {
+	OrderedExecution::new(PoW::new(u64::MAX / 4), |state, extrinsic| {
+		state.wrapping_mul(31).wrapping_add(extrinsic)
+	})
+}
+
+/// A block storing the given body, whose state root is the result of executing `executed`.
+#[cfg(test)]
+fn sealed_block(
+	engine: &OrderedExecution<PoW>,
+	parent: &Header<ExecutionDigest<u64>>,
+	body: Vec<u64>,
+	executed: &[u64],
+) -> Block<ExecutionDigest<u64>> {
+	let partial = Header {
+		parent: hash(parent),
+		height: parent.height + 1,
+		extrinsics_root: merkle_root(&body),
+		state_root: engine.execute(parent.state_root, executed),
+		consensus_digest: (),
+	};
+	let header = engine.seal(&parent.consensus_digest, partial).unwrap();
+	Block { header, body }
+}
+
+#[test]
+fn cs_ordered_execution_accepts_in_order_state() {
+	let engine = engine();
+	let genesis = engine.genesis_header();
+	let block = sealed_block(&engine, &genesis, vec![1, 2, 3], &[1, 2, 3]);
+
+	assert_eq!(block.header.state_root, (31 + 2) * 31 + 3);
+	assert_eq!(engine.check_block(&genesis.consensus_digest, &block), Ok(()));
+
+	let child = sealed_block(&engine, &block.header, vec![4], &[4]);
+	assert_eq!(engine.check_block(&block.header.consensus_digest, &child), Ok(()));
+}
+
+#[test]
+fn cs_ordered_execution_rejects_reordered_state() {
+	let engine = engine();
+	let genesis = engine.genesis_header();
+	let reordered = sealed_block(&engine, &genesis, vec![1, 2, 3], &[3, 2, 1]);
+	assert_ne!(engine.execute(0, &[1, 2, 3]), engine.execute(0, &[3, 2, 1]));
+
+	// The header alone looks fine. Only executing the body reveals the reordering.
+	assert!(engine.validate(&genesis.consensus_digest, &reordered.header));
+	assert_eq!(
+		engine.check_block(&genesis.consensus_digest, &reordered),
+		Err(ChainError::BadState)
+	);
+}
@@ -3,8 +3,66 @@
 //!
 //! This is the same logic we implemented previously. Here we re-implement it in the
 //! generic consensus framework that we will use throughout the rest of the chapter.
-use crate::hash;
-use super::{Consensus, Header};
+use std::time::Duration;
+
+use super::{genesis_with_digest, Consensus, EngineKind, Header, BASE_RULES};
+use crate::{hash, meets_threshold, ChainError};
+
+/// The hardest threshold an engine may be configured or retargeted to. Below it, blocks take so
+/// many attempts to mine that the chain would effectively deadlock. The difficulty bomb is the one
+/// deliberate exception, as grinding the chain to a halt is exactly its purpose.
+pub const MIN_THRESHOLD: u64 = u64::MAX >> 40;
+
+/// The easiest threshold an engine may be configured or retargeted to. Above it, most nonces are
+/// valid, so the work no longer means anything.
+pub const MAX_THRESHOLD: u64 = u64::MAX / 2;
+
+/// A threshold outside of `[MIN_THRESHOLD, MAX_THRESHOLD]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThresholdError {
+	/// The threshold is below `MIN_THRESHOLD`, so the chain would deadlock.
+	TooHard,
+	/// The threshold is above `MAX_THRESHOLD`, so the work would be trivial.
+	TooEasy,
+}
+
+impl ThresholdError {
+	/// The nearest threshold that is in range.
+	pub fn bound(&self) -> u64 {
+		match self {
+			ThresholdError::TooHard => MIN_THRESHOLD,
+			ThresholdError::TooEasy => MAX_THRESHOLD,
+		}
+	}
+}
+
+/// The given threshold, if it is within `[MIN_THRESHOLD, MAX_THRESHOLD]`.
+///
+/// Thresholds are taken as `u128` so that retargeting can compute them without overflowing.
+pub fn checked_threshold(threshold: u128) -> Result<u64, ThresholdError> {
+	if threshold < MIN_THRESHOLD as u128 {
+		Err(ThresholdError::TooHard)
+	} else if threshold > MAX_THRESHOLD as u128 {
+		Err(ThresholdError::TooEasy)
+	} else {
+		Ok(threshold as u64)
+	}
+}
+
+/// The given threshold, clamped into `[MIN_THRESHOLD, MAX_THRESHOLD]`.
+pub fn clamp_threshold(threshold: u128) -> u64 {
+	checked_threshold(threshold).unwrap_or_else(|e| e.bound())
+}
+
+/// The expected number of nonces a miner must try to find a hash below the given threshold.
+///
+/// This is the same `2^64 / (threshold + 1)` that `block_work` credits a mined header with.
+pub fn expected_attempts(threshold: u64) -> u64 {
+	((u64::MAX as u128 + 1) / (threshold as u128 + 1)).try_into().unwrap_or(u64::MAX)
+}
+
+/// The bits of a seeded starting nonce that `PoW::seal_seeded` keeps.
+pub const SEED_NONCE_MASK: u64 = u32::MAX as u64;
 
 /// A Proof of Work consensus engine. This is the same consensus logic that we
 /// implemented in the previous chapter. Here we simply re-implement it in the
@@ -13,47 +71,1164 @@ pub struct PoW {
 	threshold: u64,
 }
 
+impl PoW {
+	/// Create a new engine that requires header hashes to be below the given threshold. The
+	/// threshold is clamped into `[MIN_THRESHOLD, MAX_THRESHOLD]`.
+	pub fn new(threshold: u64) -> Self {
+		PoW { threshold: clamp_threshold(threshold.into()) }
+	}
+
+	/// Mine a PoW seal for the partial header, trying only the nonces in `[start, end)`.
+	///
+	/// Returns `None` if none of those nonces meet the threshold. Handing disjoint ranges to
+	/// different workers lets them mine the same header without duplicating each other's work.
+	pub fn seal_in_range(
+		&self,
+		_: &u64,
+		partial_header: Header<()>,
+		start: u64,
+		end: u64,
+	) -> Option<Header<u64>> {
+		let mut header = partial_header.with_digest(0);
+
+		(start..end).find_map(|nonce| {
+			header.consensus_digest = nonce;
+			meets_threshold(&header, self.threshold).then(|| header.clone())
+		})
+	}
+
+	/// Mine a PoW seal for the partial header, searching upward from a starting nonce derived from
+	/// the given seed rather than from zero.
+	///
+	/// The same seed always finds the same nonce for the same header, which lets tests pin exact
+	/// winning nonces. The starting nonce keeps only the bits in `SEED_NONCE_MASK`, so there is
+	/// always plenty of room to search above it.
+	pub fn seal_seeded(
+		&self,
+		parent_digest: &u64,
+		partial_header: Header<()>,
+		seed: u64,
+	) -> Option<Header<u64>> {
+		let start = hash(&seed) & SEED_NONCE_MASK;
+		self.seal_in_range(parent_digest, partial_header, start, u64::MAX)
+	}
+
+	/// Mine a PoW seal for the partial header, making up to `attempts` attempts that each try at
+	/// most `budget` nonces.
+	///
+	/// Every attempt starts where the previous one stopped, so a retry explores fresh nonces rather
+	/// than repeating a search that has already failed. This is how a miner with a limited budget
+	/// per round keeps making progress on the same header.
+	pub fn seal_with_retries(
+		&self,
+		parent_digest: &u64,
+		partial_header: Header<()>,
+		budget: u64,
+		attempts: u64,
+	) -> Option<Header<u64>> {
+		let mut miner = BudgetedMiner::new(PoW { threshold: self.threshold }, budget);
+		(0..attempts).find_map(|_| miner.try_seal(parent_digest, partial_header.clone()))
+	}
+
+	/// Mine a PoW seal for the partial header that also honors the given commitment, so that the
+	/// block can only be accepted by engines expecting that commitment. See `CommittedPoW`.
+	pub fn seal_committing(
+		&self,
+		_: &u64,
+		partial_header: Header<()>,
+		commitment: u64,
+	) -> Option<Header<u64>> {
+		let mut header = partial_header.with_digest(0);
+
+		while !meets_threshold(&header, self.threshold) || !honors_commitment(&header, commitment) {
+			header.consensus_digest = header.consensus_digest.checked_add(1)?;
+		}
+		Some(header)
+	}
+}
+
 impl Consensus for PoW {
 	type Digest = u64;
 
 	/// Check that the provided header's hash is below the required threshold.
 	/// This does not rely on the parent digest at all.
-	fn validate(&self, _: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-		
-			if header.parent >= self.threshold{
-				return false;
-			}
-			true
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
 
+	fn check_header(
+		&self,
+		_: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if meets_threshold(header, self.threshold) {
+			Ok(())
+		} else {
+			Err(ChainError::InsufficientWork)
+		}
 	}
 
 	/// Mine a new PoW seal for the partial header provided.
 	/// This does not rely on the parent digest at all.
+	///
+	/// No hash is below a threshold of zero, so that gives up immediately rather than trying every
+	/// nonce.
 	fn seal(&self, _: &Self::Digest, partial_header: Header<()>) -> Option<Header<Self::Digest>> {
-		let mut ret_header = Header::<Self::Digest>{
-			parent: partial_header.parent,
-			height: partial_header.height,
-			state_root: partial_header.state_root,
-			extrinsics_root: partial_header.extrinsics_root,
-			consensus_digest: 0,
-		};
+		if self.threshold == 0 {
+			return None;
+		}
+		let mut ret_header = partial_header.with_digest(0u64);
 
-		loop{
-			ret_header.consensus_digest += 1;
-			let hash_value = hash(&ret_header);
-			if hash_value > self.threshold{
-				break
-			}
+		while !meets_threshold(&ret_header, self.threshold) {
+			ret_header.consensus_digest = ret_header.consensus_digest.checked_add(1)?;
 		}
-		return Some(ret_header);
+		Some(ret_header)
+	}
+
+	/// Genesis is not mined, so it carries nonce zero.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(0)
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::Pow
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		expected_attempts(self.threshold)
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["pow-threshold"]].concat()
 	}
 }
 
 
+/// A Proof of Work miner that tries at most `budget` nonces per attempt, and remembers where its
+/// search stopped.
+///
+/// A failed attempt resumes from the first nonce it did not try, and a successful one from just
+/// after the winning nonce, so neither retries nor the blocks that follow repeat work that has
+/// already been done.
+pub struct BudgetedMiner {
+	pow: PoW,
+	budget: u64,
+	next_nonce: u64,
+}
+
+impl BudgetedMiner {
+	/// Create a new miner for the given engine, starting its search from nonce zero.
+	pub fn new(pow: PoW, budget: u64) -> Self {
+		BudgetedMiner { pow, budget, next_nonce: 0 }
+	}
+
+	/// The nonce the next attempt will start from.
+	pub fn next_nonce(&self) -> u64 {
+		self.next_nonce
+	}
+
+	/// Make a single attempt to seal the partial header, trying `budget` nonces from where the
+	/// previous attempt stopped. Once the search reaches the last nonce, it starts over from zero.
+	pub fn try_seal(
+		&mut self,
+		parent_digest: &u64,
+		partial_header: Header<()>,
+	) -> Option<Header<u64>> {
+		let start = self.next_nonce;
+		let end = start.saturating_add(self.budget);
+		let sealed = self.pow.seal_in_range(parent_digest, partial_header, start, end);
+		let resume = sealed
+			.as_ref()
+			.map_or(Some(end), |header| header.consensus_digest.checked_add(1));
+		self.next_nonce = resume.filter(|nonce| *nonce < u64::MAX).unwrap_or(0);
+		sealed
+	}
+
+	/// Author up to `count` blocks on top of the given parent, making up to `attempts` attempts at
+	/// each one. Each block adds its height to the state, following the adder convention.
+	///
+	/// Stops early if a block can not be sealed within its attempts, so the returned chain may be
+	/// shorter than asked for.
+	pub fn author_blocks(
+		&mut self,
+		parent: &Header<u64>,
+		count: u64,
+		attempts: u64,
+	) -> Vec<Header<u64>> {
+		let mut chain: Vec<Header<u64>> = Vec::new();
+		for _ in 0..count {
+			let parent = chain.last().unwrap_or(parent);
+			let partial = Header {
+				parent: hash(parent),
+				height: parent.height + 1,
+				state_root: parent.state_root + parent.height + 1,
+				extrinsics_root: parent.height + 1,
+				consensus_digest: (),
+			};
+			let Some(header) = (0..attempts)
+				.find_map(|_| self.try_seal(&parent.consensus_digest, partial.clone()))
+			else {
+				break;
+			};
+			chain.push(header);
+		}
+		chain
+	}
+}
+
 /// Create a PoW consensus engine that has a difficulty threshold such that roughly 1 in 100 blocks
-/// with randomly drawn nonces will be valid. That is: the threshold should be u64::max_value() /
+/// with randomly drawn nonces will be valid. That is: the threshold should be u64::MAX /
 /// 100.
-pub fn moderate_difficulty_pow() -> impl Consensus {
-	let threshold = u64::max_value() / 100;
+pub fn moderate_difficulty_pow() -> impl Consensus<Digest = u64> {
+	let threshold = u64::MAX / 100;
     PoW { threshold }
 }
+
+/// The most a single retarget may scale the threshold by, in either direction. Bitcoin uses the
+/// same bound.
+pub const MAX_RETARGET_FACTOR: u64 = 4;
+
+/// A Proof of Work engine that retargets its difficulty every `window` blocks.
+///
+/// Real-world chains retarget based on how long the last few blocks took to produce. We don't
+/// have timestamps in our headers, but honest miners search nonces upward from zero, so the nonce
+/// tells us roughly how many attempts a block took. At the end of every window we scale the
+/// threshold so that the average number of attempts moves toward `target_attempts`.
+///
+/// The threshold each header was mined against, and the attempts spent so far in its window, are
+/// carried in the digest. Each header's digest follows from its parent's, so every node agrees on
+/// the difficulty of every header no matter which headers it imported before. The nonce is chosen
+/// by the miner, so it is only a claim about the attempts made. A miner can claim a huge nonce to
+/// make the chain look slow, so no retarget moves the threshold by more than
+/// `MAX_RETARGET_FACTOR`.
+pub struct RetargetingPoW {
+	initial_threshold: u64,
+	window: u64,
+	target_attempts: u64,
+}
+
+/// The digest of a `RetargetingPoW` engine. The nonce, along with the threshold the header was
+/// mined against and the attempts claimed by the headers of its window up to and including it.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RetargetDigest {
+	pub(crate) nonce: u64,
+	pub(crate) threshold: u64,
+	pub(crate) window_attempts: u64,
+}
+
+impl RetargetingPoW {
+	/// Create a new retargeting engine whose genesis sets the given threshold. The threshold is
+	/// clamped into `[MIN_THRESHOLD, MAX_THRESHOLD]`. The window must not be empty.
+	pub fn new(threshold: u64, window: u64, target_attempts: u64) -> Self {
+		assert!(window > 0, "the retarget window must not be empty");
+		RetargetingPoW {
+			initial_threshold: clamp_threshold(threshold.into()),
+			window,
+			target_attempts,
+		}
+	}
+
+	/// The threshold that a header at the given height must be mined below, along with the
+	/// attempts already spent in its window, given its parent's digest.
+	///
+	/// The first header of each window retargets according to the attempts of the window before.
+	fn next_window(&self, parent_digest: &RetargetDigest, height: u64) -> (u64, u64) {
+		let parent_height = height.saturating_sub(1);
+		if parent_height == 0 || !parent_height.is_multiple_of(self.window) {
+			return (parent_digest.threshold, parent_digest.window_attempts);
+		}
+		let average_attempts = parent_digest.window_attempts / self.window;
+		let threshold = self
+			.retargeted_threshold(parent_digest.threshold, average_attempts)
+			.unwrap_or_else(|e| e.bound());
+		(threshold, 0)
+	}
+
+	/// The threshold that a header at the given height must be mined below, given its parent's
+	/// digest.
+	pub fn next_threshold(&self, parent_digest: &RetargetDigest, height: u64) -> u64 {
+		self.next_window(parent_digest, height).0
+	}
+
+	/// The threshold after a window, mined at the given threshold, in which blocks took the given
+	/// average number of attempts.
+	///
+	/// Blocks that came too easily push the threshold down, and vice versa, by at most
+	/// `MAX_RETARGET_FACTOR`. Returns an error if the retargeted threshold would be out of range,
+	/// in which case the engine moves to the nearest bound instead.
+	pub fn retargeted_threshold(
+		&self,
+		threshold: u64,
+		average_attempts: u64,
+	) -> Result<u64, ThresholdError> {
+		let threshold = threshold as u128;
+		let factor = MAX_RETARGET_FACTOR as u128;
+		let scaled = (threshold * average_attempts.max(1) as u128)
+			.checked_div(self.target_attempts as u128)
+			.unwrap_or(u128::MAX);
+		checked_threshold(scaled.clamp(threshold / factor, threshold * factor))
+	}
+}
+
+impl Consensus for RetargetingPoW {
+	type Digest = RetargetDigest;
+
+	/// Check that the header claims the threshold and window its parent calls for, and that its
+	/// hash is below that threshold.
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let (threshold, window_attempts) = self.next_window(parent_digest, header.height);
+		let digest = &header.consensus_digest;
+		if digest.threshold != threshold ||
+			digest.window_attempts !=
+				window_attempts.saturating_add(digest.nonce.saturating_add(1))
+		{
+			return Err(ChainError::WrongDifficulty);
+		}
+		if meets_threshold(header, threshold) {
+			Ok(())
+		} else {
+			Err(ChainError::InsufficientWork)
+		}
+	}
+
+	/// Mine a new PoW seal against the threshold the parent calls for, starting from nonce zero.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let (threshold, window_attempts) = self.next_window(parent_digest, partial_header.height);
+		let mut nonce: u64 = 0;
+		loop {
+			let window_attempts = window_attempts.saturating_add(nonce.saturating_add(1));
+			let header =
+				partial_header.with_digest(RetargetDigest { nonce, threshold, window_attempts });
+			if meets_threshold(&header, threshold) {
+				return Some(header);
+			}
+			nonce = nonce.checked_add(1)?;
+		}
+	}
+
+	fn human_name() -> String {
+		"Retargeting Proof of Work".into()
+	}
+
+	/// Genesis is not mined. It sets the initial threshold and starts with an empty window.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(RetargetDigest {
+			nonce: 0,
+			threshold: self.initial_threshold,
+			window_attempts: 0,
+		})
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::RetargetingPow
+	}
+
+	/// The cost at the initial threshold. Later windows may be cheaper or more expensive.
+	fn estimate_seal_cost(&self) -> u64 {
+		expected_attempts(self.initial_threshold)
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["retargeted-pow-threshold"]].concat()
+	}
+}
+
+/// A Proof of Work engine with a difficulty bomb, as Ethereum used to discourage miners from
+/// staying on PoW.
+///
+/// Up to the bomb height, headers are mined against the base threshold. After that, the threshold
+/// halves every `period` blocks, so blocks get exponentially harder to mine until the chain grinds
+/// to a halt.
+pub struct BombPoW {
+	base_threshold: u64,
+	bomb_height: u64,
+	period: u64,
+}
+
+impl BombPoW {
+	/// Create a new engine whose bomb goes off at the given height. The base threshold is clamped
+	/// into `[MIN_THRESHOLD, MAX_THRESHOLD]`. The period must not be zero.
+	pub fn new(base_threshold: u64, bomb_height: u64, period: u64) -> Self {
+		assert!(period > 0, "the bomb period must not be zero");
+		BombPoW { base_threshold: clamp_threshold(base_threshold.into()), bomb_height, period }
+	}
+
+	/// The threshold that a header at the given height must be mined below.
+	pub fn threshold_at(&self, height: u64) -> u64 {
+		if height < self.bomb_height {
+			return self.base_threshold;
+		}
+		let halvings = (height - self.bomb_height) / self.period;
+		u32::try_from(halvings)
+			.ok()
+			.and_then(|halvings| self.base_threshold.checked_shr(halvings))
+			.unwrap_or(0)
+	}
+}
+
+impl Consensus for BombPoW {
+	type Digest = u64;
+
+	/// Check that the header's hash is below the threshold for its height.
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	fn check_header(
+		&self,
+		_: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if meets_threshold(header, self.threshold_at(header.height)) {
+			Ok(())
+		} else {
+			Err(ChainError::InsufficientWork)
+		}
+	}
+
+	/// Mine a new PoW seal against the threshold for the header's height. Once the threshold has
+	/// reached zero, no header can ever be sealed.
+	fn seal(&self, _: &Self::Digest, partial_header: Header<()>) -> Option<Header<Self::Digest>> {
+		let threshold = self.threshold_at(partial_header.height);
+		if threshold == 0 {
+			return None;
+		}
+		let mut header = partial_header.with_digest(0u64);
+
+		while !meets_threshold(&header, threshold) {
+			header.consensus_digest = header.consensus_digest.checked_add(1)?;
+		}
+		Some(header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(0)
+	}
+
+	/// The cost before the bomb goes off. Every `period` blocks after that, it doubles.
+	fn estimate_seal_cost(&self) -> u64 {
+		expected_attempts(self.base_threshold)
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["pow-threshold-with-bomb"]].concat()
+	}
+
+	fn human_name() -> String {
+		"Difficulty Bomb Proof of Work".into()
+	}
+}
+
+/// A limit on how fast a `ThrottledMiner` may try nonces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Throttle {
+	/// The number of nonces tried between pauses.
+	pub batch: u64,
+	/// How long to pause after each batch.
+	pub pause: Duration,
+}
+
+impl Throttle {
+	/// A throttle that caps the hash rate at roughly `hashes_per_second`, by pausing after every
+	/// `batch` nonces. The time spent hashing is not accounted for, so the real rate is a little
+	/// lower.
+	pub fn hash_rate(hashes_per_second: u64, batch: u64) -> Self {
+		assert!(hashes_per_second > 0, "the hash rate must not be zero");
+		assert!(batch > 0, "the batch must not be empty");
+		Throttle { batch, pause: Duration::from_secs_f64(batch as f64 / hashes_per_second as f64) }
+	}
+}
+
+/// A Proof of Work engine that can mine slowly on purpose.
+///
+/// Unthrottled mining keeps a CPU core busy and finds blocks faster than anyone can watch, which
+/// is not what you want in a live demo. With a throttle, the miner sleeps between batches of
+/// nonces so block production can be observed in real time. Without one, it mines exactly like the
+/// inner `PoW`. Either way, validation is unaffected.
+pub struct ThrottledMiner {
+	pow: PoW,
+	throttle: Option<Throttle>,
+}
+
+impl ThrottledMiner {
+	/// Create a new, unthrottled miner for the given engine.
+	pub fn new(pow: PoW) -> Self {
+		ThrottledMiner { pow, throttle: None }
+	}
+
+	/// Limit how fast this miner tries nonces.
+	pub fn throttle(mut self, throttle: Throttle) -> Self {
+		self.throttle = Some(throttle);
+		self
+	}
+}
+
+impl Consensus for ThrottledMiner {
+	type Digest = u64;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.pow.validate(parent_digest, header)
+	}
+
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		self.pow.check_header(parent_digest, header)
+	}
+
+	/// Mine a new PoW seal, pausing after each batch of nonces if the miner is throttled.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let Some(throttle) = self.throttle else {
+			return self.pow.seal(parent_digest, partial_header);
+		};
+		let mut header = partial_header.with_digest(0u64);
+
+		let mut attempts_in_batch = 0;
+		while !meets_threshold(&header, self.pow.threshold) {
+			attempts_in_batch += 1;
+			if attempts_in_batch == throttle.batch {
+				std::thread::sleep(throttle.pause);
+				attempts_in_batch = 0;
+			}
+			header.consensus_digest = header.consensus_digest.checked_add(1)?;
+		}
+		Some(header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.pow.genesis_header()
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.pow.kind()
+	}
+
+	/// The number of nonces is unaffected by the throttle, even though trying them takes longer.
+	fn estimate_seal_cost(&self) -> u64 {
+		self.pow.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		self.pow.rules()
+	}
+
+	fn human_name() -> String {
+		"Throttled Proof of Work".into()
+	}
+}
+
+/// The number of low bits of `hash(header) ^ commitment` that must be zero for a header to honor a
+/// commitment. Each bit doubles the expected mining work.
+pub const COMMITMENT_BITS: u32 = 4;
+
+/// Whether the header's hash matches the given commitment in its low `COMMITMENT_BITS` bits.
+fn honors_commitment(header: &Header<u64>, commitment: u64) -> bool {
+	(hash(header) ^ commitment).trailing_zeros() >= COMMITMENT_BITS
+}
+
+/// A Proof of Work engine whose blocks must honor a commitment made before they were mined.
+///
+/// In commit-reveal schemes, a party first publishes a commitment, and later reveals data that
+/// must match it. Here the block itself is bound to the commitment: besides meeting the threshold,
+/// its hash must agree with the commitment in its low bits. A block mined without knowing the
+/// commitment, even one with plenty of work, is almost always rejected.
+pub struct CommittedPoW {
+	pow: PoW,
+	commitment: u64,
+}
+
+impl CommittedPoW {
+	/// Create a new engine that requires blocks to meet the given engine's threshold and to honor
+	/// the given commitment.
+	pub fn new(pow: PoW, commitment: u64) -> Self {
+		CommittedPoW { pow, commitment }
+	}
+}
+
+impl Consensus for CommittedPoW {
+	type Digest = u64;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check the work first, then the commitment.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		self.pow.check_header(parent_digest, header)?;
+		if !honors_commitment(header, self.commitment) {
+			return Err(ChainError::InvalidSeal);
+		}
+		Ok(())
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.pow.seal_committing(parent_digest, partial_header, self.commitment)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.pow.genesis_header()
+	}
+
+	/// Each commitment bit halves the share of valid hashes that are accepted.
+	fn estimate_seal_cost(&self) -> u64 {
+		self.pow.estimate_seal_cost().saturating_mul(1 << COMMITMENT_BITS)
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.pow.rules();
+		rules.push("pow-commitment");
+		rules
+	}
+
+	fn human_name() -> String {
+		"Committed Proof of Work".into()
+	}
+}
+
+#[cfg(test)]
+use super::test_support::{build_valid_chain, sealed_child};
+
+#[cfg(test)]
+fn sealed_chain<C: Consensus>(
+	engine: &C,
+	parent_digest: &C::Digest,
+	n: u64,
+) -> Vec<Header<C::Digest>> {
+	let mut chain: Vec<Header<C::Digest>> = Vec::new();
+	let mut parent_digest = parent_digest.clone();
+	for height in 1..=n {
+		let partial = Header {
+			parent: chain.last().map(hash).unwrap_or_default(),
+			height,
+			state_root: 0,
+			extrinsics_root: 0,
+			consensus_digest: (),
+		};
+		let header = engine.seal(&parent_digest, partial).expect("engine can seal");
+		parent_digest = header.consensus_digest.clone();
+		chain.push(header);
+	}
+	chain
+}
+
+#[test]
+fn cs_1_retargeting_difficulty_unchanged_before_window_fills() {
+	let engine = RetargetingPoW::new(u64::MAX / 2, 4, 50);
+	let genesis = engine.genesis_header();
+	let chain = build_valid_chain(&engine, 4);
+
+	assert_eq!(engine.check_sub_chain(&genesis, &chain), Ok(()));
+	assert!(chain.iter().all(|header| header.consensus_digest.threshold == MAX_THRESHOLD));
+}
+
+#[test]
+fn cs_1_retargeting_difficulty_increases_after_easy_window() {
+	let initial = u64::MAX / 2;
+	let engine = RetargetingPoW::new(initial, 4, 50);
+	let genesis = engine.genesis_header();
+	let chain = build_valid_chain(&engine, 5);
+
+	assert_eq!(engine.check_sub_chain(&genesis, &chain), Ok(()));
+	let retargeted = chain[4].consensus_digest.threshold;
+	assert!(retargeted < initial);
+	assert!(retargeted >= initial / MAX_RETARGET_FACTOR);
+	assert_eq!(retargeted, engine.next_threshold(&chain[3].consensus_digest, 5));
+}
+
+#[test]
+fn cs_1_retargeting_rejects_header_claiming_stale_difficulty() {
+	let engine = RetargetingPoW::new(u64::MAX / 2, 4, 50);
+	let chain = build_valid_chain(&engine, 4);
+
+	// The first header of the next window was mined against the threshold of the previous one.
+	let stale = RetargetingPoW::new(u64::MAX / 2, 5, 50);
+	let next = sealed_child(&stale, &chain[3], 5);
+	assert_eq!(
+		engine.check_header(&chain[3].consensus_digest, &next),
+		Err(ChainError::WrongDifficulty)
+	);
+
+	// Claiming fewer attempts than the nonce shows is caught too.
+	let mut understated = chain[3].clone();
+	understated.consensus_digest.window_attempts -= 1;
+	assert_eq!(
+		engine.check_header(&chain[2].consensus_digest, &understated),
+		Err(ChainError::WrongDifficulty)
+	);
+}
+
+#[test]
+fn cs_1_threshold_bounds_are_checked_and_clamped() {
+	assert_eq!(checked_threshold(0), Err(ThresholdError::TooHard));
+	assert_eq!(checked_threshold(u64::MAX.into()), Err(ThresholdError::TooEasy));
+	assert_eq!(checked_threshold(u128::MAX), Err(ThresholdError::TooEasy));
+	assert_eq!(checked_threshold(MIN_THRESHOLD.into()), Ok(MIN_THRESHOLD));
+	assert_eq!(checked_threshold(MAX_THRESHOLD.into()), Ok(MAX_THRESHOLD));
+
+	assert_eq!(PoW::new(0).threshold, MIN_THRESHOLD);
+	assert_eq!(PoW::new(u64::MAX).threshold, MAX_THRESHOLD);
+	assert_eq!(
+		RetargetingPoW::new(0, 4, 50).genesis_header().consensus_digest.threshold,
+		MIN_THRESHOLD
+	);
+	assert_eq!(BombPoW::new(0, 10, 5).threshold_at(0), MIN_THRESHOLD);
+	assert_eq!(BombPoW::new(u64::MAX, 10, 5).threshold_at(0), MAX_THRESHOLD);
+}
+
+#[test]
+fn cs_1_retarget_out_of_range_is_an_error_and_clamped() {
+	let engine = RetargetingPoW::new(MIN_THRESHOLD, 1, u64::MAX);
+	// Blocks came instantly, but the threshold is already as hard as it may get.
+	assert_eq!(engine.retargeted_threshold(MIN_THRESHOLD, 1), Err(ThresholdError::TooHard));
+
+	let engine = RetargetingPoW::new(MAX_THRESHOLD, 1, 1);
+	assert_eq!(engine.retargeted_threshold(MAX_THRESHOLD, u64::MAX), Err(ThresholdError::TooEasy));
+	let engine = RetargetingPoW::new(MAX_THRESHOLD, 1, 0);
+	assert_eq!(engine.retargeted_threshold(MAX_THRESHOLD, 1), Err(ThresholdError::TooEasy));
+}
+
+#[test]
+fn cs_1_retarget_moves_at_most_max_factor() {
+	let engine = RetargetingPoW::new(MIN_THRESHOLD, 1, 1);
+
+	// A miner claims a block took far too many attempts, to make the next one much easier.
+	let nonce = 1 << 40;
+	let claimed = RetargetDigest { nonce, threshold: MIN_THRESHOLD, window_attempts: nonce + 1 };
+	assert_eq!(engine.next_threshold(&claimed, 2), MIN_THRESHOLD * MAX_RETARGET_FACTOR);
+
+	// A block that came instantly makes the next one harder by no more than the same factor.
+	let engine = RetargetingPoW::new(MAX_THRESHOLD, 1, u64::MAX);
+	let instant = RetargetDigest { nonce: 0, threshold: MAX_THRESHOLD, window_attempts: 1 };
+	assert_eq!(engine.next_threshold(&instant, 2), MAX_THRESHOLD / MAX_RETARGET_FACTOR);
+}
+
+#[test]
+fn cs_1_committed_pow_accepts_block_honoring_commitment() {
+	let commitment = 0xABCD;
+	let engine = CommittedPoW::new(PoW::new(u64::MAX / 4), commitment);
+	let partial =
+		Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+
+	let header = PoW::new(u64::MAX / 4).seal_committing(&0, partial, commitment).unwrap();
+	assert!(meets_threshold(&header, u64::MAX / 4));
+	assert_eq!((hash(&header) ^ commitment) % (1 << COMMITMENT_BITS), 0);
+	assert_eq!(engine.check_header(&0, &header), Ok(()));
+	assert!(engine.self_test());
+}
+
+#[test]
+fn cs_1_committed_pow_rejects_block_meeting_only_threshold() {
+	let commitment = 0xABCD;
+	let engine = CommittedPoW::new(PoW::new(u64::MAX / 4), commitment);
+	let mut header =
+		Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: 0 };
+	while !meets_threshold(&header, u64::MAX / 4) || honors_commitment(&header, commitment) {
+		header.consensus_digest += 1;
+	}
+
+	assert!(PoW::new(u64::MAX / 4).validate(&0, &header));
+	assert_eq!(engine.check_header(&0, &header), Err(ChainError::InvalidSeal));
+}
+
+#[test]
+fn cs_1_moderate_pow_sealed_header_validates() {
+	let engine = moderate_difficulty_pow();
+	let partial =
+		Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	let header = engine.seal(&0, partial).unwrap();
+
+	assert!(engine.validate(&0, &header));
+}
+
+#[test]
+fn cs_1_pow_rejects_header_above_threshold() {
+	let engine = PoW { threshold: u64::MAX / 100 };
+	let mut header =
+		Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: 0 };
+	while meets_threshold(&header, engine.threshold) {
+		header.consensus_digest += 1;
+	}
+
+	assert_eq!(engine.check_header(&0, &header), Err(ChainError::InsufficientWork));
+}
+
+#[test]
+fn cs_1_pow_has_no_seal_author() {
+	let engine = moderate_difficulty_pow();
+	let partial =
+		Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	let header = engine.seal(&0, partial).unwrap();
+
+	assert_eq!(engine.seal_author(&header), None);
+}
+
+#[test]
+fn cs_1_pow_seal_in_range_finds_winning_nonce_inside_range() {
+	let engine = PoW { threshold: u64::MAX / 100 };
+	let partial =
+		|| Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	let nonce = engine.seal(&0, partial()).unwrap().consensus_digest;
+
+	let header = engine.seal_in_range(&0, partial(), nonce.saturating_sub(5), nonce + 5).unwrap();
+	assert_eq!(header.consensus_digest, nonce);
+	assert!(engine.validate(&0, &header));
+}
+
+#[test]
+fn cs_1_pow_seal_in_range_none_when_winning_nonce_outside_range() {
+	let engine = PoW { threshold: u64::MAX / 100 };
+	let partial =
+		|| Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	let nonce = engine.seal(&0, partial()).unwrap().consensus_digest;
+
+	// Sealing searches upward from zero, so no nonce below the first winner qualifies.
+	assert_eq!(engine.seal_in_range(&0, partial(), 0, nonce), None);
+}
+
+#[test]
+fn cs_1_pow_seal_retry_explores_new_nonces() {
+	let engine = PoW { threshold: u64::MAX / 100 };
+	let partial =
+		|| Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	let nonce = engine.seal(&0, partial()).unwrap().consensus_digest;
+	assert!(nonce > 0);
+
+	// A budget of `nonce` attempts falls just short of the winner on the first attempt, and the
+	// retry picks up from there.
+	assert_eq!(engine.seal_with_retries(&0, partial(), nonce, 1), None);
+	let header = engine.seal_with_retries(&0, partial(), nonce, 2).unwrap();
+	assert_eq!(header.consensus_digest, nonce);
+	assert!(engine.validate(&0, &header));
+}
+
+#[test]
+fn cs_1_author_blocks_carries_nonce_forward() {
+	let engine = PoW::new(u64::MAX / 100);
+	let genesis = engine.genesis_header();
+	let mut miner = BudgetedMiner::new(PoW::new(u64::MAX / 100), 50);
+
+	let chain = miner.author_blocks(&genesis, 3, 100);
+	assert_eq!(chain.len(), 3);
+	assert_eq!(engine.check_sub_chain(&genesis, &chain), Ok(()));
+
+	// Each block's search started just after the previous block's winning nonce.
+	assert!(chain.windows(2).all(|pair| pair[0].consensus_digest < pair[1].consensus_digest));
+	assert_eq!(miner.next_nonce(), chain[2].consensus_digest + 1);
+}
+
+#[test]
+fn cs_1_throttled_miner_seals_the_same_header_more_slowly() {
+	let partial =
+		|| Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	let unthrottled = ThrottledMiner::new(PoW::new(u64::MAX / 100));
+	let expected = unthrottled.seal(&0, partial()).unwrap();
+	assert!(expected.consensus_digest > 0);
+
+	// One nonce per millisecond, so every failed nonce costs at least a millisecond.
+	let throttled =
+		ThrottledMiner::new(PoW::new(u64::MAX / 100)).throttle(Throttle::hash_rate(1000, 1));
+	let start = std::time::Instant::now();
+	let header = throttled.seal(&0, partial()).unwrap();
+
+	assert_eq!(header, expected);
+	assert!(start.elapsed() >= Duration::from_millis(expected.consensus_digest));
+	assert!(throttled.validate(&0, &header));
+}
+
+/// A toy PoW engine whose threshold is retargeted from the parent's state root. Each unit of
+/// parent state halves the threshold, so busier chains are mined harder. The parent state is not
+/// part of the parent digest, so this engine can only validate a header given its parent header.
+#[cfg(test)]
+struct StateRetargetingPoW {
+	base_threshold: u64,
+}
+
+#[cfg(test)]
+impl StateRetargetingPoW {
+	fn threshold_after(&self, parent: &Header<u64>) -> u64 {
+		self.base_threshold >> parent.state_root.min(63)
+	}
+}
+
+#[cfg(test)]
+impl Consensus for StateRetargetingPoW {
+	type Digest = u64;
+
+	/// Without the parent header we do not know the threshold, so nothing is valid.
+	fn validate(&self, _: &Self::Digest, _: &Header<Self::Digest>) -> bool {
+		false
+	}
+
+	fn validate_with_parent(&self, parent: &Header<u64>, header: &Header<u64>) -> bool {
+		meets_threshold(header, self.threshold_after(parent))
+	}
+
+	/// Without the parent header we do not know the threshold, so nothing can be sealed.
+	fn seal(&self, _: &Self::Digest, _: Header<()>) -> Option<Header<Self::Digest>> {
+		None
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(0)
+	}
+}
+
+#[test]
+fn cs_1_validate_with_parent_defaults_to_validate() {
+	let engine = moderate_difficulty_pow();
+	let genesis =
+		Header { parent: 0, height: 0, state_root: 0, extrinsics_root: 0, consensus_digest: 0 };
+	let chain = sealed_chain(&engine, &0, 1);
+
+	assert!(engine.validate_with_parent(&genesis, &chain[0]));
+}
+
+#[test]
+fn cs_1_state_retargeting_only_validates_with_parent() {
+	let engine = StateRetargetingPoW { base_threshold: u64::MAX / 2 };
+	let parent =
+		Header { parent: 0, height: 0, state_root: 4, extrinsics_root: 0, consensus_digest: 0 };
+	let mut header = Header {
+		parent: hash(&parent),
+		height: 1,
+		state_root: 4,
+		extrinsics_root: 0,
+		consensus_digest: 0,
+	};
+	while !meets_threshold(&header, engine.threshold_after(&parent)) {
+		header.consensus_digest += 1;
+	}
+
+	assert!(engine.validate_with_parent(&parent, &header));
+	assert!(!engine.validate(&parent.consensus_digest, &header));
+}
+
+#[test]
+fn cs_1_state_retargeting_rejects_work_for_easier_parent() {
+	let engine = StateRetargetingPoW { base_threshold: u64::MAX / 2 };
+	let easy_parent =
+		Header { parent: 0, height: 0, state_root: 0, extrinsics_root: 0, consensus_digest: 0 };
+	let hard_parent = Header { state_root: 8, ..easy_parent.clone() };
+
+	// Find a header that is good enough after the easy parent but not after the hard one.
+	let mut header =
+		Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: 0 };
+	while !meets_threshold(&header, engine.threshold_after(&easy_parent)) ||
+		meets_threshold(&header, engine.threshold_after(&hard_parent))
+	{
+		header.consensus_digest += 1;
+	}
+
+	assert!(engine.validate_with_parent(&easy_parent, &header));
+	assert!(!engine.validate_with_parent(&hard_parent, &header));
+}
+
+#[test]
+fn cs_1_check_sub_chain_judges_headers_with_their_parent() {
+	let engine = StateRetargetingPoW { base_threshold: u64::MAX / 2 };
+	let parent =
+		Header { parent: 0, height: 0, state_root: 4, extrinsics_root: 0, consensus_digest: 0 };
+	let mut header = Header {
+		parent: hash(&parent),
+		height: 1,
+		state_root: 4,
+		extrinsics_root: 0,
+		consensus_digest: 0,
+	};
+	while meets_threshold(&header, engine.threshold_after(&parent)) {
+		header.consensus_digest += 1;
+	}
+	let unmined = header.clone();
+	while !meets_threshold(&header, engine.threshold_after(&parent)) {
+		header.consensus_digest += 1;
+	}
+
+	// `validate` alone rejects everything, so the header is only accepted by its parent's rule.
+	assert_eq!(engine.check_sub_chain(&parent, &[header]), Ok(()));
+	assert_eq!(engine.check_sub_chain(&parent, &[unmined]), Err(ChainError::InvalidSeal));
+}
+
+#[test]
+fn cs_1_bomb_difficulty_increases_past_bomb_height() {
+	let base = u64::MAX / 2;
+	let engine = BombPoW::new(base, 10, 5);
+
+	assert_eq!(engine.threshold_at(0), base);
+	assert_eq!(engine.threshold_at(9), base);
+	assert_eq!(engine.threshold_at(10), base);
+	assert_eq!(engine.threshold_at(14), base);
+	assert_eq!(engine.threshold_at(15), base >> 1);
+	assert_eq!(engine.threshold_at(25), base >> 3);
+	assert_eq!(engine.threshold_at(10 + 5 * 64), 0);
+	assert_eq!(engine.threshold_at(u64::MAX), 0);
+}
+
+#[test]
+fn cs_1_bomb_threshold_stays_zero_at_huge_heights() {
+	let engine = BombPoW::new(u64::MAX / 2, 0, 1);
+
+	// Halving counts that only look small once truncated to 32 bits.
+	assert_eq!(engine.threshold_at((1 << 32) + 1), 0);
+	assert_eq!(engine.threshold_at((1 << 33) + 3), 0);
+}
+
+#[test]
+fn cs_1_bomb_rejects_block_mined_at_old_difficulty() {
+	let base = u64::MAX / 2;
+	let engine = BombPoW::new(base, 10, 1);
+
+	// A header after the bomb that would have been fine before it, but is not hard enough now.
+	let mut header =
+		Header { parent: 0, height: 14, state_root: 0, extrinsics_root: 0, consensus_digest: 0 };
+	while !meets_threshold(&header, base) || meets_threshold(&header, engine.threshold_at(14)) {
+		header.consensus_digest += 1;
+	}
+	assert_eq!(engine.check_header(&0, &header), Err(ChainError::InsufficientWork));
+
+	let partial =
+		Header { parent: 0, height: 14, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	let sealed = engine.seal(&0, partial).unwrap();
+	assert!(engine.validate(&0, &sealed));
+}
+
+#[test]
+fn cs_1_bomb_chain_grinds_to_halt() {
+	let engine = BombPoW::new(u64::MAX / 2, 10, 1);
+	let partial =
+		Header { parent: 0, height: 100, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+
+	assert_eq!(engine.seal(&0, partial), None);
+}
+
+#[test]
+fn cs_1_harder_pow_estimates_higher_seal_cost() {
+	let easy = PoW::new(u64::MAX / 4);
+	let hard = PoW::new(u64::MAX / 1000);
+
+	assert_eq!(easy.estimate_seal_cost(), 4);
+	assert!(hard.estimate_seal_cost() > easy.estimate_seal_cost());
+	assert!(CommittedPoW::new(PoW::new(u64::MAX / 4), 7).estimate_seal_cost() > 4);
+}
+
+#[test]
+fn cs_1_seeded_seal_finds_first_valid_nonce_from_seed() {
+	let engine = PoW::new(u64::MAX / 100);
+	let partial =
+		Header { parent: 0, height: 1, state_root: 0, extrinsics_root: 0, consensus_digest: () };
+	// Search for the winner directly, rather than pinning a nonce that depends on the hasher.
+	let first_valid_from = |start: u64| {
+		(start..).find(|&nonce| {
+			let header = Header {
+				parent: 0,
+				height: 1,
+				state_root: 0,
+				extrinsics_root: 0,
+				consensus_digest: nonce,
+			};
+			engine.validate(&0, &header)
+		})
+	};
+
+	let sealed = engine.seal_seeded(&0, partial.clone(), 42).unwrap();
+	assert_eq!(Some(sealed.consensus_digest), first_valid_from(hash(&42u64) & SEED_NONCE_MASK));
+	assert!(engine.validate(&0, &sealed));
+	assert_eq!(engine.seal_seeded(&0, partial.clone(), 42), Some(sealed.clone()));
+	// The default search still starts at zero.
+	assert_eq!(Some(engine.seal(&0, partial).unwrap().consensus_digest), first_valid_from(0));
+}
+
+/// Thresholds below this are too hard to seal in a fuzz iteration, so only `validate` is run.
+#[cfg(any(test, feature = "fuzzing"))]
+const POW_FUZZ_MIN_SEALABLE: u64 = u64::MAX >> 10;
+
+/// Check `validate` and `seal` on one arbitrary input. Neither may panic, `validate` must agree
+/// with the threshold, and any header `seal` returns must keep the partial header's fields and
+/// validate.
+///
+/// The input is read as six little-endian `u64`s: a header's parent, height, state root,
+/// extrinsics root, and nonce, and then a threshold. Shorter inputs are ignored, and any bytes
+/// after the sixth word are unused. This is the entry point of the `pow_round_trip` fuzz target.
+#[cfg(any(test, feature = "fuzzing"))]
+pub fn fuzz_pow_round_trip(data: &[u8]) {
+	let words: Vec<u64> = data
+		.chunks_exact(std::mem::size_of::<u64>())
+		.take(6)
+		.map(|word| u64::from_le_bytes(word.try_into().expect("chunks are exactly 8 bytes")))
+		.collect();
+	let &[parent, height, state_root, extrinsics_root, nonce, threshold] = &words[..] else {
+		return;
+	};
+
+	let engine = PoW { threshold };
+	let header = Header { parent, height, state_root, extrinsics_root, consensus_digest: nonce };
+	assert_eq!(engine.validate(&nonce, &header), hash(&header) < threshold);
+
+	if threshold != 0 && threshold < POW_FUZZ_MIN_SEALABLE {
+		return;
+	}
+	let partial = Header { parent, height, state_root, extrinsics_root, consensus_digest: () };
+	match engine.seal(&nonce, partial) {
+		Some(sealed) => {
+			assert_eq!(Header { consensus_digest: nonce, ..sealed }, header);
+			assert!(engine.validate(&nonce, &sealed));
+		},
+		None => assert_eq!(threshold, 0),
+	}
+}
+
+/// Replay the seed corpus of the `pow_round_trip` fuzz target. Inputs that once broke `PoW` are
+/// added to the corpus, so they stay fixed without running the fuzzer. See `fuzz/README.md` for
+/// how to run the fuzzer itself.
+#[test]
+fn cs_1_fuzz_pow_seed_corpus() {
+	let seeds = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/seeds/pow_round_trip");
+	let mut replayed = 0;
+	for entry in std::fs::read_dir(seeds).expect("the seed corpus is checked in") {
+		fuzz_pow_round_trip(&std::fs::read(entry.unwrap().path()).unwrap());
+		replayed += 1;
+	}
+	assert!(replayed > 0);
+}
+
+/// A bounded fuzz run over `PoW`, with inputs drawn from a hash sequence so every run checks the
+/// same inputs. Run it on its own with `cargo test cs_1_fuzz_pow`.
+#[test]
+fn cs_1_fuzz_pow_validate_seal_round_trip() {
+	for i in 0..200u64 {
+		let fields = [0, 1, 2, 3, 4].map(|field| hash(&(i, field)));
+		// Mostly sealable thresholds, with the occasional arbitrary one.
+		let threshold = hash(&(i, "threshold"));
+		let threshold = if i % 10 == 0 { threshold } else { threshold | POW_FUZZ_MIN_SEALABLE };
+		let data: Vec<u8> =
+			fields.iter().chain([&threshold]).flat_map(|word| word.to_le_bytes()).collect();
+		fuzz_pow_round_trip(&data);
+	}
+	// Inputs too short to hold every field are ignored.
+	fuzz_pow_round_trip(&[0; 47]);
+}
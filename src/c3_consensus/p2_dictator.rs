@@ -7,7 +7,7 @@
 //! from the underlying consensus-related logic. Instead, we just use the `ConsensusAuthority` enum
 //! from the module root.
 
-use super::{Consensus, ConsensusAuthority, Header};
+use super::{genesis_with_digest, Consensus, ConsensusAuthority, EngineKind, Header, BASE_RULES};
 /// Dictator consensus is an identity-based consensus algorithm. It specifies a single dictator
 /// identity who is the only identity authorized to sign valid blocks. Any block signed by the
 /// dictator is valid (at the consensus level), and any block not signed by the dictator is invalid.
@@ -25,12 +25,23 @@ impl Consensus for DictatorConsensus {
 
 	/// Sign the given partial header by the dictator
 	fn seal(&self, _: &Self::Digest, partial_header: Header<()>) -> Option<Header<Self::Digest>> {
-		Some(Header::<Self::Digest>{
-			parent: partial_header.parent,
-			height: partial_header.height,
-			state_root: partial_header.state_root,
-			extrinsics_root: partial_header.extrinsics_root,
-			consensus_digest: self.dictator,
-		})
+		Some(partial_header.with_digest(self.dictator))
+	}
+
+	/// The authority's signature is the whole digest.
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		Some(header.consensus_digest)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(self.dictator)
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::Dictator
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["dictator-signature"]].concat()
 	}
 }
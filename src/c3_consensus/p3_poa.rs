@@ -9,7 +9,14 @@
 //! Even when using the Proof of Stake configuration, the underlying consensus logic is identical to
 //! the proof of authority we are writing here.
 
-use super::{Consensus, ConsensusAuthority, Header};
+use super::{
+	authority_registry::AuthorityRegistry,
+	clock::Clock,
+	genesis_with_digest,
+	safe::{checked_slot_index, next_slot},
+	Consensus, ConsensusAuthority, EngineKind, Header, BASE_RULES,
+};
+use crate::{hash, ChainError};
 
 /// A Proof of Authority consensus engine. If any of the authorities have signed the block, it is
 /// valid.
@@ -34,35 +41,78 @@ impl Consensus for SimplePoa {
 		parent_digest: &Self::Digest,
 		partial_header: Header<()>,
 	) -> Option<Header<Self::Digest>> {
-		
-		let authority = self.authorities.first().cloned()?; // Using the first authority for simplicity
-        let header = Header {
-            parent: partial_header.parent,
-            height: partial_header.height,
-            extrinsics_root: partial_header.extrinsics_root,
-            state_root: partial_header.state_root,
-            consensus_digest: authority,
-        };
-        Some(header)
+		// Using the first authority for simplicity
+		let authority = self.authorities.first().cloned()?;
+		Some(partial_header.with_digest(authority))
+	}
+
+	/// The authority's signature is the whole digest.
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		Some(header.consensus_digest)
+	}
+
+	/// Genesis is not signed; it names the first authority, or Alice if there are none.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(self.authorities.first().copied().unwrap_or(ConsensusAuthority::Alice))
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::SimplePoa
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["any-authority-signature"]].concat()
 	}
 }
 
 /// A Proof of Authority consensus engine. Only one authority is valid at each block height.
 /// As ever, the genesis block does not require a seal. After that the authorities take turns
 /// in order.
-struct PoaRoundRobinByHeight {
+pub struct PoaRoundRobinByHeight {
 	authorities: Vec<ConsensusAuthority>,
 }
 
+impl PoaRoundRobinByHeight {
+	/// Create a new engine in which the given authorities take turns in order.
+	///
+	/// With no authorities, no height has an author, so every header is rejected and nothing can
+	/// be sealed.
+	pub fn new(authorities: Vec<ConsensusAuthority>) -> Self {
+		PoaRoundRobinByHeight { authorities }
+	}
+
+	/// The authority whose turn it is to sign at the given height.
+	fn authority_for_height(&self, height: u64) -> Option<&ConsensusAuthority> {
+		self.authorities.get(checked_slot_index(height, self.authorities.len())?)
+	}
+
+	/// The address to connect to in order to reach the authority who should author the block at
+	/// the given height, if that authority has registered one.
+	pub fn author_address<'r>(
+		&self,
+		registry: &'r AuthorityRegistry,
+		height: u64,
+	) -> Option<&'r str> {
+		registry.resolve(*self.authority_for_height(height)?)
+	}
+}
+
 impl Consensus for PoaRoundRobinByHeight {
 	type Digest = ConsensusAuthority;
 
 	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-		// Check if the authority corresponding to the block height is valid
-        if let Some(authority) = self.authorities.get((header.height % self.authorities.len() as u64) as usize) {
-            return header.consensus_digest == *authority;
-        }
-        false
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	fn check_header(
+		&self,
+		_: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		match self.authority_for_height(header.height) {
+			Some(authority) if header.consensus_digest == *authority => Ok(()),
+			_ => Err(ChainError::WrongAuthority),
+		}
 	}
 
 	fn seal(
@@ -70,17 +120,26 @@ impl Consensus for PoaRoundRobinByHeight {
 		parent_digest: &Self::Digest,
 		partial_header: Header<()>,
 	) -> Option<Header<Self::Digest>> {
-		// Find the authority corresponding to the next block height
-        let authority = self.authorities.get((partial_header.height % self.authorities.len() as u64) as usize)?;
-        // Create the header with the chosen authority
-        let header = Header {
-            parent: partial_header.parent,
-            height: partial_header.height,
-            extrinsics_root: partial_header.extrinsics_root,
-            state_root: partial_header.state_root,
-            consensus_digest: *authority,
-        };
-        Some(header)
+		let authority = self.authority_for_height(partial_header.height)?;
+		Some(partial_header.with_digest(*authority))
+	}
+
+	/// The authority's signature is the whole digest.
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		Some(header.consensus_digest)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let author = self.authority_for_height(0).copied().unwrap_or(ConsensusAuthority::Alice);
+		genesis_with_digest(author)
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::RoundRobinHeight
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["round-robin-by-height"]].concat()
 	}
 }
 
@@ -93,47 +152,590 @@ impl Consensus for PoaRoundRobinByHeight {
 ///
 /// A common PoA scheme that works around these weaknesses is to divide time into slots, and then do
 /// a round robin by slot instead of by height
-struct PoaRoundRobinBySlot {
+///
+/// Nothing ties these slots to time, so an authority could claim a slot far in the future and skew
+/// everyone else's schedule. An engine may bound how many slots a block can skip past its parent.
+pub struct PoaRoundRobinBySlot {
 	authorities: Vec<ConsensusAuthority>,
+	max_slot_gap: u64,
+}
+
+impl PoaRoundRobinBySlot {
+	/// Create a new engine in which the given authorities take turns slot by slot.
+	pub fn new(authorities: Vec<ConsensusAuthority>) -> Self {
+		PoaRoundRobinBySlot { authorities, max_slot_gap: u64::MAX }
+	}
+
+	/// Create a new engine in which a block's slot may be at most `max_slot_gap` slots after its
+	/// parent's.
+	pub fn with_max_slot_gap(authorities: Vec<ConsensusAuthority>, max_slot_gap: u64) -> Self {
+		PoaRoundRobinBySlot { authorities, max_slot_gap }
+	}
+
+	/// The authority whose turn it is to sign in the given slot.
+	fn authority_for_slot(&self, slot: u64) -> Option<&ConsensusAuthority> {
+		self.authorities.get(checked_slot_index(slot, self.authorities.len())?)
+	}
+
+	/// The address to connect to in order to reach the authority who should author the block in
+	/// the given slot, if that authority has registered one.
+	pub fn author_address<'r>(&self, registry: &'r AuthorityRegistry, slot: u64) -> Option<&'r str> {
+		registry.resolve(*self.authority_for_slot(slot)?)
+	}
 }
 
 /// A digest used for PoaRoundRobinBySlot. The digest contains the slot number as well as the
 /// signature. In addition to checking that the right signer has signed for the slot, you must check
 /// that the slot is always strictly increasing. But remember that slots may be skipped.
 #[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
-struct SlotDigest {
-	slot: u64,
-	signature: ConsensusAuthority,
+pub struct SlotDigest {
+	pub(crate) slot: u64,
+	pub(crate) signature: ConsensusAuthority,
 }
 
 impl Consensus for PoaRoundRobinBySlot {
 	type Digest = SlotDigest;
 
 	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-		header.consensus_digest.signature == self.authorities[header.consensus_digest.slot as usize]
-            && header.consensus_digest.slot > parent_digest.slot
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if header.consensus_digest.slot <= parent_digest.slot {
+			return Err(ChainError::NonMonotonicSlot);
+		}
+		if header.consensus_digest.slot - parent_digest.slot > self.max_slot_gap {
+			return Err(ChainError::SlotGapTooLarge);
+		}
+		match self.authority_for_slot(header.consensus_digest.slot) {
+			Some(authority) if header.consensus_digest.signature == *authority => Ok(()),
+			_ => Err(ChainError::WrongAuthority),
+		}
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		// Sign in the slot immediately following the parent's slot
+		let slot = next_slot(parent_digest.slot)?;
+		let authority = self.authority_for_slot(slot)?;
+		// Create the slot digest for the next slot
+		let slot_digest = SlotDigest { slot, signature: *authority };
+		Some(partial_header.with_digest(slot_digest))
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		Some(header.consensus_digest.signature)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		header.consensus_digest.slot
+	}
+
+	/// Genesis claims slot zero, so the first block may claim any later slot.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let signature = self.authority_for_slot(0).copied().unwrap_or(ConsensusAuthority::Alice);
+		genesis_with_digest(SlotDigest { slot: 0, signature })
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::RoundRobinSlot
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["increasing-slot", "max-slot-gap", "round-robin-by-slot"]].concat()
+	}
+}
+
+/// Round robin by slot, where the slots are derived from the time, as in Substrate's Aura.
+///
+/// Time is divided into slots of `slot_duration` milliseconds, and it is the turn of authority
+/// `slot % authorities.len()` to sign during each slot. A block may not claim a slot that has not
+/// started yet according to the local clock, so an authority cannot skip ahead of the others.
+pub struct Aura<C: Clock> {
+	authorities: Vec<ConsensusAuthority>,
+	slot_duration: u64,
+	clock: C,
+}
+
+impl<C: Clock> Aura<C> {
+	/// Create a new engine with the given authorities and slot duration in milliseconds.
+	pub fn new(authorities: Vec<ConsensusAuthority>, slot_duration: u64, clock: C) -> Self {
+		Aura { authorities, slot_duration, clock }
+	}
+
+	/// The slot that is in progress according to the clock.
+	pub fn current_slot(&self) -> Option<u64> {
+		self.clock.now().checked_div(self.slot_duration)
+	}
+
+	/// The authority whose turn it is to sign in the given slot.
+	fn authority_for_slot(&self, slot: u64) -> Option<&ConsensusAuthority> {
+		self.authorities.get(checked_slot_index(slot, self.authorities.len())?)
+	}
+}
+
+impl<C: Clock> Consensus for Aura<C> {
+	type Digest = SlotDigest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
 	}
 
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let slot = header.consensus_digest.slot;
+		if slot <= parent_digest.slot {
+			return Err(ChainError::NonMonotonicSlot);
+		}
+		if self.current_slot().map_or(true, |current| slot > current) {
+			return Err(ChainError::FutureSlot);
+		}
+		match self.authority_for_slot(slot) {
+			Some(authority) if header.consensus_digest.signature == *authority => Ok(()),
+			_ => Err(ChainError::WrongAuthority),
+		}
+	}
+
+	/// Sign the partial header in the current slot. Only one block can be authored per slot, so
+	/// this fails if the parent was already authored in the current slot.
 	fn seal(
 		&self,
 		parent_digest: &Self::Digest,
 		partial_header: Header<()>,
 	) -> Option<Header<Self::Digest>> {
-		// Find the authority corresponding to the current slot
-        let authority = self.authorities.get(parent_digest.slot as usize)?;
-        // Create the slot digest for the next slot
-        let slot_digest = SlotDigest {
-            slot: parent_digest.slot + 1,
-            signature: *authority,
-        };
-        
-        // Create the header with the slot digest
-        Some(Header {
-            parent: partial_header.parent,
-            height: partial_header.height,
-            extrinsics_root: partial_header.extrinsics_root,
-            state_root: partial_header.state_root,
-            consensus_digest: slot_digest,
-        })
+		let slot = self.current_slot()?;
+		if slot <= parent_digest.slot {
+			return None;
+		}
+		let authority = self.authority_for_slot(slot)?;
+
+		Some(partial_header.with_digest(SlotDigest { slot, signature: *authority }))
+	}
+
+	fn human_name() -> String {
+		"Aura".into()
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		Some(header.consensus_digest.signature)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		header.consensus_digest.slot
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let signature = self.authority_for_slot(0).copied().unwrap_or(ConsensusAuthority::Alice);
+		genesis_with_digest(SlotDigest { slot: 0, signature })
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::Aura
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["increasing-slot", "slot-not-in-future", "round-robin-by-slot"]].concat()
+	}
+}
+
+/// A Proof of Authority consensus engine in which the author for each height is chosen
+/// pseudo-randomly from a seed.
+///
+/// The eligible authority is `authorities[hash((height, epoch_seed)) % authorities.len()]`. Before
+/// the seed is known, nobody can predict who will author a given height, but once it is known
+/// anyone can re-derive the schedule and verify it. Leader election in real-world protocols
+/// like BABE follows the same idea, using a VRF rather than a plain hash.
+///
+/// The seed is expected to change from epoch to epoch. Here it is advanced by hand. The
+/// `EpochRandomizedPoa` engine derives it from the chain itself instead.
+pub struct RandomizedPoa {
+	authorities: Vec<ConsensusAuthority>,
+	epoch_seed: u64,
+}
+
+/// The authority who is eligible to author at the given height in an epoch with the given seed,
+/// if there are any authorities.
+pub(crate) fn randomized_author(
+	authorities: &[ConsensusAuthority],
+	height: u64,
+	epoch_seed: u64,
+) -> Option<ConsensusAuthority> {
+	let index = checked_slot_index(hash(&(height, epoch_seed)), authorities.len())?;
+	authorities.get(index).copied()
+}
+
+impl RandomizedPoa {
+	/// Create a new engine with the given authorities, starting at the given epoch seed.
+	pub fn new(authorities: Vec<ConsensusAuthority>, epoch_seed: u64) -> Self {
+		RandomizedPoa { authorities, epoch_seed }
+	}
+
+	/// Move on to a new epoch whose schedule is derived from the given seed.
+	pub fn advance_epoch(&mut self, epoch_seed: u64) {
+		self.epoch_seed = epoch_seed;
+	}
+
+	/// The authority who is eligible to author at the given height in the current epoch, if any.
+	pub fn expected_author(&self, height: u64) -> Option<ConsensusAuthority> {
+		randomized_author(&self.authorities, height, self.epoch_seed)
+	}
+}
+
+impl Consensus for RandomizedPoa {
+	type Digest = ConsensusAuthority;
+
+	/// Re-derive the eligible authority from the seed and check that they signed the header.
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	fn check_header(
+		&self,
+		_: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if self.expected_author(header.height) == Some(header.consensus_digest) {
+			Ok(())
+		} else {
+			Err(ChainError::WrongAuthority)
+		}
+	}
+
+	/// Sign the partial header as the authority who is eligible at its height.
+	fn seal(&self, _: &Self::Digest, partial_header: Header<()>) -> Option<Header<Self::Digest>> {
+		let authority = self.expected_author(partial_header.height)?;
+		Some(partial_header.with_digest(authority))
+	}
+
+	fn human_name() -> String {
+		"Randomized Proof of Authority".into()
+	}
+
+	/// The authority's signature is the whole digest.
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		Some(header.consensus_digest)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(self.expected_author(0).unwrap_or(ConsensusAuthority::Alice))
+	}
+
+	fn kind(&self) -> EngineKind {
+		EngineKind::RandomizedPoa
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["random-authority"]].concat()
+	}
+}
+
+/// Whether rotating from the `before` authority set to the `after` set is safe to do in one step.
+///
+/// If too many authorities are replaced at once, the new authorities could form a dishonest
+/// majority before the honest ones have a chance to notice. So a rotation is only safe if the
+/// number of authorities removed, or added, is at most `max_churn_percent` percent of the
+/// original set.
+pub fn is_safe_rotation(
+	before: &[ConsensusAuthority],
+	after: &[ConsensusAuthority],
+	max_churn_percent: u64,
+) -> bool {
+	let removed = before.iter().filter(|a| !after.contains(a)).count();
+	let added = after.iter().filter(|a| !before.contains(a)).count();
+	let churn = removed.max(added) as u64;
+
+	churn.saturating_mul(100) <= max_churn_percent.saturating_mul(before.len() as u64)
+}
+
+#[cfg(test)]
+fn all_authorities() -> Vec<ConsensusAuthority> {
+	vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob, ConsensusAuthority::Charlie]
+}
+
+#[test]
+fn cs_3_round_robin_without_authorities_rejects_and_cannot_seal() {
+	let engine = PoaRoundRobinByHeight::new(vec![]);
+	let partial =
+		Header { parent: 0, height: 1, extrinsics_root: 0, state_root: 0, consensus_digest: () };
+
+	for authority in all_authorities() {
+		assert_eq!(
+			engine.check_header(&authority, &partial.with_digest(authority)),
+			Err(ChainError::WrongAuthority)
+		);
+	}
+	assert!(engine.seal(&ConsensusAuthority::Alice, partial).is_none());
+}
+
+#[test]
+fn cs_3_randomized_same_seed_same_schedule() {
+	let first = RandomizedPoa::new(all_authorities(), 42);
+	let second = RandomizedPoa::new(all_authorities(), 42);
+
+	for height in 0..32 {
+		assert_eq!(first.expected_author(height), second.expected_author(height));
+	}
+}
+
+#[test]
+fn cs_3_randomized_different_seed_different_schedule() {
+	let first = RandomizedPoa::new(all_authorities(), 42);
+	let second = RandomizedPoa::new(all_authorities(), 43);
+
+	let first_schedule: Vec<_> = (0..32).map(|h| first.expected_author(h)).collect();
+	let second_schedule: Vec<_> = (0..32).map(|h| second.expected_author(h)).collect();
+	assert_ne!(first_schedule, second_schedule);
+}
+
+#[test]
+fn cs_3_randomized_validates_only_expected_author() {
+	let engine = RandomizedPoa::new(all_authorities(), 7);
+	let partial =
+		Header { parent: 0, height: 5, extrinsics_root: 0, state_root: 0, consensus_digest: () };
+	let mut header = engine.seal(&ConsensusAuthority::Alice, partial).unwrap();
+	assert!(engine.validate(&ConsensusAuthority::Alice, &header));
+
+	let imposter = all_authorities().into_iter().find(|a| *a != header.consensus_digest).unwrap();
+	header.consensus_digest = imposter;
+	assert!(!engine.validate(&ConsensusAuthority::Alice, &header));
+}
+
+#[test]
+fn cs_3_randomized_schedule_changes_with_epoch() {
+	let mut engine = RandomizedPoa::new(all_authorities(), 42);
+	let before: Vec<_> = (0..32).map(|h| engine.expected_author(h)).collect();
+
+	engine.advance_epoch(43);
+	let after: Vec<_> = (0..32).map(|h| engine.expected_author(h)).collect();
+
+	let fresh = RandomizedPoa::new(all_authorities(), 43);
+	assert_eq!(after, (0..32).map(|h| fresh.expected_author(h)).collect::<Vec<_>>());
+	assert_ne!(before, after);
+}
+
+#[test]
+fn cs_3_randomized_no_authorities_cannot_seal() {
+	let engine = RandomizedPoa::new(vec![], 42);
+	let partial =
+		Header { parent: 0, height: 1, extrinsics_root: 0, state_root: 0, consensus_digest: () };
+
+	assert!(engine.seal(&ConsensusAuthority::Alice, partial).is_none());
+}
+
+#[test]
+fn cs_3_aura_seal_tracks_clock_across_slot_boundaries() {
+	use super::clock::MockClock;
+
+	let clock = MockClock::new(5);
+	let engine = Aura::new(all_authorities(), 10, &clock);
+	let genesis_digest = SlotDigest { slot: 0, signature: ConsensusAuthority::Alice };
+	let partial =
+		|| Header { parent: 0, height: 1, extrinsics_root: 0, state_root: 0, consensus_digest: () };
+
+	// Still in the genesis slot, so there is nothing to author yet.
+	assert!(engine.seal(&genesis_digest, partial()).is_none());
+
+	clock.advance(5);
+	let b1 = engine.seal(&genesis_digest, partial()).unwrap();
+	assert_eq!(b1.consensus_digest, SlotDigest { slot: 1, signature: ConsensusAuthority::Bob });
+	assert!(engine.validate(&genesis_digest, &b1));
+
+	clock.advance(25);
+	let b2 = engine.seal(&b1.consensus_digest, partial()).unwrap();
+	assert_eq!(b2.consensus_digest, SlotDigest { slot: 3, signature: ConsensusAuthority::Alice });
+}
+
+#[test]
+fn cs_3_aura_rejects_future_slot_until_it_starts() {
+	use super::clock::MockClock;
+
+	let clock = MockClock::new(10);
+	let engine = Aura::new(all_authorities(), 10, &clock);
+	let genesis_digest = SlotDigest { slot: 0, signature: ConsensusAuthority::Alice };
+	let header = Header {
+		parent: 0,
+		height: 1,
+		extrinsics_root: 0,
+		state_root: 0,
+		consensus_digest: SlotDigest { slot: 2, signature: ConsensusAuthority::Charlie },
+	};
+
+	assert_eq!(engine.check_header(&genesis_digest, &header), Err(ChainError::FutureSlot));
+
+	clock.set(20);
+	assert_eq!(engine.check_header(&genesis_digest, &header), Ok(()));
+}
+
+#[test]
+fn cs_3_seal_author_round_robin() {
+	let engine = PoaRoundRobinByHeight::new(all_authorities());
+	let partial =
+		Header { parent: 0, height: 2, extrinsics_root: 0, state_root: 0, consensus_digest: () };
+	let header = engine.seal(&ConsensusAuthority::Alice, partial).unwrap();
+
+	assert_eq!(engine.seal_author(&header), Some(ConsensusAuthority::Charlie));
+}
+
+#[test]
+fn cs_3_round_robin_seal_and_validate_agree() {
+	let engine = PoaRoundRobinByHeight::new(all_authorities());
+	let genesis_digest = ConsensusAuthority::Alice;
+
+	for height in 0..=2 * all_authorities().len() as u64 {
+		let partial =
+			Header { parent: 0, height, extrinsics_root: 0, state_root: 0, consensus_digest: () };
+		let header = engine.seal(&genesis_digest, partial).unwrap();
+
+		assert_eq!(Some(&header.consensus_digest), engine.authority_for_height(height));
+		assert!(engine.validate(&genesis_digest, &header));
 	}
 }
+
+#[test]
+fn cs_3_seal_author_slot_based() {
+	let engine = PoaRoundRobinBySlot::new(all_authorities());
+	let parent_digest = SlotDigest { slot: 0, signature: ConsensusAuthority::Alice };
+	let partial =
+		Header { parent: 0, height: 1, extrinsics_root: 0, state_root: 0, consensus_digest: () };
+	let header = engine.seal(&parent_digest, partial).unwrap();
+
+	assert_eq!(engine.seal_author(&header), Some(ConsensusAuthority::Bob));
+}
+
+#[test]
+fn cs_3_slot_gap_is_bounded() {
+	let engine = PoaRoundRobinBySlot::with_max_slot_gap(all_authorities(), 3);
+	let parent_digest = SlotDigest { slot: 5, signature: ConsensusAuthority::Charlie };
+	let header = |slot| Header {
+		parent: 0,
+		height: 1,
+		extrinsics_root: 0,
+		state_root: 0,
+		consensus_digest: SlotDigest { slot, signature: *engine.authority_for_slot(slot).unwrap() },
+	};
+
+	assert_eq!(engine.check_header(&parent_digest, &header(6)), Ok(()));
+	assert_eq!(engine.check_header(&parent_digest, &header(8)), Ok(()));
+	assert_eq!(engine.check_header(&parent_digest, &header(9)), Err(ChainError::SlotGapTooLarge));
+	assert_eq!(
+		PoaRoundRobinBySlot::new(all_authorities()).check_header(&parent_digest, &header(500)),
+		Ok(())
+	);
+}
+
+#[test]
+fn cs_3_slot_chain_validates_only_from_its_genesis_digest() {
+	use super::test_support::sealed_adder_chain;
+
+	let engine = PoaRoundRobinBySlot::new(all_authorities());
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+	assert!(engine.verify_sub_chain(&genesis.consensus_digest, &chain));
+	assert_eq!(engine.longest_valid_prefix(&genesis, &chain), 3);
+
+	// A genesis digest that claims a later slot makes the first block's slot go backwards.
+	let wrong_genesis_digest = SlotDigest { slot: 1, signature: ConsensusAuthority::Bob };
+	assert!(!engine.verify_sub_chain(&wrong_genesis_digest, &chain));
+	let wrong_genesis = Header { consensus_digest: wrong_genesis_digest, ..genesis };
+	assert_eq!(engine.longest_valid_prefix(&wrong_genesis, &chain), 0);
+}
+
+#[test]
+fn cs_3_poa_engines_survive_extreme_slots_and_heights() {
+	let last_slot = SlotDigest { slot: u64::MAX, signature: ConsensusAuthority::Alice };
+	let partial = Header {
+		parent: 0,
+		height: u64::MAX,
+		extrinsics_root: 0,
+		state_root: 0,
+		consensus_digest: (),
+	};
+
+	// There is no slot after the last one, so nothing can be sealed on top of it.
+	let by_slot = PoaRoundRobinBySlot::new(all_authorities());
+	assert_eq!(by_slot.seal(&last_slot, partial.clone()), None);
+	let header = Header {
+		parent: 0,
+		height: u64::MAX,
+		extrinsics_root: 0,
+		state_root: 0,
+		consensus_digest: last_slot,
+	};
+	let genesis_digest = SlotDigest { slot: 0, signature: ConsensusAuthority::Alice };
+	assert_eq!(by_slot.check_header(&genesis_digest, &header), Ok(()));
+
+	let by_height = PoaRoundRobinByHeight::new(all_authorities());
+	let sealed = by_height.seal(&ConsensusAuthority::Alice, partial.clone()).unwrap();
+	assert!(by_height.validate(&ConsensusAuthority::Alice, &sealed));
+
+	// Without authorities, nobody's turn ever comes.
+	assert_eq!(PoaRoundRobinBySlot::new(vec![]).seal(&genesis_digest, partial), None);
+	assert_eq!(RandomizedPoa::new(vec![], 42).expected_author(u64::MAX), None);
+}
+
+#[test]
+fn cs_3_engines_report_their_kind() {
+	use super::{clock::MockClock, p1_pow::moderate_difficulty_pow};
+
+	let clock = MockClock::new(0);
+	assert_eq!(().kind(), EngineKind::Trivial);
+	assert_eq!(moderate_difficulty_pow().kind(), EngineKind::Pow);
+	assert_eq!(SimplePoa { authorities: all_authorities() }.kind(), EngineKind::SimplePoa);
+	assert_eq!(PoaRoundRobinByHeight::new(all_authorities()).kind(), EngineKind::RoundRobinHeight);
+	assert_eq!(PoaRoundRobinBySlot::new(all_authorities()).kind(), EngineKind::RoundRobinSlot);
+	assert_eq!(Aura::new(all_authorities(), 10, &clock).kind(), EngineKind::Aura);
+	assert_eq!(RandomizedPoa::new(all_authorities(), 42).kind(), EngineKind::RandomizedPoa);
+}
+
+#[test]
+fn cs_3_single_member_swap_is_safe_rotation() {
+	let before = vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob];
+	let after = vec![ConsensusAuthority::Alice, ConsensusAuthority::Charlie];
+
+	assert!(is_safe_rotation(&before, &before, 0));
+	assert!(is_safe_rotation(&before, &after, 50));
+	assert!(!is_safe_rotation(&before, &after, 49));
+}
+
+#[test]
+fn cs_3_full_replacement_is_unsafe_rotation() {
+	let before = vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob];
+	let after = vec![ConsensusAuthority::Charlie];
+
+	assert!(!is_safe_rotation(&before, &after, 50));
+	assert!(is_safe_rotation(&before, &after, 100));
+	assert!(is_safe_rotation(&before, &after, u64::MAX));
+}
+
+#[test]
+fn cs_3_round_robin_reports_next_author_address() {
+	let mut registry = AuthorityRegistry::new();
+	registry.register(ConsensusAuthority::Alice, "10.0.0.1:30333".into());
+	registry.register(ConsensusAuthority::Bob, "10.0.0.2:30333".into());
+
+	let by_height = PoaRoundRobinByHeight::new(all_authorities());
+	assert_eq!(by_height.author_address(&registry, 3), Some("10.0.0.1:30333"));
+	assert_eq!(by_height.author_address(&registry, 4), Some("10.0.0.2:30333"));
+	// Charlie has not registered an address.
+	assert_eq!(by_height.author_address(&registry, 5), None);
+
+	let by_slot = PoaRoundRobinBySlot::new(all_authorities());
+	assert_eq!(by_slot.author_address(&registry, 7), Some("10.0.0.2:30333"));
+	assert_eq!(PoaRoundRobinBySlot::new(vec![]).author_address(&registry, 7), None);
+}
+
+#[test]
+fn cs_3_poa_seal_cost_is_a_single_signature() {
+	assert_eq!(SimplePoa { authorities: all_authorities() }.estimate_seal_cost(), 1);
+	assert_eq!(PoaRoundRobinByHeight::new(all_authorities()).estimate_seal_cost(), 1);
+}
@@ -3,49 +3,81 @@
 //! engine. It is higher- order because it will wrap an inner consensus engine, such as PoW or PoA
 //! and work in either case.
 
-use std::marker::PhantomData;
-
-use super::{Consensus, Header};
-
-/// A Consensus engine that wraps another consensus engine. This engine enforces the requirement
-/// that a block must have an even state root in order to be valid
+use super::{p1_pow::PoW, Consensus, ConsensusAuthority, Header};
+use crate::ChainError;
 
 /// A Consensus engine that requires the state root to be even for the header to be valid.
 /// Wraps an inner consensus engine whose rules will also be enforced.
-struct EvenOnly<Inner: Consensus>(PhantomData<Inner>);
+pub struct EvenOnly<Inner> {
+	inner: Inner,
+}
+
+impl<Inner: Consensus> EvenOnly<Inner> {
+	/// Create a new engine that enforces the given engine's rules as well as its own.
+	pub fn new(inner: Inner) -> Self {
+		EvenOnly { inner }
+	}
+}
 
 impl<Inner: Consensus> Consensus for EvenOnly<Inner> {
 	type Digest = Inner::Digest;
 
 	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-		
-		// Delegate validation to the inner consensus engine
-        let inner_consensus = Inner::new(); // Instantiate inner consensus engine
-        let is_inner_valid = inner_consensus.validate(parent_digest, header);
+		self.check_header(parent_digest, header).is_ok()
+	}
 
-        // Check if the state root is even
-        let is_state_root_even = header.state_root % 2 == 0;
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if !header.state_root.is_multiple_of(2) {
+			return Err(ChainError::WrongParity);
+		}
+		self.inner.check_header(parent_digest, header)
+	}
 
-        // The header is valid if the inner consensus engine deems it valid and the state root is even
-        is_inner_valid && is_state_root_even
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(parent_digest, header)
 	}
 
+	/// There is no point sealing a header with an odd state root, because it could never be valid.
 	fn seal(
 		&self,
 		parent_digest: &Self::Digest,
 		partial_header: Header<()>,
 	) -> Option<Header<Self::Digest>> {
-		
-       // Delegate sealing to the inner consensus engine
-	   let inner_consensus = Inner::new(); // Instantiate inner consensus engine
-	   let sealed_header = inner_consensus.seal(parent_digest, partial_header)?;
-
-	   // Check if the state root is even
-	   if sealed_header.state_root % 2 == 0 {
-		   Some(sealed_header) // Return the sealed header if the state root is even
-	   } else {
-		   None // Return None if the state root is not even
-	   }
+		if !partial_header.state_root.is_multiple_of(2) {
+			return None;
+		}
+		self.inner.seal(parent_digest, partial_header)
+	}
+
+	/// Genesis belongs to the inner engine.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.inner.genesis_header()
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(header)
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("even-state");
+		rules
 	}
 }
 
@@ -53,20 +85,35 @@ impl<Inner: Consensus> Consensus for EvenOnly<Inner> {
 /// inner engine, create a PoW chain that is valid according to the inner consensus engine, but is
 /// not valid according to this engine because the state roots are not all even.
 fn almost_valid_but_not_all_even() -> Vec<Header<u64>> {
-	// Generate headers with alternating even and odd state roots
-    let mut headers = Vec::new();
-    let mut is_even = true;
-    for i in 0..10 {
-        let state_root = if is_even { i * 2 } else { i * 2 + 1 };
-        let header = Header {
-            parent: 0,
-            height: i as u64,
-            extrinsics_root: 0,
-            state_root,
-            consensus_digest: 0,
-        };
-        headers.push(header);
-        is_even = !is_even; // Toggle between even and odd state roots
-    }
-    headers
+	let pow = PoW::new(u64::MAX / 4);
+	let mut headers = vec![pow.genesis_header()];
+	// Each block adds its height to the state, so the roots go 0, 1, 3, 6, and are not all even.
+	for height in 1..4 {
+		let parent = headers.last().unwrap();
+		let partial = Header {
+			parent: crate::hash(parent),
+			height,
+			extrinsics_root: height,
+			state_root: parent.state_root + height,
+			consensus_digest: (),
+		};
+		headers.push(pow.seal(&parent.consensus_digest, partial).unwrap());
+	}
+	headers
+}
+
+#[test]
+fn cs_4_even_only_delegates_genesis_to_inner_engine() {
+	let pow = PoW::new(u64::MAX / 4);
+	assert_eq!(EvenOnly::new(PoW::new(u64::MAX / 4)).genesis_header(), pow.genesis_header());
+}
+
+#[test]
+fn cs_4_almost_valid_chain_is_rejected_for_odd_state() {
+	let chain = almost_valid_but_not_all_even();
+	let pow = PoW::new(u64::MAX / 4);
+	let even_pow = EvenOnly::new(PoW::new(u64::MAX / 4));
+
+	assert_eq!(pow.check_sub_chain(&chain[0], &chain[1..]), Ok(()));
+	assert_eq!(even_pow.check_sub_chain(&chain[0], &chain[1..]), Err(ChainError::WrongParity));
 }
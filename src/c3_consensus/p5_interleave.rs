@@ -3,45 +3,139 @@
 //! this we could consider interleaving PoW blocks with PoA blocks. Some very early designs of
 //! Ethereum considered this approach as a way to transition away from PoW.
 
-/// A Consensus engine that alternates back and forth between PoW and PoA sealed blocks.
-use super::{Consensus, Header};
+use super::{
+	genesis_with_digest, p1_pow::PoW, p3_poa::PoaRoundRobinByHeight, Consensus,
+	ConsensusAuthority, Header, BASE_RULES,
+};
+use crate::ChainError;
 
-/// A Consensus engine that alternates back and forth between PoW and PoA sealed blocks.
-struct AlternatingPowPoa {
-    current_engine: Box<dyn Consensus<Digest = u64>>,
+/// The digest of an interleaved chain. Each block carries the digest of the engine that sealed it.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InterleavedDigest {
+	Pow(u64),
+	Poa(ConsensusAuthority),
+}
+
+/// A Consensus engine that alternates back and forth between PoW and PoA sealed blocks. Blocks at
+/// odd heights are mined, and blocks at even heights are signed by the authorities in turn.
+pub struct AlternatingPowPoa {
+	pow: PoW,
+	poa: PoaRoundRobinByHeight,
 }
 
 impl AlternatingPowPoa {
-    /// Create a new `AlternatingPowPoa` instance, starting with the given consensus engine.
-    fn new(initial_engine: Box<dyn Consensus<Digest = u64>>) -> Self {
-        Self {
-            current_engine: initial_engine,
-        }
-    }
-
-    /// Toggle between PoW and PoA consensus engines.
-    fn toggle_engine(&mut self) {
-        // If the current engine is PoW, switch to PoA, and vice versa
-        if self.current_engine.human_name() == "Proof of Work" {
-            self.current_engine = Box::new(PoaConsensus);
-        } else {
-            self.current_engine = Box::new(PowConsensus);
-        }
-    }
+	/// Create an engine that alternates between the given PoW and PoA engines.
+	pub fn new(pow: PoW, poa: PoaRoundRobinByHeight) -> Self {
+		AlternatingPowPoa { pow, poa }
+	}
+
+	/// Whether the block at the given height is mined, rather than signed.
+	fn is_mined(height: u64) -> bool {
+		!height.is_multiple_of(2)
+	}
 }
 
 impl Consensus for AlternatingPowPoa {
-    type Digest = u64;
-
-    fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-        self.current_engine.validate(parent_digest, header)
-    }
-
-    fn seal(
-        &self,
-        parent_digest: &Self::Digest,
-        partial_header: Header<()>,
-    ) -> Option<Header<Self::Digest>> {
-        self.current_engine.seal(parent_digest, partial_header)
-    }
+	type Digest = InterleavedDigest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// PoW ignores its parent's digest, and PoA by height only looks at the height, so neither
+	/// engine needs a parent digest of its own type.
+	fn check_header(
+		&self,
+		_: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		match (Self::is_mined(header.height), header.consensus_digest) {
+			(true, InterleavedDigest::Pow(nonce)) =>
+				self.pow.check_header(&0, &header.with_digest(nonce)),
+			(false, InterleavedDigest::Poa(authority)) =>
+				self.poa.check_header(&authority, &header.with_digest(authority)),
+			_ => Err(ChainError::InvalidSeal),
+		}
+	}
+
+	fn seal(
+		&self,
+		_: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		if Self::is_mined(partial_header.height) {
+			Some(self.pow.seal(&0, partial_header)?.map_digest(InterleavedDigest::Pow))
+		} else {
+			let authority = self.poa.genesis_header().consensus_digest;
+			Some(self.poa.seal(&authority, partial_header)?.map_digest(InterleavedDigest::Poa))
+		}
+	}
+
+	/// Genesis is at an even height, so it carries the PoA engine's genesis digest.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(InterleavedDigest::Poa(self.poa.genesis_header().consensus_digest))
+	}
+
+	/// Only signed blocks have a designated author.
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		match header.consensus_digest {
+			InterleavedDigest::Pow(_) => None,
+			InterleavedDigest::Poa(authority) => Some(authority),
+		}
+	}
+
+	/// Half of the blocks are mined, so sealing costs half as much as PoW on average.
+	fn estimate_seal_cost(&self) -> u64 {
+		self.pow.estimate_seal_cost() / 2
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["pow-threshold", "round-robin-by-height", "engine-by-height-parity"]]
+			.concat()
+	}
+}
+
+#[cfg(test)]
+use super::test_support::sealed_adder_chain;
+
+#[cfg(test)]
+fn engine() -> AlternatingPowPoa {
+	AlternatingPowPoa::new(
+		PoW::new(u64::MAX / 4),
+		PoaRoundRobinByHeight::new(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob]),
+	)
+}
+
+#[test]
+fn cs_interleave_alternates_between_engines() {
+	let engine = engine();
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 4);
+
+	assert!(matches!(chain[0].consensus_digest, InterleavedDigest::Pow(_)));
+	assert_eq!(chain[1].consensus_digest, InterleavedDigest::Poa(ConsensusAuthority::Alice));
+	assert!(matches!(chain[2].consensus_digest, InterleavedDigest::Pow(_)));
+	assert_eq!(chain[3].consensus_digest, InterleavedDigest::Poa(ConsensusAuthority::Alice));
+	assert_eq!(engine.check_sub_chain(&genesis, &chain), Ok(()));
+	assert_eq!(engine.seal_author(&chain[0]), None);
+	assert_eq!(engine.seal_author(&chain[1]), Some(ConsensusAuthority::Alice));
+}
+
+#[test]
+fn cs_interleave_rejects_the_wrong_engine_at_a_height() {
+	let engine = engine();
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 2);
+
+	// A signed block where a mined one is due, and the other way around.
+	let signed_at_odd = chain[0].with_digest(InterleavedDigest::Poa(ConsensusAuthority::Bob));
+	let mined_at_even = chain[1].with_digest(InterleavedDigest::Pow(0));
+	assert_eq!(
+		engine.check_header(&genesis.consensus_digest, &signed_at_odd),
+		Err(ChainError::InvalidSeal)
+	);
+	assert_eq!(
+		engine.check_header(&chain[0].consensus_digest, &mined_at_even),
+		Err(ChainError::InvalidSeal)
+	);
 }
@@ -6,35 +6,71 @@
 //! be enforced before or after the fork, but rather delegates to existing consensus engines
 //! for that. Here we simply write the logic for detecting whether we are before or after the fork.
 
-use std::marker::PhantomData;
-
-use super::{Consensus, Header};
-
+use super::{
+	genesis_with_digest,
+	p1_pow::PoW,
+	p3_poa::{is_safe_rotation, PoaRoundRobinByHeight},
+	Consensus, ConsensusAuthority, Header, BASE_RULES,
+};
+use crate::ChainError;
 
 /// A Higher-order consensus engine that represents a change from one set of consensus rules
 /// (Before) to another set (After) at a specific block height
-struct Forked<D, Before, After> {
+///
+/// The first block after the fork builds on a parent sealed by the old engine, whose digest the new
+/// engine may not understand. So the new engine treats that parent as if it were its own genesis.
+pub struct Forked<Before, After> {
+	before: Before,
+	after: After,
 	/// The first block height at which the new consensus rules apply
 	fork_height: u64,
-	phdata: PhantomData<(D, Before, After)>,
 }
 
-impl<D, B, A> Consensus for Forked<D, B, A>
+impl<Before, After> Forked<Before, After>
+where
+	Before: Consensus,
+	After: Consensus<Digest = Before::Digest>,
+{
+	/// Create a new engine that follows `before` up to the given height, and `after` from that
+	/// height on.
+	pub fn new(before: Before, after: After, fork_height: u64) -> Self {
+		Forked { before, after, fork_height }
+	}
+
+	/// The engine in charge of the given height, and the parent digest it should build on.
+	fn engine_for<'a>(
+		&'a self,
+		height: u64,
+		parent_digest: &'a Before::Digest,
+	) -> (&'a dyn Consensus<Digest = Before::Digest>, Before::Digest) {
+		if height < self.fork_height {
+			(&self.before, parent_digest.clone())
+		} else if height == self.fork_height {
+			(&self.after, self.after.genesis_header().consensus_digest)
+		} else {
+			(&self.after, parent_digest.clone())
+		}
+	}
+}
+
+impl<Before, After> Consensus for Forked<Before, After>
 where
-	D: Clone + core::fmt::Debug + Eq + PartialEq + std::hash::Hash,
-	B: Consensus,
-	A: Consensus,
-	B::Digest: Into<D>,
-	A::Digest: Into<D>,
+	Before: Consensus,
+	After: Consensus<Digest = Before::Digest>,
 {
-	type Digest = D;
+	type Digest = Before::Digest;
 
 	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-		if header.height < self.fork_height {
-            B::validate(&self, parent_digest.into(), header)
-        } else {
-            A::validate(&self,parent_digest.into(), header)
-        }
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let (engine, parent_digest) = self.engine_for(header.height, parent_digest);
+		engine.check_header(&parent_digest, header)
 	}
 
 	fn seal(
@@ -42,48 +78,131 @@ where
 		parent_digest: &Self::Digest,
 		partial_header: Header<()>,
 	) -> Option<Header<Self::Digest>> {
-		if partial_header.height < self.fork_height {
-            B::seal(parent_digest.into(), partial_header)
-        } else {
-            A::seal(parent_digest.into(), partial_header)
-        }
+		let (engine, parent_digest) = self.engine_for(partial_header.height, parent_digest);
+		engine.seal(&parent_digest, partial_header)
+	}
+
+	/// Genesis belongs to whichever engine governs height zero.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		if self.fork_height == 0 {
+			self.after.genesis_header()
+		} else {
+			self.before.genesis_header()
+		}
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		if header.height < self.fork_height {
+			self.before.seal_author(header)
+		} else {
+			self.after.seal_author(header)
+		}
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		if header.height < self.fork_height {
+			self.before.seal_slot(header)
+		} else {
+			self.after.seal_slot(header)
+		}
+	}
+
+	/// The engine does not know which side of the fork the next block is on, so it assumes the
+	/// more expensive one.
+	fn estimate_seal_cost(&self) -> u64 {
+		self.before.estimate_seal_cost().max(self.after.estimate_seal_cost())
+	}
+
+	/// The rules of both engines, each listed once, since either may be in charge of a block.
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.before.rules();
+		for rule in self.after.rules() {
+			if !rules.contains(&rule) {
+				rules.push(rule);
+			}
+		}
+		rules.push("engine-by-fork-height");
+		rules
 	}
 }
 
 /// Create a PoA consensus engine that changes authorities part way through the chain's history.
 /// Given the initial authorities, the authorities after the fork, and the height at which the fork
 /// occurs.
+///
+/// Replacing too many authorities at once is unsafe, so if more than `max_churn_percent` percent of
+/// the authorities change, the block at the fork height is invalid.
 fn change_authorities(
 	fork_height: u64,
 	initial_authorities: Vec<ConsensusAuthority>,
 	final_authorities: Vec<ConsensusAuthority>,
-) -> impl Consensus {
+	max_churn_percent: u64,
+) -> impl Consensus<Digest = ConsensusAuthority> {
 	/// A PoA consensus engine that changes authorities part way through the chain's history.
-    struct PoAChangeAuthorities {
-        fork_height: u64,
-        initial_authorities: Vec<ConsensusAuthority>,
-        final_authorities: Vec<ConsensusAuthority>,
-    }
+	struct PoAChangeAuthorities {
+		forked: Forked<PoaRoundRobinByHeight, PoaRoundRobinByHeight>,
+		rotation_is_safe: bool,
+	}
+
+	impl Consensus for PoAChangeAuthorities {
+		type Digest = ConsensusAuthority;
+
+		fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+			self.check_header(parent_digest, header).is_ok()
+		}
+
+		fn check_header(
+			&self,
+			parent_digest: &Self::Digest,
+			header: &Header<Self::Digest>,
+		) -> Result<(), ChainError> {
+			// The block that enacts the rotation is only valid if the rotation is safe
+			if header.height == self.forked.fork_height && !self.rotation_is_safe {
+				return Err(ChainError::WrongAuthority);
+			}
+			self.forked.check_header(parent_digest, header)
+		}
+
+		fn seal(
+			&self,
+			parent_digest: &Self::Digest,
+			partial_header: Header<()>,
+		) -> Option<Header<Self::Digest>> {
+			if partial_header.height == self.forked.fork_height && !self.rotation_is_safe {
+				return None;
+			}
+			self.forked.seal(parent_digest, partial_header)
+		}
+
+		fn genesis_header(&self) -> Header<Self::Digest> {
+			self.forked.genesis_header()
+		}
 
-    impl Consensus for PoAChangeAuthorities {
-        type Digest = ConsensusAuthority;
+		fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+			self.forked.seal_author(header)
+		}
 
-        fn validate(&self, _: &Self::Digest, _: &Header<Self::Digest>) -> bool {
-            // Add validation logic here
-            true // Placeholder, change as needed
-        }
+		fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+			self.forked.seal_slot(header)
+		}
 
-        fn seal(&self, _: &Self::Digest, _: Header<()>) -> Option<Header<Self::Digest>> {
-            // Add sealing logic here
-            None // Placeholder, change as needed
-        }
-    }
+		fn rules(&self) -> Vec<&'static str> {
+			let mut rules = self.forked.rules();
+			rules.push("safe-rotation");
+			rules
+		}
+	}
 
-    PoAChangeAuthorities {
-        fork_height,
-        initial_authorities,
-        final_authorities,
-    }
+	let rotation_is_safe =
+		is_safe_rotation(&initial_authorities, &final_authorities, max_churn_percent);
+	PoAChangeAuthorities {
+		forked: Forked::new(
+			PoaRoundRobinByHeight::new(initial_authorities),
+			PoaRoundRobinByHeight::new(final_authorities),
+			fork_height,
+		),
+		rotation_is_safe,
+	}
 }
 
 /// Create a PoW consensus engine that changes the difficulty part way through the chain's history.
@@ -91,58 +210,8 @@ fn change_difficulty(
 	fork_height: u64,
 	initial_difficulty: u64,
 	final_difficulty: u64,
-) -> impl Consensus {
-	// Define and return the ChangingDifficultyPoW instance directly within the function
-    struct ChangingDifficultyPoW<C: Consensus> {
-        inner: C,
-        fork_height: u64,
-        initial_difficulty: u64,
-        final_difficulty: u64,
-    }
-
-    impl<C: Consensus> Consensus for ChangingDifficultyPoW<C> {
-        type Digest = C::Digest;
-
-        fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-            self.inner.validate(parent_digest, header)
-        }
-
-        fn seal(
-            &self,
-            parent_digest: &Self::Digest,
-            partial_header: Header<()>,
-        ) -> Option<Header<Self::Digest>> {
-            let sealed_header = self.inner.seal(parent_digest, partial_header)?;
-            // Check if the fork height has been reached
-            if sealed_header.height >= self.fork_height {
-                // Adjust the difficulty based on the fork height
-                // In this example, we simply use the final difficulty
-                // You can implement more sophisticated logic here
-                // For simplicity, we assume that the difficulty change is immediate
-                let new_difficulty = if sealed_header.height == self.fork_height {
-                    self.final_difficulty
-                } else {
-                    sealed_header.difficulty // Retain the current difficulty until the fork height
-                };
-                // Update the difficulty in the sealed header
-                let modified_header = Header {
-                    difficulty: new_difficulty,
-                    ..sealed_header
-                };
-                Some(modified_header)
-            } else {
-                Some(sealed_header)
-            }
-        }
-    }
-
-    // Return an instance of ChangingDifficultyPoW with the specified parameters
-    ChangingDifficultyPoW {
-        inner: (),
-        fork_height,
-        initial_difficulty,
-        final_difficulty,
-    }
+) -> impl Consensus<Digest = u64> {
+	Forked::new(PoW::new(initial_difficulty), PoW::new(final_difficulty), fork_height)
 }
 
 /// Earlier in this chapter we implemented a consensus rule in which blocks are only considered
@@ -153,42 +222,79 @@ fn change_difficulty(
 /// Create a consensus engine that introduces the even-only logic only after the given fork height.
 /// Other than the evenness requirement, the consensus rules should not change at the fork. This
 /// function should work with either PoW, PoA, or anything else as the underlying consensus engine.
-fn even_after_given_height<Original: Consensus>(fork_height: u64) -> impl Consensus {
-	// Define a new consensus engine that wraps the original consensus engine
-    struct EvenAfterGivenHeight<Inner: Consensus> {
-        inner: Inner,
-        fork_height: u64,
-    }
-
-    impl<Inner: Consensus> Consensus for EvenAfterGivenHeight<Inner> {
-        type Digest = Inner::Digest;
-
-        fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-            // Check if the fork height has been reached
-            if header.height >= self.fork_height {
-                // If after the fork height, also validate the evenness of the state root
-                header.state_root % 2 == 0 && self.inner.validate(parent_digest, header)
-            } else {
-                // If before the fork height, only validate using the original consensus rules
-                self.inner.validate(parent_digest, header)
-            }
-        }
-
-        fn seal(
-            &self,
-            parent_digest: &Self::Digest,
-            partial_header: Header<()>,
-        ) -> Option<Header<Self::Digest>> {
-            // Delegate sealing to the inner consensus engine
-            self.inner.seal(parent_digest, partial_header)
-        }
-    }
-
-    // Return an instance of EvenAfterGivenHeight with the specified parameters
-    EvenAfterGivenHeight {
-        inner: Original::default(), // Instantiate the original consensus engine
-        fork_height,
-    }
+fn even_after_given_height<Original: Consensus>(
+	original: Original,
+	fork_height: u64,
+) -> impl Consensus<Digest = Original::Digest> {
+	/// Wraps the original consensus engine, and requires even state roots from the fork on.
+	struct EvenAfterGivenHeight<Inner> {
+		inner: Inner,
+		fork_height: u64,
+	}
+
+	impl<Inner: Consensus> Consensus for EvenAfterGivenHeight<Inner> {
+		type Digest = Inner::Digest;
+
+		fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+			self.check_header(parent_digest, header).is_ok()
+		}
+
+		fn check_header(
+			&self,
+			parent_digest: &Self::Digest,
+			header: &Header<Self::Digest>,
+		) -> Result<(), ChainError> {
+			if header.height >= self.fork_height && !header.state_root.is_multiple_of(2) {
+				return Err(ChainError::WrongParity);
+			}
+			self.inner.check_header(parent_digest, header)
+		}
+
+		fn validate_seal_only(
+			&self,
+			parent_digest: &Self::Digest,
+			header: &Header<Self::Digest>,
+		) -> bool {
+			self.inner.validate_seal_only(parent_digest, header)
+		}
+
+		fn seal(
+			&self,
+			parent_digest: &Self::Digest,
+			partial_header: Header<()>,
+		) -> Option<Header<Self::Digest>> {
+			if partial_header.height >= self.fork_height
+				&& !partial_header.state_root.is_multiple_of(2)
+			{
+				return None;
+			}
+			self.inner.seal(parent_digest, partial_header)
+		}
+
+		fn genesis_header(&self) -> Header<Self::Digest> {
+			self.inner.genesis_header()
+		}
+
+		fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+			self.inner.seal_author(header)
+		}
+
+		fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+			self.inner.seal_slot(header)
+		}
+
+		fn estimate_seal_cost(&self) -> u64 {
+			self.inner.estimate_seal_cost()
+		}
+
+		fn rules(&self) -> Vec<&'static str> {
+			let mut rules = self.inner.rules();
+			rules.push("even-state-after-fork");
+			rules
+		}
+	}
+
+	EvenAfterGivenHeight { inner: original, fork_height }
 }
 
 /// So far we have considered the simpler case where the consensus engines before and after the fork
@@ -203,6 +309,16 @@ enum PowOrPoaDigest {
 	Poa(ConsensusAuthority),
 }
 
+impl PowOrPoaDigest {
+	/// The authority who signed a PoA block. PoW blocks have no designated author.
+	fn author(&self) -> Option<ConsensusAuthority> {
+		match self {
+			PowOrPoaDigest::Pow(_) => None,
+			PowOrPoaDigest::Poa(authority) => Some(*authority),
+		}
+	}
+}
+
 impl From<u64> for PowOrPoaDigest {
 	fn from(d: u64) -> Self {
 		PowOrPoaDigest::Pow(d)
@@ -223,32 +339,187 @@ fn pow_to_poa(
 	fork_height: u64,
 	difficulty: u64,
 	authorities: Vec<ConsensusAuthority>,
-) -> impl Consensus {
+) -> impl Consensus<Digest = PowOrPoaDigest> {
+	/// PoW up to the fork height, and round robin PoA from then on.
+	struct PowToPoa {
+		pow: PoW,
+		poa: PoaRoundRobinByHeight,
+		fork_height: u64,
+	}
+
 	impl Consensus for PowToPoa {
 		type Digest = PowOrPoaDigest;
-	
+
 		fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
-			// Determine if the block is before or after the fork height
-			if header.height < self.fork_height {
-				// Validate PoW block
-				// Implement logic to validate PoW block
-			} else {
-				// Validate PoA block
-				// Implement logic to validate PoA block
+			self.check_header(parent_digest, header).is_ok()
+		}
+
+		/// PoW ignores its parent's digest, and PoA by height only looks at the height, so neither
+		/// engine needs a parent digest of its own type.
+		fn check_header(
+			&self,
+			_: &Self::Digest,
+			header: &Header<Self::Digest>,
+		) -> Result<(), ChainError> {
+			match (header.height < self.fork_height, header.consensus_digest) {
+				(true, PowOrPoaDigest::Pow(nonce)) =>
+					self.pow.check_header(&0, &header.with_digest(nonce)),
+				(false, PowOrPoaDigest::Poa(authority)) =>
+					self.poa.check_header(&authority, &header.with_digest(authority)),
+				_ => Err(ChainError::InvalidSeal),
 			}
 		}
-	
-		fn seal(&self, parent_digest: &Self::Digest, partial_header: Header<()>) -> Option<Header<Self::Digest>> {
-			// Determine if the block is before or after the fork height
-			if partial_header.height < self.fork_height {
-				// Seal PoW block
-				// Implement logic to seal PoW block
+
+		fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+			header.consensus_digest.author()
+		}
+
+		fn seal(
+			&self,
+			_: &Self::Digest,
+			partial_header: Header<()>,
+		) -> Option<Header<Self::Digest>> {
+			let header = if partial_header.height < self.fork_height {
+				let sealed = self.pow.seal(&0, partial_header)?;
+				sealed.map_digest(Into::into)
 			} else {
-				// Seal PoA block
-				// Implement logic to seal PoA block
-			}
+				let authority = self.poa.genesis_header().consensus_digest;
+				let sealed = self.poa.seal(&authority, partial_header)?;
+				sealed.map_digest(Into::into)
+			};
+			Some(header)
 		}
+
+		/// The chain launches under PoW, so genesis carries nonce zero.
+		fn genesis_header(&self) -> Header<Self::Digest> {
+			genesis_with_digest(PowOrPoaDigest::Pow(0))
+		}
+
+		fn estimate_seal_cost(&self) -> u64 {
+			self.pow.estimate_seal_cost()
+		}
+
+		fn rules(&self) -> Vec<&'static str> {
+			[&BASE_RULES[..], &["pow-threshold", "round-robin-by-height", "engine-by-fork-height"]]
+				.concat()
+		}
+	}
+
+	PowToPoa {
+		pow: PoW::new(difficulty),
+		poa: PoaRoundRobinByHeight::new(authorities),
+		fork_height,
+	}
+}
+
+#[cfg(test)]
+use super::test_support::{check_adder_chain, sealed_adder_chain, sealed_child};
+
+#[test]
+fn cs_6_pow_or_poa_digest_author() {
+	assert_eq!(PowOrPoaDigest::Pow(7).author(), None);
+	assert_eq!(
+		PowOrPoaDigest::Poa(ConsensusAuthority::Bob).author(),
+		Some(ConsensusAuthority::Bob)
+	);
+}
+
+#[test]
+fn cs_6_forked_switches_engine_at_fork_height() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let engine = Forked::new(
+		PoaRoundRobinByHeight::new(vec![Alice]),
+		PoaRoundRobinByHeight::new(vec![Alice, Bob]),
+		3,
+	);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 4);
+
+	let authors: Vec<_> = chain.iter().map(|header| header.consensus_digest).collect();
+	assert_eq!(authors, vec![Alice, Alice, Bob, Alice]);
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_6_change_authorities_rejects_unsafe_rotation() {
+	use ConsensusAuthority::{Alice, Bob, Charlie};
+
+	let safe = change_authorities(2, vec![Alice, Bob], vec![Alice, Charlie], 50);
+	let genesis = safe.genesis_header();
+	let chain = sealed_adder_chain(&safe, &genesis, 3);
+	assert_eq!(chain[1].consensus_digest, Alice);
+	assert_eq!(chain[2].consensus_digest, Charlie);
+	assert_eq!(check_adder_chain(&safe, &genesis, &chain), Ok(()));
+
+	// Replacing both authorities at once is more churn than allowed, so no block at the fork
+	// height can be sealed or accepted, even one signed by the new authority due to sign it.
+	let unsafe_rotation = change_authorities(2, vec![Alice, Bob], vec![Charlie], 50);
+	let first = sealed_child(&unsafe_rotation, &genesis, 1);
+	let at_fork = Header {
+		parent: crate::hash(&first),
+		height: 2,
+		extrinsics_root: 2,
+		state_root: 3,
+		consensus_digest: (),
+	};
+	assert_eq!(unsafe_rotation.seal(&first.consensus_digest, at_fork.clone()), None);
+	let signed = Header {
+		parent: at_fork.parent,
+		height: 2,
+		extrinsics_root: 2,
+		state_root: 3,
+		consensus_digest: Charlie,
+	};
+	assert_eq!(
+		unsafe_rotation.check_header(&first.consensus_digest, &signed),
+		Err(ChainError::WrongAuthority)
+	);
+}
+
+#[test]
+fn cs_6_change_difficulty_raises_difficulty_at_fork() {
+	let engine = change_difficulty(2, u64::MAX / 4, u64::MAX / 64);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+
+	// A block at the fork height that only meets the difficulty from before the fork.
+	let mut weak = sealed_adder_chain(&PoW::new(u64::MAX / 4), &genesis, 2).remove(1);
+	while crate::hash(&weak) < u64::MAX / 64 {
+		weak.consensus_digest += 1;
 	}
-	
-	/// Define the enum PowOrPoaDigest and implement the From trait for conversions from u64 and ConsensusAuthority.
+	assert_eq!(engine.check_header(&0, &weak), Err(ChainError::InsufficientWork));
+}
+
+#[test]
+fn cs_6_even_after_given_height_allows_odd_before_fork() {
+	let engine = even_after_given_height(PoW::new(u64::MAX / 4), 2);
+	let genesis = engine.genesis_header();
+	let odd = |height, parent| Header {
+		parent,
+		height,
+		extrinsics_root: 0,
+		state_root: 3,
+		consensus_digest: (),
+	};
+
+	let first = engine.seal(&0, odd(1, crate::hash(&genesis))).unwrap();
+	assert_eq!(engine.check_sub_chain(&genesis, std::slice::from_ref(&first)), Ok(()));
+	assert_eq!(engine.seal(&0, odd(2, crate::hash(&first))), None);
+}
+
+#[test]
+fn cs_6_pow_to_poa_switches_digest_at_fork() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let engine = pow_to_poa(2, u64::MAX / 4, vec![Alice, Bob]);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+
+	assert!(matches!(chain[0].consensus_digest, PowOrPoaDigest::Pow(_)));
+	assert_eq!(chain[1].consensus_digest, PowOrPoaDigest::Poa(Alice));
+	assert_eq!(chain[2].consensus_digest, PowOrPoaDigest::Poa(Bob));
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+	assert_eq!(engine.seal_author(&chain[2]), Some(Bob));
 }
@@ -0,0 +1,234 @@
+//! In chapter 2 we imagined political factions who wanted state roots to be even, or odd.
+//! `EvenOnly` brought the strictest version of that rule into this chapter's framework. Here we
+//! generalize it into a higher-order engine for either parity, that also tolerates a limited number
+//! of blocks of the wrong parity within a sliding window of recent blocks.
+//!
+//! As described on [`Consensus::Digest`], its digest records which of the recent blocks had the
+//! wrong parity, alongside the inner engine's digest.
+
+use super::{Consensus, ConsensusAuthority, Header};
+use crate::ChainError;
+
+/// The digest of a `ParityRule` engine. The inner engine's digest, along with a record of which
+/// recent blocks had a state root of the wrong parity.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ParityDigest<D> {
+	pub(crate) inner: D,
+	/// One bit for each block in the window, with the most recent block in the lowest bit. A bit is
+	/// set when that block's state root had the wrong parity.
+	pub(crate) misses: u64,
+}
+
+/// A Consensus engine that requires state roots to have the wanted parity, except for at most
+/// `tolerance` blocks among the most recent `window` blocks. Wraps an inner consensus engine whose
+/// rules will also be enforced.
+pub struct ParityRule<Inner> {
+	inner: Inner,
+	want_even: bool,
+	tolerance: usize,
+	window: u32,
+}
+
+impl<Inner: Consensus> ParityRule<Inner> {
+	/// Create a new engine on top of the given engine. The window may be at most 64 blocks long.
+	pub fn new(inner: Inner, want_even: bool, tolerance: usize, window: u32) -> Self {
+		assert!(window <= u64::BITS, "the window is at most 64 blocks long");
+		ParityRule { inner, want_even, tolerance, window }
+	}
+
+	/// The record of wrong-parity blocks after a child with the given state root is added to the
+	/// parent's record.
+	fn misses_after(&self, parent_misses: u64, state_root: u64) -> u64 {
+		let wrong = state_root.is_multiple_of(2) != self.want_even;
+		let window_mask = u64::MAX.checked_shr(u64::BITS - self.window).unwrap_or(0);
+		((parent_misses << 1) | wrong as u64) & window_mask
+	}
+
+	/// The header as the inner engine sees it, carrying only the inner digest.
+	fn inner_header(header: &Header<ParityDigest<Inner::Digest>>) -> Header<Inner::Digest> {
+		header.with_digest(header.consensus_digest.inner.clone())
+	}
+}
+
+impl<Inner: Consensus> Consensus for ParityRule<Inner> {
+	type Digest = ParityDigest<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that the digest records the header's parity correctly, that the window does not hold
+	/// too many wrong-parity blocks, and that the inner engine accepts the header.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let misses = self.misses_after(parent_digest.misses, header.state_root);
+		if header.consensus_digest.misses != misses {
+			return Err(ChainError::InvalidSeal);
+		}
+		if misses.count_ones() as usize > self.tolerance {
+			return Err(ChainError::WrongParity);
+		}
+		self.inner.check_header(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Seal the partial header with the inner engine. Headers that would exceed the tolerance can
+	/// not be sealed.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let misses = self.misses_after(parent_digest.misses, partial_header.state_root);
+		if misses.count_ones() as usize > self.tolerance {
+			return None;
+		}
+		let header = self.inner.seal(&parent_digest.inner, partial_header)?;
+
+		Some(header.map_digest(|inner| ParityDigest { inner, misses }))
+	}
+
+	/// Genesis starts with a clean window.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.inner.genesis_header();
+
+		genesis.map_digest(|inner| ParityDigest { inner, misses: 0 })
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header))
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(&Self::inner_header(header))
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("parity-window");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Parity Rule {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	header_builder::HeaderBuilder, p1_pow::moderate_difficulty_pow, test_support::sealed_child,
+};
+
+#[cfg(test)]
+fn genesis() -> Header<ParityDigest<u64>> {
+	Header {
+		parent: 0,
+		height: 0,
+		extrinsics_root: 0,
+		state_root: 0,
+		consensus_digest: ParityDigest { inner: 0, misses: 0 },
+	}
+}
+
+/// Seal a chain carrying the given extrinsics with an engine that tolerates anything, so that it
+/// can be checked against stricter engines.
+#[cfg(test)]
+fn lenient_chain(
+	want_even: bool,
+	window: u32,
+	extrinsics: &[u64],
+) -> Vec<Header<ParityDigest<u64>>> {
+	let lenient = ParityRule::new(moderate_difficulty_pow(), want_even, 64, window);
+	let mut chain: Vec<Header<ParityDigest<u64>>> = Vec::new();
+	for extrinsic in extrinsics {
+		let parent = chain.last().cloned().unwrap_or_else(genesis);
+		chain.push(sealed_child(&lenient, &parent, *extrinsic));
+	}
+	chain
+}
+
+#[cfg(test)]
+fn partial_child(parent: &Header<ParityDigest<u64>>, extrinsic: u64) -> Header<()> {
+	HeaderBuilder::new()
+		.child_of(parent)
+		.extrinsics_root(extrinsic)
+		.state_root(parent.state_root + extrinsic)
+		.build()
+		.unwrap()
+}
+
+#[test]
+fn cs_parity_even_tolerance_zero() {
+	let engine = ParityRule::new(moderate_difficulty_pow(), true, 0, 4);
+
+	// States 2, 4, 4
+	let valid = lenient_chain(true, 4, &[2, 2, 0]);
+	assert_eq!(engine.check_sub_chain(&genesis(), &valid), Ok(()));
+
+	// States 2, 3
+	let invalid = lenient_chain(true, 4, &[2, 1]);
+	assert_eq!(engine.check_sub_chain(&genesis(), &invalid), Err(ChainError::WrongParity));
+	assert_eq!(engine.seal(&invalid[0].consensus_digest, partial_child(&invalid[0], 1)), None);
+}
+
+#[test]
+fn cs_parity_odd_tolerance_zero() {
+	let engine = ParityRule::new(moderate_difficulty_pow(), false, 0, 4);
+
+	// States 1, 3, 5
+	let valid = lenient_chain(false, 4, &[1, 2, 2]);
+	assert_eq!(engine.check_sub_chain(&genesis(), &valid), Ok(()));
+
+	// States 1, 2
+	let invalid = lenient_chain(false, 4, &[1, 1]);
+	assert_eq!(engine.check_sub_chain(&genesis(), &invalid), Err(ChainError::WrongParity));
+}
+
+#[test]
+fn cs_parity_even_tolerance_one() {
+	let engine = ParityRule::new(moderate_difficulty_pow(), true, 1, 3);
+
+	// States 1, 2, 4, 5. The two odd states are far enough apart to fit in separate windows.
+	let valid = lenient_chain(true, 3, &[1, 1, 2, 1]);
+	assert_eq!(engine.check_sub_chain(&genesis(), &valid), Ok(()));
+
+	// States 1, 2, 3. Both odd states fall within the same window.
+	let invalid = lenient_chain(true, 3, &[1, 1, 1]);
+	assert_eq!(engine.check_sub_chain(&genesis(), &invalid), Err(ChainError::WrongParity));
+}
+
+#[test]
+fn cs_parity_odd_tolerance_one() {
+	let engine = ParityRule::new(moderate_difficulty_pow(), false, 1, 3);
+
+	// States 2, 3, 5, 6.
+	let valid = lenient_chain(false, 3, &[2, 1, 2, 1]);
+	assert_eq!(engine.check_sub_chain(&genesis(), &valid), Ok(()));
+
+	// States 2, 4.
+	let invalid = lenient_chain(false, 3, &[2, 2]);
+	assert_eq!(engine.check_sub_chain(&genesis(), &invalid), Err(ChainError::WrongParity));
+}
+
+#[test]
+fn cs_parity_digest_must_record_parity() {
+	let engine = ParityRule::new(moderate_difficulty_pow(), true, 1, 3);
+	let mut chain = lenient_chain(true, 3, &[1]);
+	chain[0].consensus_digest.misses = 0;
+
+	assert_eq!(engine.check_sub_chain(&genesis(), &chain), Err(ChainError::InvalidSeal));
+}
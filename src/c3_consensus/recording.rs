@@ -0,0 +1,145 @@
+//! When a node rejects a block, it usually just drops it. That makes it hard to work out later
+//! what a misbehaving peer was sending. This module provides a higher-order engine that keeps a
+//! record of the most recent rejections, and why they happened, without changing which headers
+//! are accepted.
+
+use std::{cell::RefCell, collections::VecDeque};
+
+use super::{Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::ChainError;
+
+/// A Consensus engine that wraps another engine and records the headers it rejects.
+///
+/// At most `capacity` rejections are kept. When the record is full, the oldest rejection is
+/// forgotten.
+pub struct RecordingConsensus<Inner: Consensus> {
+	inner: Inner,
+	capacity: usize,
+	rejections: RefCell<VecDeque<(Header<Inner::Digest>, ChainError)>>,
+}
+
+impl<Inner: Consensus> RecordingConsensus<Inner> {
+	/// Create a new engine that records up to `capacity` of the given engine's rejections.
+	pub fn new(inner: Inner, capacity: usize) -> Self {
+		RecordingConsensus { inner, capacity, rejections: RefCell::new(VecDeque::new()) }
+	}
+
+	/// The most recently rejected headers along with the reason each was rejected, oldest first.
+	pub fn rejections(&self) -> Vec<(Header<Inner::Digest>, ChainError)> {
+		self.rejections.borrow().iter().cloned().collect()
+	}
+}
+
+impl<Inner: Consensus> Consensus for RecordingConsensus<Inner> {
+	type Digest = Inner::Digest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check the header with the inner engine, and record it if it is rejected.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let result = self.inner.check_header(parent_digest, header);
+		if let Err(error) = result {
+			if self.capacity > 0 {
+				let mut rejections = self.rejections.borrow_mut();
+				if rejections.len() >= self.capacity {
+					rejections.pop_front();
+				}
+				rejections.push_back((header.clone(), error));
+			}
+		}
+		result
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.inner.seal(parent_digest, partial_header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.inner.genesis_header()
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(header)
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		self.inner.rules()
+	}
+
+	fn human_name() -> String {
+		format!("Recording {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{p3_poa::PoaRoundRobinByHeight, test_support::sealed_adder_chain};
+
+#[cfg(test)]
+fn round_robin() -> PoaRoundRobinByHeight {
+	PoaRoundRobinByHeight::new(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob])
+}
+
+#[test]
+fn cs_recording_logs_exactly_the_rejected_headers() {
+	let engine = RecordingConsensus::new(round_robin(), 8);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 4);
+
+	let mut forged = chain.clone();
+	forged[1].consensus_digest = ConsensusAuthority::Charlie;
+	forged[3].consensus_digest = ConsensusAuthority::Bob;
+
+	let mut parent = &genesis;
+	for header in &forged {
+		let accepted = engine.validate(&parent.consensus_digest, header);
+		assert_eq!(accepted, round_robin().validate(&parent.consensus_digest, header));
+		parent = header;
+	}
+
+	assert_eq!(
+		engine.rejections(),
+		vec![
+			(forged[1].clone(), ChainError::WrongAuthority),
+			(forged[3].clone(), ChainError::WrongAuthority),
+		]
+	);
+}
+
+#[test]
+fn cs_recording_forgets_oldest_rejection() {
+	let engine = RecordingConsensus::new(round_robin(), 2);
+	let genesis = engine.genesis_header();
+	let mut forged = sealed_adder_chain(&engine, &genesis, 3);
+	for header in &mut forged {
+		header.consensus_digest = ConsensusAuthority::Charlie;
+	}
+
+	for header in &forged {
+		assert!(!engine.validate(&genesis.consensus_digest, header));
+	}
+
+	let rejected: Vec<_> = engine.rejections().into_iter().map(|(header, _)| header).collect();
+	assert_eq!(rejected, forged[1..].to_vec());
+}
@@ -0,0 +1,373 @@
+//! Real blockchains pay whoever authored each block a fixed block reward, credited to an account
+//! of the author's choosing, often called the coinbase. Here we model that as a higher-order
+//! consensus engine that adds an author to each header, along with the state transition that
+//! credits the reward to them.
+//!
+//! The header is shared by every engine in this chapter, so, like the slot in `SlotDigest`, the
+//! author lives in the digest. The balances are not consensus data, but state, so the header's
+//! state root commits to them, and `BlockReward::execute` checks that it does.
+//!
+//! In identity-based engines, the author is simply the authority that signed the block. In Proof of
+//! Work, anyone may author a block, so the miner names themselves when sealing it. The inner engine
+//! seals a header whose extrinsics root also commits to the author, so the author is part of the
+//! data being mined, and can not be swapped out by whoever relays the block.
+
+use super::{Consensus, ConsensusAuthority, EngineKind, Header};
+use crate::{hash, ChainError};
+
+/// The digest of a `BlockReward` engine. The inner engine's digest along with the block's author.
+#[derive(Hash, Debug, PartialEq, Eq, Clone)]
+pub struct RewardDigest<D> {
+	pub(crate) inner: D,
+	/// The account credited with this block's reward, if anyone claimed it.
+	pub(crate) author: Option<ConsensusAuthority>,
+}
+
+/// The state that block rewards are credited to. Every balance starts at zero in genesis.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Balances(Vec<(ConsensusAuthority, u64)>);
+
+impl Balances {
+	/// The balance of the given account.
+	pub fn balance(&self, account: ConsensusAuthority) -> u64 {
+		self.0.iter().find(|(a, _)| *a == account).map_or(0, |(_, balance)| *balance)
+	}
+
+	/// The balances after crediting the given amount to the given account, if there is one.
+	/// Accounts are kept in the order they were first credited.
+	///
+	/// Returns `None` if the account's balance would overflow.
+	fn credited(&self, account: Option<ConsensusAuthority>, amount: u64) -> Option<Self> {
+		let mut balances = self.clone();
+		let Some(account) = account else {
+			return Some(balances);
+		};
+		match balances.0.iter_mut().find(|(a, _)| *a == account) {
+			Some((_, balance)) => *balance = balance.checked_add(amount)?,
+			None => balances.0.push((account, amount)),
+		}
+		Some(balances)
+	}
+}
+
+/// A Consensus engine that credits a fixed reward to the author of every block.
+/// Wraps an inner consensus engine whose rules will also be enforced.
+pub struct BlockReward<Inner> {
+	inner: Inner,
+	reward: u64,
+}
+
+impl<Inner: Consensus> BlockReward<Inner> {
+	/// Create a new engine that pays the given reward per block on top of the given engine.
+	pub fn new(inner: Inner, reward: u64) -> Self {
+		BlockReward { inner, reward }
+	}
+
+	/// The extrinsics root the inner engine seals, which also commits to the author.
+	fn committed_root(extrinsics_root: u64, author: Option<ConsensusAuthority>) -> u64 {
+		hash(&(extrinsics_root, author))
+	}
+
+	/// The header as the inner engine sees it, carrying only the inner digest, and committing to
+	/// the author in its extrinsics root.
+	fn inner_header(header: &Header<RewardDigest<Inner::Digest>>) -> Header<Inner::Digest> {
+		let digest = &header.consensus_digest;
+		Header {
+			extrinsics_root: Self::committed_root(header.extrinsics_root, digest.author),
+			..header.with_digest(digest.inner.clone())
+		}
+	}
+
+	/// Seal the partial header with the inner engine, committing to the given author.
+	fn seal_for(
+		&self,
+		parent_digest: &RewardDigest<Inner::Digest>,
+		partial_header: Header<()>,
+		author: Option<ConsensusAuthority>,
+	) -> Option<Header<RewardDigest<Inner::Digest>>> {
+		let extrinsics_root = partial_header.extrinsics_root;
+		let committed = Header {
+			extrinsics_root: Self::committed_root(extrinsics_root, author),
+			..partial_header
+		};
+		let header = self.inner.seal(&parent_digest.inner, committed)?;
+
+		Some(Header {
+			extrinsics_root,
+			..header.map_digest(|inner| RewardDigest { inner, author })
+		})
+	}
+
+	/// Seal the partial header with the inner engine, and credit the reward to the given author.
+	///
+	/// This is how Proof of Work miners claim their reward. Identity-based engines already know
+	/// who signed the block, so this returns `None` if the signer is not the given author.
+	pub fn seal_with_author(
+		&self,
+		parent_digest: &RewardDigest<Inner::Digest>,
+		partial_header: Header<()>,
+		author: ConsensusAuthority,
+	) -> Option<Header<RewardDigest<Inner::Digest>>> {
+		let header = self.seal_for(parent_digest, partial_header, Some(author))?;
+		if self.seal_author(&header).is_some_and(|signer| signer != author) {
+			return None;
+		}
+		Some(header)
+	}
+
+	/// The balances after the given author is credited with one block reward, along with the
+	/// state root a header must carry to commit to them.
+	///
+	/// Returns `None` if the author's balance would overflow.
+	pub fn state_after(
+		&self,
+		parent_state: &Balances,
+		author: Option<ConsensusAuthority>,
+	) -> Option<(Balances, u64)> {
+		let balances = parent_state.credited(author, self.reward)?;
+		let state_root = hash(&balances);
+		Some((balances, state_root))
+	}
+
+	/// Execute the state transition of the given header on top of its parent's balances, crediting
+	/// exactly one block reward to its author.
+	///
+	/// Fails with `WrongReward` if the header's state root does not commit to the balances after
+	/// that reward.
+	pub fn execute(
+		&self,
+		parent_state: &Balances,
+		header: &Header<RewardDigest<Inner::Digest>>,
+	) -> Result<Balances, ChainError> {
+		match self.state_after(parent_state, header.consensus_digest.author) {
+			Some((balances, state_root)) if state_root == header.state_root => Ok(balances),
+			_ => Err(ChainError::WrongReward),
+		}
+	}
+}
+
+impl<Inner: Consensus> Consensus for BlockReward<Inner> {
+	type Digest = RewardDigest<Inner::Digest>;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that the inner engine accepts the header, which also commits to the author, and that
+	/// the author is the signer in identity-based engines. The reward itself is checked by
+	/// `execute`.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let inner_header = Self::inner_header(header);
+		self.inner.check_header(&parent_digest.inner, &inner_header)?;
+
+		if let Some(signer) = self.inner.seal_author(&inner_header) {
+			if header.consensus_digest.author != Some(signer) {
+				return Err(ChainError::WrongAuthority);
+			}
+		}
+		Ok(())
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(&parent_digest.inner, &Self::inner_header(header))
+	}
+
+	/// Seal the partial header with the inner engine, and credit the reward to its signer.
+	///
+	/// Identity-based engines know who will sign the block before it is sealed, so the author is
+	/// whoever signs a header that names no author. Engines that do not know who sealed the block,
+	/// such as Proof of Work, leave the reward unclaimed. Use `seal_with_author` to claim it.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		let unclaimed = self.seal_for(parent_digest, partial_header.clone(), None)?;
+		match self.seal_author(&unclaimed) {
+			Some(signer) => self.seal_with_author(parent_digest, partial_header, signer),
+			None => Some(unclaimed),
+		}
+	}
+
+	/// The genesis block pays no reward.
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		let genesis = self.inner.genesis_header();
+
+		genesis.map_digest(|inner| RewardDigest { inner, author: None })
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(&Self::inner_header(header))
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(&Self::inner_header(header))
+	}
+
+	fn kind(&self) -> EngineKind {
+		self.inner.kind()
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("block-reward");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Block Reward {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{header_builder::HeaderBuilder, p1_pow::PoW, p3_poa::PoaRoundRobinByHeight};
+
+/// A partial child of the given parent committing to the given state.
+#[cfg(test)]
+fn partial_child<D: std::hash::Hash>(parent: &Header<D>, state_root: u64) -> Header<()> {
+	HeaderBuilder::new().child_of(parent).state_root(state_root).build().unwrap()
+}
+
+/// Author a chain of `n` blocks on top of genesis, with each block's reward going to its signer,
+/// or to the given miner if the engine has no signers. Returns the chain and the final state.
+#[cfg(test)]
+fn rewarded_chain<Inner: Consensus>(
+	engine: &BlockReward<Inner>,
+	miner: ConsensusAuthority,
+	n: u64,
+) -> (Vec<Header<RewardDigest<Inner::Digest>>>, Balances) {
+	let mut chain = vec![engine.genesis_header()];
+	let mut state = Balances::default();
+	for _ in 0..n {
+		let parent = chain.last().unwrap();
+		// Identity-based engines know their signer from a trial seal.
+		let trial = engine.seal(&parent.consensus_digest, partial_child(parent, 0)).unwrap();
+		let author = engine.seal_author(&trial).unwrap_or(miner);
+
+		let (balances, state_root) = engine.state_after(&state, Some(author)).unwrap();
+		let header = engine
+			.seal_with_author(&parent.consensus_digest, partial_child(parent, state_root), author)
+			.unwrap();
+		chain.push(header);
+		state = balances;
+	}
+	(chain, state)
+}
+
+/// Check the chain's consensus and execute it from empty balances, returning the final state.
+#[cfg(test)]
+fn execute_chain<Inner: Consensus>(
+	engine: &BlockReward<Inner>,
+	chain: &[Header<RewardDigest<Inner::Digest>>],
+) -> Result<Balances, ChainError> {
+	engine.check_sub_chain(&chain[0], &chain[1..])?;
+	chain[1..]
+		.iter()
+		.try_fold(Balances::default(), |state, header| engine.execute(&state, header))
+}
+
+#[test]
+fn cs_pow_author_is_rewarded_per_block() {
+	use ConsensusAuthority::Alice;
+
+	let engine = BlockReward::new(PoW::new(u64::MAX / 4), 50);
+	let (chain, _) = rewarded_chain(&engine, Alice, 3);
+
+	for height in 1..chain.len() {
+		let state = execute_chain(&engine, &chain[..=height]).unwrap();
+		assert_eq!(state.balance(Alice), 50 * height as u64);
+	}
+}
+
+#[test]
+fn cs_poa_signer_is_rewarded() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let engine = BlockReward::new(PoaRoundRobinByHeight::new(vec![Alice, Bob]), 50);
+	let (chain, state) = rewarded_chain(&engine, Alice, 3);
+
+	// Bob signs heights 1 and 3, Alice signs height 2.
+	assert_eq!(state.balance(Bob), 100);
+	assert_eq!(state.balance(Alice), 50);
+	assert_eq!(execute_chain(&engine, &chain), Ok(state));
+
+	// Only the signer may claim the reward.
+	let genesis = &chain[0];
+	assert_eq!(
+		engine.seal_with_author(&genesis.consensus_digest, partial_child(genesis, 0), Alice),
+		None
+	);
+}
+
+#[test]
+fn cs_wrong_reward_is_rejected() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let engine = BlockReward::new(PoW::new(u64::MAX / 4), 50);
+	let genesis = engine.genesis_header();
+	let greedy = Balances(vec![(Alice, 51)]);
+	let header = engine
+		.seal_with_author(&genesis.consensus_digest, partial_child(&genesis, hash(&greedy)), Alice)
+		.unwrap();
+
+	// The seal is fine, but the state transition credits too much.
+	assert_eq!(engine.check_header(&genesis.consensus_digest, &header), Ok(()));
+	assert_eq!(engine.execute(&Balances::default(), &header), Err(ChainError::WrongReward));
+
+	let stolen = Balances(vec![(Bob, 50)]);
+	let header = engine
+		.seal_with_author(&genesis.consensus_digest, partial_child(&genesis, hash(&stolen)), Alice)
+		.unwrap();
+	assert_eq!(engine.execute(&Balances::default(), &header), Err(ChainError::WrongReward));
+}
+
+#[test]
+fn cs_relayer_can_not_swap_pow_author() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let engine = BlockReward::new(PoW::new(u64::MAX >> 16), 50);
+	let (chain, _) = rewarded_chain(&engine, Alice, 1);
+
+	// A relayer swaps in their own account, along with a matching state root. The work was done
+	// for a header naming Alice, so it does not carry over.
+	let mut swapped = chain[1].clone();
+	swapped.consensus_digest.author = Some(Bob);
+	swapped.state_root = engine.state_after(&Balances::default(), Some(Bob)).unwrap().1;
+
+	assert_ne!(
+		BlockReward::<PoW>::inner_header(&swapped).extrinsics_root,
+		BlockReward::<PoW>::inner_header(&chain[1]).extrinsics_root
+	);
+	assert_eq!(
+		engine.check_header(&chain[0].consensus_digest, &swapped),
+		Err(ChainError::InsufficientWork)
+	);
+}
+
+#[test]
+fn cs_poa_reward_must_go_to_signer() {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let engine = BlockReward::new(PoaRoundRobinByHeight::new(vec![Alice, Bob]), 50);
+	let genesis = engine.genesis_header();
+	let mut header = engine.seal(&genesis.consensus_digest, partial_child(&genesis, 0)).unwrap();
+	assert_eq!(header.consensus_digest.author, Some(Bob));
+	header.consensus_digest.author = Some(Alice);
+
+	assert_eq!(
+		engine.check_header(&genesis.consensus_digest, &header),
+		Err(ChainError::WrongAuthority)
+	);
+}
@@ -0,0 +1,46 @@
+//! Slot and height arithmetic shared by the Proof of Authority engines.
+//!
+//! Headers come from untrusted peers, so every slot and height an engine computes with may be as
+//! large as `u64::MAX`, and an engine may have been configured with no authorities at all. These
+//! helpers return `None` in those cases instead of overflowing or dividing by zero.
+
+/// The position in a set of `authorities` members whose turn it is in the given slot. A slot is
+/// whatever the engine takes turns by, such as a height, a clock slot, or a random draw.
+///
+/// Every engine maps its slots to authorities through this, rather than with `%` directly, so an
+/// empty set returns `None` instead of dividing by zero.
+pub fn checked_slot_index(slot: u64, authorities: usize) -> Option<usize> {
+	let index = slot.checked_rem(u64::try_from(authorities).ok()?)?;
+	usize::try_from(index).ok()
+}
+
+/// The slot after the given one, unless the given slot is the last one there is.
+pub fn next_slot(slot: u64) -> Option<u64> {
+	slot.checked_add(1)
+}
+
+#[test]
+fn cs_safe_checked_slot_index_wraps_around_the_set() {
+	assert_eq!(checked_slot_index(0, 3), Some(0));
+	assert_eq!(checked_slot_index(7, 3), Some(1));
+}
+
+#[test]
+fn cs_safe_checked_slot_index_handles_the_last_slot() {
+	assert_eq!(checked_slot_index(u64::MAX, 3), Some(0));
+	assert_eq!(checked_slot_index(u64::MAX, 2), Some(1));
+	assert_eq!(checked_slot_index(u64::MAX, 1), Some(0));
+}
+
+#[test]
+fn cs_safe_checked_slot_index_handles_an_empty_set() {
+	assert_eq!(checked_slot_index(0, 0), None);
+	assert_eq!(checked_slot_index(u64::MAX, 0), None);
+}
+
+#[test]
+fn cs_safe_next_slot_does_not_overflow() {
+	assert_eq!(next_slot(0), Some(1));
+	assert_eq!(next_slot(u64::MAX - 1), Some(u64::MAX));
+	assert_eq!(next_slot(u64::MAX), None);
+}
@@ -0,0 +1,65 @@
+//! A light client does not download every header. It trusts a checkpoint, and only wants to know
+//! that some particular block is in the canonical chain, for example the block that includes a
+//! payment to it. This is Simplified Payment Verification, as described in the Bitcoin whitepaper.
+//!
+//! Headers only link to their parents, so the proof is every header from just after the checkpoint
+//! up to the target. The light client checks that they link together and are validly sealed, which
+//! is far cheaper than checking the blocks themselves.
+
+use super::Header;
+
+/// The headers that link a trusted checkpoint to a target header, in ascending height order. The
+/// checkpoint itself is not included, and the target is the last header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpvProof<D> {
+	pub headers: Vec<Header<D>>,
+}
+
+#[cfg(test)]
+use super::{p3_poa::PoaRoundRobinByHeight, test_support::build_valid_chain, Consensus};
+#[cfg(test)]
+use crate::{hash, ChainError};
+
+#[cfg(test)]
+fn engine() -> PoaRoundRobinByHeight {
+	use super::ConsensusAuthority::{Alice, Bob};
+
+	PoaRoundRobinByHeight::new(vec![Alice, Bob])
+}
+
+#[test]
+fn cs_spv_proof_round_trip() {
+	let engine = engine();
+	let chain = [vec![engine.genesis_header()], build_valid_chain(&engine, 6)].concat();
+	// The light client trusts the header at height 2, and wants to know about the one at height 5.
+	let checkpoint = &chain[2];
+
+	let proof = engine.spv_proof(&chain[2..], 5).unwrap();
+	assert_eq!(proof.headers, chain[3..=5].to_vec());
+	assert_eq!(engine.verify_spv_proof(checkpoint, &proof, hash(&chain[5])), Ok(()));
+
+	// The checkpoint proves itself, and nothing can be proven beyond the chain or before it.
+	let empty = engine.spv_proof(&chain[2..], 2).unwrap();
+	assert_eq!(engine.verify_spv_proof(checkpoint, &empty, hash(checkpoint)), Ok(()));
+	assert_eq!(engine.spv_proof(&chain[2..], 7), None);
+	assert_eq!(engine.spv_proof(&chain[2..], 1), None);
+
+	// A valid proof of one header does not prove another.
+	assert_eq!(
+		engine.verify_spv_proof(checkpoint, &proof, hash(&chain[4])),
+		Err(ChainError::UnprovenTarget)
+	);
+}
+
+#[test]
+fn cs_spv_proof_with_swapped_header_is_rejected() {
+	let engine = engine();
+	let chain = [vec![engine.genesis_header()], build_valid_chain(&engine, 4)].concat();
+	let mut proof = engine.spv_proof(&chain, 4).unwrap();
+	proof.headers.swap(1, 2);
+
+	assert_eq!(
+		engine.verify_spv_proof(&chain[0], &proof, hash(&chain[4])),
+		Err(ChainError::WrongParent)
+	);
+}
@@ -0,0 +1,153 @@
+//! Long-lived chains rarely fork only once. Each upgrade activates new rules at some height, and
+//! all the old rules must still be applied to the blocks that came before it, so that new nodes
+//! can sync from genesis.
+//!
+//! `StageRules` generalizes the two-way fork from `p6_forking::Forked` to any number of fork
+//! points. The stages are boxed engines, so they may be of different types, but they must share a
+//! digest type. Each stage is simply forked off the stages before it, so, as in the two-way fork,
+//! the first block of each stage treats its parent as if it were the new stage's own genesis.
+
+use super::{p6_forking::Forked, Consensus, ConsensusAuthority, ConsensusExt, Header};
+use crate::ChainError;
+
+/// One stage of a `StageRules` engine. The height of its first block, and the engine in charge
+/// from that height on.
+pub type Stage<'a, D> = (u64, Box<dyn Consensus<Digest = D> + 'a>);
+
+/// A Consensus engine that applies a different engine at each stage of the chain's history.
+pub struct StageRules<'a, D> {
+	/// Every stage after the first, forked off the ones before it.
+	engine: Box<dyn Consensus<Digest = D> + 'a>,
+}
+
+impl<'a, D> StageRules<'a, D>
+where
+	D: Clone + core::fmt::Debug + Eq + std::hash::Hash + 'a,
+{
+	/// Create an engine from the given stages. The first stage must activate at genesis, and each
+	/// later stage strictly after the one before it.
+	pub fn new(stages: Vec<Stage<'a, D>>) -> Self {
+		assert_eq!(stages.first().map(|(height, _)| *height), Some(0), "no rules at genesis");
+		assert!(
+			stages.windows(2).all(|pair| pair[0].0 < pair[1].0),
+			"stages must activate in increasing order"
+		);
+		let mut stages = stages.into_iter();
+		let (_, first) = stages.next().expect("the first stage activates at genesis");
+		let engine = stages.fold(first, |before, (activation, after)| {
+			Forked::new(before, after, activation).boxed()
+		});
+		StageRules { engine }
+	}
+}
+
+impl<'a, D> Consensus for StageRules<'a, D>
+where
+	D: Clone + core::fmt::Debug + Eq + std::hash::Hash + 'a,
+{
+	type Digest = D;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check the header with whichever stage is active at its height.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		self.engine.check_header(parent_digest, header)
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.engine.validate_seal_only(parent_digest, header)
+	}
+
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.engine.seal(parent_digest, partial_header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.engine.genesis_header()
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.engine.seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.engine.seal_slot(header)
+	}
+
+	/// The engine does not know which stage the next block is in, so it assumes the most
+	/// expensive one.
+	fn estimate_seal_cost(&self) -> u64 {
+		self.engine.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		self.engine.rules()
+	}
+
+	fn human_name() -> String {
+		"Staged Rules".into()
+	}
+}
+
+#[cfg(test)]
+use super::{
+	config::{ConfiguredDigest, ConsensusConfig},
+	test_support::{check_adder_chain, sealed_adder_chain, sealed_child},
+};
+
+/// Proof of Work, then Proof of Work with even states only from height 3, then Proof of Authority
+/// from height 5.
+#[cfg(test)]
+fn three_stages() -> StageRules<'static, ConfiguredDigest> {
+	use ConsensusAuthority::{Alice, Bob};
+
+	let pow = || ConsensusConfig::pow(u64::MAX / 4);
+	StageRules::new(vec![
+		(0, pow().build()),
+		(3, ConsensusConfig::even_only(pow()).build()),
+		(5, ConsensusConfig::poa_round_robin(vec![Alice, Bob]).build()),
+	])
+}
+
+#[test]
+fn cs_stage_rules_chain_crosses_both_boundaries() {
+	let engine = three_stages();
+	let genesis = engine.genesis_header();
+	// The adder chain's states are 1, 3, 6, 10, 15, and 21, so the second stage's are even.
+	let chain = sealed_adder_chain(&engine, &genesis, 6);
+
+	assert!(matches!(chain[3].consensus_digest, ConfiguredDigest::Work(_)));
+	assert_eq!(chain[4].consensus_digest, ConfiguredDigest::Authority(ConsensusAuthority::Bob));
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_stage_rules_enforces_the_active_stage() {
+	let engine = three_stages();
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 4);
+
+	// An odd state was fine in the first stage, but not in the second.
+	let pow = ConsensusConfig::pow(u64::MAX / 4).build();
+	let odd = sealed_child(&*pow, &chain[1], 2);
+	assert_eq!(odd.state_root, 5);
+	assert_eq!(engine.check_sub_chain(&chain[1], &[odd]), Err(ChainError::WrongParity));
+
+	// Proof of Work is no longer accepted once the third stage is active.
+	let mined = sealed_child(&*pow, &chain[3], 5);
+	assert!(engine.check_sub_chain(&chain[3], &[mined]).is_err());
+}
@@ -0,0 +1,142 @@
+//! Many currencies have a fixed supply. Under the adder convention used by the chains in this
+//! chapter, the state root is the total amount issued so far, so a supply cap is simply an upper
+//! bound on the state root. This module provides a higher-order engine that enforces such a cap at
+//! the consensus layer.
+
+use super::{Consensus, ConsensusAuthority, Header};
+use crate::ChainError;
+
+/// A Consensus engine that rejects every header whose state root is above `cap`, and refuses to
+/// seal one. Wraps an inner consensus engine whose rules will also be enforced.
+pub struct SupplyCapped<Inner> {
+	inner: Inner,
+	cap: u64,
+}
+
+impl<Inner: Consensus> SupplyCapped<Inner> {
+	/// Create a new engine on top of the given engine, whose supply may never exceed `cap`.
+	pub fn new(inner: Inner, cap: u64) -> Self {
+		SupplyCapped { inner, cap }
+	}
+}
+
+impl<Inner: Consensus> Consensus for SupplyCapped<Inner> {
+	type Digest = Inner::Digest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check that the header's state is within the cap, and that the inner engine accepts it.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		if header.state_root > self.cap {
+			return Err(ChainError::SupplyCapExceeded);
+		}
+		self.inner.check_header(parent_digest, header)
+	}
+
+	fn validate_seal_only(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		self.inner.validate_seal_only(parent_digest, header)
+	}
+
+	fn validate_with_parent(
+		&self,
+		parent: &Header<Self::Digest>,
+		header: &Header<Self::Digest>,
+	) -> bool {
+		header.state_root <= self.cap && self.inner.validate_with_parent(parent, header)
+	}
+
+	/// Seal the partial header with the inner engine, unless its state breaches the cap.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		if partial_header.state_root > self.cap {
+			return None;
+		}
+		self.inner.seal(parent_digest, partial_header)
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		self.inner.genesis_header()
+	}
+
+	fn seal_author(&self, header: &Header<Self::Digest>) -> Option<ConsensusAuthority> {
+		self.inner.seal_author(header)
+	}
+
+	fn seal_slot(&self, header: &Header<Self::Digest>) -> u64 {
+		self.inner.seal_slot(header)
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		self.inner.estimate_seal_cost()
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		let mut rules = self.inner.rules();
+		rules.push("supply-cap");
+		rules
+	}
+
+	fn human_name() -> String {
+		format!("Supply Capped {}", Inner::human_name())
+	}
+}
+
+#[cfg(test)]
+use super::{
+	p1_pow::moderate_difficulty_pow,
+	test_support::{check_adder_chain, sealed_adder_chain},
+};
+
+#[test]
+fn cs_supply_capped_accepts_chain_up_to_cap() {
+	// The adder chain issues 1 + 2 + 3 = 6 over three blocks.
+	let engine = SupplyCapped::new(moderate_difficulty_pow(), 6);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+
+	assert_eq!(chain.last().unwrap().state_root, 6);
+	assert_eq!(check_adder_chain(&engine, &genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_supply_capped_rejects_block_beyond_cap() {
+	use crate::hash;
+
+	let engine = SupplyCapped::new(moderate_difficulty_pow(), 6);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 3);
+	let tip = chain.last().unwrap();
+	let partial = Header {
+		parent: hash(tip),
+		height: tip.height + 1,
+		extrinsics_root: 1,
+		state_root: tip.state_root + 1,
+		consensus_digest: (),
+	};
+	assert_eq!(engine.seal(&tip.consensus_digest, partial.clone()), None);
+
+	// A header sealed by the uncapped inner engine is still rejected.
+	let beyond = moderate_difficulty_pow().seal(&tip.consensus_digest, partial).unwrap();
+	assert_eq!(
+		engine.check_header(&tip.consensus_digest, &beyond),
+		Err(ChainError::SupplyCapExceeded)
+	);
+	assert!(!engine.validate_with_parent(tip, &beyond));
+	assert_eq!(
+		check_adder_chain(&engine, &genesis, &[chain, vec![beyond]].concat()),
+		Err(ChainError::SupplyCapExceeded)
+	);
+}
@@ -0,0 +1,129 @@
+//! A node that joins the network late has a lot of history to catch up on. Checking every header
+//! from genesis is slow, so the node starts from a trusted checkpoint instead, such as a recent
+//! header shipped with its software, and only checks the headers that come after it.
+//!
+//! Headers arrive from a peer in batches. Each batch is checked and imported as a whole, so fork
+//! choice is applied as the node catches up, and a peer that sends an invalid header loses the
+//! whole batch it was in. Syncing stops at the first invalid batch, because nothing after it can be
+//! trusted to connect.
+
+use std::collections::HashMap;
+
+use super::{chain_store::ChainStore, Consensus, Header};
+use crate::{hash, ChainError};
+
+/// Why a sync stopped before the peer ran out of headers. The index of the rejected batch among
+/// those that were checked, and the reason its first invalid header was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyncError {
+	pub batch: usize,
+	pub error: ChainError,
+}
+
+/// The result of catching up with a peer.
+#[derive(Debug)]
+pub struct SyncOutcome<D> {
+	/// The headers from the checkpoint onwards, with the best chain as the canonical head.
+	pub store: ChainStore<D>,
+	/// How many of the peer's headers were imported.
+	pub imported: usize,
+	/// Why syncing stopped early, if it did.
+	pub error: Option<SyncError>,
+}
+
+/// Sync the peer's headers on top of the trusted checkpoint, in batches of `batch_size`.
+///
+/// Headers at or below the checkpoint's height are skipped, because the checkpoint vouches for
+/// them. Every other header must connect to the checkpoint through headers that were already
+/// imported, or that come earlier in its batch.
+pub fn sync_from<C: Consensus + ?Sized>(
+	engine: &C,
+	peer_headers: impl IntoIterator<Item = Header<C::Digest>>,
+	trusted_checkpoint: Header<C::Digest>,
+	batch_size: usize,
+) -> SyncOutcome<C::Digest> {
+	assert!(batch_size > 0, "batches must hold at least one header");
+
+	let checkpoint_height = trusted_checkpoint.height;
+	let mut outcome =
+		SyncOutcome { store: ChainStore::new(trusted_checkpoint), imported: 0, error: None };
+	let mut headers = peer_headers
+		.into_iter()
+		.filter(|header| header.height > checkpoint_height)
+		.peekable();
+
+	let mut batch = 0;
+	while headers.peek().is_some() {
+		let staged: Vec<_> = headers.by_ref().take(batch_size).collect();
+		if let Err(error) = check_batch(engine, &outcome.store, &staged) {
+			outcome.error = Some(SyncError { batch, error });
+			return outcome;
+		}
+		outcome.imported += staged.len();
+		for header in staged {
+			outcome.store.import(header).expect("checked headers connect to the store");
+		}
+		batch += 1;
+	}
+	outcome
+}
+
+/// Check every header in the batch against its parent, which is either already in the store or
+/// earlier in the batch. Nothing is imported, so a rejected batch leaves the store untouched
+/// without having to copy it first.
+fn check_batch<C: Consensus + ?Sized>(
+	engine: &C,
+	store: &ChainStore<C::Digest>,
+	batch: &[Header<C::Digest>],
+) -> Result<(), ChainError> {
+	let mut staged = HashMap::new();
+	for header in batch {
+		let parent = staged
+			.get(&header.parent)
+			.copied()
+			.or_else(|| store.get(header.parent))
+			.ok_or(ChainError::UnknownParent)?;
+		engine.check_sub_chain(parent, std::slice::from_ref(header))?;
+		staged.insert(hash(header), header);
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+use super::{p3_poa::PoaRoundRobinByHeight, test_support::build_valid_chain, ConsensusAuthority};
+
+#[cfg(test)]
+fn engine() -> PoaRoundRobinByHeight {
+	PoaRoundRobinByHeight::new(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob])
+}
+
+#[test]
+fn cs_sync_valid_stream_from_checkpoint() {
+	let engine = engine();
+	let chain = build_valid_chain(&engine, 7);
+
+	// The peer sends everything from genesis, but the node already trusts the second header.
+	let outcome = sync_from(&engine, chain.clone(), chain[1].clone(), 2);
+	assert_eq!(outcome.error, None);
+	assert_eq!(outcome.imported, 5);
+	assert_eq!(outcome.store.genesis(), hash(&chain[1]));
+	assert_eq!(outcome.store.head(), hash(&chain[6]));
+	assert!(!outcome.store.contains(hash(&chain[0])));
+}
+
+#[test]
+fn cs_sync_stops_at_corrupted_batch() {
+	let engine = engine();
+	let genesis = engine.genesis_header();
+	let mut chain = build_valid_chain(&engine, 6);
+	// The fourth header is signed by the wrong authority.
+	chain[3].consensus_digest = chain[2].consensus_digest;
+
+	let outcome = sync_from(&engine, chain.clone(), genesis, 2);
+	assert_eq!(outcome.error, Some(SyncError { batch: 1, error: ChainError::WrongAuthority }));
+	assert_eq!(outcome.imported, 2);
+	assert_eq!(outcome.store.head(), hash(&chain[1]));
+
+	// The valid header that shared a batch with the invalid one was discarded too.
+	assert!(!outcome.store.contains(hash(&chain[2])));
+}
@@ -0,0 +1,422 @@
+//! Helpers shared by the tests throughout this chapter.
+//!
+//! Test chains built here follow the simple adder convention from chapter 2. The extrinsics root
+//! holds a single extrinsic, and the state root is the running sum of all extrinsics so far. That
+//! gives the tests a notion of "bad state" without tying the consensus engines to any particular
+//! state machine.
+
+use super::{p3_poa::SlotDigest, Consensus, ConsensusAuthority, Header};
+use crate::{hash, ChainError};
+
+/// A sealed child of the given parent carrying the given extrinsic.
+pub fn sealed_child<C: Consensus + ?Sized>(
+	engine: &C,
+	parent: &Header<C::Digest>,
+	extrinsic: u64,
+) -> Header<C::Digest> {
+	let partial = Header {
+		parent: hash(parent),
+		height: parent.height + 1,
+		extrinsics_root: extrinsic,
+		state_root: parent.state_root + extrinsic,
+		consensus_digest: (),
+	};
+	engine
+		.seal(&parent.consensus_digest, partial)
+		.expect("test engines can always seal")
+}
+
+/// Build a chain of `n` blocks on top of the given genesis, sealed by the given engine.
+/// Block `i` carries the extrinsic `i`.
+pub fn sealed_adder_chain<C: Consensus + ?Sized>(
+	engine: &C,
+	genesis: &Header<C::Digest>,
+	n: u64,
+) -> Vec<Header<C::Digest>> {
+	let mut chain: Vec<Header<C::Digest>> = Vec::new();
+	for extrinsic in 1..=n {
+		let parent = chain.last().unwrap_or(genesis);
+		let child = sealed_child(engine, parent, extrinsic);
+		chain.push(child);
+	}
+	chain
+}
+
+/// Build a valid chain of `len` blocks on top of the engine's own genesis, sealed by the engine.
+/// Like `sealed_adder_chain`, block `i` carries the extrinsic `i`.
+pub fn build_valid_chain<C: Consensus + ?Sized>(engine: &C, len: usize) -> Vec<Header<C::Digest>> {
+	let len = u64::try_from(len).expect("test chains are never that long");
+	sealed_adder_chain(engine, &engine.genesis_header(), len)
+}
+
+/// Check a chain according to the engine's rules and the adder state convention.
+pub fn check_adder_chain<C: Consensus + ?Sized>(
+	engine: &C,
+	genesis: &Header<C::Digest>,
+	chain: &[Header<C::Digest>],
+) -> Result<(), ChainError> {
+	let mut parent = genesis;
+	for header in chain {
+		engine.check_sub_chain(parent, std::slice::from_ref(header))?;
+		if header.state_root != parent.state_root + header.extrinsics_root {
+			return Err(ChainError::BadState);
+		}
+		parent = header;
+	}
+	Ok(())
+}
+
+/// A single, specific thing that can be wrong with a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Defect {
+	WrongParent,
+	SkippedHeight,
+	BadState,
+	UnminedPow,
+	WrongAuthority,
+	NonMonotonicSlot,
+}
+
+impl Defect {
+	/// The reason a verifier should give for rejecting a chain with this defect.
+	pub fn expected_error(&self) -> ChainError {
+		match self {
+			Defect::WrongParent => ChainError::WrongParent,
+			Defect::SkippedHeight => ChainError::WrongHeight,
+			Defect::BadState => ChainError::BadState,
+			Defect::UnminedPow => ChainError::InsufficientWork,
+			Defect::WrongAuthority => ChainError::WrongAuthority,
+			Defect::NonMonotonicSlot => ChainError::NonMonotonicSlot,
+		}
+	}
+}
+
+/// A digest type that knows how to be corrupted in a way that is specific to its engine.
+pub trait TamperDigest: Sized {
+	/// The consensus-level defect that tampering with this digest produces.
+	const DEFECT: Defect;
+
+	/// Candidate replacements for a correctly sealed digest. At least one of them should be
+	/// rejected by the engine for the reason given by `DEFECT`.
+	fn candidates(&self, parent: &Self) -> Vec<Self>;
+}
+
+impl TamperDigest for u64 {
+	const DEFECT: Defect = Defect::UnminedPow;
+
+	fn candidates(&self, _: &Self) -> Vec<Self> {
+		(1..10_000).map(|offset| self.wrapping_add(offset)).collect()
+	}
+}
+
+impl TamperDigest for ConsensusAuthority {
+	const DEFECT: Defect = Defect::WrongAuthority;
+
+	fn candidates(&self, _: &Self) -> Vec<Self> {
+		[ConsensusAuthority::Alice, ConsensusAuthority::Bob, ConsensusAuthority::Charlie]
+			.into_iter()
+			.filter(|authority| authority != self)
+			.collect()
+	}
+}
+
+impl TamperDigest for SlotDigest {
+	const DEFECT: Defect = Defect::NonMonotonicSlot;
+
+	fn candidates(&self, parent: &Self) -> Vec<Self> {
+		vec![SlotDigest { slot: parent.slot, signature: self.signature }]
+	}
+}
+
+/// A copy of a valid chain with exactly one defect introduced.
+#[derive(Clone, Debug)]
+pub struct TamperedChain<D> {
+	pub defect: Defect,
+	pub chain: Vec<Header<D>>,
+}
+
+/// Produces variants of a valid chain, each with a single specific defect.
+///
+/// The defect is introduced at one block, and every later block is re-linked and re-sealed on top
+/// of it so that the defect is the only thing wrong with the chain.
+pub struct TamperedChainBuilder<'a, C: Consensus> {
+	engine: &'a C,
+	genesis: Header<C::Digest>,
+	chain: Vec<Header<C::Digest>>,
+	at: usize,
+}
+
+impl<'a, C> TamperedChainBuilder<'a, C>
+where
+	C: Consensus,
+	C::Digest: TamperDigest,
+{
+	/// Start from the given valid chain. By default the defect goes in the middle block.
+	pub fn new(engine: &'a C, genesis: Header<C::Digest>, chain: Vec<Header<C::Digest>>) -> Self {
+		assert!(!chain.is_empty(), "there must be at least one block to tamper with");
+		let at = chain.len() / 2;
+		TamperedChainBuilder { engine, genesis, chain, at }
+	}
+
+	/// Introduce the defect at the given index instead.
+	pub fn at(mut self, index: usize) -> Self {
+		assert!(index < self.chain.len(), "tampered index must be within the chain");
+		self.at = index;
+		self
+	}
+
+	/// All the single-defect variants of the chain that this engine's digest supports.
+	pub fn variants(&self) -> Vec<TamperedChain<C::Digest>> {
+		[Defect::WrongParent, Defect::SkippedHeight, Defect::BadState, C::Digest::DEFECT]
+			.into_iter()
+			.map(|defect| TamperedChain { defect, chain: self.tampered(defect) })
+			.collect()
+	}
+
+	/// The chain with the given defect introduced.
+	pub fn tampered(&self, defect: Defect) -> Vec<Header<C::Digest>> {
+		let mut chain = self.chain[..self.at].to_vec();
+		let parent = chain.last().unwrap_or(&self.genesis).clone();
+		let original = &self.chain[self.at];
+
+		let mut partial = Header {
+			parent: hash(&parent),
+			height: parent.height + 1,
+			extrinsics_root: original.extrinsics_root,
+			state_root: parent.state_root + original.extrinsics_root,
+			consensus_digest: (),
+		};
+		match defect {
+			Defect::WrongParent => partial.parent = partial.parent.wrapping_add(1),
+			Defect::SkippedHeight => partial.height += 1,
+			Defect::BadState => partial.state_root += 1,
+			_ => (),
+		}
+		let mut header = self
+			.engine
+			.seal(&parent.consensus_digest, partial)
+			.expect("test engines can always seal");
+
+		if defect == C::Digest::DEFECT {
+			header.consensus_digest = header
+				.consensus_digest
+				.candidates(&parent.consensus_digest)
+				.into_iter()
+				.find(|candidate| {
+					let mut tampered = header.clone();
+					tampered.consensus_digest = candidate.clone();
+					self.engine.check_header(&parent.consensus_digest, &tampered) ==
+						Err(defect.expected_error())
+				})
+				.expect("some candidate digest exhibits the defect");
+		}
+		chain.push(header);
+
+		// Rebuild the rest of the chain so the single defect is the only problem.
+		for original in &self.chain[self.at + 1..] {
+			let child = sealed_child(self.engine, chain.last().unwrap(), original.extrinsics_root);
+			chain.push(child);
+		}
+		chain
+	}
+}
+
+fn assert_all_variants_rejected<C>(engine: &C)
+where
+	C: Consensus,
+	C::Digest: TamperDigest,
+{
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(engine, &genesis, 5);
+	assert_eq!(check_adder_chain(engine, &genesis, &chain), Ok(()));
+
+	for at in 0..chain.len() {
+		let builder = TamperedChainBuilder::new(engine, genesis.clone(), chain.clone()).at(at);
+		for variant in builder.variants() {
+			assert_eq!(variant.chain.len(), chain.len());
+			assert_eq!(
+				check_adder_chain(engine, &genesis, &variant.chain),
+				Err(variant.defect.expected_error()),
+				"{:?} at block {} was not rejected for the right reason",
+				variant.defect,
+				at,
+			);
+		}
+	}
+}
+
+#[test]
+fn cs_tampered_pow_chains_are_rejected() {
+	let engine = super::p1_pow::PoW::new(u64::MAX / 4);
+
+	assert_all_variants_rejected(&engine);
+}
+
+#[test]
+fn cs_tampered_round_robin_chains_are_rejected() {
+	let engine = super::p3_poa::PoaRoundRobinByHeight::new(vec![
+		ConsensusAuthority::Alice,
+		ConsensusAuthority::Bob,
+		ConsensusAuthority::Charlie,
+	]);
+
+	assert_all_variants_rejected(&engine);
+}
+
+#[test]
+fn cs_tampered_slot_chains_are_rejected() {
+	let engine = super::p3_poa::PoaRoundRobinBySlot::new(vec![
+		ConsensusAuthority::Alice,
+		ConsensusAuthority::Bob,
+		ConsensusAuthority::Charlie,
+	]);
+
+	assert_all_variants_rejected(&engine);
+}
+
+/// Check that a chain sealed on top of the engine's own genesis header is valid.
+fn assert_genesis_is_a_valid_parent<C: Consensus>(engine: &C) {
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(engine, &genesis, 3);
+
+	assert_eq!(check_adder_chain(engine, &genesis, &chain), Ok(()));
+}
+
+#[test]
+fn cs_genesis_header_is_a_valid_parent() {
+	let authorities =
+		vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob, ConsensusAuthority::Charlie];
+
+	assert_genesis_is_a_valid_parent(&());
+	assert_genesis_is_a_valid_parent(&super::p1_pow::moderate_difficulty_pow());
+	assert_genesis_is_a_valid_parent(&super::p1_pow::RetargetingPoW::new(u64::MAX / 4, 100, 4));
+	assert_genesis_is_a_valid_parent(&super::p3_poa::PoaRoundRobinByHeight::new(
+		authorities.clone(),
+	));
+	assert_genesis_is_a_valid_parent(&super::p3_poa::PoaRoundRobinBySlot::new(authorities.clone()));
+	assert_genesis_is_a_valid_parent(&super::p3_poa::RandomizedPoa::new(authorities, 42));
+}
+
+#[test]
+fn cs_build_valid_chain_validates_for_pow_and_round_robin() {
+	let pow = super::p1_pow::PoW::new(u64::MAX / 4);
+	let chain = build_valid_chain(&pow, 4);
+	assert_eq!(chain.len(), 4);
+	assert_eq!(check_adder_chain(&pow, &pow.genesis_header(), &chain), Ok(()));
+
+	let poa = super::p3_poa::PoaRoundRobinByHeight::new(vec![
+		ConsensusAuthority::Alice,
+		ConsensusAuthority::Bob,
+	]);
+	let chain = build_valid_chain(&poa, 4);
+	assert_eq!(chain.len(), 4);
+	assert_eq!(check_adder_chain(&poa, &poa.genesis_header(), &chain), Ok(()));
+	assert!(build_valid_chain(&poa, 0).is_empty());
+}
+
+/// Check that validating nothing on top of the engine's own genesis succeeds in every validator.
+fn assert_empty_chain_is_valid<C: Consensus + ?Sized>(engine: &C) {
+	let genesis = engine.genesis_header();
+
+	assert!(engine.verify_sub_chain(&genesis.consensus_digest, &[]));
+	assert_eq!(engine.longest_valid_prefix(&genesis, &[]), 0);
+	assert_eq!(engine.check_sub_chain(&genesis, &[]), Ok(()));
+	assert_eq!(check_adder_chain(engine, &genesis, &[]), Ok(()));
+}
+
+#[test]
+fn cs_empty_chain_is_valid_for_every_engine() {
+	use super::{
+		config::ConsensusConfig,
+		p1_pow::PoW,
+		p3_poa::{PoaRoundRobinByHeight, PoaRoundRobinBySlot},
+	};
+
+	let authorities = vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob];
+	let pow = || ConsensusConfig::pow(u64::MAX / 4);
+	let poa = || ConsensusConfig::poa_round_robin(authorities.clone());
+
+	assert_empty_chain_is_valid(&());
+	assert_empty_chain_is_valid(&PoW::new(u64::MAX / 4));
+	assert_empty_chain_is_valid(&PoaRoundRobinByHeight::new(authorities.clone()));
+	assert_empty_chain_is_valid(&PoaRoundRobinBySlot::new(authorities.clone()));
+	assert_empty_chain_is_valid(&*ConsensusConfig::even_only(pow()).build());
+	assert_empty_chain_is_valid(&*ConsensusConfig::forked(pow(), poa(), 0).build());
+	assert_empty_chain_is_valid(&*ConsensusConfig::forked(pow(), poa(), 5).build());
+
+	// Even engines that could never seal a block have a valid empty chain.
+	assert_empty_chain_is_valid(&PoaRoundRobinBySlot::new(vec![]));
+}
+
+#[test]
+fn cs_aura_genesis_header_claims_slot_zero() {
+	use super::{clock::MockClock, p3_poa::Aura};
+
+	let clock = MockClock::new(50);
+	let engine = Aura::new(vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob], 10, &clock);
+	let genesis = engine.genesis_header();
+	let child = sealed_child(&engine, &genesis, 1);
+
+	assert_eq!(genesis.consensus_digest.slot, 0);
+	assert_eq!(check_adder_chain(&engine, &genesis, &[child]), Ok(()));
+}
+
+#[test]
+fn cs_boxed_engines_validate_through_trait_objects() {
+	use super::{from_fns::from_fns, p1_pow::PoW, ConsensusExt, EngineKind};
+
+	// Sealed by the strictest of the engines, so the chain is valid for all of them.
+	let miner = super::p1_pow::moderate_difficulty_pow();
+	let genesis = miner.genesis_header();
+	let chain = sealed_adder_chain(&miner, &genesis, 3);
+
+	let engines: Vec<Box<dyn Consensus<Digest = u64>>> = vec![
+		miner.boxed(),
+		PoW::new(u64::MAX / 4).boxed(),
+		from_fns(|_: &u64, _: &Header<u64>| true, |_: &u64, _: Header<()>| None).boxed(),
+	];
+
+	assert_eq!(engines[0].kind(), EngineKind::Pow);
+	assert_eq!(engines[2].kind(), EngineKind::Custom);
+	for engine in &engines {
+		assert!(engine.verify_sub_chain(&genesis.consensus_digest, &chain));
+	}
+}
+
+#[test]
+fn cs_self_test_passes_for_each_engine() {
+	use super::{
+		cached::Cached, clock::MockClock, monotonic_state::MonotonicState, p1_pow::*, p3_poa::*,
+		parity_rule::ParityRule, uncles::UnclePoW,
+	};
+
+	let authorities =
+		vec![ConsensusAuthority::Alice, ConsensusAuthority::Bob, ConsensusAuthority::Charlie];
+	let clock = MockClock::new(50);
+
+	assert!(().self_test());
+	assert!(moderate_difficulty_pow().self_test());
+	assert!(RetargetingPoW::new(u64::MAX / 4, 100, 4).self_test());
+	assert!(BombPoW::new(u64::MAX / 4, 10, 5).self_test());
+	assert!(PoaRoundRobinByHeight::new(authorities.clone()).self_test());
+	assert!(PoaRoundRobinBySlot::new(authorities.clone()).self_test());
+	assert!(Aura::new(authorities.clone(), 10, &clock).self_test());
+	assert!(RandomizedPoa::new(authorities, 42).self_test());
+	assert!(UnclePoW::new(u64::MAX / 4, 2, 5).self_test());
+	assert!(MonotonicState::new(moderate_difficulty_pow()).self_test());
+	assert!(ParityRule::new(moderate_difficulty_pow(), true, 0, 4).self_test());
+	assert!(Cached::new(moderate_difficulty_pow(), 8).self_test());
+}
+
+#[test]
+fn cs_self_test_catches_seal_that_disagrees_with_validate() {
+	use super::from_fns::from_fns;
+
+	// Seals every header, but only accepts headers with a nonzero digest.
+	let engine = from_fns(
+		|_: &u64, header: &Header<u64>| header.consensus_digest != 0,
+		|_: &u64, partial: Header<()>| Some(partial.with_digest(0)),
+	);
+
+	assert!(!engine.self_test());
+}
@@ -0,0 +1,305 @@
+//! In Proof of Work, two miners sometimes find blocks at the same height, and only one of them ends
+//! up on the canonical chain. The loser's work is wasted, which hurts small miners the most, and
+//! makes the chain less secure than all the work done on it would suggest.
+//!
+//! Ethereum's answer was to let blocks reference a few of these recently orphaned blocks, known as
+//! uncles or ommers, and to reward their miners too. This is the inclusion incentive behind the
+//! GHOST protocol. Here we model it in the consensus framework.
+//!
+//! As described on [`Consensus::Digest`], the engine carries the history it needs in its digest. It
+//! records the hashes of recent ancestors, so that uncles can be checked to really be orphaned
+//! siblings of the chain, and the uncles referenced by recent blocks, so that no uncle can be
+//! rewarded twice.
+
+use super::{
+	genesis_with_digest,
+	p1_pow::{clamp_threshold, expected_attempts},
+	Consensus, Header, BASE_RULES,
+};
+use crate::{hash, meets_threshold, ChainError};
+
+type Hash = u64;
+
+/// The most uncles a single block may reference.
+const MAX_UNCLES: usize = 2;
+
+/// The digest of an `UnclePoW` engine.
+#[derive(Hash, Debug, PartialEq, Eq, Clone)]
+pub struct UncleDigest {
+	pub(crate) nonce: u64,
+	/// The orphaned headers this block references.
+	pub(crate) uncles: Vec<Header<UncleDigest>>,
+	/// The height and hash of every uncle referenced by this block or one of its recent ancestors
+	/// that is still young enough to be referenced again.
+	pub(crate) recent_uncles: Vec<(u64, Hash)>,
+	/// The hashes of the most recent ancestors of this block, oldest first, ending with its parent.
+	pub(crate) ancestors: Vec<Hash>,
+}
+
+/// A Proof of Work engine in which blocks may reference up to two uncles.
+///
+/// Each uncle must be a validly mined header from at most `max_generations` blocks ago. Its parent
+/// must be an ancestor of the block, but the uncle itself must not be, and it must not have been
+/// referenced before. The author credits `uncle_reward` to the state root for each uncle, so it is
+/// paid out along with the block.
+pub struct UnclePoW {
+	threshold: u64,
+	max_generations: u64,
+	uncle_reward: u64,
+}
+
+impl UnclePoW {
+	/// Create a new engine with the given PoW threshold, uncle age limit, and uncle reward. The
+	/// threshold is clamped into `[MIN_THRESHOLD, MAX_THRESHOLD]`.
+	pub fn new(threshold: u64, max_generations: u64, uncle_reward: u64) -> Self {
+		UnclePoW { threshold: clamp_threshold(threshold.into()), max_generations, uncle_reward }
+	}
+
+	/// The total reward credited to the state root of the given header for its uncles.
+	///
+	/// Consensus does not execute blocks, so it can not tell whether the state root really
+	/// includes this credit. The execution layer must add it when checking the state root.
+	pub fn uncle_credit(&self, header: &Header<UncleDigest>) -> u64 {
+		self.uncle_reward * header.consensus_digest.uncles.len() as u64
+	}
+
+	/// The digest of a child of the given parent that references the given uncles, without a
+	/// nonce.
+	///
+	/// Returns `None` if the uncles break any of the rules.
+	fn digest_after(
+		&self,
+		parent_digest: &UncleDigest,
+		parent: Hash,
+		height: u64,
+		uncles: Vec<Header<UncleDigest>>,
+	) -> Option<UncleDigest> {
+		if uncles.len() > MAX_UNCLES {
+			return None;
+		}
+
+		// Uncles may be up to `max_generations` blocks old, so their parents may be one older.
+		let mut ancestors = parent_digest.ancestors.clone();
+		ancestors.push(parent);
+		let keep = (self.max_generations as usize).saturating_add(1).min(ancestors.len());
+		ancestors.drain(..ancestors.len() - keep);
+
+		let oldest = height.saturating_sub(self.max_generations);
+		let mut recent: Vec<(u64, Hash)> = parent_digest
+			.recent_uncles
+			.iter()
+			.filter(|(uncle_height, _)| *uncle_height >= oldest)
+			.copied()
+			.collect();
+		for uncle in &uncles {
+			let uncle_hash = hash(uncle);
+			if uncle.height >= height || uncle.height < oldest {
+				return None;
+			}
+			if ancestors.contains(&uncle_hash) || !ancestors.contains(&uncle.parent) {
+				return None;
+			}
+			if !meets_threshold(uncle, self.threshold) {
+				return None;
+			}
+			if recent.iter().any(|(_, h)| *h == uncle_hash) {
+				return None;
+			}
+			recent.push((uncle.height, uncle_hash));
+		}
+		Some(UncleDigest { nonce: 0, uncles, recent_uncles: recent, ancestors })
+	}
+
+	/// Mine a PoW seal for the partial header that references the given uncles, and credit the
+	/// uncle rewards to its state root.
+	///
+	/// Returns `None` if the uncles may not be referenced by this header.
+	pub fn seal_with_uncles(
+		&self,
+		parent_digest: &UncleDigest,
+		partial_header: Header<()>,
+		uncles: Vec<Header<UncleDigest>>,
+	) -> Option<Header<UncleDigest>> {
+		let credit = self.uncle_reward * uncles.len() as u64;
+		let digest =
+			self.digest_after(parent_digest, partial_header.parent, partial_header.height, uncles)?;
+
+		let mut header = Header {
+			state_root: partial_header.state_root.checked_add(credit)?,
+			..partial_header.with_digest(digest)
+		};
+		while !meets_threshold(&header, self.threshold) {
+			header.consensus_digest.nonce = header.consensus_digest.nonce.checked_add(1)?;
+		}
+		Some(header)
+	}
+}
+
+impl Consensus for UnclePoW {
+	type Digest = UncleDigest;
+
+	fn validate(&self, parent_digest: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		self.check_header(parent_digest, header).is_ok()
+	}
+
+	/// Check the uncles against the history recorded by the parent, then check the work.
+	fn check_header(
+		&self,
+		parent_digest: &Self::Digest,
+		header: &Header<Self::Digest>,
+	) -> Result<(), ChainError> {
+		let digest = &header.consensus_digest;
+		let expected = self
+			.digest_after(parent_digest, header.parent, header.height, digest.uncles.clone())
+			.ok_or(ChainError::InvalidUncle)?;
+		if digest.recent_uncles != expected.recent_uncles || digest.ancestors != expected.ancestors
+		{
+			return Err(ChainError::InvalidSeal);
+		}
+		if !meets_threshold(header, self.threshold) {
+			return Err(ChainError::InsufficientWork);
+		}
+		Ok(())
+	}
+
+	/// Check only the work, not the uncles.
+	fn validate_seal_only(&self, _: &Self::Digest, header: &Header<Self::Digest>) -> bool {
+		meets_threshold(header, self.threshold)
+	}
+
+	/// Mine a PoW seal that references no uncles.
+	fn seal(
+		&self,
+		parent_digest: &Self::Digest,
+		partial_header: Header<()>,
+	) -> Option<Header<Self::Digest>> {
+		self.seal_with_uncles(parent_digest, partial_header, Vec::new())
+	}
+
+	fn genesis_header(&self) -> Header<Self::Digest> {
+		genesis_with_digest(UncleDigest {
+			nonce: 0,
+			uncles: Vec::new(),
+			recent_uncles: Vec::new(),
+			ancestors: Vec::new(),
+		})
+	}
+
+	fn estimate_seal_cost(&self) -> u64 {
+		expected_attempts(self.threshold)
+	}
+
+	fn rules(&self) -> Vec<&'static str> {
+		[&BASE_RULES[..], &["pow-threshold", "valid-uncles", "uncle-rewards"]].concat()
+	}
+
+	fn human_name() -> String {
+		"Proof of Work with Uncles".into()
+	}
+}
+
+#[cfg(test)]
+use super::{
+	header_builder::HeaderBuilder,
+	test_support::{sealed_adder_chain, sealed_child},
+};
+
+/// A partial child of the given parent carrying the given extrinsic.
+#[cfg(test)]
+fn partial_child(parent: &Header<UncleDigest>, extrinsic: u64) -> Header<()> {
+	HeaderBuilder::new()
+		.child_of(parent)
+		.extrinsics_root(extrinsic)
+		.state_root(parent.state_root + extrinsic)
+		.build()
+		.unwrap()
+}
+
+/// Mine the given header as is, without checking its uncles.
+#[cfg(test)]
+fn mined(engine: &UnclePoW, mut header: Header<UncleDigest>) -> Header<UncleDigest> {
+	while !meets_threshold(&header, engine.threshold) {
+		header.consensus_digest.nonce += 1;
+	}
+	header
+}
+
+#[test]
+fn cs_uncle_reference_is_valid_and_credited() {
+	let engine = UnclePoW::new(u64::MAX / 4, 2, 5);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 1);
+	let orphan = sealed_child(&engine, &genesis, 7);
+
+	let child = engine
+		.seal_with_uncles(&chain[0].consensus_digest, partial_child(&chain[0], 2), vec![orphan])
+		.unwrap();
+
+	assert_eq!(engine.check_header(&chain[0].consensus_digest, &child), Ok(()));
+	assert_eq!(engine.uncle_credit(&child), 5);
+	assert_eq!(child.state_root, chain[0].state_root + 2 + 5);
+}
+
+#[test]
+fn cs_too_old_uncle_is_rejected() {
+	let engine = UnclePoW::new(u64::MAX / 4, 1, 5);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 2);
+	let orphan = sealed_child(&engine, &genesis, 7);
+	let parent = &chain[1];
+
+	let uncles = vec![orphan.clone()];
+	assert_eq!(
+		engine.seal_with_uncles(&parent.consensus_digest, partial_child(parent, 3), uncles),
+		None
+	);
+
+	// An author who references the uncle anyway produces an invalid block.
+	let mut child = engine.seal(&parent.consensus_digest, partial_child(parent, 3)).unwrap();
+	child.consensus_digest.recent_uncles.push((orphan.height, hash(&orphan)));
+	child.consensus_digest.uncles.push(orphan);
+	let child = mined(&engine, child);
+	assert!(engine.validate_seal_only(&parent.consensus_digest, &child));
+	assert_eq!(
+		engine.check_header(&parent.consensus_digest, &child),
+		Err(ChainError::InvalidUncle)
+	);
+}
+
+#[test]
+fn cs_duplicated_uncle_is_rejected() {
+	let engine = UnclePoW::new(u64::MAX / 4, 3, 5);
+	let genesis = engine.genesis_header();
+	let chain = sealed_adder_chain(&engine, &genesis, 1);
+	let orphan = sealed_child(&engine, &genesis, 7);
+	let parent = engine
+		.seal_with_uncles(
+			&chain[0].consensus_digest,
+			partial_child(&chain[0], 2),
+			vec![orphan.clone()],
+		)
+		.unwrap();
+
+	// The uncle was already rewarded by the parent.
+	let uncles = vec![orphan.clone()];
+	assert_eq!(
+		engine.seal_with_uncles(&parent.consensus_digest, partial_child(&parent, 3), uncles),
+		None
+	);
+
+	let mut child = engine.seal(&parent.consensus_digest, partial_child(&parent, 3)).unwrap();
+	child.consensus_digest.recent_uncles.push((orphan.height, hash(&orphan)));
+	child.consensus_digest.uncles.push(orphan.clone());
+	let child = mined(&engine, child);
+	assert_eq!(
+		engine.check_header(&parent.consensus_digest, &child),
+		Err(ChainError::InvalidUncle)
+	);
+
+	// Nor may a block reference the same uncle twice.
+	let uncles = vec![orphan.clone(), orphan];
+	assert_eq!(
+		engine.seal_with_uncles(&chain[0].consensus_digest, partial_child(&chain[0], 2), uncles),
+		None
+	);
+}
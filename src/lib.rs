@@ -1,6 +1,7 @@
 //! Learn the fundamentals of blockchain by building it from scratch.
 
 use std::{
+	cmp::{Ordering, Reverse},
 	collections::hash_map::DefaultHasher,
 	hash::{Hash, Hasher},
 };
@@ -10,9 +11,211 @@ mod c2_blockchain;
 mod c3_consensus;
 mod c4_framework;
 
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub use c3_consensus::fuzzing;
+
 // Simple helper to do some hashing.
 fn hash<T: Hash>(t: &T) -> u64 {
 	let mut s = DefaultHasher::new();
 	t.hash(&mut s);
 	s.finish()
 }
+
+/// The root of a binary Merkle tree whose leaves are the hashes of the given items.
+///
+/// Each level hashes adjacent pairs, and an odd node out is carried up to the next level unchanged.
+/// Bitcoin pairs it with itself instead, which lets a body with its last item repeated share a
+/// root with the original (CVE-2012-2459). Leaves and internal nodes are hashed under different
+/// tags, so an internal node can never pass for a leaf. The root of no items is zero.
+fn merkle_root<T: Hash>(items: &[T]) -> u64 {
+	let mut level: Vec<u64> = items.iter().map(|item| hash(&(MERKLE_LEAF, item))).collect();
+	if level.is_empty() {
+		return 0;
+	}
+	while level.len() > 1 {
+		level = level
+			.chunks(2)
+			.map(|pair| match pair {
+				[left, right] => hash(&(MERKLE_NODE, left, right)),
+				[odd] => *odd,
+				_ => unreachable!("chunks of two"),
+			})
+			.collect();
+	}
+	level[0]
+}
+
+/// Domain tags for the leaves and internal nodes of a Merkle tree.
+const MERKLE_LEAF: u8 = 0;
+const MERKLE_NODE: u8 = 1;
+
+/// Domain tags prepended to the hash preimage of each chapter's headers.
+///
+/// The headers of chapters 2 and 3 are structurally similar, so without a tag, two headers with
+/// equal fields could hash identically even though they belong to different kinds of chain.
+const C2_HEADER_DOMAIN: u8 = b'2';
+const C3_HEADER_DOMAIN: u8 = b'3';
+
+/// Hash a header's fields under the given domain tag. Every chapter's `Hash` impl for its headers
+/// goes through here, so no header is ever hashed without its tag.
+fn hash_with_domain<T: Hash, H: Hasher>(domain: u8, fields: T, hasher: &mut H) {
+	(domain, fields).hash(hasher)
+}
+
+/// A header that fork choice can rank. The headers of every chapter know their own height.
+trait ChainHeader: Hash {
+	fn height(&self) -> u64;
+}
+
+/// The amount of work a header represents.
+///
+/// Finding a hash below some value `h` takes, on average, `2^64 / (h + 1)` attempts, so that is
+/// the work we credit a header with. This is the same idea as the `THRESHOLD - block_hash`
+/// formula from chapter 2, but it adds up correctly across blocks mined at different difficulties.
+fn block_work<H: ChainHeader>(header: &H) -> u128 {
+	(u64::MAX as u128 + 1) / (hash(header) as u128 + 1)
+}
+
+/// The single order every fork choice in this crate agrees on. The chain with the higher tip
+/// ranks higher, then the chain with more work, and then the one whose tip has the lowest hash.
+///
+/// Nothing about a chain depends on the order a node learned about it, so nodes that know the same
+/// chains always agree on how they rank. Both chains should start from the same genesis. An empty
+/// chain ranks below every other chain.
+fn canonical_cmp<H: ChainHeader>(a: &[H], b: &[H]) -> Ordering {
+	let key = |chain: &[H]| {
+		let tip = chain.last()?;
+		Some(canonical_key(tip.height(), chain.iter().map(block_work).sum(), hash(tip)))
+	};
+	key(a).cmp(&key(b))
+}
+
+/// The key `canonical_cmp` ranks a chain by, given the height of its tip, its total work, and the
+/// hash of its tip.
+fn canonical_key(height: u64, total_work: u128, tip: u64) -> (u64, u128, Reverse<u64>) {
+	(height, total_work, Reverse(tip))
+}
+
+/// The Proof of Work rule, shared by every PoW chain in this crate. An item meets the threshold
+/// when its hash is strictly below it.
+fn meets_threshold<T: Hash>(item: &T, threshold: u64) -> bool {
+	hash(item) < threshold
+}
+
+/// The reasons a chain can be rejected by the verifiers throughout this crate.
+///
+/// Most verifiers in the early chapters simply return a `bool`, which is fine for learning, but
+/// makes it hard to tell _why_ a chain was rejected. Verifiers that report a reason use this enum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChainError {
+	/// The header's parent hash is not the hash of the previous header.
+	WrongParent,
+	/// The header's parent is not known, so the header cannot be connected to the chain.
+	UnknownParent,
+	/// The header's height is not exactly one more than its parent's.
+	WrongHeight,
+	/// The state in the header does not match the result of executing the block.
+	BadState,
+	/// The header's total weight is not the sum of the weights of the block's extrinsics.
+	WrongWeight,
+	/// The block weighs more than the chain allows.
+	Overweight,
+	/// The header's hash does not meet the Proof of Work threshold.
+	InsufficientWork,
+	/// The header claims a different difficulty than its parent calls for.
+	WrongDifficulty,
+	/// The header was signed by an authority who was not allowed to sign it.
+	WrongAuthority,
+	/// The header's slot is not strictly greater than its parent's slot.
+	NonMonotonicSlot,
+	/// The header's slot has not started yet according to the local clock.
+	FutureSlot,
+	/// The header's state root is lower than its parent's, in a chain where state may not decrease.
+	DecreasingState,
+	/// Too many recent headers have a state root of the wrong parity.
+	WrongParity,
+	/// The block applies a time-locked extrinsic before its activation height.
+	InactiveTimeLock,
+	/// The block's extrinsics are not in canonical (ascending) order.
+	UnsortedExtrinsics,
+	/// The block references an uncle that is too old, not an orphaned sibling of the chain, not
+	/// validly mined, or already referenced.
+	InvalidUncle,
+	/// The header is above the last height of a chain that has a defined end.
+	BeyondMaxHeight,
+	/// The balances committed to by the header do not credit exactly one block reward to its
+	/// author.
+	WrongReward,
+	/// Too few distinct authorities signed the most recent blocks.
+	TooFewSigners,
+	/// The header's extrinsics root is not the Merkle root of the block's extrinsics.
+	WrongExtrinsicsRoot,
+	/// The header commits to an older head of a bridged chain than its parent does.
+	DecreasingBridgeCommitment,
+	/// The header was sealed sooner after its parent than the minimum block interval allows.
+	BlockTooSoon,
+	/// The header's timestamp is further ahead of the local clock than the allowed drift.
+	FutureTimestamp,
+	/// The header was sealed by an engine whose grace window has closed.
+	GraceWindowClosed,
+	/// The header's seal is invalid for a reason the consensus engine does not report.
+	InvalidSeal,
+	/// The header's slot is further ahead of its parent's slot than the engine allows.
+	SlotGapTooLarge,
+	/// The header, or its parent, was built for a different network.
+	WrongNetwork,
+	/// The block's extrinsics tree is deeper than the engine allows, or than its header claims.
+	TreeTooDeep,
+	/// The header's state root is above the chain's supply cap.
+	SupplyCapExceeded,
+	/// The header's author signed a conflicting header at the same height, or was slashed for
+	/// doing so before.
+	Equivocation,
+	/// The proof is valid, but it does not end at the header it was meant to prove.
+	UnprovenTarget,
+}
+
+#[test]
+fn meets_threshold_just_above_hash() {
+	let item = (1u64, 2u64);
+	assert!(meets_threshold(&item, hash(&item) + 1));
+}
+
+#[test]
+fn meets_threshold_exactly_at_hash() {
+	let item = (1u64, 2u64);
+	assert!(!meets_threshold(&item, hash(&item)));
+}
+
+#[test]
+fn meets_threshold_just_below_hash() {
+	let item = (1u64, 2u64);
+	assert!(!meets_threshold(&item, hash(&item) - 1));
+}
+
+#[test]
+fn merkle_root_commits_to_order_and_content() {
+	let root = merkle_root(&[1u64, 2, 3]);
+
+	assert_eq!(root, merkle_root(&[1u64, 2, 3]));
+	assert_ne!(root, merkle_root(&[2u64, 1, 3]));
+	assert_ne!(root, merkle_root(&[1u64, 2, 4]));
+	assert_eq!(merkle_root(&[7u64]), hash(&(MERKLE_LEAF, 7u64)));
+	assert_eq!(merkle_root::<u64>(&[]), 0);
+}
+
+#[test]
+fn merkle_root_does_not_collide_when_the_last_item_is_repeated() {
+	assert_ne!(merkle_root(&[1u64, 2, 3]), merkle_root(&[1u64, 2, 3, 3]));
+	assert_ne!(merkle_root(&[1u64, 2, 3, 4, 5]), merkle_root(&[1u64, 2, 3, 4, 5, 5]));
+	assert_ne!(merkle_root(&[1u64, 2, 3, 4, 5]), merkle_root(&[1u64, 2, 3, 4, 5, 5, 5, 5]));
+}
+
+#[test]
+fn merkle_root_internal_node_is_not_a_leaf() {
+	let node = hash(&(MERKLE_NODE, hash(&(MERKLE_LEAF, 1u64)), hash(&(MERKLE_LEAF, 2u64))));
+
+	assert_eq!(merkle_root(&[1u64, 2]), node);
+	assert_ne!(merkle_root(&[node]), node);
+}